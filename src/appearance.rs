@@ -0,0 +1,181 @@
+use std::env;
+use std::process::Command;
+use std::str::FromStr;
+
+use log::{info, warn};
+
+use crate::ForegroundColor;
+
+/// Default background when the system uses a light appearance
+pub const LIGHT_BACKGROUND: image::Rgba<u8> = image::Rgba([0xF2, 0xF0, 0xE6, 0xFF]);
+
+#[derive(Clone, Debug, PartialEq)]
+/// Value of `--fg`, either a fixed foreground or the one matching the system appearance
+pub enum ForegroundSetting {
+    /// `light`, `dark` or a `#RRGGBB` ink
+    Fixed(ForegroundColor),
+    /// Light drawings in dark mode, dark drawings on a light background otherwise
+    System,
+}
+
+impl FromStr for ForegroundSetting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.eq_ignore_ascii_case("system") {
+            true => Ok(ForegroundSetting::System),
+            false => s.parse().map(ForegroundSetting::Fixed),
+        }
+    }
+}
+
+impl ForegroundSetting {
+    /// Foreground color and, for `System`, the default background that goes with it
+    pub fn resolve(&self) -> (ForegroundColor, Option<image::Rgba<u8>>) {
+        match self {
+            ForegroundSetting::Fixed(fg) => (fg.clone(), None),
+            ForegroundSetting::System => {
+                let appearance = Appearance::detect().unwrap_or_else(|| {
+                    warn!("cannot detect the system appearance, assuming dark");
+                    Appearance::Dark
+                });
+                (appearance.foreground(), appearance.default_background())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Light or dark mode of the desktop
+pub enum Appearance {
+    Light,
+    Dark,
+}
+
+impl Appearance {
+    /// Current appearance of the desktop, `None` if it cannot be determined
+    pub fn detect() -> Option<Appearance> {
+        let appearance = if cfg!(target_os = "macos") {
+            // The key only exists in dark mode, reading it fails otherwise
+            let output = Command::new("defaults")
+                .args(["read", "-g", "AppleInterfaceStyle"])
+                .output()
+                .ok()?;
+            Some(
+                match String::from_utf8_lossy(&output.stdout).contains("Dark") {
+                    true => Appearance::Dark,
+                    false => Appearance::Light,
+                },
+            )
+        } else if cfg!(windows) {
+            let output = command_output(
+                "reg",
+                &[
+                    "query",
+                    r"HKCU\Software\Microsoft\Windows\CurrentVersion\Themes\Personalize",
+                    "/v",
+                    "AppsUseLightTheme",
+                ],
+            )?;
+            Some(match output.contains("0x0") {
+                true => Appearance::Dark,
+                false => Appearance::Light,
+            })
+        } else {
+            detect_freedesktop()
+        };
+        info!("detected system appearance {:?}", appearance);
+        appearance
+    }
+
+    /// Foreground that keeps drawings readable on this appearance
+    pub fn foreground(&self) -> ForegroundColor {
+        match self {
+            Appearance::Light => ForegroundColor::Dark,
+            Appearance::Dark => ForegroundColor::Light,
+        }
+    }
+
+    /// Background used when none is configured, `None` for the regular dark default
+    pub fn default_background(&self) -> Option<image::Rgba<u8>> {
+        match self {
+            Appearance::Light => Some(LIGHT_BACKGROUND),
+            Appearance::Dark => None,
+        }
+    }
+}
+
+/// GNOME's color scheme, falling back to the GTK theme name
+fn detect_freedesktop() -> Option<Appearance> {
+    if let Some(scheme) = command_output(
+        "gsettings",
+        &["get", "org.gnome.desktop.interface", "color-scheme"],
+    ) {
+        match scheme.trim().trim_matches('\'') {
+            "prefer-dark" => return Some(Appearance::Dark),
+            "prefer-light" => return Some(Appearance::Light),
+            _ => {}
+        }
+    }
+
+    let theme = env::var("GTK_THEME").ok().or_else(|| {
+        command_output(
+            "gsettings",
+            &["get", "org.gnome.desktop.interface", "gtk-theme"],
+        )
+    })?;
+    Some(theme_appearance(&theme))
+}
+
+/// Appearance implied by a theme name such as `Adwaita-dark` or `Adwaita:dark`
+fn theme_appearance(theme: &str) -> Appearance {
+    match theme.to_lowercase().contains("dark") {
+        true => Appearance::Dark,
+        false => Appearance::Light,
+    }
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("'Adwaita-dark'", Appearance::Dark)]
+    #[case("Adwaita:dark", Appearance::Dark)]
+    #[case("'Yaru'", Appearance::Light)]
+    fn theme_name_appearance(#[case] theme: &str, #[case] expected: Appearance) {
+        assert_eq!(theme_appearance(theme), expected);
+    }
+
+    #[test]
+    fn fixed_settings_keep_default_background() {
+        assert_eq!(
+            ForegroundSetting::Fixed(ForegroundColor::Dark).resolve(),
+            (ForegroundColor::Dark, None)
+        );
+        assert_eq!(
+            Appearance::Light.default_background(),
+            Some(LIGHT_BACKGROUND)
+        );
+    }
+
+    #[rstest]
+    #[case("system", ForegroundSetting::System)]
+    #[case("Dark", ForegroundSetting::Fixed(ForegroundColor::Dark))]
+    #[case(
+        "#88C0D0",
+        ForegroundSetting::Fixed(ForegroundColor::Custom(image::Rgba([0x88, 0xC0, 0xD0, 0xFF])))
+    )]
+    fn foreground_setting_parse(#[case] input: &str, #[case] expected: ForegroundSetting) {
+        assert_eq!(input.parse::<ForegroundSetting>(), Ok(expected));
+    }
+}
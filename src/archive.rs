@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use jiff::civil::Date;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::net::{ComicFetcher, UreqFetcher};
+use crate::{state, XkcdError};
+
+const ARCHIVE_URL: &str = "https://xkcd.com/archive/";
+const INDEX_FILE: &str = "archive.json";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+/// Publish dates and titles of all comics, from the xkcd archive page
+pub struct ArchiveIndex {
+    pub comics: BTreeMap<u64, Date>,
+    /// Titles as shown in the archive, missing in indexes written by older versions
+    pub titles: BTreeMap<u64, String>,
+}
+
+impl ArchiveIndex {
+    pub fn path(cache_dir: &Path) -> PathBuf {
+        cache_dir.join(INDEX_FILE)
+    }
+
+    /// Index stored in the cache directory, empty if there is none yet
+    pub fn load(cache_dir: &Path) -> Result<ArchiveIndex, XkcdError> {
+        state::load_json(&ArchiveIndex::path(cache_dir))
+    }
+
+    pub fn save(&self, cache_dir: &Path) -> Result<(), XkcdError> {
+        state::save_json(&ArchiveIndex::path(cache_dir), self)
+    }
+
+    /// Download the archive page with `fetcher` and index it
+    pub fn fetch_with(fetcher: &dyn ComicFetcher) -> Result<ArchiveIndex, XkcdError> {
+        info!("downloading comic archive from {}", ARCHIVE_URL);
+        let mut html = String::new();
+        fetcher.get(ARCHIVE_URL)?.read_to_string(&mut html)?;
+        let index = ArchiveIndex::parse(&html);
+        info!("indexed {} comics", index.comics.len());
+        Ok(index)
+    }
+
+    /// Index the links of the archive page, `<a href="/927/" title="2011-7-20">Standards</a>`
+    fn parse(html: &str) -> ArchiveIndex {
+        let mut index = ArchiveIndex::default();
+        for link in html.split("<a href=\"/").skip(1) {
+            let Some((num, date, title)) = parse_link(link) else {
+                continue;
+            };
+            index.comics.insert(num, date);
+            index.titles.insert(num, title);
+        }
+        index
+    }
+
+    /// Comic published on `date`, or the one closest to it, the earlier one on a tie
+    pub fn nearest(&self, date: Date) -> Option<u64> {
+        self.comics
+            .iter()
+            .min_by_key(|(num, published)| {
+                let days = (date - **published).get_days().unsigned_abs();
+                (days, **published > date, **num)
+            })
+            .map(|(num, _)| *num)
+    }
+
+    fn newest(&self) -> Option<Date> {
+        self.comics.values().max().copied()
+    }
+}
+
+/// Number, date and title of an archive link, starting after its `<a href="/`
+fn parse_link(link: &str) -> Option<(u64, Date, String)> {
+    let (num, rest) = link.split_once('/')?;
+    let (date, rest) = rest.strip_prefix("\" title=\"")?.split_once('"')?;
+    let mut parts = date.split('-');
+    let date = Date::new(
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+    )
+    .ok()?;
+    let title = rest
+        .strip_prefix('>')
+        .and_then(|rest| rest.split_once("</a>"))
+        .map(|(title, _)| unescape(title))
+        .unwrap_or_default();
+    Some((num.parse().ok()?, date, title))
+}
+
+/// Replace the HTML entities found in comic titles
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Number of the comic published on or nearest to `date`
+///
+/// Uses the archive index in `cache_dir`, which is downloaded again when `date` is after the
+/// newest comic it knows about.
+pub fn comic_for_date(date: Date, cache_dir: &Path) -> Result<u64, XkcdError> {
+    let mut index = ArchiveIndex::load(cache_dir)?;
+    if index.newest().is_none_or(|newest| newest < date) {
+        index = ArchiveIndex::fetch_with(&UreqFetcher)?;
+        index.save(cache_dir)?;
+    }
+    let num = index.nearest(date).ok_or(XkcdError::NotFound)?;
+    info!("comic {} is the nearest to {}", num, date);
+    Ok(num)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::civil::date;
+    use rstest::rstest;
+
+    const ARCHIVE: &str = r#"<div id="middleContainer" class="box">
+<a href="/1000/" title="2012-1-6">1000 Comics</a><br/>
+<a href="/999/" title="2012-1-4">Cougar</a><br/>
+<a href="/998/" title="2012-1-2">Alternate Universe &amp; Co</a><br/>
+<a href="/about/">About</a>
+</div>"#;
+
+    #[test]
+    fn parse_archive() {
+        let index = ArchiveIndex::parse(ARCHIVE);
+        assert_eq!(
+            index.comics,
+            BTreeMap::from([
+                (998, date(2012, 1, 2)),
+                (999, date(2012, 1, 4)),
+                (1000, date(2012, 1, 6)),
+            ])
+        );
+        assert_eq!(index.titles[&1000], "1000 Comics");
+        assert_eq!(index.titles[&998], "Alternate Universe & Co");
+    }
+
+    #[rstest]
+    #[case(date(2012, 1, 4), Some(999))]
+    // Between two comics, the earlier one wins
+    #[case(date(2012, 1, 5), Some(999))]
+    #[case(date(2011, 12, 25), Some(998))]
+    #[case(date(2013, 1, 1), Some(1000))]
+    fn nearest_comic(#[case] day: Date, #[case] expected: Option<u64>) {
+        assert_eq!(ArchiveIndex::parse(ARCHIVE).nearest(day), expected);
+    }
+
+    #[test]
+    fn cached_index_is_used() {
+        let dir = tempfile::tempdir().unwrap();
+        ArchiveIndex::parse(ARCHIVE).save(dir.path()).unwrap();
+        // Before the newest known comic, so no download is needed
+        assert_eq!(comic_for_date(date(2012, 1, 2), dir.path()).unwrap(), 998);
+    }
+}
@@ -0,0 +1,416 @@
+//! Async versions of the cached downloads, enabled with the `async` feature
+//!
+//! [`ComicCache::get_metadata_async`] and [`ComicCache::download_comic_async`] behave like
+//! [`ComicCache::metadata`] and [`ComicCache::download_comic`] and share their cache files, but
+//! make their requests with an [`AsyncFetcher`] instead of blocking on the network.
+//! [`ReqwestFetcher`] is the one to use from tokio:
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), xkcd_wallpaper::XkcdError> {
+//! use xkcd_wallpaper::asynchronous::ReqwestFetcher;
+//! use xkcd_wallpaper::cache::{CachePolicy, ComicCache};
+//! use xkcd_wallpaper::net::ClientConfig;
+//!
+//! let fetcher = ReqwestFetcher::new(&ClientConfig::default())?;
+//! let cache = ComicCache::new("/tmp/xkcd").with_fetcher(fetcher);
+//! let comic = cache.download_comic_async(Some(927), CachePolicy::Prefer).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Unlike the blocking client, failed requests are not retried and partial images are not
+//! resumed: a `Retry-After` is returned in [`XkcdError::RateLimited`] for the caller to honor.
+
+use std::future::Future;
+use std::io::Cursor;
+
+use jiff::Timestamp;
+use log::{info, warn};
+use reqwest::StatusCode;
+
+use crate::cache::{CachePolicy, ComicCache};
+use crate::download;
+use crate::net::{self, ClientConfig, Fetched, Validators};
+use crate::{ComicImage, Metadata, Resolution, XkcdError};
+
+/// Async HTTP client of a [`ComicCache`], the counterpart of [`net::ComicFetcher`]
+pub trait AsyncFetcher: Sync {
+    /// Body of the response to a GET request, an error for any status but 2xx
+    fn get(&self, url: &str) -> impl Future<Output = Result<Vec<u8>, XkcdError>> + Send;
+
+    /// Like `get`, sending `validators` so that an unchanged `url` is not downloaded again
+    ///
+    /// The default implementation always downloads it.
+    fn get_if_modified(
+        &self,
+        url: &str,
+        _validators: &Validators,
+    ) -> impl Future<Output = Result<Fetched, XkcdError>> + Send {
+        async move {
+            let body = self.get(url).await?;
+            Ok(Fetched::Modified(
+                Box::new(Cursor::new(body)),
+                Validators::default(),
+            ))
+        }
+    }
+}
+
+/// [`AsyncFetcher`] over a `reqwest` client, which needs a tokio runtime
+#[derive(Clone, Debug)]
+pub struct ReqwestFetcher {
+    client: reqwest::Client,
+}
+
+impl ReqwestFetcher {
+    /// Client with the timeouts and proxy of `config`
+    ///
+    /// Without a proxy in `config`, the one in `ALL_PROXY`, `HTTPS_PROXY` or `HTTP_PROXY` is
+    /// used. Only HTTP proxies are supported.
+    pub fn new(config: &ClientConfig) -> Result<ReqwestFetcher, XkcdError> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(net::USER_AGENT)
+            .connect_timeout(config.connect_timeout)
+            .read_timeout(config.read_timeout);
+        if let Some(proxy) = &config.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|err| XkcdError::Config(format!("invalid proxy '{}': {}", proxy, err)))?;
+            builder = builder.proxy(proxy);
+        }
+        Ok(ReqwestFetcher {
+            client: builder.build()?,
+        })
+    }
+
+    /// Send a GET request with `validators`, a 304 response is returned as well
+    async fn send(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<reqwest::Response, XkcdError> {
+        let mut request = self.client.get(url);
+        if let Some(etag) = &validators.etag {
+            request = request.header("If-None-Match", etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header("If-Modified-Since", last_modified);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() || status == StatusCode::NOT_MODIFIED {
+            return Ok(response);
+        }
+
+        let retry_after = response
+            .headers()
+            .get("retry-after")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| net::parse_retry_after(value, Timestamp::now()));
+        info!("server responded with {} for {}", status, url);
+        Err(XkcdError::from_status(status.as_u16(), retry_after))
+    }
+}
+
+impl From<reqwest::Client> for ReqwestFetcher {
+    fn from(client: reqwest::Client) -> Self {
+        ReqwestFetcher { client }
+    }
+}
+
+impl AsyncFetcher for ReqwestFetcher {
+    async fn get(&self, url: &str) -> Result<Vec<u8>, XkcdError> {
+        let response = self.send(url, &Validators::default()).await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Err(XkcdError::Http(StatusCode::NOT_MODIFIED.as_u16()));
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    async fn get_if_modified(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<Fetched, XkcdError> {
+        let response = self.send(url, validators).await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Fetched::NotModified);
+        }
+        let validators = Validators::from_headers(response.headers());
+        let body = response.bytes().await?.to_vec();
+        Ok(Fetched::Modified(Box::new(Cursor::new(body)), validators))
+    }
+}
+
+impl From<reqwest::Error> for XkcdError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            XkcdError::Timeout
+        } else {
+            XkcdError::AsyncNetwork(err)
+        }
+    }
+}
+
+impl<F: AsyncFetcher> ComicCache<F> {
+    /// Download a comic through the cache, the newest one for `None`, see
+    /// [`ComicCache::download_comic`]
+    pub async fn download_comic_async(
+        &self,
+        comic: Option<u32>,
+        policy: CachePolicy,
+    ) -> Result<ComicImage, XkcdError> {
+        let metadata = self.get_metadata_async(comic, policy).await?;
+        self.image_async(&metadata, policy).await
+    }
+
+    /// Metadata of a comic, the newest one for `None`, see [`ComicCache::metadata`]
+    pub async fn get_metadata_async(
+        &self,
+        comic: Option<u32>,
+        policy: CachePolicy,
+    ) -> Result<Metadata, XkcdError> {
+        if let Some(metadata) = self.metadata_without_request(comic, policy)? {
+            return Ok(metadata);
+        }
+
+        let url = Metadata::url(comic);
+        info!("downloading metadata from url {}", url);
+        if policy == CachePolicy::Bypass {
+            let body = self
+                .fetcher()
+                .get(&url)
+                .await
+                .map_err(|err| err.for_comic(comic))?;
+            return Metadata::read(body.as_slice());
+        }
+
+        let cached = self.revalidated(&url)?;
+        let validators = cached
+            .as_ref()
+            .map(|(validators, _)| validators.clone())
+            .unwrap_or_default();
+        let fetched = self
+            .fetcher()
+            .get_if_modified(&url, &validators)
+            .await
+            .map_err(|err| err.for_comic(comic))?;
+        self.store_metadata(url, fetched, cached)
+    }
+
+    /// Image of a comic, at 2x resolution when available, see [`ComicCache::image`]
+    pub async fn image_async(
+        &self,
+        metadata: &Metadata,
+        policy: CachePolicy,
+    ) -> Result<ComicImage, XkcdError> {
+        if let Some(comic) = self.image_without_request(metadata, policy)? {
+            return Ok(comic);
+        }
+        let (bytes, resolution) = self.download_img_async(metadata).await?;
+        self.store_image(metadata, bytes, resolution, policy)
+    }
+
+    /// Bytes of the 2x image of a comic, or of the regular one when it has none
+    async fn download_img_async(
+        &self,
+        metadata: &Metadata,
+    ) -> Result<(Vec<u8>, Resolution), XkcdError> {
+        let page_url = format!("https://xkcd.com/{}/", metadata.num);
+        info!("looking for a 2x image on {}", page_url);
+        let page = self
+            .fetcher()
+            .get(&page_url)
+            .await
+            .map(|page| String::from_utf8_lossy(&page).into_owned());
+
+        if let Some(scaled_url) = download::scaled_image_in(metadata, &page_url, page) {
+            info!("downloading img {}", scaled_url);
+            match self.fetcher().get(&scaled_url).await {
+                Ok(bytes) => return Ok((bytes, Resolution::Double)),
+                Err(err) => warn!(
+                    "cannot get image with 2x resolution ({}), falling back to regular res. {}",
+                    err, metadata.img
+                ),
+            }
+        }
+
+        info!("downloading img {}", metadata.img);
+        Ok((
+            self.fetcher().get(&metadata.img).await?,
+            Resolution::Regular,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Mutex;
+    use std::thread;
+
+    /// Serves comic 927, yielding to the executor before each response
+    struct FakeFetcher {
+        responses: HashMap<&'static str, Vec<u8>>,
+        requests: Mutex<Vec<String>>,
+    }
+
+    impl FakeFetcher {
+        fn new() -> Self {
+            let mut png = Vec::new();
+            image::DynamicImage::ImageRgba8(image::RgbaImage::new(2, 1))
+                .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+                .unwrap();
+            let responses = HashMap::from([
+                (
+                    "https://xkcd.com/927/info.0.json",
+                    br#"{"num": 927, "safe_title": "Standards", "img": "https://imgs.xkcd.com/comics/standards.png", "day": "20", "month": "7", "year": "2011"}"#.to_vec(),
+                ),
+                (
+                    "https://xkcd.com/927/",
+                    br#"<img src="//imgs.xkcd.com/comics/standards.png" srcset="//imgs.xkcd.com/comics/standards_2x.png 2x"/>"#.to_vec(),
+                ),
+                ("https://imgs.xkcd.com/comics/standards_2x.png", png),
+            ]);
+            FakeFetcher {
+                responses,
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    impl AsyncFetcher for FakeFetcher {
+        async fn get(&self, url: &str) -> Result<Vec<u8>, XkcdError> {
+            self.requests.lock().unwrap().push(url.to_string());
+            tokio::task::yield_now().await;
+            self.responses.get(url).cloned().ok_or(XkcdError::NotFound)
+        }
+    }
+
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn download_comic_async_fills_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ComicCache::new(dir.path()).with_fetcher(FakeFetcher::new());
+
+        let comic = block_on(cache.download_comic_async(Some(927), CachePolicy::Prefer)).unwrap();
+        assert_eq!(comic.metadata.num, 927);
+        assert_eq!(comic.resolution, Resolution::Double);
+        assert_eq!(
+            cache.fetcher().requests(),
+            [
+                "https://xkcd.com/927/info.0.json",
+                "https://xkcd.com/927/",
+                "https://imgs.xkcd.com/comics/standards_2x.png",
+            ]
+        );
+
+        block_on(cache.download_comic_async(Some(927), CachePolicy::Prefer)).unwrap();
+        assert_eq!(cache.fetcher().requests().len(), 3);
+
+        // The blocking API reads the same cache
+        let comic = ComicCache::new(dir.path())
+            .download_comic(Some(927), CachePolicy::Offline)
+            .unwrap();
+        assert_eq!(comic.resolution, Resolution::Double);
+    }
+
+    #[test]
+    fn get_metadata_async_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ComicCache::new(dir.path()).with_fetcher(FakeFetcher::new());
+
+        assert!(matches!(
+            block_on(cache.get_metadata_async(Some(1), CachePolicy::Prefer)),
+            Err(XkcdError::ComicNotFound(1))
+        ));
+        assert!(matches!(
+            block_on(cache.get_metadata_async(Some(927), CachePolicy::Offline)),
+            Err(XkcdError::NotCached(_))
+        ));
+        assert_eq!(cache.fetcher().requests().len(), 1);
+    }
+
+    fn local_fetcher() -> ReqwestFetcher {
+        ReqwestFetcher::from(reqwest::Client::builder().no_proxy().build().unwrap())
+    }
+
+    /// Answer one request on a local port with `response`, returning the URL to request
+    fn serve_once(response: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                request.push_str(&line);
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            request
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn reqwest_fetcher_revalidates() {
+        let fetcher = local_fetcher();
+        let (url, server) = serve_once(
+            "HTTP/1.1 200 OK\r\nETag: \"abc\"\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}",
+        );
+        let Fetched::Modified(mut body, validators) =
+            block_on(fetcher.get_if_modified(&url, &Validators::default())).unwrap()
+        else {
+            panic!("expected a body");
+        };
+        let mut bytes = String::new();
+        body.read_to_string(&mut bytes).unwrap();
+        assert_eq!(bytes, "{}");
+        assert_eq!(validators.etag.as_deref(), Some("\"abc\""));
+        server.join().unwrap();
+
+        let (url, server) = serve_once("HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n");
+        assert!(matches!(
+            block_on(fetcher.get_if_modified(&url, &validators)),
+            Ok(Fetched::NotModified)
+        ));
+        assert!(server
+            .join()
+            .unwrap()
+            .to_lowercase()
+            .contains("if-none-match: \"abc\""));
+    }
+
+    #[test]
+    fn reqwest_fetcher_rate_limited() {
+        let fetcher = local_fetcher();
+        let (url, server) = serve_once(
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 7\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+        assert!(matches!(
+            block_on(fetcher.get(&url)),
+            Err(XkcdError::RateLimited {
+                retry_after: Some(delay)
+            }) if delay.as_secs() == 7
+        ));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+use log::info;
+
+use crate::{ForegroundColor, ScreenDimensions};
+
+#[derive(Clone, Debug, PartialEq)]
+/// What the comic is placed on
+pub enum Background {
+    Color(Rgba<u8>),
+    /// A picture, scaled and cropped to cover the screen
+    Image(DynamicImage),
+    /// The comic itself over `color`, blurred by `sigma` pixels of the screen and covering it
+    Blur {
+        color: Rgba<u8>,
+        sigma: f32,
+    },
+}
+
+impl From<Rgba<u8>> for Background {
+    fn from(color: Rgba<u8>) -> Self {
+        Background::Color(color)
+    }
+}
+
+impl Background {
+    /// Color replacing the comic's own background, transparent where a picture shows through
+    pub fn fill(&self) -> Rgba<u8> {
+        match self {
+            Background::Color(color) => *color,
+            Background::Image(_) | Background::Blur { .. } => Rgba([0, 0, 0, 0]),
+        }
+    }
+
+    /// Single color standing for the background, the average color of a picture
+    pub fn color(&self) -> Rgba<u8> {
+        match self {
+            Background::Color(color) | Background::Blur { color, .. } => *color,
+            Background::Image(img) => {
+                // A thumbnail averages well enough and keeps this cheap for large photos
+                let thumbnail = img.thumbnail(64, 64).into_rgb8();
+                let count = (thumbnail.width() * thumbnail.height()).max(1) as u64;
+                let mut sums = [0u64; 3];
+                for pixel in thumbnail.pixels() {
+                    for (sum, channel) in sums.iter_mut().zip(pixel.0) {
+                        *sum += channel as u64;
+                    }
+                }
+                let [r, g, b] = sums.map(|sum| (sum / count) as u8);
+                Rgba([r, g, b, 255])
+            }
+        }
+    }
+
+    /// Light drawings on a dark background and dark ones on a light background
+    pub fn contrasting_foreground(&self) -> ForegroundColor {
+        match ForegroundColor::Custom(self.color()).is_light() {
+            true => ForegroundColor::Dark,
+            false => ForegroundColor::Light,
+        }
+    }
+
+    /// The background behind `comic`, turning [`Background::Blur`] into the blurred picture
+    ///
+    /// `comic` is the recolored comic before scaling, with [`Background::fill`] as background.
+    pub fn behind(self, comic: &RgbaImage, screen_dimensions: &ScreenDimensions) -> Background {
+        let Background::Blur { color, sigma } = self else {
+            return self;
+        };
+        let (width, height) = comic.dimensions();
+        let mut flattened = RgbaImage::from_pixel(width, height, color);
+        imageops::overlay(&mut flattened, comic, 0, 0);
+        // Blurring before covering the screen is much cheaper, the comic is enlarged by `cover`
+        let cover = (screen_dimensions.width as f32 / width.max(1) as f32)
+            .max(screen_dimensions.height as f32 / height.max(1) as f32);
+        info!("blurring comic by {} for the background", sigma);
+        Background::Image(DynamicImage::ImageRgba8(imageops::fast_blur(
+            &flattened,
+            sigma / cover,
+        )))
+    }
+
+    /// The background at the size of the screen
+    pub fn canvas(&self, screen_dimensions: &ScreenDimensions) -> RgbaImage {
+        let (width, height) = (screen_dimensions.width, screen_dimensions.height);
+        match self {
+            Background::Color(color) | Background::Blur { color, .. } => {
+                RgbaImage::from_pixel(width, height, *color)
+            }
+            Background::Image(img) => {
+                info!(
+                    "covering {}x{} with a {}x{} background image",
+                    width,
+                    height,
+                    img.width(),
+                    img.height()
+                );
+                img.resize_to_fill(width, height, FilterType::Lanczos3)
+                    .into_rgba8()
+            }
+        }
+    }
+
+    /// Rows `top..top + rows` of the screen-sized background
+    ///
+    /// `canvas` is [`Background::canvas`] computed once for a picture, so that bands are cut
+    /// from it rather than scaling the picture again for every band.
+    pub(crate) fn band(
+        &self,
+        canvas: Option<&RgbaImage>,
+        width: u32,
+        top: u32,
+        rows: u32,
+    ) -> RgbaImage {
+        match canvas {
+            Some(canvas) => imageops::crop_imm(canvas, 0, top, width, rows).to_image(),
+            None => RgbaImage::from_pixel(width, rows, self.color()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Rgba([0x1D, 0x1F, 0x21, 255]), ForegroundColor::Light)]
+    #[case(Rgba([0xF2, 0xF0, 0xE6, 255]), ForegroundColor::Dark)]
+    fn contrasting_foreground(#[case] color: Rgba<u8>, #[case] expected: ForegroundColor) {
+        let picture = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 30, color));
+        let background = Background::Image(picture);
+        assert_eq!(background.color(), color);
+        assert_eq!(background.contrasting_foreground(), expected);
+    }
+
+    #[test]
+    fn image_covers_screen() {
+        // Left half black, right half white
+        let picture = RgbaImage::from_fn(200, 100, |x, _| match x < 100 {
+            true => Rgba([0, 0, 0, 255]),
+            false => Rgba([255, 255, 255, 255]),
+        });
+        let background = Background::Image(DynamicImage::ImageRgba8(picture));
+        let screen = ScreenDimensions {
+            width: 100,
+            height: 100,
+        };
+        let canvas = background.canvas(&screen);
+        assert_eq!(canvas.dimensions(), (100, 100));
+        // The sides are cropped, keeping the middle of the picture
+        assert_eq!(canvas.get_pixel(10, 50), &Rgba([0, 0, 0, 255]));
+        assert_eq!(canvas.get_pixel(90, 50), &Rgba([255, 255, 255, 255]));
+        let band = background.band(Some(&canvas), 100, 40, 20);
+        assert_eq!(band.dimensions(), (100, 20));
+    }
+
+    #[test]
+    fn blurred_comic() {
+        let color = Rgba([0x1F, 0x24, 0x1F, 255]);
+        // A transparent comic with a single line of ink across the middle
+        let comic = RgbaImage::from_fn(40, 20, |_, y| match y == 10 {
+            true => Rgba([255, 255, 255, 255]),
+            false => Rgba([0, 0, 0, 0]),
+        });
+        let screen = ScreenDimensions {
+            width: 160,
+            height: 90,
+        };
+        let background = Background::Blur { color, sigma: 20.0 }.behind(&comic, &screen);
+        let canvas = background.canvas(&screen);
+        assert_eq!(canvas.dimensions(), (160, 90));
+        // The line is spread into a glow fading towards the edges
+        let middle = canvas.get_pixel(80, 45)[0];
+        let edge = canvas.get_pixel(80, 0)[0];
+        assert!(color[0] < edge && edge < middle && middle < 255);
+    }
+}
@@ -0,0 +1,153 @@
+use image::imageops;
+use image::{Rgba, RgbaImage};
+use log::info;
+
+use crate::decoration::rounded_rectangle;
+use crate::layout::Corner;
+use crate::locale::Language;
+use crate::text::TextStyle;
+use crate::{format_comic, Metadata, ScreenDimensions};
+
+#[derive(Clone, Debug, PartialEq)]
+/// Small label like `#3084 — 2025-06-20` drawn in a corner of the wallpaper
+pub struct Badge {
+    pub corner: Corner,
+    /// Text of the badge, with the placeholders of output file names
+    pub format: String,
+    /// Text in the background color of the wallpaper unless the style has its own color
+    pub style: TextStyle,
+    /// Color of the rounded rectangle behind the text, that of the drawings when `None`
+    pub background: Option<Rgba<u8>>,
+    /// Opacity of the whole badge, from 0 to 1
+    pub opacity: f32,
+}
+
+impl Default for Badge {
+    fn default() -> Self {
+        Badge {
+            corner: Corner::BottomLeft,
+            format: "#%n — %F".to_string(),
+            style: TextStyle {
+                size: 16,
+                ..Default::default()
+            },
+            background: None,
+            opacity: 0.8,
+        }
+    }
+}
+
+impl Badge {
+    /// The badge of `metadata`, for a wallpaper with drawings in `ink` on `paper`
+    pub fn render(&self, metadata: &Metadata, ink: Rgba<u8>, paper: Rgba<u8>) -> RgbaImage {
+        let text = format_comic(&self.format, metadata, Language::from_env());
+        let mut paper = paper;
+        paper[3] = 255;
+        let label = self.style.render(&text, paper);
+        let (padding_x, padding_y) = (self.style.line_height() / 2, self.style.line_height() / 4);
+        let (width, height) = (
+            label.width() + 2 * padding_x,
+            label.height() + 2 * padding_y,
+        );
+        let background = self.background.unwrap_or(ink);
+        let mut badge = rounded_rectangle(width, height, height as f32 / 2.0, background);
+        imageops::overlay(&mut badge, &label, padding_x as i64, padding_y as i64);
+        let opacity = self.opacity.clamp(0.0, 1.0);
+        for pixel in badge.pixels_mut() {
+            pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+        }
+        badge
+    }
+
+    /// Draw the badge of `metadata` in its corner of `img`
+    pub fn overlay(
+        &self,
+        img: &mut RgbaImage,
+        metadata: &Metadata,
+        ink: Rgba<u8>,
+        paper: Rgba<u8>,
+    ) {
+        let badge = self.render(metadata, ink, paper);
+        let (width, height) = img.dimensions();
+        let (x, y) = self.corner.position(
+            &ScreenDimensions { width, height },
+            badge.width(),
+            badge.height(),
+        );
+        info!(
+            "drawing {}x{} badge at {},{}",
+            badge.width(),
+            badge.height(),
+            x,
+            y
+        );
+        imageops::overlay(img, &badge, x, y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            num: 1,
+            year: "2006".to_string(),
+            month: "1".to_string(),
+            day: "1".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn number_and_date() {
+        let (ink, paper) = (Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255]));
+        let badge = Badge {
+            style: TextStyle {
+                size: 8,
+                ..Default::default()
+            },
+            opacity: 1.0,
+            ..Default::default()
+        };
+        let img = badge.render(&metadata(), ink, paper);
+        // "#1 - 2006-01-01" is 15 glyphs, 89 pixels, with 4 pixels of padding on each side
+        assert_eq!(img.dimensions(), (97, 12));
+        assert_eq!(img.get_pixel(0, 0)[3], 0);
+        // The first column of `#`, a gap between its two bars
+        assert_eq!(img.get_pixel(4, 4), &paper);
+        assert_eq!(img.get_pixel(4, 5), &ink);
+    }
+
+    #[test]
+    fn colors_and_opacity() {
+        let badge = Badge {
+            style: TextStyle {
+                size: 8,
+                color: Some(Rgba([255, 0, 0, 255])),
+                ..Default::default()
+            },
+            background: Some(Rgba([0, 0, 255, 255])),
+            opacity: 0.5,
+            corner: Corner::TopRight,
+            ..Default::default()
+        };
+        let mut img = RgbaImage::from_pixel(200, 100, Rgba([0, 0, 0, 0]));
+        badge.overlay(
+            &mut img,
+            &metadata(),
+            Rgba([0, 0, 0, 255]),
+            Rgba([0, 0, 0, 255]),
+        );
+        // 2 pixels from the top right corner
+        assert_eq!(
+            img.get_pixel(200 - 2 - 97 + 4, 2 + 5),
+            &Rgba([0, 0, 255, 128])
+        );
+        assert_eq!(
+            img.get_pixel(200 - 2 - 97 + 4, 2 + 4),
+            &Rgba([255, 0, 0, 128])
+        );
+        assert_eq!(img.get_pixel(200 - 1, 2 + 6)[3], 0);
+    }
+}
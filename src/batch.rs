@@ -0,0 +1,291 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use log::{info, warn};
+
+use crate::background::Background;
+use crate::cache::{CachePolicy, ComicCache};
+use crate::decoration::Decoration;
+use crate::layout::Layout;
+use crate::net::{ComicFetcher, UreqFetcher};
+use crate::recolor::Recolor;
+use crate::{
+    get_wallpaper_from_comic, ComicImage, ForegroundColor, Image, Metadata, ScreenDimensions,
+    XkcdError,
+};
+
+/// Largest number of comics a list may expand to, guards against typos such as `1..30000`
+const MAX_COMICS: usize = 10_000;
+
+#[derive(Clone, Debug, PartialEq)]
+/// Comic numbers given as a comma separated list of numbers and inclusive ranges, e.g.
+/// `1,42,100..150`
+pub struct ComicList(pub Vec<u32>);
+
+impl FromStr for ComicList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |value: &str| {
+            value
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|num| *num > 0)
+                .ok_or(format!("Invalid comic number '{}'", value.trim()))
+        };
+
+        let mut comics = Vec::new();
+        for item in s.split(',').filter(|item| !item.trim().is_empty()) {
+            match item.split_once("..") {
+                Some((first, last)) => {
+                    let (first, last) = (number(first)?, number(last.trim_start_matches('='))?);
+                    if first > last {
+                        return Err(format!("Empty comic range '{}'", item.trim()));
+                    }
+                    comics.extend(first..=last);
+                }
+                None => comics.push(number(item)?),
+            }
+            if comics.len() > MAX_COMICS {
+                return Err(format!("More than {MAX_COMICS} comics in '{s}'"));
+            }
+        }
+        match comics.is_empty() {
+            true => Err("No comics given".to_string()),
+            false => Ok(ComicList(comics)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// How every wallpaper of a [`Batch`] is rendered
+pub struct WallpaperStyle {
+    pub fg: ForegroundColor,
+    pub bg: Background,
+    pub screen_dimensions: ScreenDimensions,
+    pub layout: Layout,
+    pub recolor: Recolor,
+    pub decoration: Decoration,
+}
+
+/// How a [`Batch`] turns each downloaded comic into its result
+///
+/// [`WallpaperStyle`] renders a wallpaper, closures taking the comic can do anything else with
+/// it, such as writing the wallpaper to disk.
+pub trait Render {
+    type Output;
+
+    fn render(&mut self, comic: ComicImage) -> Result<Self::Output, XkcdError>;
+}
+
+impl Render for WallpaperStyle {
+    type Output = Image;
+
+    fn render(&mut self, comic: ComicImage) -> Result<Image, XkcdError> {
+        Ok(get_wallpaper_from_comic(
+            comic,
+            self.fg.clone(),
+            self.bg.clone(),
+            self.screen_dimensions.clone(),
+            self.layout,
+            self.recolor,
+            self.decoration.clone(),
+        ))
+    }
+}
+
+impl<T, G: FnMut(ComicImage) -> Result<T, XkcdError>> Render for G {
+    type Output = T;
+
+    fn render(&mut self, comic: ComicImage) -> Result<T, XkcdError> {
+        self(comic)
+    }
+}
+
+/// Downloads and renders comics one at a time, yielding each comic number with its wallpaper
+/// or the error that comic ran into
+///
+/// A failing comic does not end the iteration, the next one is tried. Numbers without a comic,
+/// such as 404, are skipped.
+pub struct Batch<F = UreqFetcher, R = WallpaperStyle> {
+    comics: std::vec::IntoIter<u32>,
+    render: R,
+    fetcher: F,
+    cache: Option<(PathBuf, CachePolicy)>,
+    large: bool,
+}
+
+impl<R: Render> Batch<UreqFetcher, R> {
+    /// Batch of `comics`, each rendered by `render`, usually a [`WallpaperStyle`]
+    pub fn new(comics: impl IntoIterator<Item = u32>, render: R) -> Self {
+        Batch {
+            comics: comics.into_iter().collect::<Vec<_>>().into_iter(),
+            render,
+            fetcher: UreqFetcher,
+            cache: None,
+            large: false,
+        }
+    }
+}
+
+impl<F: ComicFetcher, R: Render> Batch<F, R> {
+    /// Download with `fetcher` instead of the default client
+    pub fn with_fetcher<G: ComicFetcher>(self, fetcher: G) -> Batch<G, R> {
+        Batch {
+            comics: self.comics,
+            render: self.render,
+            fetcher,
+            cache: self.cache,
+            large: self.large,
+        }
+    }
+
+    /// Keep downloaded comics in the [`ComicCache`] in `dir`
+    pub fn with_cache(self, dir: impl Into<PathBuf>, policy: CachePolicy) -> Self {
+        Batch {
+            cache: Some((dir.into(), policy)),
+            ..self
+        }
+    }
+
+    /// Use the large version of the comics that link one, like [`Metadata::to_large_image`]
+    ///
+    /// Large images are not cached, and not looked for with [`CachePolicy::Offline`].
+    pub fn with_large(self, large: bool) -> Self {
+        Batch { large, ..self }
+    }
+
+    fn render(&mut self, num: u32) -> Result<R::Output, XkcdError> {
+        let cache = self.cache.as_ref().map(|(dir, policy)| {
+            (
+                ComicCache::new(dir.clone()).with_fetcher(&self.fetcher),
+                *policy,
+            )
+        });
+        let metadata = match &cache {
+            Some((cache, policy)) => cache.metadata(Some(num), *policy)?,
+            None => Metadata::from_comic_id_with(&self.fetcher, Some(num))?,
+        };
+        let offline = matches!(cache, Some((_, CachePolicy::Offline)));
+        let large = match self.large && !offline {
+            true => metadata
+                .to_large_image_with(&self.fetcher)
+                .unwrap_or_else(|err| {
+                    warn!("cannot get large image, using the regular one: {}", err);
+                    None
+                }),
+            false => None,
+        };
+        let comic = match (large, &cache) {
+            (Some(comic), _) => comic,
+            (None, Some((cache, policy))) => cache.image(&metadata, *policy)?,
+            (None, None) => metadata.to_image_with(&self.fetcher)?,
+        };
+        self.render.render(comic)
+    }
+}
+
+impl<F: ComicFetcher, R: Render> Iterator for Batch<F, R> {
+    type Item = (u32, Result<R::Output, XkcdError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let num = self.comics.next()?;
+            info!("rendering comic {} of the batch", num);
+            match self.render(num) {
+                Err(XkcdError::ComicNotFound(_)) => {
+                    info!("skipping comic {}, it does not exist", num)
+                }
+                result => return Some((num, result)),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.comics.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::io::{Cursor, Read};
+
+    #[rstest]
+    #[case("1,42,303", vec![1, 42, 303])]
+    #[case("100..103", vec![100, 101, 102, 103])]
+    #[case("5..=6, 1", vec![5, 6, 1])]
+    fn comic_list_parse_ok(#[case] input: &str, #[case] expected: Vec<u32>) {
+        assert_eq!(input.parse(), Ok(ComicList(expected)));
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("0")]
+    #[case("10..5")]
+    #[case("1..100000")]
+    #[case("a,b")]
+    fn comic_list_parse_error(#[case] input: &str) {
+        assert!(input.parse::<ComicList>().is_err());
+    }
+
+    /// Only knows comic 1, a 1x1 image, and comic 3 whose image is missing
+    struct OneComic;
+
+    impl ComicFetcher for OneComic {
+        fn get(&self, url: &str) -> Result<Box<dyn Read + Send>, XkcdError> {
+            match url {
+                "https://xkcd.com/1/info.0.json" => Ok(Box::new(Cursor::new(
+                    r#"{"num": 1, "safe_title": "Barrel", "img": "https://imgs.xkcd.com/comics/barrel.png", "day": "1", "month": "1", "year": "2006"}"#,
+                ))),
+                "https://xkcd.com/3/info.0.json" => Ok(Box::new(Cursor::new(
+                    r#"{"num": 3, "safe_title": "Island", "img": "https://imgs.xkcd.com/comics/island.jpg", "day": "1", "month": "1", "year": "2006"}"#,
+                ))),
+                "https://imgs.xkcd.com/comics/barrel.png" => {
+                    let mut png = Vec::new();
+                    image::DynamicImage::ImageRgba8(image::RgbaImage::new(1, 1))
+                        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+                        .unwrap();
+                    Ok(Box::new(Cursor::new(png)))
+                }
+                _ => Err(XkcdError::NotFound),
+            }
+        }
+    }
+
+    #[test]
+    fn errors_do_not_stop_the_batch() {
+        let style = WallpaperStyle {
+            fg: ForegroundColor::Light,
+            bg: Background::Blur {
+                color: image::Rgba([0, 0, 0, 255]),
+                sigma: 2.0,
+            },
+            screen_dimensions: ScreenDimensions {
+                width: 8,
+                height: 8,
+            },
+            layout: Layout::default(),
+            recolor: Recolor::default(),
+            decoration: Decoration::default(),
+        };
+        let results = Batch::new([2, 3, 1], style)
+            .with_fetcher(OneComic)
+            .map(|(num, result)| (num, result.map(|wallpaper| wallpaper.img.width())))
+            .collect::<Vec<_>>();
+        // Comic 2 does not exist and is skipped
+        assert_eq!(results.len(), 2);
+        assert!(matches!(results[0], (3, Err(XkcdError::NotFound))));
+        assert!(matches!(results[1], (1, Ok(8))));
+    }
+
+    #[test]
+    fn closures_render_comics() {
+        let titles = Batch::new([1, 2], |comic: ComicImage| Ok(comic.metadata.safe_title))
+            .with_fetcher(OneComic)
+            .collect::<Vec<_>>();
+        assert!(matches!(&titles[..], [(1, Ok(title))] if title == "Barrel"));
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use image::DynamicImage;
+use log::{info, warn};
+
+use crate::{ScreenDimensions, XkcdError};
+
+const THEME_NAME: &str = "xkcd-wallpaper";
+
+/// Video modes commonly available to GRUB through `GRUB_GFXMODE`
+pub const GRUB_RESOLUTIONS: &[(u32, u32)] = &[
+    (640, 480),
+    (800, 600),
+    (1024, 768),
+    (1280, 1024),
+    (1366, 768),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+    (3840, 2160),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+/// Boot screen the wallpaper is exported for
+pub enum BootTarget {
+    Grub,
+    Plymouth,
+}
+
+impl BootTarget {
+    /// Convert an image into a format the boot screen can display
+    ///
+    /// GRUB only reliably reads 8-bit RGB PNGs, so the alpha channel is dropped.
+    pub fn prepare_image(&self, img: DynamicImage, dimensions: &ScreenDimensions) -> DynamicImage {
+        match self {
+            BootTarget::Grub => {
+                if !GRUB_RESOLUTIONS.contains(&(dimensions.width, dimensions.height)) {
+                    warn!(
+                        "{}x{} is not a common GRUB video mode, GRUB may not display it",
+                        dimensions.width, dimensions.height
+                    );
+                }
+                DynamicImage::ImageRgb8(img.into_rgb8())
+            }
+            BootTarget::Plymouth => DynamicImage::ImageRgba8(img.into_rgba8()),
+        }
+    }
+
+    /// Copy the image and configuration into the system below `root`, usually `/`
+    ///
+    /// Returns the written files, the command in `next_step` must be run afterwards.
+    pub fn install(
+        &self,
+        image: &Path,
+        dimensions: &ScreenDimensions,
+        root: &Path,
+    ) -> Result<Vec<PathBuf>, XkcdError> {
+        let files = match self {
+            BootTarget::Grub => {
+                let background = root.join("boot/grub").join(format!("{THEME_NAME}.png"));
+                let snippet = format!(
+                    "# Generated by xkcd-wallpaper\nGRUB_BACKGROUND=\"/boot/grub/{THEME_NAME}.png\"\nGRUB_GFXMODE=\"{}x{}\"\n",
+                    dimensions.width, dimensions.height
+                );
+                vec![
+                    (background, None),
+                    (
+                        root.join("etc/default/grub.d")
+                            .join(format!("{THEME_NAME}.cfg")),
+                        Some(snippet),
+                    ),
+                ]
+            }
+            BootTarget::Plymouth => {
+                let theme_dir = root.join("usr/share/plymouth/themes").join(THEME_NAME);
+                let theme = format!(
+                    "[Plymouth Theme]\nName={THEME_NAME}\nDescription=xkcd comic boot background\nModuleName=script\n\n[script]\nImageDir=/usr/share/plymouth/themes/{THEME_NAME}\nScriptFile=/usr/share/plymouth/themes/{THEME_NAME}/{THEME_NAME}.script\n"
+                );
+                let script = "image = Image(\"background.png\");\nscaled = image.Scale(Window.GetWidth(), Window.GetHeight());\nsprite = Sprite(scaled);\nsprite.SetPosition(0, 0, -100);\n".to_string();
+                vec![
+                    (theme_dir.join("background.png"), None),
+                    (
+                        theme_dir.join(format!("{THEME_NAME}.plymouth")),
+                        Some(theme),
+                    ),
+                    (theme_dir.join(format!("{THEME_NAME}.script")), Some(script)),
+                ]
+            }
+        };
+
+        let mut written = Vec::new();
+        for (path, contents) in files {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            info!("installing {}", path.display());
+            match contents {
+                Some(contents) => fs::write(&path, contents)?,
+                None => {
+                    fs::copy(image, &path)?;
+                }
+            }
+            written.push(path);
+        }
+        Ok(written)
+    }
+
+    /// Command that activates the installed files
+    pub fn next_step(&self) -> String {
+        match self {
+            BootTarget::Grub => "update-grub (or grub-mkconfig -o /boot/grub/grub.cfg)".to_string(),
+            BootTarget::Plymouth => format!("plymouth-set-default-theme -R {THEME_NAME}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn grub_image_is_rgb() {
+        let img = DynamicImage::new_rgba8(4, 4);
+        let dimensions = ScreenDimensions {
+            width: 4,
+            height: 4,
+        };
+        assert!(matches!(
+            BootTarget::Grub.prepare_image(img, &dimensions),
+            DynamicImage::ImageRgb8(_)
+        ));
+    }
+
+    #[rstest]
+    #[case(BootTarget::Grub, 2)]
+    #[case(BootTarget::Plymouth, 3)]
+    fn install_writes_files(#[case] target: BootTarget, #[case] expected: usize) {
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("wallpaper.png");
+        fs::write(&image, b"png").unwrap();
+        let dimensions = ScreenDimensions {
+            width: 1920,
+            height: 1080,
+        };
+
+        let written = target
+            .install(&image, &dimensions, &dir.path().join("root"))
+            .unwrap();
+        assert_eq!(written.len(), expected);
+        assert!(written.iter().all(|path| path.exists()));
+    }
+}
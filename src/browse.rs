@@ -0,0 +1,400 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Position, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::archive::ArchiveIndex;
+use crate::cache::ComicCache;
+use crate::history::HistoryEntry;
+use crate::net::ComicFetcher;
+use crate::XkcdError;
+
+/// Width of the comic list, the preview takes the rest of the terminal
+const LIST_WIDTH: u16 = 40;
+/// Narrower terminals only show the list
+const MIN_PREVIEW_WIDTH: u16 = 20;
+
+#[derive(Clone, Debug, PartialEq)]
+/// A comic that can be picked in `browse`
+pub struct BrowseEntry {
+    pub num: u64,
+    pub title: String,
+    /// Previously generated wallpaper or cached comic image shown as preview
+    pub preview: Option<PathBuf>,
+}
+
+impl BrowseEntry {
+    /// One entry per comic known locally, newest comic first
+    ///
+    /// Comics come from the cache, the archive index and the history. Titles are taken from the
+    /// cached metadata when there is some, and the last wallpaper generated for a comic is
+    /// previewed, or else its cached image.
+    pub fn from_index<F: ComicFetcher>(
+        cache: &ComicCache<F>,
+        archive: &ArchiveIndex,
+        history: &[HistoryEntry],
+    ) -> Result<Vec<BrowseEntry>, XkcdError> {
+        let mut entries = BTreeMap::new();
+        for num in archive.comics.keys() {
+            BrowseEntry::of(&mut entries, *num);
+        }
+        for (num, title) in &archive.titles {
+            BrowseEntry::of(&mut entries, *num).title = title.clone();
+        }
+        for (num, metadata) in cache.index()? {
+            let entry = BrowseEntry::of(&mut entries, num);
+            entry.title = metadata.safe_title.clone();
+            entry.preview = cache.cached_image(&metadata);
+        }
+        for generated in history {
+            let entry = BrowseEntry::of(&mut entries, generated.num);
+            if entry.title.is_empty() {
+                entry.title = generated.title.clone();
+            }
+            if generated.output.is_file() {
+                entry.preview = Some(generated.output.clone());
+            }
+        }
+        Ok(entries.into_values().rev().collect())
+    }
+
+    /// Entry of comic `num`, added without a title if there is none yet
+    fn of(entries: &mut BTreeMap<u64, BrowseEntry>, num: u64) -> &mut BrowseEntry {
+        entries.entry(num).or_insert_with(|| BrowseEntry {
+            num,
+            title: String::new(),
+            preview: None,
+        })
+    }
+
+    fn label(&self) -> String {
+        format!("#{} {}", self.num, self.title)
+    }
+}
+
+/// Score `text` against a fuzzy `query`, `None` if the query characters do not appear in order
+///
+/// Consecutive matches and matches at the start of a word score higher, gaps lower.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for query_char in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = position + text[position..].iter().position(|c| *c == query_char)?;
+        score += 10;
+        match previous {
+            Some(previous) if previous + 1 == found => score += 15,
+            Some(previous) => score -= (found - previous - 1) as i64,
+            None => score -= found as i64,
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 5;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+/// Entries matching `query`, best match first
+pub fn filter<'a>(entries: &'a [BrowseEntry], query: &str) -> Vec<&'a BrowseEntry> {
+    let mut matches: Vec<_> = entries
+        .iter()
+        .filter_map(|entry| fuzzy_score(query, &entry.label()).map(|score| (score, entry)))
+        .collect();
+    matches.sort_by_key(|(score, _)| -score);
+    matches.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// Render an image with half-block characters, two pixels per terminal cell
+pub fn preview_lines(
+    path: &Path,
+    columns: u16,
+    rows: u16,
+) -> Result<Vec<Line<'static>>, XkcdError> {
+    let img = image::open(path)?
+        .thumbnail(columns as u32, rows as u32 * 2)
+        .into_rgb8();
+
+    let mut lines = Vec::new();
+    for y in (0..img.height()).step_by(2) {
+        let spans: Vec<Span> = (0..img.width())
+            .map(|x| {
+                let top = img.get_pixel(x, y);
+                let bottom = img.get_pixel(x, (y + 1).min(img.height() - 1));
+                let style = Style::new()
+                    .fg(Color::Rgb(top[0], top[1], top[2]))
+                    .bg(Color::Rgb(bottom[0], bottom[1], bottom[2]));
+                Span::styled("\u{2580}", style)
+            })
+            .collect();
+        lines.push(Line::from(spans));
+    }
+    Ok(lines)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// What a key press does in the picker
+enum Action {
+    Type(char),
+    Erase,
+    Up,
+    Down,
+    Pick,
+    Cancel,
+    Nothing,
+}
+
+impl From<KeyEvent> for Action {
+    fn from(key: KeyEvent) -> Self {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if control => Action::Cancel,
+            KeyCode::Char('p') if control => Action::Up,
+            KeyCode::Char('n') if control => Action::Down,
+            KeyCode::Char(c) if !control => Action::Type(c),
+            KeyCode::Backspace => Action::Erase,
+            KeyCode::Up => Action::Up,
+            KeyCode::Down => Action::Down,
+            KeyCode::Enter => Action::Pick,
+            KeyCode::Esc => Action::Cancel,
+            _ => Action::Nothing,
+        }
+    }
+}
+
+/// Let the user pick an entry with fuzzy search, `None` if cancelled
+pub fn pick(entries: &[BrowseEntry]) -> Result<Option<&BrowseEntry>, XkcdError> {
+    let mut terminal = ratatui::try_init()?;
+    let picked = run(&mut terminal, entries);
+    ratatui::try_restore()?;
+    picked
+}
+
+fn run<'a>(
+    terminal: &mut DefaultTerminal,
+    entries: &'a [BrowseEntry],
+) -> Result<Option<&'a BrowseEntry>, XkcdError> {
+    let mut query = String::new();
+    let mut selected = 0;
+    let mut previews = HashMap::new();
+    loop {
+        let matches = filter(entries, &query);
+        selected = selected.min(matches.len().saturating_sub(1));
+        terminal.draw(|frame| draw(frame, &query, &matches, selected, &mut previews))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match Action::from(key) {
+            Action::Type(c) => query.push(c),
+            Action::Erase => {
+                query.pop();
+            }
+            Action::Up => selected = selected.saturating_sub(1),
+            Action::Down => selected += 1,
+            Action::Pick if !matches.is_empty() => return Ok(Some(matches[selected])),
+            Action::Cancel => return Ok(None),
+            Action::Pick | Action::Nothing => {}
+        }
+    }
+}
+
+/// Query line on top, list of matches on the left with the preview of the selected one on its
+/// right, and a status line at the bottom
+fn draw(
+    frame: &mut Frame,
+    query: &str,
+    matches: &[&BrowseEntry],
+    selected: usize,
+    previews: &mut HashMap<(u64, u16, u16), Vec<Line<'static>>>,
+) {
+    let [query_area, body, status_area] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [list_area, preview_area] =
+        Layout::horizontal([Constraint::Length(LIST_WIDTH), Constraint::Min(0)]).areas(body);
+
+    frame.render_widget(Paragraph::new(format!("> {query}")), query_area);
+    frame.set_cursor_position(Position::new(
+        query_area.x + query.chars().count() as u16 + 2,
+        query_area.y,
+    ));
+
+    let items: Vec<ListItem> = matches
+        .iter()
+        .map(|entry| ListItem::new(entry.label()))
+        .collect();
+    let list = List::new(items).highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(selected));
+    frame.render_stateful_widget(list, list_area, &mut state);
+
+    let status = format!(
+        "{} of {}  up/down select  enter generate  esc quit",
+        matches.len().min(selected + 1),
+        matches.len()
+    );
+    frame.render_widget(
+        Paragraph::new(status).style(Style::new().add_modifier(Modifier::DIM)),
+        status_area,
+    );
+
+    if let Some(entry) = matches.get(selected) {
+        draw_preview(frame, entry, preview_area, previews);
+    }
+}
+
+/// Preview of `entry` in `area`, rendered once per size
+fn draw_preview(
+    frame: &mut Frame,
+    entry: &BrowseEntry,
+    area: Rect,
+    previews: &mut HashMap<(u64, u16, u16), Vec<Line<'static>>>,
+) {
+    // One column apart from the list
+    let area = Rect {
+        x: area.x + 1,
+        width: area.width.saturating_sub(1),
+        ..area
+    };
+    if area.width < MIN_PREVIEW_WIDTH || area.height == 0 {
+        return;
+    }
+    let lines = previews
+        .entry((entry.num, area.width, area.height))
+        .or_insert_with(|| match &entry.preview {
+            Some(path) => preview_lines(path, area.width, area.height)
+                .unwrap_or_else(|err| vec![Line::from(format!("cannot preview: {err}"))]),
+            None => vec![Line::from("no preview available")],
+        });
+    frame.render_widget(Paragraph::new(lines.clone()), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+    use image::{Rgb, RgbImage};
+    use jiff::civil::date;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use rstest::rstest;
+
+    fn entry(num: u64, title: &str) -> BrowseEntry {
+        BrowseEntry {
+            num,
+            title: title.to_string(),
+            preview: None,
+        }
+    }
+
+    #[rstest]
+    #[case("", "Standards", Some(0))]
+    #[case("std", "Standards", Some(48))]
+    #[case("stan", "Standards", Some(90))]
+    #[case("xyz", "Standards", None)]
+    fn fuzzy_score_cases(#[case] query: &str, #[case] text: &str, #[case] expected: Option<i64>) {
+        assert_eq!(fuzzy_score(query, text), expected);
+    }
+
+    #[test]
+    fn filter_orders_by_score() {
+        let entries = [
+            entry(1, "Barrel - Part 1"),
+            entry(927, "Standards"),
+            entry(1053, "Ten Thousand"),
+        ];
+        let matches: Vec<_> = filter(&entries, "and")
+            .iter()
+            .map(|entry| entry.num)
+            .collect();
+        assert_eq!(matches, vec![927, 1053]);
+        assert_eq!(filter(&entries, "927")[0].num, 927);
+    }
+
+    #[test]
+    fn entries_from_local_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let metadata = Metadata {
+            num: 927,
+            safe_title: "Standards".to_string(),
+            img: "https://imgs.xkcd.com/comics/standards.png".to_string(),
+            ..Default::default()
+        };
+        std::fs::write(
+            dir.path().join("927.json"),
+            serde_json::to_string(&metadata).unwrap(),
+        )
+        .unwrap();
+        RgbImage::from_pixel(2, 2, Rgb([0, 0, 0]))
+            .save(dir.path().join("927.png"))
+            .unwrap();
+        let archive = ArchiveIndex {
+            comics: BTreeMap::from([(1, date(2006, 1, 1)), (927, date(2011, 7, 20))]),
+            titles: BTreeMap::from([(1, "Barrel - Part 1".to_string())]),
+        };
+
+        let entries = BrowseEntry::from_index(&ComicCache::new(dir.path()), &archive, &[]).unwrap();
+        assert_eq!(
+            entries,
+            [
+                BrowseEntry {
+                    num: 927,
+                    title: "Standards".to_string(),
+                    preview: Some(dir.path().join("927.png")),
+                },
+                entry(1, "Barrel - Part 1"),
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case(KeyCode::Enter, KeyModifiers::NONE, Action::Pick)]
+    #[case(KeyCode::Backspace, KeyModifiers::NONE, Action::Erase)]
+    #[case(KeyCode::Up, KeyModifiers::NONE, Action::Up)]
+    #[case(KeyCode::Char('n'), KeyModifiers::CONTROL, Action::Down)]
+    #[case(KeyCode::Char('c'), KeyModifiers::CONTROL, Action::Cancel)]
+    #[case(KeyCode::Esc, KeyModifiers::NONE, Action::Cancel)]
+    #[case(KeyCode::Char('é'), KeyModifiers::NONE, Action::Type('é'))]
+    #[case(KeyCode::PageUp, KeyModifiers::NONE, Action::Nothing)]
+    fn key_actions(
+        #[case] code: KeyCode,
+        #[case] modifiers: KeyModifiers,
+        #[case] expected: Action,
+    ) {
+        assert_eq!(Action::from(KeyEvent::new(code, modifiers)), expected);
+    }
+
+    #[test]
+    fn draw_list_and_status() {
+        let entries = [entry(927, "Standards"), entry(1053, "Ten Thousand")];
+        let matches = filter(&entries, "");
+        let mut terminal = Terminal::new(TestBackend::new(80, 6)).unwrap();
+        terminal
+            .draw(|frame| draw(frame, "", &matches, 1, &mut HashMap::new()))
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| {
+            (0..buffer.area.width)
+                .map(|x| buffer[(x, y)].symbol())
+                .collect::<String>()
+        };
+        assert!(row(0).starts_with("> "));
+        assert!(row(1).starts_with("#927 Standards"));
+        assert!(row(2).starts_with("#1053 Ten Thousand"));
+        assert!(row(1).contains("no preview available"));
+        assert!(row(5).starts_with("2 of 2"));
+    }
+}
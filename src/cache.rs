@@ -0,0 +1,1036 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::net::{self, ComicFetcher, Fetched, UreqFetcher, Validators};
+use crate::{
+    download_img, download_img_with, state, timings, ComicImage, Metadata, Resolution, XkcdError,
+};
+
+/// Checksums of cached files by file name, in the cache directory
+const CHECKSUMS_FILE: &str = "checksums.json";
+/// Validators of cached metadata by URL, in the cache directory
+const VALIDATORS_FILE: &str = "validators.json";
+/// Resolution of cached images by comic number, in the cache directory
+const RESOLUTIONS_FILE: &str = "resolutions.json";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// Limits on the cache directory, the `[cache]` section of the configuration file
+///
+/// ```toml
+/// [cache]
+/// max_size = "500M"
+/// max_age_days = 90
+/// ```
+///
+/// Comics over `max_age_days` are removed first, then the least recently used comics until the
+/// cache fits in `max_size`. Both limits are optional.
+pub struct CacheConfig {
+    pub max_size: Option<ByteSize>,
+    pub max_age_days: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Size in bytes, written as a number or with a `K`, `M` or `G` suffix (powers of 1024)
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: u64 = number.parse().map_err(|_| format!("invalid size {s:?}"))?;
+        let multiplier: u64 = match unit.trim().to_uppercase().trim_end_matches("IB") {
+            "" | "B" => 1,
+            "K" => 1 << 10,
+            "M" => 1 << 20,
+            "G" => 1 << 30,
+            _ => return Err(format!("invalid size unit in {s:?}")),
+        };
+        number
+            .checked_mul(multiplier)
+            .map(ByteSize)
+            .ok_or_else(|| format!("size {s:?} is too large"))
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bytes(u64),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Bytes(bytes) => Ok(ByteSize(bytes)),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Files of a single comic in the cache, e.g. `927.png` and `927.json`
+struct CachedComic {
+    num: u64,
+    files: Vec<PathBuf>,
+    size: u64,
+    modified: SystemTime,
+    /// Last access, the modification time on file systems that do not record it
+    used: SystemTime,
+}
+
+impl CacheConfig {
+    /// Remove cached comics exceeding the limits, returning the numbers of the removed comics
+    pub fn enforce(&self, cache_dir: &Path) -> Result<Vec<u64>, XkcdError> {
+        if self.max_size.is_none() && self.max_age_days.is_none() {
+            return Ok(Vec::new());
+        }
+        let comics = cached_comics(cache_dir)?;
+        let evicted = self.select_evictions(comics, SystemTime::now());
+        for comic in &evicted {
+            info!("evicting comic {} from the cache", comic.num);
+            for file in &comic.files {
+                match fs::remove_file(file) {
+                    Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                    _ => {}
+                }
+            }
+        }
+        Ok(evicted.iter().map(|comic| comic.num).collect())
+    }
+
+    fn select_evictions(&self, mut comics: Vec<CachedComic>, now: SystemTime) -> Vec<CachedComic> {
+        let mut evicted = Vec::new();
+        if let Some(days) = self.max_age_days {
+            let max_age = Duration::from_secs(days * 24 * 60 * 60);
+            let (old, fresh) = comics.into_iter().partition(|comic| {
+                now.duration_since(comic.modified)
+                    .is_ok_and(|age| age > max_age)
+            });
+            evicted = old;
+            comics = fresh;
+        }
+
+        if let Some(ByteSize(max_size)) = self.max_size {
+            // Most recently used first, so the least recently used are popped first
+            comics.sort_by_key(|comic| std::cmp::Reverse(comic.used));
+            let mut size: u64 = comics.iter().map(|comic| comic.size).sum();
+            while size > max_size {
+                let Some(comic) = comics.pop() else { break };
+                size -= comic.size;
+                evicted.push(comic);
+            }
+        }
+        evicted
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// How [`ComicCache`] uses cached files
+pub enum CachePolicy {
+    /// Use cached files, downloading and caching the missing ones
+    #[default]
+    Prefer,
+    /// Download again and replace the cached files, metadata that the server reports as
+    /// unchanged is kept
+    Refresh,
+    /// Neither read nor write the cache
+    Bypass,
+    /// Only use cached files, never download
+    Offline,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+/// What an unfinished `.part` download in the cache holds
+struct PartialDownload {
+    url: String,
+    validators: Validators,
+}
+
+/// `path` with `suffix` appended to its file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+/// Validators of a metadata response and the comic it was for
+struct CachedResponse {
+    num: u64,
+    validators: Validators,
+}
+
+/// Comic images and metadata stored by comic number, e.g. `927.png` and `927.json`
+pub struct ComicCache<F = UreqFetcher> {
+    dir: PathBuf,
+    fetcher: F,
+}
+
+impl ComicCache {
+    pub fn new(dir: impl Into<PathBuf>) -> ComicCache {
+        ComicCache {
+            dir: dir.into(),
+            fetcher: UreqFetcher,
+        }
+    }
+}
+
+impl<F: ComicFetcher> ComicCache<F> {
+    /// Metadata and image of a comic, the newest one for `None`
+    pub fn download_comic(
+        &self,
+        comic: Option<u32>,
+        policy: CachePolicy,
+    ) -> Result<ComicImage, XkcdError> {
+        let metadata = self.metadata(comic, policy)?;
+        self.image(&metadata, policy)
+    }
+
+    /// Metadata of a comic, the newest one for `None`
+    ///
+    /// The newest comic's metadata is always requested, as its number is not known in advance,
+    /// except with [`CachePolicy::Offline`] which uses the newest cached comic instead. The
+    /// request carries the `ETag` and `Last-Modified` of the cached copy, so it is only
+    /// downloaded again when it changed.
+    pub fn metadata(&self, comic: Option<u32>, policy: CachePolicy) -> Result<Metadata, XkcdError> {
+        if let Some(metadata) = self.metadata_without_request(comic, policy)? {
+            return Ok(metadata);
+        }
+        if policy == CachePolicy::Bypass {
+            return Metadata::from_comic_id_with(&self.fetcher, comic);
+        }
+
+        let url = Metadata::url(comic);
+        let cached = self.revalidated(&url)?;
+        let validators = cached.as_ref().map(|(validators, _)| validators);
+
+        info!("downloading metadata from url {}", url);
+        let _timer = timings::stage("metadata");
+        let fetched = self
+            .fetcher
+            .get_if_modified(&url, validators.unwrap_or(&Validators::default()))
+            .map_err(|err| err.for_comic(comic))?;
+        self.store_metadata(url, fetched, cached)
+    }
+
+    /// Image of a comic, at 2x resolution when available
+    pub fn image(&self, metadata: &Metadata, policy: CachePolicy) -> Result<ComicImage, XkcdError> {
+        if let Some(comic) = self.image_without_request(metadata, policy)? {
+            return Ok(comic);
+        }
+        let path = self.image_path(metadata);
+        let (bytes, resolution) = {
+            let _timer = timings::stage("download");
+            match policy {
+                CachePolicy::Bypass => download_img(&self.fetcher, metadata)?,
+                _ => download_img_with(&self.fetcher, metadata, |url| {
+                    self.download_resumable(url, &path)
+                })?,
+            }
+        };
+        self.store_image(metadata, bytes, resolution, policy)
+    }
+
+    /// Download `url` for the cached file `path`, resuming a partial download left next to it
+    ///
+    /// The bytes arrive in `<path>.part`, along with `<path>.part.json` describing them, so that
+    /// a retry or a later run continues where an interrupted download stopped.
+    fn download_resumable(&self, url: &str, path: &Path) -> Result<Vec<u8>, XkcdError> {
+        let part = with_suffix(path, ".part");
+        let sidecar = with_suffix(&part, ".json");
+        if let Err(err) = fs::create_dir_all(&self.dir) {
+            warn!(
+                "cannot keep partial downloads in {}: {}",
+                self.dir.display(),
+                err
+            );
+            let mut bytes = Vec::new();
+            self.fetcher.get(url)?.read_to_end(&mut bytes)?;
+            return Ok(bytes);
+        }
+
+        net::retry_policy().run(&format!("download of {url}"), || {
+            let partial: PartialDownload = state::load_json(&sidecar).unwrap_or_default();
+            let offset = match partial.url == url && !partial.validators.is_empty() {
+                true => fs::metadata(&part).map_or(0, |metadata| metadata.len()),
+                false => 0,
+            };
+            let ranged = match self.fetcher.get_range(url, offset, &partial.validators) {
+                // The part may already be complete
+                Err(XkcdError::Http(416)) if offset > 0 => {
+                    self.fetcher.get_range(url, 0, &Validators::default())?
+                }
+                ranged => ranged?,
+            };
+
+            let mut file = match ranged.offset {
+                0 => {
+                    let partial = PartialDownload {
+                        url: url.to_string(),
+                        validators: ranged.validators,
+                    };
+                    state::save_json(&sidecar, &partial)?;
+                    fs::File::create(&part)?
+                }
+                resumed if resumed == offset => {
+                    info!("resuming download of {} at byte {}", url, offset);
+                    fs::OpenOptions::new().append(true).open(&part)?
+                }
+                resumed => {
+                    return Err(XkcdError::Other(format!(
+                        "server resumed {url} at byte {resumed} instead of {offset}"
+                    )))
+                }
+            };
+            let mut body = ranged.body;
+            std::io::copy(&mut body, &mut file)?;
+            Ok(())
+        })?;
+
+        let bytes = fs::read(&part)?;
+        for file in [&part, &sidecar] {
+            if let Err(err) = fs::remove_file(file) {
+                warn!("failed to remove {}: {}", file.display(), err);
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+impl<F> ComicCache<F> {
+    /// Download with `fetcher` instead of the default client
+    pub fn with_fetcher<G>(self, fetcher: G) -> ComicCache<G> {
+        ComicCache {
+            dir: self.dir,
+            fetcher,
+        }
+    }
+
+    /// Client the downloads are made with
+    pub fn fetcher(&self) -> &F {
+        &self.fetcher
+    }
+
+    /// Metadata answered without a request: always with [`CachePolicy::Offline`], and with
+    /// [`CachePolicy::Prefer`] when the comic is cached
+    pub(crate) fn metadata_without_request(
+        &self,
+        comic: Option<u32>,
+        policy: CachePolicy,
+    ) -> Result<Option<Metadata>, XkcdError> {
+        if policy == CachePolicy::Offline {
+            return match comic {
+                Some(num) => self
+                    .cached_metadata(num.into())?
+                    .ok_or(XkcdError::NotCached(Some(num.into())))
+                    .map(Some),
+                None => self
+                    .index()?
+                    .into_values()
+                    .next_back()
+                    .ok_or(XkcdError::NotCached(None))
+                    .map(Some),
+            };
+        }
+        if let (Some(num), CachePolicy::Prefer) = (comic, policy) {
+            if let Some(metadata) = self.cached_metadata(num.into())? {
+                info!("using cached metadata of comic {}", num);
+                return Ok(Some(metadata));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Validators and metadata of the cached response to `url`, to revalidate it with the
+    /// server as long as the metadata it describes is still cached
+    pub(crate) fn revalidated(
+        &self,
+        url: &str,
+    ) -> Result<Option<(Validators, Metadata)>, XkcdError> {
+        Ok(match self.load_responses().get(url) {
+            Some(response) => self
+                .cached_metadata(response.num)?
+                .map(|metadata| (response.validators.clone(), metadata)),
+            None => None,
+        })
+    }
+
+    /// Parse and cache the metadata `fetched` from `url`, or return the `cached` metadata when
+    /// it was not modified
+    pub(crate) fn store_metadata(
+        &self,
+        url: String,
+        fetched: Fetched,
+        cached: Option<(Validators, Metadata)>,
+    ) -> Result<Metadata, XkcdError> {
+        let (body, validators) = match fetched {
+            Fetched::Modified(body, validators) => (body, validators),
+            Fetched::NotModified => {
+                let (_, metadata) = cached.ok_or(XkcdError::Http(304))?;
+                info!("metadata of comic {} is unchanged", metadata.num);
+                return Ok(metadata);
+            }
+        };
+        let metadata = Metadata::read(body)?;
+        let json = serde_json::to_vec_pretty(&metadata)
+            .map_err(|err| XkcdError::Other(err.to_string()))?;
+        self.store(&self.metadata_path(metadata.num), &json);
+        if !validators.is_empty() {
+            let response = CachedResponse {
+                num: metadata.num,
+                validators,
+            };
+            let mut responses = self.load_responses();
+            responses.insert(url, response);
+            if let Err(err) = state::save_json(&self.dir.join(VALIDATORS_FILE), &responses) {
+                warn!("failed to save validators: {}", err);
+            }
+        }
+        Ok(metadata)
+    }
+
+    /// Cached image of a comic, with [`CachePolicy::Prefer`] and [`CachePolicy::Offline`]
+    ///
+    /// A corrupt cached image is downloaded again, except offline where it is an error.
+    pub(crate) fn image_without_request(
+        &self,
+        metadata: &Metadata,
+        policy: CachePolicy,
+    ) -> Result<Option<ComicImage>, XkcdError> {
+        if let CachePolicy::Bypass | CachePolicy::Refresh = policy {
+            return Ok(None);
+        }
+        let path = self.image_path(metadata);
+        match fs::read(&path) {
+            Ok(bytes) => match metadata.decode(bytes, self.resolution(metadata.num)) {
+                Ok(comic) => {
+                    info!("using cached image {}", path.display());
+                    Ok(Some(comic))
+                }
+                Err(err) if policy == CachePolicy::Offline => Err(err),
+                Err(err) => {
+                    warn!("downloading corrupt {} again: {}", path.display(), err);
+                    Ok(None)
+                }
+            },
+            Err(err) if err.kind() == ErrorKind::NotFound => match policy {
+                CachePolicy::Offline => Err(XkcdError::NotCached(Some(metadata.num))),
+                _ => Ok(None),
+            },
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Cache the downloaded image `bytes`, unless bypassing the cache, and decode them
+    pub(crate) fn store_image(
+        &self,
+        metadata: &Metadata,
+        bytes: Vec<u8>,
+        resolution: Resolution,
+        policy: CachePolicy,
+    ) -> Result<ComicImage, XkcdError> {
+        if policy != CachePolicy::Bypass {
+            self.store(&self.image_path(metadata), &bytes);
+            self.store_resolution(metadata.num, resolution);
+        }
+        metadata.decode(bytes, resolution)
+    }
+
+    /// Validators of cached responses by URL, empty if they cannot be read
+    fn load_responses(&self) -> BTreeMap<String, CachedResponse> {
+        state::load_json(&self.dir.join(VALIDATORS_FILE))
+            .inspect_err(|err| warn!("ignoring unreadable validators: {}", err))
+            .unwrap_or_default()
+    }
+
+    /// Cached metadata of comic `num`, `None` if it is not cached or cannot be read
+    pub fn cached_metadata(&self, num: u64) -> Result<Option<Metadata>, XkcdError> {
+        let path = self.metadata_path(num);
+        match fs::read(&path) {
+            Ok(contents) => Ok(serde_json::from_slice(&contents)
+                .inspect_err(|err| warn!("ignoring malformed {}: {}", path.display(), err))
+                .ok()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Resolution the cached image of comic `num` was downloaded at
+    fn resolution(&self, num: u64) -> Resolution {
+        let resolutions: BTreeMap<u64, Resolution> =
+            state::load_json(&self.dir.join(RESOLUTIONS_FILE))
+                .inspect_err(|err| warn!("ignoring unreadable resolutions: {}", err))
+                .unwrap_or_default();
+        resolutions.get(&num).copied().unwrap_or_default()
+    }
+
+    fn store_resolution(&self, num: u64, resolution: Resolution) {
+        let path = self.dir.join(RESOLUTIONS_FILE);
+        let mut resolutions: BTreeMap<u64, Resolution> =
+            state::load_json(&path).unwrap_or_default();
+        resolutions.insert(num, resolution);
+        if let Err(err) = state::save_json(&path, &resolutions) {
+            warn!("failed to save resolutions: {}", err);
+        }
+    }
+
+    /// Metadata of all cached comics, by comic number
+    pub fn index(&self) -> Result<BTreeMap<u64, Metadata>, XkcdError> {
+        let mut index = BTreeMap::new();
+        for comic in cached_comics(&self.dir)? {
+            if let Some(metadata) = self.cached_metadata(comic.num)? {
+                index.insert(comic.num, metadata);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Path of the cached image of a comic, `None` if it is not cached
+    pub fn cached_image(&self, metadata: &Metadata) -> Option<PathBuf> {
+        Some(self.image_path(metadata)).filter(|path| path.is_file())
+    }
+
+    fn metadata_path(&self, num: u64) -> PathBuf {
+        self.dir.join(format!("{num}.json"))
+    }
+
+    /// `<num>.<ext>` with the extension of the image URL
+    fn image_path(&self, metadata: &Metadata) -> PathBuf {
+        let path = self.dir.join(format!("{}.png", metadata.num));
+        match metadata.img.rsplit_once('.') {
+            Some((_, ext)) if is_image(Path::new(&format!("x.{ext}"))) => {
+                path.with_extension(ext.to_lowercase())
+            }
+            _ => path,
+        }
+    }
+
+    /// Write a file into the cache and record its checksum, failures only cost a later download
+    fn store(&self, path: &Path, contents: &[u8]) {
+        let stored = fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(path, contents))
+            .map_err(XkcdError::from)
+            .and_then(|()| {
+                let mut checksums = Checksums::load(&self.dir)?;
+                checksums.record(path)?;
+                checksums.save()
+            });
+        if let Err(err) = stored {
+            warn!("failed to cache {}: {}", path.display(), err);
+        }
+    }
+}
+
+/// Comics in the cache, grouped by the comic number their files are named after
+fn cached_comics(cache_dir: &Path) -> Result<Vec<CachedComic>, XkcdError> {
+    let entries = match fs::read_dir(cache_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut comics: BTreeMap<u64, CachedComic> = BTreeMap::new();
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let path = entry.path();
+        let Some(num) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        if !metadata.is_file() {
+            continue;
+        }
+
+        let modified = metadata.modified()?;
+        let used = metadata.accessed().unwrap_or(modified).max(modified);
+        let comic = comics.entry(num).or_insert_with(|| CachedComic {
+            num,
+            files: Vec::new(),
+            size: 0,
+            modified,
+            used,
+        });
+        comic.files.push(path);
+        comic.size += metadata.len();
+        comic.modified = comic.modified.min(modified);
+        comic.used = comic.used.max(used);
+    }
+    Ok(comics.into_values().collect())
+}
+
+/// CRC-32 of a file, as lowercase hex
+pub fn checksum(path: &Path) -> Result<String, XkcdError> {
+    Ok(format!("{:08x}", crc32fast::hash(&fs::read(path)?)))
+}
+
+/// Checksums recorded when files are written to the cache
+pub struct Checksums {
+    path: PathBuf,
+    sums: BTreeMap<String, String>,
+}
+
+impl Checksums {
+    /// Load the checksums of `cache_dir`, empty if none were recorded yet
+    pub fn load(cache_dir: &Path) -> Result<Checksums, XkcdError> {
+        let path = cache_dir.join(CHECKSUMS_FILE);
+        let sums = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|err| {
+                warn!("ignoring malformed {}: {}", path.display(), err);
+                BTreeMap::new()
+            }),
+            Err(err) if err.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Checksums { path, sums })
+    }
+
+    pub fn get(&self, file_name: &str) -> Option<&str> {
+        self.sums.get(file_name).map(String::as_str)
+    }
+
+    /// Record the checksum of `path`, a file in the cache directory
+    pub fn record(&mut self, path: &Path) -> Result<(), XkcdError> {
+        let sum = checksum(path)?;
+        self.sums.insert(file_name(path), sum);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.sums.remove(&file_name(path));
+    }
+
+    pub fn save(&self) -> Result<(), XkcdError> {
+        let contents = serde_json::to_vec_pretty(&self.sums)
+            .map_err(|err| XkcdError::Other(err.to_string()))?;
+        fs::write(&self.path, contents)?;
+        Ok(())
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Why a cached file is considered corrupt
+pub enum Problem {
+    ChecksumMismatch { expected: String, actual: String },
+    Undecodable(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::ChecksumMismatch { expected, actual } => {
+                write!(f, "checksum {actual} does not match recorded {expected}")
+            }
+            Problem::Undecodable(reason) => write!(f, "cannot be decoded: {reason}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A corrupt file found by [`verify`]
+pub struct Corrupt {
+    pub num: u64,
+    pub path: PathBuf,
+    pub problem: Problem,
+}
+
+impl Corrupt {
+    /// Whether the file is a comic image, which can be downloaded again
+    pub fn is_image(&self) -> bool {
+        is_image(&self.path)
+    }
+}
+
+/// Check cached files against their recorded checksums and make sure they can be decoded
+pub fn verify(cache_dir: &Path) -> Result<Vec<Corrupt>, XkcdError> {
+    let checksums = Checksums::load(cache_dir)?;
+    let mut corrupt = Vec::new();
+    for comic in cached_comics(cache_dir)? {
+        for path in comic.files {
+            info!("verifying {}", path.display());
+            if let Some(problem) = check_file(&path, checksums.get(&file_name(&path)))? {
+                corrupt.push(Corrupt {
+                    num: comic.num,
+                    path,
+                    problem,
+                });
+            }
+        }
+    }
+    Ok(corrupt)
+}
+
+fn check_file(path: &Path, expected: Option<&str>) -> Result<Option<Problem>, XkcdError> {
+    if let Some(expected) = expected {
+        let actual = checksum(path)?;
+        if actual != expected {
+            return Ok(Some(Problem::ChecksumMismatch {
+                expected: expected.to_string(),
+                actual,
+            }));
+        }
+    }
+
+    let decoded = if is_image(path) {
+        image::open(path).map(|_| ()).map_err(|err| err.to_string())
+    } else if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_slice::<serde_json::Value>(&fs::read(path)?)
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    } else {
+        Ok(())
+    };
+    Ok(decoded.err().map(Problem::Undecodable))
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ["png", "jpg", "jpeg", "gif"].contains(&ext.to_lowercase().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("1024", 1024)]
+    #[case("500M", 500 << 20)]
+    #[case("2 GiB", 2 << 30)]
+    #[case("64k", 64 << 10)]
+    fn byte_size_parse(#[case] input: &str, #[case] expected: u64) {
+        assert_eq!(input.parse::<ByteSize>().unwrap(), ByteSize(expected));
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("M")]
+    #[case("5T")]
+    fn byte_size_parse_error(#[case] input: &str) {
+        assert!(input.parse::<ByteSize>().is_err());
+    }
+
+    /// Serves comic 927 and counts the requests
+    #[derive(Default)]
+    struct CountingFetcher(std::sync::atomic::AtomicUsize);
+
+    impl ComicFetcher for CountingFetcher {
+        fn get(&self, url: &str) -> Result<Box<dyn std::io::Read + Send>, XkcdError> {
+            use std::io::Cursor;
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            match url {
+                "https://xkcd.com/927/info.0.json" => Ok(Box::new(Cursor::new(
+                    r#"{"num": 927, "safe_title": "Standards", "img": "https://imgs.xkcd.com/comics/standards.png", "day": "20", "month": "7", "year": "2011"}"#,
+                ))),
+                "https://xkcd.com/927/" => Ok(Box::new(Cursor::new(
+                    r#"<img src="//imgs.xkcd.com/comics/standards.png" srcset="//imgs.xkcd.com/comics/standards_2x.png 2x"/>"#,
+                ))),
+                "https://imgs.xkcd.com/comics/standards_2x.png" => {
+                    let mut png = Vec::new();
+                    image::DynamicImage::ImageRgba8(image::RgbaImage::new(2, 1))
+                        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+                        .unwrap();
+                    Ok(Box::new(Cursor::new(png)))
+                }
+                _ => Err(XkcdError::NotFound),
+            }
+        }
+    }
+
+    impl CountingFetcher {
+        fn requests(&self) -> usize {
+            self.0.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn comic_cache_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let fetcher = CountingFetcher::default();
+        let cache = ComicCache::new(dir.path()).with_fetcher(&fetcher);
+
+        cache
+            .download_comic(Some(927), CachePolicy::Bypass)
+            .unwrap();
+        assert_eq!(fetcher.requests(), 3);
+        assert!(cache.index().unwrap().is_empty());
+
+        cache
+            .download_comic(Some(927), CachePolicy::Prefer)
+            .unwrap();
+        assert_eq!(fetcher.requests(), 6);
+        let comic = cache
+            .download_comic(Some(927), CachePolicy::Prefer)
+            .unwrap();
+        assert_eq!(fetcher.requests(), 6);
+        assert_eq!(
+            (comic.img.width(), comic.metadata.safe_title.as_str()),
+            (2, "Standards")
+        );
+        assert_eq!(comic.resolution, Resolution::Double);
+        assert_eq!(cache.index().unwrap().keys().collect::<Vec<_>>(), [&927]);
+        assert!(verify(dir.path()).unwrap().is_empty());
+        assert!(Checksums::load(dir.path())
+            .unwrap()
+            .get("927.png")
+            .is_some());
+
+        cache
+            .download_comic(Some(927), CachePolicy::Refresh)
+            .unwrap();
+        assert_eq!(fetcher.requests(), 9);
+    }
+
+    /// Serves the newest comic with an `ETag`, and a 304 when it is sent back
+    #[derive(Default)]
+    struct RevalidatingFetcher(CountingFetcher);
+
+    impl ComicFetcher for RevalidatingFetcher {
+        fn get(&self, url: &str) -> Result<Box<dyn std::io::Read + Send>, XkcdError> {
+            self.0
+                .get(url.replace("xkcd.com/info", "xkcd.com/927/info").as_str())
+        }
+
+        fn get_if_modified(
+            &self,
+            url: &str,
+            validators: &Validators,
+        ) -> Result<Fetched, XkcdError> {
+            match validators.etag.as_deref() {
+                Some("\"v1\"") => Ok(Fetched::NotModified),
+                _ => Ok(Fetched::Modified(
+                    self.get(url)?,
+                    Validators {
+                        etag: Some("\"v1\"".to_string()),
+                        last_modified: None,
+                    },
+                )),
+            }
+        }
+    }
+
+    #[test]
+    fn latest_metadata_is_revalidated() {
+        let dir = tempfile::tempdir().unwrap();
+        let fetcher = RevalidatingFetcher::default();
+        let cache = ComicCache::new(dir.path()).with_fetcher(&fetcher);
+
+        for _ in 0..2 {
+            let metadata = cache.metadata(None, CachePolicy::Prefer).unwrap();
+            assert_eq!(metadata.num, 927);
+        }
+        assert_eq!(fetcher.0.requests(), 1);
+
+        // Without the cached copy, the validators must not be sent
+        fs::remove_file(dir.path().join("927.json")).unwrap();
+        cache.metadata(None, CachePolicy::Prefer).unwrap();
+        assert_eq!(fetcher.0.requests(), 2);
+    }
+
+    /// Serves the 2x image of comic 927 with an `ETag`, from any offset
+    struct RangeFetcher {
+        png: Vec<u8>,
+        offsets: std::sync::Mutex<Vec<u64>>,
+    }
+
+    impl ComicFetcher for RangeFetcher {
+        fn get(&self, _url: &str) -> Result<Box<dyn std::io::Read + Send>, XkcdError> {
+            Err(XkcdError::NotFound)
+        }
+
+        fn get_range(
+            &self,
+            url: &str,
+            offset: u64,
+            validators: &Validators,
+        ) -> Result<crate::net::Ranged, XkcdError> {
+            if url != "https://imgs.xkcd.com/comics/standards_2x.png" {
+                return Err(XkcdError::NotFound);
+            }
+            self.offsets.lock().unwrap().push(offset);
+            let offset = match validators.etag.as_deref() {
+                Some("\"png\"") => offset,
+                _ => 0,
+            };
+            Ok(crate::net::Ranged {
+                body: Box::new(std::io::Cursor::new(self.png[offset as usize..].to_vec())),
+                offset,
+                validators: Validators {
+                    etag: Some("\"png\"".to_string()),
+                    last_modified: None,
+                },
+            })
+        }
+    }
+
+    #[rstest]
+    #[case("https://imgs.xkcd.com/comics/standards_2x.png", Some("\"png\""), 10)]
+    #[case("https://imgs.xkcd.com/comics/other_2x.png", Some("\"png\""), 0)]
+    #[case("https://imgs.xkcd.com/comics/standards_2x.png", None, 0)]
+    fn partial_download_is_resumed(
+        #[case] url: &str,
+        #[case] etag: Option<&str>,
+        #[case] expected_offset: u64,
+    ) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgba8(image::RgbaImage::new(2, 1))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        // Left behind by an interrupted download
+        fs::write(dir.path().join("927.png.part"), &png[..10]).unwrap();
+        let partial = PartialDownload {
+            url: url.to_string(),
+            validators: Validators {
+                etag: etag.map(str::to_string),
+                last_modified: None,
+            },
+        };
+        state::save_json(&dir.path().join("927.png.part.json"), &partial).unwrap();
+
+        let fetcher = RangeFetcher {
+            png: png.clone(),
+            offsets: Default::default(),
+        };
+        let metadata: Metadata = serde_json::from_str(
+            r#"{"num": 927, "safe_title": "Standards", "img": "https://imgs.xkcd.com/comics/standards.png", "day": "20", "month": "7", "year": "2011"}"#,
+        )
+        .unwrap();
+        let comic = ComicCache::new(dir.path())
+            .with_fetcher(&fetcher)
+            .image(&metadata, CachePolicy::Prefer)
+            .unwrap();
+
+        assert_eq!(comic.img.width(), 2);
+        assert_eq!(*fetcher.offsets.lock().unwrap(), [expected_offset]);
+        assert_eq!(fs::read(dir.path().join("927.png")).unwrap(), png);
+        assert!(!dir.path().join("927.png.part").exists());
+        assert!(!dir.path().join("927.png.part.json").exists());
+    }
+
+    #[test]
+    fn offline_uses_only_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let fetcher = CountingFetcher::default();
+        let cache = ComicCache::new(dir.path()).with_fetcher(&fetcher);
+        assert!(matches!(
+            cache.download_comic(None, CachePolicy::Offline),
+            Err(XkcdError::NotCached(None))
+        ));
+
+        cache
+            .download_comic(Some(927), CachePolicy::Prefer)
+            .unwrap();
+        let requests = fetcher.requests();
+        for comic in [Some(927), None] {
+            let comic = cache.download_comic(comic, CachePolicy::Offline).unwrap();
+            assert_eq!(comic.metadata.num, 927);
+        }
+        assert!(matches!(
+            cache.download_comic(Some(1), CachePolicy::Offline),
+            Err(XkcdError::NotCached(Some(1)))
+        ));
+
+        fs::remove_file(dir.path().join("927.png")).unwrap();
+        assert!(matches!(
+            cache.download_comic(Some(927), CachePolicy::Offline),
+            Err(XkcdError::NotCached(Some(927)))
+        ));
+        assert_eq!(fetcher.requests(), requests);
+    }
+
+    fn comic(num: u64, size: u64, days_ago: u64, used_days_ago: u64) -> CachedComic {
+        let day = Duration::from_secs(24 * 60 * 60);
+        CachedComic {
+            num,
+            files: Vec::new(),
+            size,
+            modified: SystemTime::UNIX_EPOCH + day * (100 - days_ago as u32),
+            used: SystemTime::UNIX_EPOCH + day * (100 - used_days_ago as u32),
+        }
+    }
+
+    #[rstest]
+    #[case(CacheConfig { max_size: Some(ByteSize(25)), max_age_days: None }, vec![2])]
+    #[case(CacheConfig { max_size: Some(ByteSize(5)), max_age_days: None }, vec![2, 3, 1])]
+    #[case(CacheConfig { max_size: None, max_age_days: Some(30) }, vec![1])]
+    #[case(CacheConfig { max_size: Some(ByteSize(15)), max_age_days: Some(30) }, vec![1, 2])]
+    fn select_evictions(#[case] config: CacheConfig, #[case] expected: Vec<u64>) {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(100 * 24 * 60 * 60);
+        let comics = vec![comic(1, 10, 40, 1), comic(2, 10, 5, 3), comic(3, 10, 2, 2)];
+        let evicted: Vec<_> = config
+            .select_evictions(comics, now)
+            .iter()
+            .map(|comic| comic.num)
+            .collect();
+        assert_eq!(evicted, expected);
+    }
+
+    #[test]
+    fn enforce_removes_all_files_of_a_comic() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("927.png"), [0u8; 10]).unwrap();
+        fs::write(dir.path().join("927.json"), [0u8; 10]).unwrap();
+        fs::write(dir.path().join("index.json"), [0u8; 50]).unwrap();
+
+        let config = CacheConfig {
+            max_size: Some(ByteSize(0)),
+            max_age_days: None,
+        };
+        assert_eq!(config.enforce(dir.path()).unwrap(), vec![927]);
+        assert!(!dir.path().join("927.png").exists());
+        assert!(!dir.path().join("927.json").exists());
+        assert!(dir.path().join("index.json").exists());
+    }
+
+    #[test]
+    fn verify_finds_corrupt_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("1.png");
+        image::RgbaImage::new(2, 2).save(&good).unwrap();
+        let tampered = dir.path().join("2.png");
+        image::RgbaImage::new(2, 2).save(&tampered).unwrap();
+        fs::write(dir.path().join("3.png"), b"not a png").unwrap();
+        fs::write(dir.path().join("3.json"), br#"{"num": 3}"#).unwrap();
+
+        let mut checksums = Checksums::load(dir.path()).unwrap();
+        checksums.record(&good).unwrap();
+        checksums.record(&tampered).unwrap();
+        checksums.save().unwrap();
+        image::RgbaImage::new(3, 3).save(&tampered).unwrap();
+
+        let corrupt = verify(dir.path()).unwrap();
+        assert_eq!(
+            corrupt.iter().map(|c| c.num).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert!(matches!(
+            corrupt[0].problem,
+            Problem::ChecksumMismatch { .. }
+        ));
+        assert!(matches!(corrupt[1].problem, Problem::Undecodable(_)));
+    }
+}
@@ -0,0 +1,342 @@
+use image::imageops::{self, overlay, FilterType};
+use image::{DynamicImage, RgbaImage};
+use log::info;
+
+use crate::background::Background;
+use crate::decoration::Decoration;
+use crate::recolor::Recolor;
+use crate::{timings, ForegroundColor, Image, ScreenDimensions};
+
+/// Top left corner and size of a comic in a collage
+pub type Tile = (i64, i64, u32, u32);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+/// How the comics of a collage are arranged
+pub enum Arrangement {
+    /// Rows of equal cells, each comic fitted in its cell
+    #[default]
+    Grid,
+    /// Columns of equal width, each comic going to the shortest column
+    Masonry,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Several comics on one wallpaper
+pub struct Collage {
+    pub arrangement: Arrangement,
+    /// Pixels between the comics and around them
+    pub spacing: u32,
+    /// Number of columns, the one showing the comics the largest when `None`
+    pub columns: Option<u32>,
+}
+
+impl Default for Collage {
+    fn default() -> Self {
+        Collage {
+            arrangement: Arrangement::default(),
+            spacing: 32,
+            columns: None,
+        }
+    }
+}
+
+impl Collage {
+    /// Where comics of `sizes` go on the screen, in the same order
+    pub fn tiles(&self, sizes: &[(u32, u32)], screen_dimensions: &ScreenDimensions) -> Vec<Tile> {
+        if sizes.is_empty() {
+            return Vec::new();
+        }
+        let arrange = |columns: usize| match self.arrangement {
+            Arrangement::Grid => self.grid(sizes, screen_dimensions, columns),
+            Arrangement::Masonry => self.masonry(sizes, screen_dimensions, columns),
+        };
+        let tiles = match self.columns {
+            Some(columns) => arrange((columns as usize).clamp(1, sizes.len())),
+            None => (1..=sizes.len())
+                .map(arrange)
+                .max_by_key(|tiles| {
+                    tiles
+                        .iter()
+                        .map(|(_, _, width, height)| *width as u64 * *height as u64)
+                        .sum::<u64>()
+                })
+                .unwrap_or_default(),
+        };
+        info!("arranged {} comics as {:?}", sizes.len(), tiles);
+        tiles
+    }
+
+    fn grid(
+        &self,
+        sizes: &[(u32, u32)],
+        screen_dimensions: &ScreenDimensions,
+        columns: usize,
+    ) -> Vec<Tile> {
+        let rows = sizes.len().div_ceil(columns);
+        let spacing = self.spacing as f64;
+        let cell_width = cell(screen_dimensions.width, columns, spacing);
+        let cell_height = cell(screen_dimensions.height, rows, spacing);
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(index, &(width, height))| {
+                let (row, column) = (index / columns, index % columns);
+                // The last row is centered when it isn't full
+                let in_row = columns.min(sizes.len() - row * columns);
+                let indent = (columns - in_row) as f64 * (cell_width + spacing) / 2.0;
+                let scale =
+                    (cell_width / width.max(1) as f64).min(cell_height / height.max(1) as f64);
+                let (tile_width, tile_height) = scaled(width, height, scale);
+                let x = spacing + column as f64 * (cell_width + spacing) + indent;
+                let y = spacing + row as f64 * (cell_height + spacing);
+                (
+                    (x + (cell_width - tile_width as f64) / 2.0).round() as i64,
+                    (y + (cell_height - tile_height as f64) / 2.0).round() as i64,
+                    tile_width,
+                    tile_height,
+                )
+            })
+            .collect()
+    }
+
+    fn masonry(
+        &self,
+        sizes: &[(u32, u32)],
+        screen_dimensions: &ScreenDimensions,
+        columns: usize,
+    ) -> Vec<Tile> {
+        let spacing = self.spacing as f64;
+        // Comics of a column are as high as the column is wide times the sum of their ratios
+        let ratio = |(width, height): (u32, u32)| height as f64 / width.max(1) as f64;
+        let mut stacks: Vec<(f64, Vec<usize>)> = vec![(0.0, Vec::new()); columns];
+        for (index, &size) in sizes.iter().enumerate() {
+            if let Some(stack) = stacks.iter_mut().min_by(|a, b| a.0.total_cmp(&b.0)) {
+                stack.0 += ratio(size);
+                stack.1.push(index);
+            }
+        }
+        let stack_height = |(ratios, comics): &(f64, Vec<usize>), width: f64| {
+            width * ratios + comics.len().saturating_sub(1) as f64 * spacing
+        };
+        // Narrower columns when the tallest would not fit on the screen
+        let available = screen_dimensions.height as f64 - 2.0 * spacing;
+        let column_width = stacks
+            .iter()
+            .filter(|(ratios, _)| *ratios > 0.0)
+            .map(|(ratios, comics)| {
+                (available - comics.len().saturating_sub(1) as f64 * spacing) / ratios
+            })
+            .fold(cell(screen_dimensions.width, columns, spacing), f64::min)
+            .floor()
+            .max(1.0);
+        let block_width = columns as f64 * (column_width + spacing) - spacing;
+        let block_height = stacks
+            .iter()
+            .map(|stack| stack_height(stack, column_width))
+            .fold(0.0, f64::max);
+        let left = (screen_dimensions.width as f64 - block_width) / 2.0;
+        let top = (screen_dimensions.height as f64 - block_height) / 2.0;
+
+        let mut tiles = vec![(0, 0, 0, 0); sizes.len()];
+        for (column, (_, comics)) in stacks.iter().enumerate() {
+            let x = left + column as f64 * (column_width + spacing);
+            let mut y = top;
+            for &index in comics {
+                let (width, height) = sizes[index];
+                let (tile_width, tile_height) =
+                    scaled(width, height, column_width / width.max(1) as f64);
+                tiles[index] = (x.round() as i64, y.round() as i64, tile_width, tile_height);
+                y += tile_height as f64 + spacing;
+            }
+        }
+        tiles
+    }
+
+    /// Wallpaper with all of `comics`, named and recorded after the first one
+    ///
+    /// Comics are recolored and decorated like [`crate::get_wallpaper_from_comic`] does, without
+    /// captions. A blurred background is made of the first comic.
+    pub fn compose(
+        &self,
+        comics: Vec<Image>,
+        fg_color: ForegroundColor,
+        background: impl Into<Background>,
+        screen_dimensions: ScreenDimensions,
+        recolor: Recolor,
+        decoration: Decoration,
+    ) -> Image {
+        let _timer = timings::stage("composite");
+        let mut background = background.into();
+        let (metadata, resolution) = comics
+            .first()
+            .map(|comic| (comic.metadata.clone(), comic.resolution))
+            .unwrap_or_default();
+        let fill = decoration.fill().unwrap_or(background.fill());
+        let comics = comics
+            .into_iter()
+            .map(|comic| recolor.apply(comic.img, &fg_color, fill))
+            .collect::<Vec<_>>();
+        if let Some(first) = comics.first() {
+            background = background.behind(first, &screen_dimensions);
+        }
+
+        // Decorations keep the spacing between them rather than between the comics
+        let collage = Collage {
+            spacing: self.spacing + 2 * decoration.extent(),
+            ..*self
+        };
+        let sizes = comics.iter().map(RgbaImage::dimensions).collect::<Vec<_>>();
+        let tiles = collage.tiles(&sizes, &screen_dimensions);
+        let mut canvas = background.canvas(&screen_dimensions);
+        for (comic, (x, y, width, height)) in comics.into_iter().zip(tiles) {
+            let comic = match comic.dimensions() == (width, height) {
+                true => comic,
+                false => imageops::resize(&comic, width, height, FilterType::Lanczos3),
+            };
+            for (layer, dx, dy) in decoration.under(width, height) {
+                overlay(&mut canvas, &layer, x + dx, y + dy);
+            }
+            overlay(&mut canvas, &comic, x, y);
+        }
+
+        Image {
+            img: DynamicImage::ImageRgba8(canvas),
+            metadata,
+            resolution,
+        }
+    }
+}
+
+/// Size of each of `count` cells across `length` pixels, with `spacing` between and around them
+///
+/// Whole pixels, so that rounding the tiles never closes the spacing between them.
+fn cell(length: u32, count: usize, spacing: f64) -> f64 {
+    ((length as f64 - (count + 1) as f64 * spacing) / count as f64)
+        .floor()
+        .max(1.0)
+}
+
+/// A `width` x `height` comic scaled by `scale`, at least a pixel in each direction
+fn scaled(width: u32, height: u32, scale: f64) -> (u32, u32) {
+    let side = |length: u32| ((length as f64 * scale).round() as u32).max(1);
+    (side(width), side(height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+    use image::Rgba;
+    use rstest::rstest;
+
+    const ULTRAWIDE: ScreenDimensions = ScreenDimensions {
+        width: 3440,
+        height: 1440,
+    };
+
+    #[test]
+    fn grid_side_by_side() {
+        let collage = Collage {
+            spacing: 40,
+            ..Default::default()
+        };
+        // Three square comics on an ultrawide screen go in a single row
+        let tiles = collage.tiles(&[(500, 500); 3], &ULTRAWIDE);
+        assert_eq!(
+            tiles,
+            [
+                (40, 174, 1093, 1093),
+                (1173, 174, 1093, 1093),
+                (2306, 174, 1093, 1093),
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case(Arrangement::Grid)]
+    #[case(Arrangement::Masonry)]
+    fn tiles_stay_apart(#[case] arrangement: Arrangement) {
+        let collage = Collage {
+            arrangement,
+            spacing: 20,
+            ..Default::default()
+        };
+        let sizes = [(740, 300), (400, 600), (600, 600), (300, 900), (1000, 350)];
+        let tiles = collage.tiles(&sizes, &ULTRAWIDE);
+        assert_eq!(tiles.len(), sizes.len());
+        for (index, &(x, y, width, height)) in tiles.iter().enumerate() {
+            // On the screen, at least the spacing away from its edges
+            assert!(x >= 20 && y >= 20);
+            assert!(x + width as i64 <= 3420 && y + height as i64 <= 1420);
+            // Keeping the aspect ratio of the comic
+            let (comic_width, comic_height) = sizes[index];
+            let ratio = comic_width as f64 / comic_height as f64;
+            assert!((width as f64 / height as f64 - ratio).abs() < 0.02);
+            for &(other_x, other_y, other_width, other_height) in &tiles[index + 1..] {
+                let apart_x =
+                    x + width as i64 + 20 <= other_x || other_x + other_width as i64 + 20 <= x;
+                let apart_y =
+                    y + height as i64 + 20 <= other_y || other_y + other_height as i64 + 20 <= y;
+                assert!(apart_x || apart_y, "{:?}", tiles);
+            }
+        }
+    }
+
+    #[test]
+    fn masonry_fills_shortest_column() {
+        let collage = Collage {
+            arrangement: Arrangement::Masonry,
+            spacing: 0,
+            columns: Some(2),
+        };
+        let screen = ScreenDimensions {
+            width: 200,
+            height: 400,
+        };
+        // A tall comic in the first column, the two wide ones stacked in the second
+        let tiles = collage.tiles(&[(100, 200), (100, 50), (100, 50)], &screen);
+        assert_eq!(
+            tiles,
+            [(0, 100, 100, 200), (100, 100, 100, 50), (100, 150, 100, 50)]
+        );
+    }
+
+    #[test]
+    fn compose_comics() {
+        let comic = |color| Image {
+            img: DynamicImage::ImageRgba8(RgbaImage::from_pixel(10, 10, color)),
+            metadata: Metadata {
+                num: 1,
+                ..Default::default()
+            },
+            resolution: Default::default(),
+        };
+        let (bg, ink) = (Rgba([255, 255, 255, 255]), Rgba([0, 0, 0, 255]));
+        let collage = Collage {
+            spacing: 10,
+            columns: Some(2),
+            ..Default::default()
+        };
+        let screen = ScreenDimensions {
+            width: 70,
+            height: 40,
+        };
+        let wallpaper = collage.compose(
+            vec![comic(ink), comic(Rgba([40, 40, 40, 255]))],
+            ForegroundColor::Dark,
+            bg,
+            screen,
+            Recolor::default(),
+            Decoration::default(),
+        );
+        assert_eq!(wallpaper.metadata.num, 1);
+        let img = wallpaper.img.into_rgba8();
+        // Two 20 pixel squares 10 pixels apart
+        assert_eq!(img.get_pixel(9, 20), &bg);
+        assert_eq!(img.get_pixel(10, 10), &ink);
+        assert_eq!(img.get_pixel(29, 29), &ink);
+        assert_eq!(img.get_pixel(35, 20), &bg);
+        assert_ne!(img.get_pixel(40, 10), &bg);
+        assert_eq!(img.get_pixel(60, 20), &bg);
+    }
+}
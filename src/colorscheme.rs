@@ -0,0 +1,92 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use image::Rgba;
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+/// Terminal color scheme formats that can be exported
+pub enum SchemeFormat {
+    Alacritty,
+    Kitty,
+    Xresources,
+}
+
+impl FromStr for SchemeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <SchemeFormat as clap::ValueEnum>::from_str(s, true).map_err(|_| {
+            format!("Unknown scheme format '{s}', expected alacritty, kitty or xresources")
+        })
+    }
+}
+
+/// Render a color scheme snippet matching a wallpaper's background and foreground
+pub fn render_scheme(format: SchemeFormat, bg: Rgba<u8>, fg: Rgba<u8>) -> String {
+    let selection = blend(bg, fg, 0.25);
+    let (bg, fg, selection) = (hex(bg), hex(fg), hex(selection));
+
+    let mut output = String::new();
+    match format {
+        SchemeFormat::Alacritty => {
+            let _ = writeln!(output, "[colors.primary]");
+            let _ = writeln!(output, "background = \"{bg}\"");
+            let _ = writeln!(output, "foreground = \"{fg}\"");
+            let _ = writeln!(output, "\n[colors.cursor]");
+            let _ = writeln!(output, "text = \"{bg}\"");
+            let _ = writeln!(output, "cursor = \"{fg}\"");
+            let _ = writeln!(output, "\n[colors.selection]");
+            let _ = writeln!(output, "text = \"{fg}\"");
+            let _ = writeln!(output, "background = \"{selection}\"");
+        }
+        SchemeFormat::Kitty => {
+            let _ = writeln!(output, "background {bg}");
+            let _ = writeln!(output, "foreground {fg}");
+            let _ = writeln!(output, "cursor {fg}");
+            let _ = writeln!(output, "cursor_text_color {bg}");
+            let _ = writeln!(output, "selection_background {selection}");
+            let _ = writeln!(output, "selection_foreground {fg}");
+        }
+        SchemeFormat::Xresources => {
+            let _ = writeln!(output, "*.background: {bg}");
+            let _ = writeln!(output, "*.foreground: {fg}");
+            let _ = writeln!(output, "*.cursorColor: {fg}");
+            let _ = writeln!(output, "*.color0: {bg}");
+            let _ = writeln!(output, "*.color7: {fg}");
+        }
+    }
+    output
+}
+
+fn hex(color: Rgba<u8>) -> String {
+    let [r, g, b, _] = color.0;
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// Linear interpolation between `from` and `to`, `amount` ranges from 0 to 1
+fn blend(from: Rgba<u8>, to: Rgba<u8>, amount: f32) -> Rgba<u8> {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+    Rgba([
+        mix(from[0], to[0]),
+        mix(from[1], to[1]),
+        mix(from[2], to[2]),
+        255,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(SchemeFormat::Alacritty, "background = \"#1f241f\"")]
+    #[case(SchemeFormat::Kitty, "selection_background #575b57")]
+    #[case(SchemeFormat::Xresources, "*.foreground: #ffffff")]
+    fn render_scheme_ok(#[case] format: SchemeFormat, #[case] expected_line: &str) {
+        let bg = Rgba([0x1F, 0x24, 0x1F, 0xFF]);
+        let fg = Rgba([0xFF, 0xFF, 0xFF, 0xFF]);
+        let output = render_scheme(format, bg, fg);
+        assert!(output.lines().any(|line| line == expected_line), "{output}");
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use serde::Deserialize;
+
+use crate::cache::CacheConfig;
+use crate::dirs::{config_dir, DirOverrides};
+use crate::download::DownloadConfig;
+use crate::hooks::HooksConfig;
+use crate::net::NetworkConfig;
+use crate::quality::InteractiveConfig;
+use crate::schedule::{CalendarRule, DayNightConfig};
+use crate::share::ShareConfig;
+use crate::workspace::WorkspacesConfig;
+use crate::XkcdError;
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// User configuration, read from `config.toml` in the platform config directory
+///
+/// ```toml
+/// [paths]
+/// cache_dir = "/var/cache/xkcd-wallpaper"
+/// state_dir = "/var/lib/xkcd-wallpaper"
+/// ```
+///
+/// See [`DayNightConfig`] for the `[daynight]` section, [`CalendarRule`] for `[[calendar]]`
+/// [`ShareConfig`] for `[share]`, [`NetworkConfig`] for `[network]`, [`HooksConfig`]
+/// for `[hooks]`, [`DownloadConfig`] for `[download]`, [`CacheConfig`] for `[cache]`,
+/// [`WorkspacesConfig`] for `[workspaces]` and [`InteractiveConfig`] for `[interactive]`.
+pub struct Config {
+    pub paths: DirOverrides,
+    pub daynight: DayNightConfig,
+    pub calendar: Vec<CalendarRule>,
+    pub share: ShareConfig,
+    pub network: NetworkConfig,
+    pub hooks: HooksConfig,
+    pub download: DownloadConfig,
+    pub cache: CacheConfig,
+    pub workspaces: WorkspacesConfig,
+    pub interactive: InteractiveConfig,
+}
+
+impl Config {
+    /// Default location of the configuration file
+    pub fn default_path() -> PathBuf {
+        config_dir().join("config.toml")
+    }
+
+    /// Load configuration from `path`, a missing file results in the default configuration
+    pub fn load(path: &Path) -> Result<Config, XkcdError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                info!("no config file at {}, using defaults", path.display());
+                return Ok(Config::default());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        info!("loading config from {}", path.display());
+        Config::parse(&contents)
+    }
+
+    fn parse(contents: &str) -> Result<Config, XkcdError> {
+        toml_edit::de::from_str(contents).map_err(|err| XkcdError::Config(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("", Config::default())]
+    #[case(
+        "[paths]\ncache_dir = \"/tmp/cache\"",
+        Config { paths: DirOverrides { cache_dir: Some("/tmp/cache".into()), ..Default::default() }, ..Default::default() }
+    )]
+    #[case(
+        "[paths]\ntemp_dir = \"/tmp/xkcd\"",
+        Config { paths: DirOverrides { temp_dir: Some("/tmp/xkcd".into()), ..Default::default() }, ..Default::default() }
+    )]
+    #[case(
+        "[network]\ninsecure = true",
+        Config { network: NetworkConfig { insecure: true, ..Default::default() }, ..Default::default() }
+    )]
+    #[case(
+        "[network]\nretries = 5\nretry_delay_ms = 250",
+        Config { network: NetworkConfig { retries: Some(5), retry_delay_ms: Some(250), ..Default::default() }, ..Default::default() }
+    )]
+    #[case(
+        "[download]\nlarge = \"never\"",
+        Config { download: DownloadConfig { large: crate::download::LargeImages::Never }, ..Default::default() }
+    )]
+    #[case(
+        "[cache]\nmax_size = \"1G\"",
+        Config { cache: CacheConfig { max_size: Some(crate::cache::ByteSize(1 << 30)), ..Default::default() }, ..Default::default() }
+    )]
+    #[case(
+        "[interactive]\ncomics = [1608]\naction = \"fallback\"\nfallbacks = { 1608 = \"https://example.com/a.png\" }",
+        Config { interactive: InteractiveConfig { comics: Some(vec![1608]), action: crate::quality::InteractiveAction::Fallback, fallbacks: [("1608".to_string(), "https://example.com/a.png".to_string())].into() }, ..Default::default() }
+    )]
+    #[case(
+        "[workspaces]\ncount = 3",
+        Config { workspaces: WorkspacesConfig { count: Some(3), ..Default::default() }, ..Default::default() }
+    )]
+    fn config_parse_ok(#[case] input: &str, #[case] expected: Config) {
+        assert_eq!(Config::parse(input).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("[paths]\ncache = \"/tmp\"")]
+    #[case("paths = 3")]
+    #[case("[cache]\nmax_size = \"lots\"")]
+    fn config_parse_error(#[case] input: &str) {
+        assert!(Config::parse(input).is_err());
+    }
+}
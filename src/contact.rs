@@ -0,0 +1,161 @@
+use std::path::Path;
+
+use image::imageops::overlay;
+use image::{Rgba, RgbaImage};
+use log::{info, warn};
+
+use crate::XkcdError;
+
+const BACKGROUND: Rgba<u8> = Rgba([0x20, 0x20, 0x20, 0xFF]);
+const LABEL_COLOR: Rgba<u8> = Rgba([0xEE, 0xEE, 0xEE, 0xFF]);
+/// Space between and around thumbnails, in pixels
+const GAP: u32 = 8;
+/// Size of a font pixel of the labels
+const LABEL_SCALE: u32 = 3;
+
+/// 3x5 bitmap glyphs for `0`-`9`, one row per byte with the leftmost pixel in bit 2
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+#[derive(Clone, Debug, PartialEq)]
+/// Layout of a contact sheet
+pub struct ContactSheet {
+    pub columns: u32,
+    /// Width of each thumbnail, the height follows a 16:9 cell
+    pub thumbnail_width: u32,
+}
+
+impl Default for ContactSheet {
+    fn default() -> Self {
+        ContactSheet {
+            columns: 6,
+            thumbnail_width: 320,
+        }
+    }
+}
+
+impl ContactSheet {
+    /// Compose thumbnails of the `(comic number, wallpaper)` pairs into a single image
+    ///
+    /// Wallpapers that cannot be read are left out with a warning.
+    pub fn compose(&self, wallpapers: &[(u64, &Path)]) -> Result<RgbaImage, XkcdError> {
+        let columns = self.columns.max(1);
+        let cell_width = self.thumbnail_width.max(1);
+        let cell_height = cell_width * 9 / 16;
+        let label_height = 5 * LABEL_SCALE + GAP;
+
+        let thumbnails: Vec<_> = wallpapers
+            .iter()
+            .filter_map(|(num, path)| match image::open(path) {
+                Ok(img) => Some((*num, img.thumbnail(cell_width, cell_height).into_rgba8())),
+                Err(err) => {
+                    warn!("skipping {}: {}", path.display(), err);
+                    None
+                }
+            })
+            .collect();
+        if thumbnails.is_empty() {
+            return Err(XkcdError::Other("no wallpapers to compose".to_string()));
+        }
+
+        let rows = (thumbnails.len() as u32).div_ceil(columns);
+        let used_columns = columns.min(thumbnails.len() as u32);
+        info!(
+            "composing contact sheet of {} wallpapers in {}x{} grid",
+            thumbnails.len(),
+            used_columns,
+            rows
+        );
+        let mut sheet = RgbaImage::from_pixel(
+            GAP + used_columns * (cell_width + GAP),
+            GAP + rows * (cell_height + label_height + GAP),
+            BACKGROUND,
+        );
+
+        for (index, (num, thumbnail)) in thumbnails.iter().enumerate() {
+            let (column, row) = (index as u32 % columns, index as u32 / columns);
+            let x = GAP + column * (cell_width + GAP);
+            let y = GAP + row * (cell_height + label_height + GAP);
+            overlay(
+                &mut sheet,
+                thumbnail,
+                (x + (cell_width - thumbnail.width()) / 2) as i64,
+                (y + (cell_height - thumbnail.height()) / 2) as i64,
+            );
+            draw_number(&mut sheet, x, y + cell_height + GAP, *num);
+        }
+        Ok(sheet)
+    }
+}
+
+/// Draw `num` with the bitmap font, top left corner at `x`, `y`
+fn draw_number(img: &mut RgbaImage, x: u32, y: u32, num: u64) {
+    for (index, digit) in num.to_string().bytes().enumerate() {
+        let glyph = DIGITS[(digit - b'0') as usize];
+        let glyph_x = x + index as u32 * 4 * LABEL_SCALE;
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..LABEL_SCALE {
+                    for dx in 0..LABEL_SCALE {
+                        let (px, py) = (
+                            glyph_x + column * LABEL_SCALE + dx,
+                            y + row as u32 * LABEL_SCALE + dy,
+                        );
+                        if px < img.width() && py < img.height() {
+                            img.put_pixel(px, py, LABEL_COLOR);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(3, 3, 3 * 328 + 8)]
+    #[case(2, 3, 2 * 328 + 8)]
+    #[case(5, 1, 328 + 8)]
+    fn compose_grid(#[case] count: usize, #[case] columns: u32, #[case] width: u32) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallpaper.png");
+        RgbaImage::new(640, 360).save(&path).unwrap();
+        let wallpapers: Vec<_> = (0..count as u64).map(|num| (num, path.as_path())).collect();
+
+        let sheet = ContactSheet {
+            columns,
+            thumbnail_width: 320,
+        }
+        .compose(&wallpapers)
+        .unwrap();
+        let rows = (count as u32).div_ceil(columns);
+        assert_eq!(sheet.dimensions(), (width, 8 + rows * (180 + 23 + 8)));
+    }
+
+    #[test]
+    fn draw_number_pixels() {
+        let mut img = RgbaImage::from_pixel(40, 20, BACKGROUND);
+        draw_number(&mut img, 0, 0, 17);
+        // Top of the 1 is a single pixel in the middle, the 7 starts with a full row
+        assert_eq!(*img.get_pixel(0, 0), BACKGROUND);
+        assert_eq!(*img.get_pixel(LABEL_SCALE, 0), LABEL_COLOR);
+        assert_eq!(*img.get_pixel(4 * LABEL_SCALE, 0), LABEL_COLOR);
+    }
+}
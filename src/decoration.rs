@@ -0,0 +1,572 @@
+use std::fmt;
+use std::str::FromStr;
+
+use image::imageops;
+use image::{Rgba, RgbaImage};
+
+use crate::layout::Length;
+use crate::text::{TextPosition, TextStyle};
+use crate::theme::parse_hex_color;
+use crate::{Metadata, ScreenDimensions};
+
+/// An image drawn relative to the comic, with the offset of its top left corner from the comic's
+pub(crate) type Layer = (RgbaImage, i64, i64);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// Ornaments drawn around the comic once it is placed on the screen
+pub struct Decoration {
+    pub shadow: Option<Shadow>,
+    pub border: Option<Border>,
+    pub card: Option<Card>,
+    /// Comic title drawn next to it
+    pub title: Option<(TextPosition, TextStyle)>,
+    /// Alt text wrapped at a width and drawn below the comic
+    pub alt: Option<(Length, TextStyle)>,
+}
+
+/// Text going above or below the comic
+pub(crate) struct Caption {
+    position: TextPosition,
+    img: RgbaImage,
+    /// Space between the caption and what is closer to the comic
+    spacing: u32,
+}
+
+impl Decoration {
+    /// Color replacing the comic's own background instead of the wallpaper's, that of the card
+    pub fn fill(&self) -> Option<Rgba<u8>> {
+        self.card.map(|card| card.color)
+    }
+
+    /// Distance from the comic to the outside of the card and border around it
+    pub(crate) fn extent(&self) -> u32 {
+        self.card.map_or(0, |card| card.padding) + self.border.map_or(0, |border| border.extent())
+    }
+
+    /// Title and alt text of the comic described by `metadata`, in `ink` unless they have
+    /// their own color
+    pub(crate) fn captions(
+        &self,
+        metadata: &Metadata,
+        ink: Rgba<u8>,
+        screen_dimensions: &ScreenDimensions,
+    ) -> Vec<Caption> {
+        let mut captions = Vec::new();
+        if let Some((position, style)) = &self.title {
+            captions.push(Caption {
+                position: *position,
+                img: style.render(&metadata.safe_title, ink),
+                spacing: style.line_height() / 2,
+            });
+        }
+        if let Some((max_width, style)) = &self.alt {
+            let max_width = max_width.resolve(screen_dimensions.width).max(1) as u32;
+            let lines = style.wrap(&metadata.alt, max_width);
+            if !lines.is_empty() {
+                captions.push(Caption {
+                    position: TextPosition::Below,
+                    img: style.render_lines(&lines, ink),
+                    spacing: style.line_height() / 2,
+                });
+            }
+        }
+        captions
+    }
+
+    /// Room taken by `captions` above and below the comic
+    pub(crate) fn reserved(&self, captions: &[Caption]) -> (u32, u32) {
+        let side = |position: TextPosition| {
+            captions
+                .iter()
+                .filter(|caption| caption.position == position)
+                .map(|caption| caption.spacing + caption.img.height())
+                .reduce(|total, room| total + room)
+                .map_or(0, |room| room + self.extent())
+        };
+        (side(TextPosition::Above), side(TextPosition::Below))
+    }
+
+    /// Layers of `captions` stacked above and below a `width` x `height` comic, the first
+    /// captions closest to it
+    pub(crate) fn over(&self, captions: Vec<Caption>, width: u32, height: u32) -> Vec<Layer> {
+        let extent = self.extent() as i64;
+        let (mut above, mut below) = (-extent, height as i64 + extent);
+        let mut layers = Vec::new();
+        for caption in captions {
+            let (spacing, text_height) = (caption.spacing as i64, caption.img.height() as i64);
+            let y = match caption.position {
+                TextPosition::Above => {
+                    above -= spacing + text_height;
+                    above
+                }
+                TextPosition::Below => {
+                    below += spacing + text_height;
+                    below - text_height
+                }
+            };
+            let x = (width as i64 - caption.img.width() as i64) / 2;
+            layers.push((caption.img, x, y));
+        }
+        layers
+    }
+
+    /// Layers drawn under a `width` x `height` comic, bottom first
+    pub(crate) fn under(&self, width: u32, height: u32) -> Vec<Layer> {
+        let mut layers = Vec::new();
+        let padding = self.card.map_or(0, |card| card.padding);
+        let (card_width, card_height) = (width + 2 * padding, height + 2 * padding);
+        // The shadow is cast by whatever surrounds the comic
+        let extent = self.extent();
+        let (outer_width, outer_height) = (width + 2 * extent, height + 2 * extent);
+        let corner_radius = match (self.card, self.border) {
+            (Some(card), None) => card.radius,
+            _ => 0.0,
+        };
+        if let Some(shadow) = self.shadow {
+            let (layer, dx, dy) = shadow.render(outer_width, outer_height, corner_radius);
+            layers.push((layer, dx - extent as i64, dy - extent as i64));
+        }
+        if let Some(card) = self.card {
+            let layer = rounded_rectangle(card_width, card_height, card.radius, card.color);
+            layers.push((layer, -(padding as i64), -(padding as i64)));
+        }
+        if let Some(border) = self.border {
+            let (layer, dx, dy) = border.render(card_width, card_height);
+            layers.push((layer, dx - padding as i64, dy - padding as i64));
+        }
+        layers
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Soft black shadow cast by the comic's panel
+pub struct Shadow {
+    /// Blur radius in pixels
+    pub radius: f32,
+    pub offset_x: i64,
+    pub offset_y: i64,
+    /// Opacity from 0 to 1 of the shadow right under the panel
+    pub opacity: f32,
+}
+
+impl Default for Shadow {
+    fn default() -> Self {
+        Shadow {
+            radius: 16.0,
+            offset_x: 0,
+            offset_y: 8,
+            opacity: 0.5,
+        }
+    }
+}
+
+impl Shadow {
+    /// Shadow of a `width` x `height` panel with corners rounded by `corner_radius`
+    fn render(&self, width: u32, height: u32, corner_radius: f32) -> Layer {
+        // A Gaussian is negligible beyond three times its radius
+        let spread = (self.radius * 3.0).ceil() as u32;
+        let alpha = (self.opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let panel = rounded_rectangle(width, height, corner_radius, Rgba([0, 0, 0, alpha]));
+        let mut shadow = RgbaImage::new(width + 2 * spread, height + 2 * spread);
+        imageops::replace(&mut shadow, &panel, spread as i64, spread as i64);
+        if self.radius > 0.0 {
+            shadow = imageops::fast_blur(&shadow, self.radius);
+        }
+        // Nothing of the shadow shows through the panel, whatever its background
+        let (left, top) = (spread as i64 - self.offset_x, spread as i64 - self.offset_y);
+        for (x, y, pixel) in shadow.enumerate_pixels_mut() {
+            let (x, y) = (x as i64 - left, y as i64 - top);
+            if (0..width as i64).contains(&x) && (0..height as i64).contains(&y) {
+                let covered = panel.get_pixel(x as u32, y as u32)[3] as f32 / alpha.max(1) as f32;
+                pixel[3] = (pixel[3] as f32 * (1.0 - covered)).round() as u8;
+            }
+        }
+        (
+            shadow,
+            self.offset_x - spread as i64,
+            self.offset_y - spread as i64,
+        )
+    }
+}
+
+impl FromStr for Shadow {
+    type Err = String;
+
+    /// Parse `RADIUS[,X,Y[,OPACITY]]`, missing values are the defaults
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("Invalid shadow '{s}', expected RADIUS[,X,Y[,OPACITY]]");
+        let values = s.split(',').map(str::trim).collect::<Vec<_>>();
+        let mut shadow = Shadow::default();
+        match values[..] {
+            [radius] => shadow.radius = radius.parse().map_err(|_| error())?,
+            [radius, x, y] | [radius, x, y, _] => {
+                shadow.radius = radius.parse().map_err(|_| error())?;
+                shadow.offset_x = x.parse().map_err(|_| error())?;
+                shadow.offset_y = y.parse().map_err(|_| error())?;
+            }
+            _ => return Err(error()),
+        }
+        if let [_, _, _, opacity] = values[..] {
+            shadow.opacity = opacity.parse().map_err(|_| error())?;
+        }
+        if shadow.radius < 0.0 || !(0.0..=1.0).contains(&shadow.opacity) {
+            return Err(error());
+        }
+        Ok(shadow)
+    }
+}
+
+impl fmt::Display for Shadow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.radius, self.offset_x, self.offset_y, self.opacity
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Frame around the comic, `padding` pixels away from it
+pub struct Border {
+    pub width: u32,
+    pub color: Rgba<u8>,
+    pub padding: u32,
+}
+
+impl Border {
+    /// Distance from the comic to the outside of the frame
+    fn extent(&self) -> u32 {
+        self.width + self.padding
+    }
+
+    /// Frame around a `width` x `height` comic, transparent inside
+    fn render(&self, width: u32, height: u32) -> Layer {
+        let extent = self.extent();
+        let (outer_width, outer_height) = (width + 2 * extent, height + 2 * extent);
+        let frame = RgbaImage::from_fn(outer_width, outer_height, |x, y| {
+            let inside = (self.width..outer_width - self.width).contains(&x)
+                && (self.width..outer_height - self.width).contains(&y);
+            match inside {
+                true => Rgba([0, 0, 0, 0]),
+                false => self.color,
+            }
+        });
+        (frame, -(extent as i64), -(extent as i64))
+    }
+}
+
+impl FromStr for Border {
+    type Err = String;
+
+    /// Parse `WIDTH:COLOR[:PADDING]`, such as `4px:#FFFFFF:12px`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("Invalid border '{s}', expected WIDTH:#RRGGBB[:PADDING]");
+        let pixels = |value: &str| value.trim_end_matches("px").parse::<u32>();
+        let (width, color, padding) = match s.split(':').collect::<Vec<_>>()[..] {
+            [width, color] => (width, color, "0"),
+            [width, color, padding] => (width, color, padding),
+            _ => return Err(error()),
+        };
+        Ok(Border {
+            width: pixels(width).map_err(|_| error())?,
+            color: parse_hex_color(color)?,
+            padding: pixels(padding).map_err(|_| error())?,
+        })
+    }
+}
+
+impl fmt::Display for Border {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [r, g, b, _] = self.color.0;
+        write!(
+            f,
+            "{}px:#{:02X}{:02X}{:02X}:{}px",
+            self.width, r, g, b, self.padding
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Rounded rectangle the comic sits on, `padding` pixels larger than it on every side
+pub struct Card {
+    pub color: Rgba<u8>,
+    pub radius: f32,
+    pub padding: u32,
+}
+
+impl FromStr for Card {
+    type Err = String;
+
+    /// Parse `COLOR[:RADIUS[:PADDING]]`, such as `#2E3440:24px:48px`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("Invalid card '{s}', expected #RRGGBB[:RADIUS[:PADDING]]");
+        let values = s.split(':').collect::<Vec<_>>();
+        let (color, radius, padding) = match values[..] {
+            [color] => (color, "16", "32"),
+            [color, radius] => (color, radius, "32"),
+            [color, radius, padding] => (color, radius, padding),
+            _ => return Err(error()),
+        };
+        let radius = radius
+            .trim_end_matches("px")
+            .parse::<f32>()
+            .map_err(|_| error())?;
+        if radius < 0.0 {
+            return Err(error());
+        }
+        Ok(Card {
+            color: parse_hex_color(color)?,
+            radius,
+            padding: padding
+                .trim_end_matches("px")
+                .parse()
+                .map_err(|_| error())?,
+        })
+    }
+}
+
+impl fmt::Display for Card {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [r, g, b, _] = self.color.0;
+        write!(
+            f,
+            "#{:02X}{:02X}{:02X}:{}px:{}px",
+            r, g, b, self.radius, self.padding
+        )
+    }
+}
+
+/// `width` x `height` rectangle of `color` with anti-aliased corners rounded by `radius`
+pub(crate) fn rounded_rectangle(
+    width: u32,
+    height: u32,
+    radius: f32,
+    color: Rgba<u8>,
+) -> RgbaImage {
+    let radius = radius.min(width as f32 / 2.0).min(height as f32 / 2.0);
+    RgbaImage::from_fn(width, height, |x, y| {
+        // Distance from the pixel's center to the rectangle the corner circles are centered on
+        let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+        let dx = px - px.clamp(radius, width as f32 - radius);
+        let dy = py - py.clamp(radius, height as f32 - radius);
+        let distance = (dx * dx + dy * dy).sqrt();
+        let coverage = match distance > 0.0 {
+            true => (radius - distance + 0.5).clamp(0.0, 1.0),
+            false => 1.0,
+        };
+        let mut pixel = color;
+        pixel[3] = (color[3] as f32 * coverage).round() as u8;
+        pixel
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("24", Shadow { radius: 24.0, ..Default::default() })]
+    #[case("8,4,-4", Shadow { radius: 8.0, offset_x: 4, offset_y: -4, opacity: 0.5 })]
+    #[case("0,10,10,1", Shadow { radius: 0.0, offset_x: 10, offset_y: 10, opacity: 1.0 })]
+    fn shadow_parse(#[case] input: &str, #[case] expected: Shadow) {
+        assert_eq!(input.parse(), Ok(expected));
+        assert_eq!(expected.to_string().parse(), Ok(expected));
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("8,4")]
+    #[case("8,4,4,2")]
+    #[case("-1")]
+    fn shadow_parse_error(#[case] input: &str) {
+        assert!(input.parse::<Shadow>().is_err());
+    }
+
+    #[test]
+    fn shadow_fades_out() {
+        let shadow = Shadow {
+            radius: 4.0,
+            offset_x: 0,
+            offset_y: 6,
+            opacity: 0.5,
+        };
+        let (layer, dx, dy) = shadow.render(40, 30, 0.0);
+        assert_eq!(layer.dimensions(), (64, 54));
+        assert_eq!((dx, dy), (-12, -6));
+        // Showing below the panel, fading out towards the edges of the layer
+        let below = layer.get_pixel(32, 38)[3];
+        assert!(0 < below && below <= 128);
+        assert!(layer.get_pixel(32, 44)[3] < below);
+        assert_eq!(layer.get_pixel(0, 0)[3], 0);
+        // Cleared under the panel itself
+        assert_eq!(layer.get_pixel(32, 27)[3], 0);
+    }
+
+    #[rstest]
+    #[case("4px:#FFFFFF", 4, 0)]
+    #[case("4:#FFFFFF:12px", 4, 12)]
+    fn border_parse(#[case] input: &str, #[case] width: u32, #[case] padding: u32) {
+        let border = Border {
+            width,
+            color: Rgba([255, 255, 255, 255]),
+            padding,
+        };
+        assert_eq!(input.parse(), Ok(border));
+        assert_eq!(border.to_string().parse(), Ok(border));
+    }
+
+    #[rstest]
+    #[case("4px")]
+    #[case("4px:white")]
+    #[case("4em:#FFFFFF")]
+    #[case("4px:#FFFFFF:1:2")]
+    fn border_parse_error(#[case] input: &str) {
+        assert!(input.parse::<Border>().is_err());
+    }
+
+    #[test]
+    fn border_frames_padding() {
+        let color = Rgba([255, 0, 0, 255]);
+        let border = Border {
+            width: 2,
+            color,
+            padding: 3,
+        };
+        let (frame, dx, dy) = border.render(10, 6);
+        assert_eq!(frame.dimensions(), (20, 16));
+        assert_eq!((dx, dy), (-5, -5));
+        assert_eq!(frame.get_pixel(1, 8), &color);
+        assert_eq!(frame.get_pixel(2, 8)[3], 0);
+        assert_eq!(frame.get_pixel(10, 15), &color);
+    }
+
+    #[test]
+    fn shadow_follows_border() {
+        let decoration = Decoration {
+            shadow: Some(Shadow {
+                radius: 0.0,
+                offset_x: 0,
+                offset_y: 4,
+                opacity: 1.0,
+            }),
+            border: Some(Border {
+                width: 1,
+                color: Rgba([255, 255, 255, 255]),
+                padding: 1,
+            }),
+            ..Default::default()
+        };
+        let layers = decoration.under(10, 10);
+        let (shadow, dx, dy) = &layers[0];
+        assert_eq!(shadow.dimensions(), (14, 14));
+        assert_eq!((*dx, *dy), (-2, 2));
+    }
+
+    #[rstest]
+    #[case("#2E3440", 16.0, 32)]
+    #[case("#2E3440:24px", 24.0, 32)]
+    #[case("#2E3440:0:8px", 0.0, 8)]
+    fn card_parse(#[case] input: &str, #[case] radius: f32, #[case] padding: u32) {
+        let card = Card {
+            color: Rgba([0x2E, 0x34, 0x40, 255]),
+            radius,
+            padding,
+        };
+        assert_eq!(input.parse(), Ok(card));
+        assert_eq!(card.to_string().parse(), Ok(card));
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("#2E3440:-4")]
+    #[case("#2E3440:4:4:4")]
+    fn card_parse_error(#[case] input: &str) {
+        assert!(input.parse::<Card>().is_err());
+    }
+
+    #[test]
+    fn card_rounds_corners() {
+        let color = Rgba([0x2E, 0x34, 0x40, 255]);
+        let decoration = Decoration {
+            card: Some(Card {
+                color,
+                radius: 8.0,
+                padding: 10,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(decoration.fill(), Some(color));
+        let layers = decoration.under(40, 20);
+        let (card, dx, dy) = &layers[0];
+        assert_eq!(card.dimensions(), (60, 40));
+        assert_eq!((*dx, *dy), (-10, -10));
+        assert_eq!(card.get_pixel(0, 0)[3], 0);
+        assert_eq!(card.get_pixel(30, 0), &color);
+        assert_eq!(card.get_pixel(30, 20), &color);
+        // Partly covered pixels along the curve
+        let edge = card.get_pixel(2, 2)[3];
+        assert!(0 < edge && edge < 255);
+    }
+
+    #[rstest]
+    #[case(TextPosition::Above, -28)]
+    #[case(TextPosition::Below, 62)]
+    fn title_beside_card(#[case] position: TextPosition, #[case] y: i64) {
+        let decoration = Decoration {
+            card: Some(Card {
+                color: Rgba([0, 0, 0, 255]),
+                radius: 0.0,
+                padding: 4,
+            }),
+            title: Some((
+                position,
+                TextStyle {
+                    size: 16,
+                    ..Default::default()
+                },
+            )),
+            ..Default::default()
+        };
+        let metadata = Metadata {
+            safe_title: "Pi".to_string(),
+            ..Default::default()
+        };
+        let captions = decoration.captions(&metadata, Rgba([255, 255, 255, 255]), &SCREEN);
+        let layers = decoration.over(captions, 50, 50);
+        let (text, x, text_y) = &layers[0];
+        assert_eq!(text.dimensions(), (22, 16));
+        assert_eq!((*x, *text_y), (14, y));
+    }
+
+    #[test]
+    fn alt_text_below_title() {
+        let style = TextStyle {
+            size: 8,
+            ..Default::default()
+        };
+        let decoration = Decoration {
+            title: Some((TextPosition::Below, style.clone())),
+            alt: Some((Length::Pixels(29), style)),
+            ..Default::default()
+        };
+        let metadata = Metadata {
+            safe_title: "Pi".to_string(),
+            alt: "make me a".to_string(),
+            ..Default::default()
+        };
+        let captions = decoration.captions(&metadata, Rgba([255, 255, 255, 255]), &SCREEN);
+        // Title and two lines of alt text, each half a line away from the previous
+        assert_eq!(decoration.reserved(&captions), (0, 4 + 8 + 4 + 18));
+        let layers = decoration.over(captions, 40, 30);
+        assert_eq!((layers[0].1, layers[0].2), (14, 34));
+        assert_eq!((layers[1].1, layers[1].2), (8, 46));
+        assert_eq!(layers[1].0.dimensions(), (23, 18));
+    }
+
+    const SCREEN: ScreenDimensions = ScreenDimensions {
+        width: 200,
+        height: 100,
+    };
+}
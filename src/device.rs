@@ -0,0 +1,82 @@
+use crate::ScreenDimensions;
+
+#[derive(Clone, Debug, PartialEq)]
+/// Output settings for e-readers and other grayscale devices
+pub struct DeviceProfile {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub width: u32,
+    pub height: u32,
+    /// Number of gray levels the panel can display
+    pub gray_levels: u8,
+    /// Whether the output is dithered down to `gray_levels`
+    pub dither: bool,
+    /// Whether landscape comics are rotated to make better use of the portrait screen
+    pub rotate_landscape: bool,
+    /// Default output path, following the device's screensaver conventions
+    pub output: &'static str,
+}
+
+/// Built-in device profiles
+pub const DEVICES: &[DeviceProfile] = &[
+    DeviceProfile {
+        name: "kindle-paperwhite",
+        description: "Kindle Paperwhite (5th gen and later), needs the linkss screensaver hack",
+        width: 1236,
+        height: 1648,
+        gray_levels: 16,
+        dither: true,
+        rotate_landscape: true,
+        output: "./linkss/screensavers/bg_xkcd_%n.png",
+    },
+    DeviceProfile {
+        name: "kobo-clara",
+        description: "Kobo Clara HD and Clara 2E",
+        width: 1072,
+        height: 1448,
+        gray_levels: 16,
+        dither: true,
+        rotate_landscape: true,
+        output: "./.kobo/screensaver/xkcd_%n.png",
+    },
+    DeviceProfile {
+        name: "remarkable",
+        description: "reMarkable 1 and 2 sleep screen",
+        width: 1404,
+        height: 1872,
+        gray_levels: 16,
+        dither: true,
+        rotate_landscape: false,
+        output: "./suspended.png",
+    },
+];
+
+impl DeviceProfile {
+    /// Look up a built-in device profile by name
+    pub fn by_name(name: &str) -> Option<&'static DeviceProfile> {
+        DEVICES
+            .iter()
+            .find(|device| device.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn screen_dimensions(&self) -> ScreenDimensions {
+        ScreenDimensions {
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("kindle-paperwhite", true)]
+    #[case("REMARKABLE", true)]
+    #[case("nook", false)]
+    fn device_by_name(#[case] name: &str, #[case] found: bool) {
+        assert_eq!(DeviceProfile::by_name(name).is_some(), found);
+    }
+}
@@ -0,0 +1,102 @@
+use std::env;
+use std::path::PathBuf;
+
+use directories::{BaseDirs, ProjectDirs};
+use log::warn;
+use serde::Deserialize;
+
+const APP_NAME: &str = "xkcd-wallpaper";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// Optional overrides for the directories used by xkcd-wallpaper
+pub struct DirOverrides {
+    pub cache_dir: Option<PathBuf>,
+    pub state_dir: Option<PathBuf>,
+    /// Deprecated and ignored since comic images are downloaded in memory, still accepted so
+    /// existing configs keep loading
+    pub temp_dir: Option<PathBuf>,
+}
+
+impl DirOverrides {
+    /// Fill unset values with the ones from `other`
+    pub fn or(self, other: DirOverrides) -> DirOverrides {
+        DirOverrides {
+            cache_dir: self.cache_dir.or(other.cache_dir),
+            state_dir: self.state_dir.or(other.state_dir),
+            temp_dir: self.temp_dir.or(other.temp_dir),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Directories where cache and state files are written
+pub struct Dirs {
+    pub cache: PathBuf,
+    pub state: PathBuf,
+}
+
+impl Dirs {
+    /// Resolve directories, using platform defaults for anything not overridden
+    pub fn resolve(overrides: DirOverrides) -> Dirs {
+        if overrides.temp_dir.is_some() {
+            warn!("temp_dir is deprecated and has no effect, remove it from [paths]");
+        }
+        let project = project_dirs();
+        Dirs {
+            cache: overrides.cache_dir.unwrap_or_else(|| match &project {
+                Some(project) => project.cache_dir().to_path_buf(),
+                None => env::temp_dir().join(APP_NAME),
+            }),
+            // Only Linux has a state directory, elsewhere state goes with the local data
+            state: overrides.state_dir.unwrap_or_else(|| match &project {
+                Some(project) => project
+                    .state_dir()
+                    .unwrap_or(project.data_local_dir())
+                    .to_path_buf(),
+                None => env::temp_dir().join(APP_NAME),
+            }),
+        }
+    }
+}
+
+/// Directory holding the configuration file
+pub fn config_dir() -> PathBuf {
+    match project_dirs() {
+        Some(project) => project.config_dir().to_path_buf(),
+        None => env::temp_dir().join(APP_NAME),
+    }
+}
+
+pub(crate) fn home_dir() -> PathBuf {
+    BaseDirs::new()
+        .map(|dirs| dirs.home_dir().to_path_buf())
+        .unwrap_or_else(env::temp_dir)
+}
+
+/// Platform directories of xkcd-wallpaper, `None` when there is no home directory
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", APP_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_take_precedence() {
+        let flags = DirOverrides {
+            cache_dir: Some("/flag/cache".into()),
+            ..Default::default()
+        };
+        let config = DirOverrides {
+            cache_dir: Some("/config/cache".into()),
+            state_dir: Some("/config/state".into()),
+            ..Default::default()
+        };
+
+        let dirs = Dirs::resolve(flags.or(config));
+        assert_eq!(dirs.cache, PathBuf::from("/flag/cache"));
+        assert_eq!(dirs.state, PathBuf::from("/config/state"));
+    }
+}
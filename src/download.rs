@@ -0,0 +1,226 @@
+use std::io::Read;
+
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::net::ComicFetcher;
+use crate::{Metadata, ScreenDimensions, XkcdError};
+
+/// Screens with at least this many pixels count as high resolution for `LargeImages::Auto`
+const HIGH_RES_PIXELS: u64 = 2560 * 1440;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// When to download the "large version" some comics link to instead of the regular image
+pub enum LargeImages {
+    /// Only for high resolution screens
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl LargeImages {
+    pub fn wanted(&self, screen_dimensions: &ScreenDimensions) -> bool {
+        match self {
+            LargeImages::Auto => {
+                screen_dimensions.width as u64 * screen_dimensions.height as u64 >= HIGH_RES_PIXELS
+            }
+            LargeImages::Always => true,
+            LargeImages::Never => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// Image download settings, the `[download]` section of the configuration file
+///
+/// ```toml
+/// [download]
+/// large = "always"
+/// ```
+pub struct DownloadConfig {
+    pub large: LargeImages,
+}
+
+/// URL of the large version of a comic, if it has one
+///
+/// The API's `link` field points to it for some comics, others only link it from the comic page.
+pub fn large_image_url(
+    fetcher: &dyn ComicFetcher,
+    metadata: &Metadata,
+) -> Result<Option<String>, XkcdError> {
+    if is_large_image(&metadata.link) {
+        return Ok(Some(absolute_url(&metadata.link)));
+    }
+
+    let page_url = format!("https://xkcd.com/{}/", metadata.num);
+    info!("looking for a large image on {}", page_url);
+    let mut page = String::new();
+    fetcher.get(&page_url)?.read_to_string(&mut page)?;
+    Ok(find_large_image(&page).map(|url| absolute_url(&url)))
+}
+
+/// URL of the 2x version of a comic image, if it has one
+///
+/// Taken from the `srcset` of the image on the comic page. When the page can't be fetched or
+/// has none, the URL is guessed from the regular image, which works for most PNG comics.
+pub fn scaled_image_url(fetcher: &dyn ComicFetcher, metadata: &Metadata) -> Option<String> {
+    let page_url = format!("https://xkcd.com/{}/", metadata.num);
+    info!("looking for a 2x image on {}", page_url);
+    let page = fetcher.get(&page_url).and_then(|mut body| {
+        let mut page = String::new();
+        body.read_to_string(&mut page)?;
+        Ok(page)
+    });
+    scaled_image_in(metadata, &page_url, page)
+}
+
+/// URL of the 2x image found in the comic `page` fetched from `page_url`, or guessed
+pub(crate) fn scaled_image_in(
+    metadata: &Metadata,
+    page_url: &str,
+    page: Result<String, XkcdError>,
+) -> Option<String> {
+    match page.map(|page| find_scaled_image(&page)) {
+        Ok(Some(url)) => Some(absolute_url(&url)),
+        Ok(None) => guess_scaled_url(&metadata.img),
+        Err(err) => {
+            warn!(
+                "cannot get comic page {} ({}), guessing 2x image",
+                page_url, err
+            );
+            guess_scaled_url(&metadata.img)
+        }
+    }
+}
+
+/// First comic image with a `2x` descriptor in a `srcset` of the HTML of a comic page
+fn find_scaled_image(html: &str) -> Option<String> {
+    html.split("srcset=\"")
+        .skip(1)
+        .filter_map(|rest| rest.split('"').next())
+        .flat_map(|srcset| srcset.split(','))
+        .find_map(
+            |candidate| match candidate.split_whitespace().collect::<Vec<_>>()[..] {
+                [url, "2x"] if url.contains("imgs.xkcd.com/comics/") => Some(url.to_string()),
+                _ => None,
+            },
+        )
+}
+
+/// 2x URL following the `name_2x.png` naming of xkcd, only PNG comics have those
+fn guess_scaled_url(url: &str) -> Option<String> {
+    let (stem, ext) = url.rsplit_once('.')?;
+    match ext.eq_ignore_ascii_case("png") && !stem.ends_with("_2x") && !stem.contains('?') {
+        true => Some(format!("{stem}_2x.{ext}")),
+        false => None,
+    }
+}
+
+fn is_large_image(url: &str) -> bool {
+    let url = url.to_lowercase();
+    url.contains("imgs.xkcd.com/comics/")
+        && url.contains("_large.")
+        && [".png", ".jpg", ".jpeg", ".gif"]
+            .iter()
+            .any(|ext| url.ends_with(ext))
+}
+
+/// First link to a large comic image in the HTML of a comic page
+fn find_large_image(html: &str) -> Option<String> {
+    html.split("href=\"")
+        .skip(1)
+        .filter_map(|rest| rest.split('"').next())
+        .find(|url| is_large_image(url))
+        .map(str::to_string)
+}
+
+/// Make protocol relative and plain http links absolute https URLs
+fn absolute_url(url: &str) -> String {
+    match url.strip_prefix("//") {
+        Some(rest) => format!("https://{rest}"),
+        None => url.replacen("http://", "https://", 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(
+        r#"<a href="//imgs.xkcd.com/comics/movie_narrative_charts_large.png">large version</a>"#,
+        Some("//imgs.xkcd.com/comics/movie_narrative_charts_large.png")
+    )]
+    #[case(
+        r#"<a href="https://xkcd.com/657/large/">x</a><a href="http://imgs.xkcd.com/comics/money_large.png">"#,
+        Some("http://imgs.xkcd.com/comics/money_large.png")
+    )]
+    #[case(r#"<img src="//imgs.xkcd.com/comics/standards.png">"#, None)]
+    fn find_large_image_in_page(#[case] html: &str, #[case] expected: Option<&str>) {
+        assert_eq!(find_large_image(html).as_deref(), expected);
+    }
+
+    #[rstest]
+    #[case(
+        r#"<img src="//imgs.xkcd.com/comics/standards.png" srcset="//imgs.xkcd.com/comics/standards_2x.png 2x"/>"#,
+        Some("//imgs.xkcd.com/comics/standards_2x.png")
+    )]
+    #[case(
+        r#"<img srcset="/s/logo.png 1x, /s/logo2x.png 2x"><img srcset="https://imgs.xkcd.com/comics/a.png 1x, https://imgs.xkcd.com/comics/a_hd.png 2x">"#,
+        Some("https://imgs.xkcd.com/comics/a_hd.png")
+    )]
+    #[case(r#"<img src="//imgs.xkcd.com/comics/sky.jpg">"#, None)]
+    fn find_scaled_image_in_page(#[case] html: &str, #[case] expected: Option<&str>) {
+        assert_eq!(find_scaled_image(html).as_deref(), expected);
+    }
+
+    #[rstest]
+    #[case(
+        "https://imgs.xkcd.com/comics/standards.png",
+        Some("https://imgs.xkcd.com/comics/standards_2x.png")
+    )]
+    #[case(
+        "https://imgs.xkcd.com/comics/a.png.b.PNG",
+        Some("https://imgs.xkcd.com/comics/a.png.b_2x.PNG")
+    )]
+    #[case("https://imgs.xkcd.com/comics/standards_2x.png", None)]
+    #[case("https://imgs.xkcd.com/comics/sky.jpg", None)]
+    #[case("https://imgs.xkcd.com/comics/flag.gif", None)]
+    fn guessed_scaled_url(#[case] url: &str, #[case] expected: Option<&str>) {
+        assert_eq!(guess_scaled_url(url).as_deref(), expected);
+    }
+
+    #[rstest]
+    #[case(
+        "//imgs.xkcd.com/comics/a_large.png",
+        "https://imgs.xkcd.com/comics/a_large.png"
+    )]
+    #[case(
+        "http://imgs.xkcd.com/comics/a_large.png",
+        "https://imgs.xkcd.com/comics/a_large.png"
+    )]
+    fn absolute_url_cases(#[case] url: &str, #[case] expected: &str) {
+        assert_eq!(absolute_url(url), expected);
+    }
+
+    #[rstest]
+    #[case(LargeImages::Auto, 1920, 1080, false)]
+    #[case(LargeImages::Auto, 3840, 2160, true)]
+    #[case(LargeImages::Always, 800, 600, true)]
+    #[case(LargeImages::Never, 7680, 4320, false)]
+    fn large_images_wanted(
+        #[case] setting: LargeImages,
+        #[case] width: u32,
+        #[case] height: u32,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(
+            setting.wanted(&ScreenDimensions { width, height }),
+            expected
+        );
+    }
+}
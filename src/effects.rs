@@ -0,0 +1,732 @@
+use std::fmt;
+use std::str::FromStr;
+
+use image::{DynamicImage, GrayImage, Luma, Rgba, RgbaImage};
+use log::info;
+
+use crate::timings;
+
+/// Luminance below which a pixel is considered ink when binarizing
+const INK_THRESHOLD: u8 = 128;
+
+/// Render an image in pure black and white with strokes thickened by `stroke_radius` pixels.
+///
+/// The input is expected to be a regular comic, i.e. dark drawings on a light background.
+pub fn high_contrast(img: &DynamicImage, stroke_radius: u32) -> DynamicImage {
+    info!("binarizing image with stroke radius {}", stroke_radius);
+    let _timer = timings::stage("transforms");
+    let mut ink = img.to_luma8();
+    for pixel in ink.pixels_mut() {
+        *pixel = if pixel.0[0] < INK_THRESHOLD {
+            Luma([255])
+        } else {
+            Luma([0])
+        };
+    }
+
+    // Dilation with a square structuring element is separable into a horizontal and a vertical pass
+    let ink = dilate_vertical(&dilate_horizontal(&ink, stroke_radius), stroke_radius);
+
+    let mut output = ink;
+    for pixel in output.pixels_mut() {
+        pixel.0[0] = 255 - pixel.0[0];
+    }
+    DynamicImage::ImageLuma8(output)
+}
+
+/// Convert an image to grayscale using Rec. 709 luminance weights
+pub fn grayscale(img: &DynamicImage) -> GrayImage {
+    let _timer = timings::stage("transforms");
+    img.to_luma8()
+}
+
+/// Convert an image to grayscale like [`grayscale`], keeping the alpha channel when it has
+/// transparent pixels
+pub fn desaturate(img: &DynamicImage) -> DynamicImage {
+    info!("converting image to grayscale");
+    let transparent =
+        img.color().has_alpha() && img.to_rgba8().pixels().any(|pixel| pixel[3] < 255);
+    match transparent {
+        true => {
+            let _timer = timings::stage("transforms");
+            DynamicImage::ImageLumaA8(img.to_luma_alpha8())
+        }
+        false => DynamicImage::ImageLuma8(grayscale(img)),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+/// How gray levels between those a display can show are approximated
+pub enum Dither {
+    /// Error diffusion to four neighbours, smooth gradients
+    #[default]
+    FloydSteinberg,
+    /// Error diffusion losing a quarter of the error, crisper line art with more contrast
+    Atkinson,
+    /// Fixed 8x8 Bayer threshold pattern, stable between similar images
+    Ordered,
+}
+
+/// Where Floyd-Steinberg dithering spreads the error: offset right, rows down and weight
+const FLOYD_STEINBERG: &[(isize, usize, f32)] = &[
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+
+/// Where Atkinson dithering spreads the error, the remaining quarter is dropped
+const ATKINSON: &[(isize, usize, f32)] = &[
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+/// Thresholds of ordered dithering, from 0 to 63
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Reduce a grayscale image to `levels` evenly spaced gray levels, dithered with `method`
+pub fn dither(img: &GrayImage, levels: u8, method: Dither) -> GrayImage {
+    info!(
+        "dithering image to {} gray levels with {:?}",
+        levels, method
+    );
+    let _timer = timings::stage("transforms");
+    let levels = levels.max(2) as f32;
+    let step = 255.0 / (levels - 1.0);
+    match method {
+        Dither::FloydSteinberg => diffuse(img, step, FLOYD_STEINBERG),
+        Dither::Atkinson => diffuse(img, step, ATKINSON),
+        Dither::Ordered => GrayImage::from_fn(img.width(), img.height(), |x, y| {
+            let scaled = img.get_pixel(x, y).0[0] as f32 / step;
+            let threshold = (BAYER[y as usize % 8][x as usize % 8] as f32 + 0.5) / 64.0;
+            let level = match scaled.fract() > threshold {
+                true => scaled.floor() + 1.0,
+                false => scaled.floor(),
+            };
+            Luma([(level * step).round().clamp(0.0, 255.0) as u8])
+        }),
+    }
+}
+
+/// Error diffusion to gray levels `step` apart, spreading the error as `kernel` says
+fn diffuse(img: &GrayImage, step: f32, kernel: &[(isize, usize, f32)]) -> GrayImage {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let mut values: Vec<f32> = img.pixels().map(|pixel| pixel.0[0] as f32).collect();
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * width + x;
+            let old = values[index];
+            let new = ((old / step).round() * step).clamp(0.0, 255.0);
+            values[index] = new;
+
+            let error = old - new;
+            for &(dx, dy, weight) in kernel {
+                let nx = x as isize + dx;
+                if nx >= 0 && (nx as usize) < width && y + dy < height {
+                    values[(y + dy) * width + nx as usize] += error * weight;
+                }
+            }
+        }
+    }
+
+    GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        Luma([values[y as usize * width + x as usize]
+            .round()
+            .clamp(0.0, 255.0) as u8])
+    })
+}
+
+/// Crop rows and columns at the edges that only have the color of the top left corner
+///
+/// Pixels within `tolerance` of that color in every channel count as the same color, so JPEG
+/// noise doesn't stop the trim. Images of a single color are returned as they are.
+pub fn trim(img: &DynamicImage, tolerance: u8) -> DynamicImage {
+    let _timer = timings::stage("transforms");
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    if width == 0 || height == 0 {
+        return img.clone();
+    }
+    let border = *rgba.get_pixel(0, 0);
+    let same = |x: u32, y: u32| {
+        rgba.get_pixel(x, y)
+            .0
+            .iter()
+            .zip(border.0)
+            .all(|(channel, border)| channel.abs_diff(border) <= tolerance)
+    };
+    let blank_row = |y: u32| (0..width).all(|x| same(x, y));
+    let blank_column = |x: u32, rows: &std::ops::Range<u32>| rows.clone().all(|y| same(x, y));
+
+    let Some(top) = (0..height).find(|&y| !blank_row(y)) else {
+        return img.clone();
+    };
+    let bottom = (top..height).rev().find(|&y| !blank_row(y)).unwrap_or(top) + 1;
+    let rows = top..bottom;
+    let left = (0..width).find(|&x| !blank_column(x, &rows)).unwrap_or(0);
+    let right = (left..width)
+        .rev()
+        .find(|&x| !blank_column(x, &rows))
+        .unwrap_or(left)
+        + 1;
+    if (left, top, right, bottom) == (0, 0, width, height) {
+        return img.clone();
+    }
+    info!(
+        "trimming {}x{} image to {}x{}",
+        width,
+        height,
+        right - left,
+        bottom - top
+    );
+    img.crop_imm(left, top, right - left, bottom - top)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Unsharp mask bringing back crisp lines after the comic is scaled up
+pub struct Sharpen {
+    /// How much of the difference with the blurred image is added back
+    pub amount: f32,
+    /// Sigma of the blur, in pixels, about the width of the edges that are sharpened
+    pub radius: f32,
+}
+
+impl Default for Sharpen {
+    fn default() -> Self {
+        Sharpen {
+            amount: 1.0,
+            radius: 1.0,
+        }
+    }
+}
+
+impl FromStr for Sharpen {
+    type Err = String;
+
+    /// Parse `AMOUNT[,RADIUS]`, the radius defaults to 1 pixel
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("Invalid sharpening '{s}', expected AMOUNT[,RADIUS] like 1,1.5");
+        let mut sharpen = Sharpen::default();
+        match s.split(',').map(str::trim).collect::<Vec<_>>()[..] {
+            [amount] => sharpen.amount = amount.parse().map_err(|_| error())?,
+            [amount, radius] => {
+                sharpen.amount = amount.parse().map_err(|_| error())?;
+                sharpen.radius = radius.parse().map_err(|_| error())?;
+            }
+            _ => return Err(error()),
+        }
+        match sharpen.amount >= 0.0 && sharpen.radius > 0.0 && sharpen.radius.is_finite() {
+            true => Ok(sharpen),
+            false => Err(error()),
+        }
+    }
+}
+
+impl fmt::Display for Sharpen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.amount, self.radius)
+    }
+}
+
+/// Sharpen the color channels of an image with an unsharp mask, leaving its alpha channel
+pub fn sharpen(img: &RgbaImage, sharpen: Sharpen) -> RgbaImage {
+    info!("sharpening image with {}", sharpen);
+    let _timer = timings::stage("transforms");
+    let blurred = image::imageops::blur(img, sharpen.radius);
+    let mut output = img.clone();
+    for (pixel, blurred) in output.pixels_mut().zip(blurred.pixels()) {
+        for channel in 0..3 {
+            let value = pixel[channel] as f32;
+            let sharpened = value + sharpen.amount * (value - blurred[channel] as f32);
+            pixel[channel] = sharpened.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    output
+}
+
+/// Add monochrome noise of up to `amount` (0 to 1) of the full range to every pixel
+///
+/// The same offset is added to the three channels so colors don't shift, and alpha is kept. The
+/// noise only depends on `seed` and the position, so a wallpaper is the same every time.
+pub fn grain(img: &mut RgbaImage, amount: f32, seed: u64) {
+    info!("adding grain of {}", amount);
+    let _timer = timings::stage("transforms");
+    for (index, pixel) in img.pixels_mut().enumerate() {
+        let hash = mix(seed ^ mix(index as u64));
+        // Sum of two uniform values, triangular noise from -1 to 1 like film grain
+        let uniform = |bits: u64| (bits & 0xFFFF) as f32 / 0xFFFF as f32;
+        let noise = (uniform(hash) + uniform(hash >> 16) - 1.0) * amount * 255.0;
+        for channel in 0..3 {
+            pixel[channel] = (pixel[channel] as f32 + noise).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Shading redrawn as dots growing with how dark it is, like newspaper print
+pub struct Halftone {
+    /// Distance between dots, in pixels of the comic
+    pub cell: u32,
+    /// Counterclockwise rotation of the grid of dots, in degrees
+    pub angle: f32,
+}
+
+impl Default for Halftone {
+    fn default() -> Self {
+        Halftone {
+            cell: 8,
+            angle: 45.0,
+        }
+    }
+}
+
+impl FromStr for Halftone {
+    type Err = String;
+
+    /// Parse `CELL[,ANGLE]`, the angle defaults to 45 degrees
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("Invalid halftone '{s}', expected CELL[,ANGLE] like 8,45");
+        let mut halftone = Halftone::default();
+        match s.split(',').map(str::trim).collect::<Vec<_>>()[..] {
+            [cell] => halftone.cell = cell.parse().map_err(|_| error())?,
+            [cell, angle] => {
+                halftone.cell = cell.parse().map_err(|_| error())?;
+                halftone.angle = angle.parse().map_err(|_| error())?;
+            }
+            _ => return Err(error()),
+        }
+        match halftone.cell >= 2 && halftone.angle.is_finite() {
+            true => Ok(halftone),
+            false => Err(error()),
+        }
+    }
+}
+
+impl fmt::Display for Halftone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.cell, self.angle)
+    }
+}
+
+/// Render an image in black and white, its grays turned into dots on a rotated grid
+///
+/// Each pixel is compared with a threshold growing from the center of its cell outwards, so a
+/// dot covers as much of the cell as the shading is dark. Black lines stay solid and white stays
+/// white, only the shading between them becomes dots.
+pub fn halftone(img: &DynamicImage, halftone: Halftone) -> DynamicImage {
+    info!("rendering halftone with {}", halftone);
+    let _timer = timings::stage("transforms");
+    let gray = img.to_luma8();
+    let cell = halftone.cell.max(1) as f32;
+    let (sin, cos) = halftone.angle.to_radians().sin_cos();
+    let output = GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+        let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+        // Position in the rotated cell, from -0.5 to 0.5 around its center
+        let u = (x * cos - y * sin) / cell;
+        let v = (x * sin + y * cos) / cell;
+        let (u, v) = (u - u.floor() - 0.5, v - v.floor() - 0.5);
+        let threshold = covered((u * u + v * v).sqrt());
+        let darkness = 1.0 - gray.get_pixel(x as u32, y as u32)[0] as f32 / 255.0;
+        match darkness > threshold {
+            true => Luma([0]),
+            false => Luma([255]),
+        }
+    });
+    DynamicImage::ImageLuma8(output)
+}
+
+/// Part of a unit square covered by a disk of `radius` at its center
+fn covered(radius: f32) -> f32 {
+    let squared = radius * radius;
+    if radius <= 0.5 {
+        return std::f32::consts::PI * squared;
+    }
+    if squared >= 0.5 {
+        return 1.0;
+    }
+    // The disk minus the four segments past the sides of the square
+    let segment = squared * (0.5 / radius).acos() - 0.5 * (squared - 0.25).sqrt();
+    std::f32::consts::PI * squared - 4.0 * segment
+}
+
+/// Distance from the center, as a part of that to the corners, where the vignette starts
+const VIGNETTE_START: f32 = 0.3;
+
+/// Darken the edges of an image, or lighten them when `paper` is light, by up to `strength`
+/// (0 to 1) in the corners
+///
+/// The vignette is an ellipse with the shape of the image, leaving the middle untouched and
+/// fading smoothly towards black or white. Alpha is kept.
+pub fn vignette(img: &mut RgbaImage, strength: f32, paper: Rgba<u8>) {
+    let [r, g, b, _] = paper.0;
+    // Rec. 601 luma, like ForegroundColor::is_light
+    let light = 299 * r as u32 + 587 * g as u32 + 114 * b as u32 >= 128 * 1000;
+    let target = match light {
+        true => 255.0,
+        false => 0.0,
+    };
+    info!(
+        "adding vignette of {} towards {}",
+        strength,
+        match light {
+            true => "white",
+            false => "black",
+        }
+    );
+    let _timer = timings::stage("transforms");
+    let (center_x, center_y) = (img.width() as f32 / 2.0, img.height() as f32 / 2.0);
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let dx = (x as f32 + 0.5 - center_x) / center_x;
+        let dy = (y as f32 + 0.5 - center_y) / center_y;
+        let distance = ((dx * dx + dy * dy) / 2.0).sqrt();
+        let t = ((distance - VIGNETTE_START) / (1.0 - VIGNETTE_START)).clamp(0.0, 1.0);
+        let weight = strength * t * t * (3.0 - 2.0 * t);
+        for channel in 0..3 {
+            let value = pixel[channel] as f32;
+            pixel[channel] = (value + (target - value) * weight).round() as u8;
+        }
+    }
+}
+
+/// SplitMix64 finalizer, a well spread hash of `value`
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn dilate_horizontal(img: &GrayImage, radius: u32) -> GrayImage {
+    GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        let start = x.saturating_sub(radius);
+        let end = (x + radius).min(img.width() - 1);
+        let max = (start..=end)
+            .map(|x| img.get_pixel(x, y).0[0])
+            .max()
+            .unwrap_or(0);
+        Luma([max])
+    })
+}
+
+fn dilate_vertical(img: &GrayImage, radius: u32) -> GrayImage {
+    GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        let start = y.saturating_sub(radius);
+        let end = (y + radius).min(img.height() - 1);
+        let max = (start..=end)
+            .map(|y| img.get_pixel(x, y).0[0])
+            .max()
+            .unwrap_or(0);
+        Luma([max])
+    })
+}
+
+/// Rotate an image counterclockwise by `degrees` around its center, on a canvas grown to hold it
+///
+/// Pixels are interpolated bilinearly, the corners left uncovered are transparent.
+pub fn rotate(img: &RgbaImage, degrees: f32) -> RgbaImage {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (width, height) = (img.width() as f32, img.height() as f32);
+    // Rounded first so right angles don't grow the canvas by a rounding error
+    let side = |length: f32| (((length * 1000.0).round() / 1000.0).ceil() as u32).max(1);
+    let rotated_width = side(width * cos.abs() + height * sin.abs());
+    let rotated_height = side(width * sin.abs() + height * cos.abs());
+    // Premultiplied so transparent pixels don't bleed their color into the edges
+    let sample = |x: i64, y: i64| match x >= 0
+        && y >= 0
+        && x < img.width() as i64
+        && y < img.height() as i64
+    {
+        true => {
+            let pixel = img.get_pixel(x as u32, y as u32);
+            let alpha = pixel[3] as f32 / 255.0;
+            [
+                pixel[0] as f32 * alpha,
+                pixel[1] as f32 * alpha,
+                pixel[2] as f32 * alpha,
+                pixel[3] as f32,
+            ]
+        }
+        false => [0.0; 4],
+    };
+    RgbaImage::from_fn(rotated_width, rotated_height, |x, y| {
+        let dx = x as f32 + 0.5 - rotated_width as f32 / 2.0;
+        let dy = y as f32 + 0.5 - rotated_height as f32 / 2.0;
+        // Where the pixel comes from, relative to pixel centers of the source
+        let source_x = dx * cos - dy * sin + width / 2.0 - 0.5;
+        let source_y = dx * sin + dy * cos + height / 2.0 - 0.5;
+        let (left, top) = (source_x.floor(), source_y.floor());
+        let (fx, fy) = (source_x - left, source_y - top);
+        let (left, top) = (left as i64, top as i64);
+        let mut sum = [0.0f32; 4];
+        for (sx, sy, weight) in [
+            (left, top, (1.0 - fx) * (1.0 - fy)),
+            (left + 1, top, fx * (1.0 - fy)),
+            (left, top + 1, (1.0 - fx) * fy),
+            (left + 1, top + 1, fx * fy),
+        ] {
+            for (total, value) in sum.iter_mut().zip(sample(sx, sy)) {
+                *total += value * weight;
+            }
+        }
+        let alpha = sum[3];
+        match alpha > 0.0 {
+            true => {
+                let channel = |value: f32| (value * 255.0 / alpha).round().clamp(0.0, 255.0) as u8;
+                Rgba([
+                    channel(sum[0]),
+                    channel(sum[1]),
+                    channel(sum[2]),
+                    alpha.round() as u8,
+                ])
+            }
+            false => Rgba([0, 0, 0, 0]),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Dither::FloydSteinberg, 8.0)]
+    #[case(Dither::Ordered, 8.0)]
+    // Atkinson drops part of the error, trading brightness for contrast
+    #[case(Dither::Atkinson, 24.0)]
+    fn dither_uses_only_allowed_levels(#[case] method: Dither, #[case] mean_error: f32) {
+        let img = GrayImage::from_fn(16, 16, |x, y| Luma([(x * 16 + y) as u8]));
+        let output = dither(&img, 2, method);
+        assert!(output
+            .pixels()
+            .all(|pixel| pixel.0[0] == 0 || pixel.0[0] == 255));
+
+        // The average brightness is preserved by the dithering
+        let mean = |img: &GrayImage| {
+            img.pixels().map(|pixel| pixel.0[0] as f32).sum::<f32>() / (16.0 * 16.0)
+        };
+        assert!((mean(&img) - mean(&output)).abs() < mean_error);
+    }
+
+    #[rstest]
+    #[case(Dither::FloydSteinberg)]
+    #[case(Dither::Atkinson)]
+    #[case(Dither::Ordered)]
+    fn dither_keeps_exact_levels(#[case] method: Dither) {
+        // Grays that the display can show are left alone
+        let img = GrayImage::from_fn(8, 8, |x, _| Luma([[0, 85, 170, 255][x as usize % 4]]));
+        assert_eq!(dither(&img, 4, method), img);
+    }
+
+    #[test]
+    fn high_contrast_thickens_strokes() {
+        let mut img = GrayImage::from_pixel(5, 5, Luma([230]));
+        img.put_pixel(2, 2, Luma([40]));
+
+        let output = high_contrast(&DynamicImage::ImageLuma8(img), 1).to_luma8();
+        for (x, y, pixel) in output.enumerate_pixels() {
+            let expected = if (1..=3).contains(&x) && (1..=3).contains(&y) {
+                0
+            } else {
+                255
+            };
+            assert_eq!(pixel.0[0], expected, "pixel ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn desaturate_by_luminance() {
+        let img = RgbaImage::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([255, 0, 0, 255]),
+            1 => Rgba([0, 255, 0, 255]),
+            _ => Rgba([0, 0, 255, 255]),
+        });
+        let gray = desaturate(&DynamicImage::ImageRgba8(img.clone()));
+        let DynamicImage::ImageLuma8(gray) = gray else {
+            panic!("opaque images lose their alpha channel");
+        };
+        // Green is by far the brightest, blue the darkest
+        let [red, green, blue] = [0, 1, 2].map(|x| gray.get_pixel(x, 0)[0]);
+        assert!(blue < red && red < green, "{red} {green} {blue}");
+
+        let mut transparent = img;
+        transparent.put_pixel(0, 0, Rgba([255, 0, 0, 0]));
+        let gray = desaturate(&DynamicImage::ImageRgba8(transparent));
+        assert_eq!(gray.color(), image::ColorType::La8);
+        assert_eq!(gray.to_luma_alpha8().get_pixel(0, 0)[1], 0);
+    }
+
+    #[test]
+    fn sharpen_steepens_edges() {
+        // A soft ramp from black to white, as left by upscaling
+        let img = RgbaImage::from_fn(12, 4, |x, _| {
+            let value = (x.saturating_sub(4) * 64).min(255) as u8;
+            Rgba([value, value, value, 255])
+        });
+        let sharpened = sharpen(&img, Sharpen::default());
+        // Flat areas stay, the dark side of the edge gets darker and the light one lighter
+        assert_eq!(sharpened.get_pixel(0, 0), img.get_pixel(0, 0));
+        assert!(sharpened.get_pixel(5, 0)[0] < img.get_pixel(5, 0)[0]);
+        assert!(sharpened.get_pixel(7, 0)[0] > img.get_pixel(7, 0)[0]);
+        assert!(sharpened.pixels().all(|pixel| pixel[3] == 255));
+    }
+
+    #[rstest]
+    #[case("0.5", Ok(Sharpen { amount: 0.5, radius: 1.0 }))]
+    #[case("2, 1.5", Ok(Sharpen { amount: 2.0, radius: 1.5 }))]
+    #[case("1,0", Err(()))]
+    #[case("-1", Err(()))]
+    #[case("1,2,3", Err(()))]
+    fn parse_sharpen(#[case] s: &str, #[case] expected: Result<Sharpen, ()>) {
+        assert_eq!(s.parse::<Sharpen>().map_err(|_| ()), expected);
+    }
+
+    #[test]
+    fn grain_is_monochrome_and_subtle() {
+        let flat = RgbaImage::from_pixel(64, 64, Rgba([40, 120, 200, 128]));
+        let mut img = flat.clone();
+        grain(&mut img, 0.05, 7);
+        assert_ne!(img, flat);
+        for pixel in img.pixels() {
+            let offset = pixel[0] as i32 - 40;
+            assert!(offset.abs() <= 13, "{offset}");
+            assert_eq!(pixel[1] as i32 - 120, offset);
+            assert_eq!(pixel[2] as i32 - 200, offset);
+            assert_eq!(pixel[3], 128);
+        }
+        // The noise averages out, leaving the color as it was
+        let mean = img.pixels().map(|pixel| pixel[0] as f32).sum::<f32>() / (64.0 * 64.0);
+        assert!((mean - 40.0).abs() < 0.5, "{mean}");
+
+        // The same seed gives the same noise
+        let (mut again, mut other) = (flat.clone(), flat);
+        grain(&mut again, 0.05, 7);
+        grain(&mut other, 0.05, 8);
+        assert_eq!(again, img);
+        assert_ne!(other, img);
+    }
+
+    #[rstest]
+    #[case(Halftone::default())]
+    #[case(Halftone { cell: 6, angle: 30.0 })]
+    #[case(Halftone { cell: 10, angle: 15.0 })]
+    fn halftone_keeps_tones(#[case] settings: Halftone) {
+        let ink = |img: &GrayImage| {
+            img.pixels().filter(|pixel| pixel[0] == 0).count() as f32 / (120.0 * 120.0)
+        };
+        for value in [0u8, 64, 128, 192, 255] {
+            let img = GrayImage::from_pixel(120, 120, Luma([value]));
+            let dots = halftone(&DynamicImage::ImageLuma8(img), settings).into_luma8();
+            assert!(dots.pixels().all(|pixel| pixel[0] == 0 || pixel[0] == 255));
+            let darkness = 1.0 - value as f32 / 255.0;
+            assert!(
+                (ink(&dots) - darkness).abs() < 0.05,
+                "{value}: {}",
+                ink(&dots)
+            );
+        }
+    }
+
+    #[test]
+    fn halftone_dots_are_clustered() {
+        let img = GrayImage::from_pixel(16, 16, Luma([192]));
+        let settings = Halftone {
+            cell: 8,
+            angle: 0.0,
+        };
+        let dots = halftone(&DynamicImage::ImageLuma8(img), settings).into_luma8();
+        // Ink in the middle of each cell, none at its corners
+        for (x, y) in [(4, 4), (12, 4), (4, 12), (12, 12)] {
+            assert_eq!(dots.get_pixel(x, y)[0], 0);
+            assert_eq!(dots.get_pixel(x - 4, y - 4)[0], 255);
+        }
+    }
+
+    #[rstest]
+    #[case("6", Ok(Halftone { cell: 6, angle: 45.0 }))]
+    #[case("10,15", Ok(Halftone { cell: 10, angle: 15.0 }))]
+    #[case("1", Err(()))]
+    #[case("8,x", Err(()))]
+    fn parse_halftone(#[case] s: &str, #[case] expected: Result<Halftone, ()>) {
+        assert_eq!(s.parse::<Halftone>().map_err(|_| ()), expected);
+    }
+
+    #[rstest]
+    #[case(Rgba([0x1F, 0x24, 0x1F, 255]), false)]
+    #[case(Rgba([0xE0, 0xDE, 0xD4, 255]), true)]
+    fn vignette_fades_edges(#[case] paper: Rgba<u8>, #[case] lighter: bool) {
+        let mut img = RgbaImage::from_pixel(160, 90, paper);
+        vignette(&mut img, 0.5, paper);
+        // The middle is untouched, the corners move halfway to black or white
+        assert_eq!(img.get_pixel(80, 45), &paper);
+        let corner = img.get_pixel(0, 0);
+        let edge = img.get_pixel(0, 45);
+        let expected = match lighter {
+            true => (paper[0] as f32 + 255.0) / 2.0,
+            false => paper[0] as f32 / 2.0,
+        };
+        assert!((corner[0] as f32 - expected).abs() <= 2.0, "{corner:?}");
+        match lighter {
+            true => assert!(paper[0] < edge[0] && edge[0] < corner[0]),
+            false => assert!(paper[0] > edge[0] && edge[0] > corner[0]),
+        }
+        assert_eq!(corner[3], 255);
+    }
+
+    #[test]
+    fn trim_uniform_borders() {
+        let white = Rgba([255, 255, 255, 255]);
+        let mut img = RgbaImage::from_pixel(20, 10, white);
+        img.put_pixel(4, 2, Rgba([0, 0, 0, 255]));
+        img.put_pixel(15, 6, Rgba([0, 0, 0, 255]));
+        // Noise in the margin is within the tolerance
+        img.put_pixel(18, 9, Rgba([250, 252, 255, 255]));
+        let trimmed = trim(&DynamicImage::ImageRgba8(img.clone()), 8);
+        assert_eq!((trimmed.width(), trimmed.height()), (12, 5));
+        assert_eq!(trimmed.to_rgba8().get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+        assert_eq!(trim(&DynamicImage::ImageRgba8(img), 0).width(), 15);
+        // Nothing to keep
+        let blank = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, white));
+        assert_eq!(trim(&blank, 8).width(), 8);
+    }
+
+    #[rstest]
+    #[case(0.0, (4, 2))]
+    #[case(90.0, (2, 4))]
+    #[case(180.0, (4, 2))]
+    #[case(45.0, (5, 5))]
+    fn rotate_grows_canvas(#[case] degrees: f32, #[case] dimensions: (u32, u32)) {
+        let img = RgbaImage::from_pixel(4, 2, Rgba([255, 0, 0, 255]));
+        assert_eq!(rotate(&img, degrees).dimensions(), dimensions);
+    }
+
+    #[test]
+    fn rotate_counterclockwise() {
+        // A red pixel in the top right corner ends up in the top left one
+        let mut img = RgbaImage::from_pixel(3, 2, Rgba([0, 0, 255, 255]));
+        img.put_pixel(2, 0, Rgba([255, 0, 0, 255]));
+        let rotated = rotate(&img, 90.0);
+        assert_eq!(rotated.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(rotated.get_pixel(1, 2), &Rgba([0, 0, 255, 255]));
+        // Edges fade out without darkening towards the transparent corners
+        let tilted = rotate(&img, 30.0);
+        assert_eq!(tilted.get_pixel(0, 0)[3], 0);
+        assert!(tilted
+            .pixels()
+            .filter(|pixel| pixel[3] > 0)
+            .all(|pixel| pixel[0] as u32 + pixel[2] as u32 >= 254));
+    }
+}
@@ -0,0 +1,225 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use ab_glyph::{point, Font as _, FontVec, PxScale, ScaleFont};
+use image::{Rgba, RgbaImage};
+use log::info;
+
+use crate::XkcdError;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// Typeface of the text drawn on wallpapers
+pub enum Font {
+    /// 5x7 pixel font built into the program
+    #[default]
+    Builtin,
+    TrueType(TrueType),
+}
+
+impl Font {
+    /// Read a TrueType (`.ttf`) or OpenType (`.otf`) font file
+    pub fn load(path: &Path) -> Result<Font, XkcdError> {
+        info!("loading font {}", path.display());
+        let data = fs::read(path)?;
+        TrueType::parse(data)
+            .map(Font::TrueType)
+            .map_err(|err| XkcdError::Config(format!("{}: {}", path.display(), err)))
+    }
+}
+
+#[derive(Clone)]
+/// A font file loaded with `ab_glyph`, shared between the styles using it
+///
+/// Kerning and hinting are ignored.
+pub struct TrueType {
+    font: Arc<FontVec>,
+}
+
+impl fmt::Debug for TrueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TrueType")
+            .field("glyphs", &self.font.glyph_count())
+            .field("units_per_em", &self.font.units_per_em())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Fonts are only equal to themselves, comparing the files would be wasted work
+impl PartialEq for TrueType {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.font, &other.font)
+    }
+}
+
+impl TrueType {
+    /// Read the tables of a font file
+    pub fn parse(data: Vec<u8>) -> Result<TrueType, String> {
+        let font = FontVec::try_from_vec(data).map_err(|err| err.to_string())?;
+        if font.height_unscaled() <= 0.0 {
+            return Err("invalid metrics".to_string());
+        }
+        Ok(TrueType {
+            font: Arc::new(font),
+        })
+    }
+
+    /// Width of `text` in pixels, for lines `size` pixels high
+    pub fn width(&self, text: &str, size: u32) -> u32 {
+        let font = self.font.as_scaled(PxScale::from(size as f32));
+        let width: f32 = text.chars().map(|c| font.h_advance(font.glyph_id(c))).sum();
+        width.ceil() as u32
+    }
+
+    /// `text` on a line `size` pixels high, anti-aliased in `color`
+    pub fn render(&self, text: &str, size: u32, color: Rgba<u8>) -> RgbaImage {
+        let font = self.font.as_scaled(PxScale::from(size as f32));
+        let (width, height) = (self.width(text, size).max(1), size.max(1));
+        let mut coverage = vec![0.0f32; (width * height) as usize];
+        let mut pen = 0.0;
+        for c in text.chars() {
+            let id = font.glyph_id(c);
+            let glyph = id.with_scale_and_position(font.scale(), point(pen, font.ascent()));
+            pen += font.h_advance(id);
+            let Some(outline) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|x, y, amount| {
+                let x = x as i64 + bounds.min.x as i64;
+                let y = y as i64 + bounds.min.y as i64;
+                if (0..width as i64).contains(&x) && (0..height as i64).contains(&y) {
+                    let cell = &mut coverage[(y as u32 * width + x as u32) as usize];
+                    *cell = (*cell + amount).min(1.0);
+                }
+            });
+        }
+        RgbaImage::from_fn(width, height, |x, y| {
+            let mut pixel = color;
+            let alpha = coverage[(y * width + x) as usize] * color[3] as f32;
+            pixel[3] = alpha.round() as u8;
+            pixel
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A font whose only glyph, for `A`, is a square filling the line and advancing by its side
+    fn square_font() -> Vec<u8> {
+        let be16 = |value: u16| value.to_be_bytes().to_vec();
+        let be32 = |value: u32| value.to_be_bytes().to_vec();
+        // Glyph 1: one contour through (0, 0), (0, 1000), (1000, 1000) and (1000, 0)
+        let mut glyf = [be16(1), be16(0), be16(0), be16(1000), be16(1000)].concat();
+        glyf.extend([be16(3), be16(0)].concat());
+        glyf.extend([0x01 | 0x10 | 0x20, 0x01 | 0x10, 0x01 | 0x20, 0x01 | 0x10]);
+        // x: 0, +0, +1000, +0 and y: 0, +1000, +0, -1000
+        glyf.extend([be16(1000)].concat());
+        glyf.extend([be16(1000), be16(-1000i16 as u16)].concat());
+        let loca = [be32(0), be32(0), be32(glyf.len() as u32)].concat();
+        let mut head = vec![0; 54];
+        head[18..20].copy_from_slice(&be16(1000));
+        head[50..52].copy_from_slice(&be16(1));
+        let mut hhea = vec![0; 36];
+        hhea[4..6].copy_from_slice(&be16(800));
+        hhea[6..8].copy_from_slice(&be16(-200i16 as u16));
+        hhea[34..36].copy_from_slice(&be16(2));
+        let mut maxp = vec![0; 6];
+        maxp[0..4].copy_from_slice(&be32(0x00005000));
+        maxp[4..6].copy_from_slice(&be16(2));
+        let hmtx = [be16(500), be16(0), be16(1000), be16(0)].concat();
+        // Format 4 with a segment for `A` and the final 0xFFFF one
+        let cmap = [
+            be16(0),
+            be16(1),
+            be16(3),
+            be16(1),
+            be32(12),
+            be16(4),
+            be16(32),
+            be16(0),
+            be16(4),
+            be16(0),
+            be16(0),
+            be16(0),
+            be16(0x41),
+            be16(0xFFFF),
+            be16(0),
+            be16(0x41),
+            be16(0xFFFF),
+            be16(1u16.wrapping_sub(0x41)),
+            be16(1),
+            be16(0),
+            be16(0),
+        ]
+        .concat();
+
+        let tables: [(&[u8; 4], Vec<u8>); 7] = [
+            (b"cmap", cmap),
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"loca", loca),
+            (b"maxp", maxp),
+        ];
+        let mut font = [be32(0x00010000), be16(tables.len() as u16), vec![0; 6]].concat();
+        let mut offset = 12 + 16 * tables.len();
+        let mut contents: Vec<u8> = Vec::new();
+        for (tag, table) in &tables {
+            font.extend(
+                [
+                    tag.to_vec(),
+                    be32(0),
+                    be32(offset as u32),
+                    be32(table.len() as u32),
+                ]
+                .concat(),
+            );
+            offset += table.len();
+            contents.extend(table);
+        }
+        font.extend(contents);
+        font
+    }
+
+    #[test]
+    fn square_glyph() {
+        let font = TrueType::parse(square_font()).unwrap();
+        assert_eq!(font.font.glyph_id('A').0, 1);
+        assert_eq!(font.font.glyph_id('B').0, 0);
+        // Lines 20 pixels high, so the square is 20 pixels wide and sits on the baseline 16
+        // pixels down
+        assert_eq!(font.width("AB", 20), 30);
+        let color = Rgba([10, 20, 30, 255]);
+        let img = font.render("AB", 20, color);
+        assert_eq!(img.dimensions(), (30, 20));
+        assert_eq!(img.get_pixel(10, 8), &color);
+        assert_eq!(img.get_pixel(19, 15), &color);
+        assert_eq!(img.get_pixel(10, 17)[3], 0);
+        assert_eq!(img.get_pixel(25, 8)[3], 0);
+    }
+
+    #[test]
+    fn anti_aliased_edges() {
+        let font = TrueType::parse(square_font()).unwrap();
+        // The baseline is 10.4 pixels down, so the square covers 40% of row 10
+        let img = font.render("A", 13, Rgba([0, 0, 0, 255]));
+        assert_eq!(img.dimensions(), (13, 13));
+        assert_eq!(img.get_pixel(5, 9)[3], 255);
+        assert_eq!(img.get_pixel(5, 10)[3], 102);
+        assert_eq!(img.get_pixel(5, 11)[3], 0);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(TrueType::parse(b"not a font".to_vec()).is_err());
+        let mut font = square_font();
+        // Rename the head table
+        font[12 + 16 * 2] = b'x';
+        assert!(TrueType::parse(font).is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use jiff::Timestamp;
+use log::{info, warn};
+
+use crate::history::HistoryEntry;
+use crate::XkcdError;
+
+const INDEX_FILE: &str = "index.html";
+const THUMBNAIL_DIR: &str = "thumbnails";
+const THUMBNAIL_SIZE: u32 = 480;
+/// Extensions of images picked up when scanning a directory
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "gif", "webp"];
+
+#[derive(Clone, Debug, PartialEq)]
+/// A wallpaper shown in the gallery
+pub struct GalleryItem {
+    pub title: String,
+    pub num: Option<u64>,
+    pub alt: Option<String>,
+    pub generated: Option<Timestamp>,
+    pub image: PathBuf,
+}
+
+impl GalleryItem {
+    /// Wallpapers from the history that still exist, newest first and once per file
+    pub fn from_history(history: &[HistoryEntry]) -> Vec<GalleryItem> {
+        let mut items: Vec<GalleryItem> = Vec::new();
+        for entry in history.iter().rev() {
+            if !entry.output.is_file() || items.iter().any(|item| item.image == entry.output) {
+                continue;
+            }
+            items.push(GalleryItem {
+                title: entry.title.clone(),
+                num: Some(entry.num),
+                alt: entry.alt.clone(),
+                generated: Some(entry.timestamp),
+                image: entry.output.clone(),
+            });
+        }
+        items
+    }
+
+    /// Images in `dir` that are not in `known`, titled after their file name
+    pub fn scan_dir(dir: &Path, known: &[GalleryItem]) -> Result<Vec<GalleryItem>, XkcdError> {
+        let mut items = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = std::path::absolute(entry?.path())?;
+            let is_image = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+            if !is_image || known.iter().any(|item| item.image == path) {
+                continue;
+            }
+            items.push(GalleryItem {
+                title: path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                num: None,
+                alt: None,
+                generated: None,
+                image: path,
+            });
+        }
+        items.sort_by(|a, b| a.title.cmp(&b.title));
+        Ok(items)
+    }
+}
+
+/// Write `index.html` and thumbnails for `items` into `out_dir`, returning the index path
+///
+/// Full-size images are linked relative to `out_dir`, or copied into it with `copy`
+/// so the gallery can be moved to another device.
+pub fn export(items: &[GalleryItem], out_dir: &Path, copy: bool) -> Result<PathBuf, XkcdError> {
+    let out_dir = std::path::absolute(out_dir)?;
+    fs::create_dir_all(out_dir.join(THUMBNAIL_DIR))?;
+    if copy {
+        fs::create_dir_all(out_dir.join("full"))?;
+    }
+
+    let mut cards = String::new();
+    for (index, item) in items.iter().enumerate() {
+        let file_name = item.image.file_name().unwrap_or_default().to_string_lossy();
+        let stem = item.image.file_stem().unwrap_or_default().to_string_lossy();
+        let thumbnail = format!("{THUMBNAIL_DIR}/{index:04}-{stem}.png");
+
+        info!("writing thumbnail for {}", item.image.display());
+        match image::open(&item.image) {
+            Ok(img) => img
+                .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+                .save(out_dir.join(&thumbnail))?,
+            Err(err) => {
+                warn!("skipping {}: {}", item.image.display(), err);
+                continue;
+            }
+        }
+
+        let link = if copy {
+            let link = format!("full/{index:04}-{file_name}");
+            fs::copy(&item.image, out_dir.join(&link))?;
+            link
+        } else {
+            relative_path(&out_dir, &item.image)
+        };
+        cards.push_str(&render_card(item, &thumbnail, &link));
+    }
+
+    let index = out_dir.join(INDEX_FILE);
+    fs::write(&index, render_page(&cards))?;
+    Ok(index)
+}
+
+fn render_card(item: &GalleryItem, thumbnail: &str, link: &str) -> String {
+    let heading = match item.num {
+        Some(num) => format!("#{num} {}", item.title),
+        None => item.title.clone(),
+    };
+    let alt = item.alt.as_deref().unwrap_or(&item.title);
+    let mut card = format!(
+        "<figure>\n<a href=\"{}\"><img src=\"{}\" alt=\"{}\" title=\"{}\" loading=\"lazy\"></a>\n<figcaption><strong>{}</strong>",
+        escape_html(&encode_url_path(link)),
+        escape_html(&encode_url_path(thumbnail)),
+        escape_html(alt),
+        escape_html(alt),
+        escape_html(&heading)
+    );
+    if let Some(generated) = item.generated {
+        card.push_str(&format!(
+            "<br><time datetime=\"{generated}\">{}</time>",
+            generated.strftime("%Y-%m-%d")
+        ));
+    }
+    if let Some(alt) = &item.alt {
+        card.push_str(&format!("<p>{}</p>", escape_html(alt)));
+    }
+    card.push_str("</figcaption>\n</figure>\n");
+    card
+}
+
+fn render_page(cards: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>xkcd wallpapers</title>
+<style>
+body {{ font-family: sans-serif; background: #1f241f; color: #eee; margin: 2em; }}
+main {{ display: grid; grid-template-columns: repeat(auto-fill, minmax(260px, 1fr)); gap: 1.5em; }}
+figure {{ margin: 0; }}
+img {{ width: 100%; border-radius: 4px; }}
+a {{ color: inherit; }}
+figcaption p {{ font-size: 0.85em; opacity: 0.8; }}
+</style>
+</head>
+<body>
+<h1>xkcd wallpapers</h1>
+<main>
+{cards}</main>
+</body>
+</html>
+"#
+    )
+}
+
+/// Escape text for use in HTML content and attribute values
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Percent-encode the characters of a relative path that have a meaning in URLs
+fn encode_url_path(path: &str) -> String {
+    path.replace('%', "%25")
+        .replace(' ', "%20")
+        .replace('#', "%23")
+        .replace('?', "%3F")
+}
+
+/// Path of absolute path `target` relative to directory `base`, both absolute
+fn relative_path(base: &Path, target: &Path) -> String {
+    let base: Vec<_> = base.components().collect();
+    let target: Vec<_> = target.components().collect();
+    if base.first() != target.first() {
+        // Different drives on Windows, no relative path exists
+        return format!("file://{}", target.iter().collect::<PathBuf>().display());
+    }
+
+    let common = base.iter().zip(&target).take_while(|(a, b)| a == b).count();
+    let mut parts: Vec<String> = vec!["..".to_string(); base.len() - common];
+    parts.extend(
+        target[common..]
+            .iter()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+                _ => None,
+            }),
+    );
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn escape_html_special_characters() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+
+    #[rstest]
+    #[case("/home/me/gallery", "/home/me/walls/a.png", "../walls/a.png")]
+    #[case("/home/me", "/home/me/walls/a.png", "walls/a.png")]
+    #[case("/home/me/gallery", "/tmp/a.png", "../../../tmp/a.png")]
+    fn relative_path_cases(#[case] base: &str, #[case] target: &str, #[case] expected: &str) {
+        assert_eq!(relative_path(Path::new(base), Path::new(target)), expected);
+    }
+
+    #[test]
+    fn encode_url_path_reserved_characters() {
+        assert_eq!(
+            encode_url_path("a b/100%#1?.png"),
+            "a%20b/100%25%231%3F.png"
+        );
+    }
+
+    #[test]
+    fn export_writes_index_and_thumbnails() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("wallpaper.png");
+        image::RgbaImage::new(1920, 1080).save(&image).unwrap();
+        let items = vec![GalleryItem {
+            title: "Standards".to_string(),
+            num: Some(927),
+            alt: Some("Fortunately, the charging one has been solved now".to_string()),
+            generated: None,
+            image,
+        }];
+
+        let index = export(&items, &dir.path().join("gallery"), true).unwrap();
+        let html = fs::read_to_string(index).unwrap();
+        assert!(html.contains("#927 Standards"));
+        assert!(html.contains("href=\"full/0000-wallpaper.png\""));
+        let thumbnail = image::open(dir.path().join("gallery/thumbnails/0000-wallpaper.png"));
+        assert_eq!(thumbnail.unwrap().width(), THUMBNAIL_SIZE);
+    }
+}
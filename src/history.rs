@@ -0,0 +1,127 @@
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use jiff::Timestamp;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::XkcdError;
+
+const HISTORY_FILE: &str = "history.jsonl";
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+/// A single generated wallpaper
+pub struct HistoryEntry {
+    pub timestamp: Timestamp,
+    pub num: u64,
+    pub title: String,
+    /// Alt text of the comic, missing in entries written by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alt: Option<String>,
+    /// Hash of the rendering options, equal hashes mean identical settings
+    pub options_hash: String,
+    pub output: PathBuf,
+}
+
+/// Log of generated wallpapers, stored as JSON Lines in the state directory
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn in_state_dir(state_dir: &Path) -> Self {
+        History {
+            path: state_dir.join(HISTORY_FILE),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append an entry to the log, creating it if needed
+    pub fn append(&self, entry: &HistoryEntry) -> Result<(), XkcdError> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut line =
+            serde_json::to_string(entry).map_err(|err| XkcdError::Other(err.to_string()))?;
+        line.push('\n');
+
+        info!("appending comic {} to {}", entry.num, self.path.display());
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// All entries, oldest first. Malformed lines are skipped with a warning.
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>, XkcdError> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut entries = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(err) => warn!("skipping malformed history line {}: {}", index + 1, err),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Stable hash of a description of the rendering options
+pub fn options_hash(options: &str) -> String {
+    // FNV-1a, unlike `DefaultHasher` it is stable across Rust versions
+    let hash = options.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::in_state_dir(&dir.path().join("state"));
+        assert!(history.entries().unwrap().is_empty());
+
+        let entry = HistoryEntry {
+            timestamp: "2025-06-20T08:00:00Z".parse().unwrap(),
+            num: 3084,
+            title: "Some title".to_string(),
+            alt: Some("Some alt text".to_string()),
+            options_hash: options_hash("2560x1440"),
+            output: PathBuf::from("/tmp/wallpaper.png"),
+        };
+        history.append(&entry).unwrap();
+        history.append(&entry).unwrap();
+        fs::write(
+            history.path(),
+            fs::read_to_string(history.path()).unwrap() + "not json\n",
+        )
+        .unwrap();
+
+        assert_eq!(history.entries().unwrap(), vec![entry.clone(), entry]);
+    }
+
+    #[test]
+    fn options_hash_is_stable() {
+        assert_eq!(options_hash(""), "cbf29ce484222325");
+        assert_ne!(options_hash("a"), options_hash("b"));
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use log::info;
+use serde::Deserialize;
+
+use crate::XkcdError;
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// Shell commands run after generating or setting a wallpaper, the `[hooks]` config section
+///
+/// `{output}`, `{num}`, `{title}` and `{alt}` are replaced with shell-quoted values.
+///
+/// ```toml
+/// [hooks]
+/// post_generate = "swww img {output}"
+/// post_set = "notify-send xkcd {title}"
+/// ```
+pub struct HooksConfig {
+    pub post_generate: Option<String>,
+    pub post_set: Option<String>,
+}
+
+/// Values substituted into hook commands
+pub struct HookContext<'a> {
+    pub output: &'a Path,
+    pub num: u64,
+    pub title: &'a str,
+    pub alt: &'a str,
+}
+
+/// Run the hook `name` with placeholders of `command` substituted from `context`
+pub fn run(name: &str, command: &str, context: &HookContext) -> Result<(), XkcdError> {
+    let command = expand(command, context);
+    info!("running {} hook: {}", name, command);
+
+    // Standard output is passed through, standard error is kept for the failure report
+    let output = shell(&command)
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|child| child.wait_with_output())
+        .map_err(|err| XkcdError::Hook(format!("{name} hook failed to start: {err}")))?;
+    if !output.status.success() {
+        return Err(XkcdError::Hook(format!(
+            "{name} hook exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Substitute the placeholders in `command`, unknown placeholders are kept as is
+fn expand(command: &str, context: &HookContext) -> String {
+    let mut expanded = String::with_capacity(command.len());
+    let mut rest = command;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find('}').map_or(rest.len(), |end| end + 1);
+        match &rest[..end] {
+            "{output}" => expanded.push_str(&quote(&context.output.display().to_string())),
+            "{num}" => expanded.push_str(&context.num.to_string()),
+            "{title}" => expanded.push_str(&quote(context.title)),
+            "{alt}" => expanded.push_str(&quote(context.alt)),
+            other => expanded.push_str(other),
+        }
+        rest = &rest[end..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.args(["-c", command]);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.args(["/C", command]);
+    shell
+}
+
+/// Quote `value` as a single shell word
+#[cfg(unix)]
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(not(unix))]
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn context() -> HookContext<'static> {
+        HookContext {
+            output: Path::new("/tmp/my wallpaper.png"),
+            num: 927,
+            title: "Tom's comic",
+            alt: "$(rm -rf ~)",
+        }
+    }
+
+    #[rstest]
+    #[case("swww img {output}", "swww img '/tmp/my wallpaper.png'")]
+    #[case("echo {num} {title}", r"echo 927 'Tom'\''s comic'")]
+    #[case("echo {alt}", "echo '$(rm -rf ~)'")]
+    #[case("awk '{print}' {unknown", "awk '{print}' {unknown")]
+    fn expand_placeholders(#[case] command: &str, #[case] expected: &str) {
+        assert_eq!(expand(command, &context()), expected);
+    }
+
+    #[test]
+    fn run_reports_failure() {
+        assert!(run("post_generate", "test {num} = 927", &context()).is_ok());
+        let err = run("post_set", "echo oops >&2; exit 3", &context()).unwrap_err();
+        assert!(err.to_string().contains("oops"));
+    }
+}
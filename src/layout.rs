@@ -0,0 +1,677 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::effects::Sharpen;
+use crate::ScreenDimensions;
+
+/// 1 / φ², the smaller part of a golden-ratio split
+const GOLDEN_SECTION: f64 = 0.381_966_011_250_105;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Horizontal composition of the comic on the screen, the comic is always vertically centered
+pub enum Placement {
+    #[default]
+    Center,
+    /// Centered on the left third line
+    ThirdsLeft,
+    /// Centered on the right third line, mirror of `thirds-left`
+    ThirdsRight,
+    /// Centered on the left golden section
+    GoldenLeft,
+    /// Centered on the right golden section, mirror of `golden-left`
+    GoldenRight,
+}
+
+impl Placement {
+    /// Horizontal position of the focal point, as a fraction of the screen width
+    pub fn focal_point(&self) -> f64 {
+        match self {
+            Placement::Center => 0.5,
+            Placement::ThirdsLeft => 1.0 / 3.0,
+            Placement::ThirdsRight => 2.0 / 3.0,
+            Placement::GoldenLeft => GOLDEN_SECTION,
+            Placement::GoldenRight => 1.0 - GOLDEN_SECTION,
+        }
+    }
+
+    /// Top left corner of a `width` x `height` comic on the screen
+    ///
+    /// The comic is kept inside the screen when centering it on the focal point would crop it.
+    pub fn position(
+        &self,
+        screen_dimensions: &ScreenDimensions,
+        width: u32,
+        height: u32,
+    ) -> (i64, i64) {
+        let (screen_width, screen_height) = (
+            screen_dimensions.width as i64,
+            screen_dimensions.height as i64,
+        );
+        let (width, height) = (width as i64, height as i64);
+
+        let centered = (screen_width - width) / 2;
+        let x = match self {
+            Placement::Center => centered,
+            _ if width >= screen_width => centered,
+            _ => {
+                let focal = (self.focal_point() * screen_width as f64).round() as i64;
+                (focal - width / 2).clamp(0, screen_width - width)
+            }
+        };
+        (x, (screen_height - height) / 2)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Edge or corner of the screen the comic is pinned to, inside the margins
+pub enum Anchor {
+    /// Vertically centered, horizontally as the [`Placement`] says
+    #[default]
+    Center,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Anchor {
+    /// Top left corner of a `width` x `height` overlay at this anchor, centered where the comic
+    /// would follow the placement
+    pub fn overlay_position(
+        &self,
+        screen_dimensions: &ScreenDimensions,
+        width: u32,
+        height: u32,
+    ) -> (i64, i64) {
+        let (horizontal, vertical) = self.alignment();
+        overlay_position(
+            screen_dimensions,
+            width,
+            height,
+            (horizontal.unwrap_or(0.5), vertical),
+        )
+    }
+
+    /// Position along each axis, as a fraction of the free space: 0 at the start, 1 at the end,
+    /// `None` where the placement decides
+    fn alignment(&self) -> (Option<f64>, f64) {
+        match self {
+            Anchor::Center => (None, 0.5),
+            Anchor::TopLeft => (Some(0.0), 0.0),
+            Anchor::TopRight => (Some(1.0), 0.0),
+            Anchor::BottomLeft => (Some(0.0), 1.0),
+            Anchor::BottomRight => (Some(1.0), 1.0),
+            Anchor::Left => (Some(0.0), 0.5),
+            Anchor::Right => (Some(1.0), 0.5),
+            Anchor::Top => (None, 0.0),
+            Anchor::Bottom => (None, 1.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Corner of the screen an overlay is drawn in
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+impl Corner {
+    /// Top left corner of a `width` x `height` overlay in this corner, see [`overlay_position`]
+    pub fn position(
+        &self,
+        screen_dimensions: &ScreenDimensions,
+        width: u32,
+        height: u32,
+    ) -> (i64, i64) {
+        let alignment = match self {
+            Corner::TopLeft => (0.0, 0.0),
+            Corner::TopRight => (1.0, 0.0),
+            Corner::BottomLeft => (0.0, 1.0),
+            Corner::BottomRight => (1.0, 1.0),
+        };
+        overlay_position(screen_dimensions, width, height, alignment)
+    }
+}
+
+/// Top left corner of a `width` x `height` overlay aligned as [`Anchor::alignment`] says, a
+/// fiftieth of the screen's short side away from its edges
+fn overlay_position(
+    screen_dimensions: &ScreenDimensions,
+    width: u32,
+    height: u32,
+    (horizontal, vertical): (f64, f64),
+) -> (i64, i64) {
+    let (screen_width, screen_height) = (screen_dimensions.width, screen_dimensions.height);
+    let margin = (screen_width.min(screen_height) / 50) as i64;
+    let free_x = screen_width as i64 - width as i64 - 2 * margin;
+    let free_y = screen_height as i64 - height as i64 - 2 * margin;
+    (
+        margin + (horizontal * free_x as f64) as i64,
+        margin + (vertical * free_y as f64) as i64,
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// A distance in pixels, or in percent of the screen width or height it applies to
+pub enum Length {
+    Pixels(i64),
+    Percent(f64),
+}
+
+impl Default for Length {
+    fn default() -> Self {
+        Length::Pixels(0)
+    }
+}
+
+impl Length {
+    /// Distance in pixels along a screen side of `reference` pixels
+    pub fn resolve(&self, reference: u32) -> i64 {
+        match self {
+            Length::Pixels(pixels) => *pixels,
+            Length::Percent(percent) => (percent / 100.0 * reference as f64).round() as i64,
+        }
+    }
+}
+
+impl FromStr for Length {
+    type Err = String;
+
+    /// Parse `40`, `40px` or `5%`, negative values are allowed
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(percent) = s.strip_suffix('%') {
+            return percent
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|percent| percent.is_finite())
+                .map(Length::Percent)
+                .ok_or_else(|| format!("Invalid percentage '{s}'"));
+        }
+        s.strip_suffix("px")
+            .unwrap_or(s)
+            .trim()
+            .parse()
+            .map(Length::Pixels)
+            .map_err(|_| {
+                format!("Invalid length '{s}', expected pixels like 40 or a percentage like 5%")
+            })
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Length::Pixels(pixels) => write!(f, "{pixels}px"),
+            Length::Percent(percent) => write!(f, "{percent}%"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Length {
+    /// A number of pixels, or a string as parsed by [`Length::from_str`]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Pixels(i64),
+            Text(String),
+        }
+        match Value::deserialize(deserializer)? {
+            Value::Pixels(pixels) => Ok(Length::Pixels(pixels)),
+            Value::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// Space kept free on each side of the screen, percentages of the top and bottom margins are of
+/// the screen height and those of the left and right ones of its width
+pub struct Margins {
+    pub top: Length,
+    pub right: Length,
+    pub bottom: Length,
+    pub left: Length,
+}
+
+impl Margins {
+    /// The same margin on every side
+    pub fn uniform(length: Length) -> Self {
+        Margins {
+            top: length,
+            right: length,
+            bottom: length,
+            left: length,
+        }
+    }
+}
+
+impl FromStr for Margins {
+    type Err = String;
+
+    /// Parse one to four comma separated lengths in CSS order: all sides, vertical and
+    /// horizontal, top, horizontal and bottom, or top, right, bottom and left
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lengths = s
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<Length>, _>>()?;
+        match lengths[..] {
+            [all] => Ok(Margins::uniform(all)),
+            [vertical, horizontal] => Ok(Margins {
+                top: vertical,
+                right: horizontal,
+                bottom: vertical,
+                left: horizontal,
+            }),
+            [top, horizontal, bottom] => Ok(Margins {
+                top,
+                right: horizontal,
+                bottom,
+                left: horizontal,
+            }),
+            [top, right, bottom, left] => Ok(Margins {
+                top,
+                right,
+                bottom,
+                left,
+            }),
+            _ => Err(format!(
+                "Invalid margins '{s}', expected one to four lengths"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Margins {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self == Margins::uniform(self.top) {
+            true => write!(f, "{}", self.top),
+            false => write!(
+                f,
+                "{},{},{},{}",
+                self.top, self.right, self.bottom, self.left
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// How the comic is sized relative to the area inside the margins
+pub enum ScaleMode {
+    /// Keep the comic's size, shrinking comics that don't fit
+    #[default]
+    Shrink,
+    /// Keep the comic's size, comics that don't fit are cropped
+    None,
+    /// Largest size that fits, keeping the aspect ratio
+    Fit,
+    /// Smallest size that covers the area, keeping the aspect ratio and cropping the rest
+    Fill,
+    /// Exactly the size of the area, ignoring the aspect ratio
+    Stretch,
+    /// Like `Fit` for an area of this percentage of the width and height
+    Percent(f64),
+}
+
+impl FromStr for ScaleMode {
+    type Err = String;
+
+    /// Parse `shrink`, `none`, `fit`, `fill`, `stretch` or a percentage like `75%`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "shrink" => Ok(ScaleMode::Shrink),
+            "none" => Ok(ScaleMode::None),
+            "fit" => Ok(ScaleMode::Fit),
+            "fill" => Ok(ScaleMode::Fill),
+            "stretch" => Ok(ScaleMode::Stretch),
+            s => s
+                .strip_suffix('%')
+                .and_then(|percent| percent.trim().parse::<f64>().ok())
+                .filter(|percent| percent.is_finite() && *percent > 0.0)
+                .map(ScaleMode::Percent)
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid scale '{s}', expected shrink, none, fit, fill, stretch or a percentage like 75%"
+                    )
+                }),
+        }
+    }
+}
+
+impl fmt::Display for ScaleMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScaleMode::Shrink => write!(f, "shrink"),
+            ScaleMode::None => write!(f, "none"),
+            ScaleMode::Fit => write!(f, "fit"),
+            ScaleMode::Fill => write!(f, "fill"),
+            ScaleMode::Stretch => write!(f, "stretch"),
+            ScaleMode::Percent(percent) => write!(f, "{percent}%"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ScaleMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// Where the comic goes on the screen
+pub struct Layout {
+    pub placement: Placement,
+    pub anchor: Anchor,
+    pub scale: ScaleMode,
+    /// Space kept free around the comic, which never grows past them
+    pub margins: Margins,
+    /// Shift to the right, applied after placement, percentages are of the screen width
+    pub offset_x: Length,
+    /// Shift downwards, applied after placement, percentages are of the screen height
+    pub offset_y: Length,
+    /// Unsharp mask applied to the comic once scaled
+    pub sharpen: Option<Sharpen>,
+}
+
+impl From<Placement> for Layout {
+    fn from(placement: Placement) -> Self {
+        Layout {
+            placement,
+            ..Default::default()
+        }
+    }
+}
+
+impl Layout {
+    /// Top left corner of a `width` x `height` comic on the screen
+    ///
+    /// The comic is placed inside the margins, at the anchor.
+    pub fn position(
+        &self,
+        screen_dimensions: &ScreenDimensions,
+        width: u32,
+        height: u32,
+    ) -> (i64, i64) {
+        let (inner, left, top) = self.inner(screen_dimensions);
+        let (x, _) = self.placement.position(&inner, width, height);
+        // Truncated like the integer division centering always used
+        let align = |fraction: f64, free: i64| (fraction * free as f64) as i64;
+        let (horizontal, vertical) = self.anchor.alignment();
+        let x = match horizontal {
+            Some(fraction) => align(fraction, inner.width as i64 - width as i64),
+            None => x,
+        };
+        let y = align(vertical, inner.height as i64 - height as i64);
+        (
+            x + left + self.offset_x.resolve(screen_dimensions.width),
+            y + top + self.offset_y.resolve(screen_dimensions.height),
+        )
+    }
+
+    /// Size of a `width` x `height` comic on the screen, as `scale` says
+    pub fn scaled_size(
+        &self,
+        screen_dimensions: &ScreenDimensions,
+        width: u32,
+        height: u32,
+    ) -> (u32, u32) {
+        let (inner, _, _) = self.inner(screen_dimensions);
+        let fits = width <= inner.width && height <= inner.height;
+        let (scale_x, scale_y) = (
+            inner.width as f64 / width.max(1) as f64,
+            inner.height as f64 / height.max(1) as f64,
+        );
+        let scale = match self.scale {
+            ScaleMode::None => return (width, height),
+            ScaleMode::Shrink if fits => return (width, height),
+            ScaleMode::Stretch => return (inner.width.max(1), inner.height.max(1)),
+            ScaleMode::Shrink | ScaleMode::Fit => scale_x.min(scale_y),
+            ScaleMode::Fill => scale_x.max(scale_y),
+            ScaleMode::Percent(percent) => scale_x.min(scale_y) * percent / 100.0,
+        };
+        let scaled = |side: u32| ((side as f64 * scale).round() as u32).max(1);
+        (scaled(width), scaled(height))
+    }
+
+    /// The same layout with `top` and `bottom` more pixels of margin, room for captions
+    pub fn reserve(&self, screen_dimensions: &ScreenDimensions, top: u32, bottom: u32) -> Layout {
+        let grow = |length: Length, extra: u32| match extra {
+            0 => length,
+            _ => Length::Pixels(length.resolve(screen_dimensions.height).max(0) + extra as i64),
+        };
+        Layout {
+            margins: Margins {
+                top: grow(self.margins.top, top),
+                bottom: grow(self.margins.bottom, bottom),
+                ..self.margins
+            },
+            ..*self
+        }
+    }
+
+    /// Area inside the margins, with the left and top margin
+    fn inner(&self, screen_dimensions: &ScreenDimensions) -> (ScreenDimensions, i64, i64) {
+        let (width, height) = (screen_dimensions.width, screen_dimensions.height);
+        let horizontal = |length: Length| length.resolve(width).max(0);
+        let vertical = |length: Length| length.resolve(height).max(0);
+        let (left, top) = (horizontal(self.margins.left), vertical(self.margins.top));
+        let inner = ScreenDimensions {
+            width: (width as i64 - left - horizontal(self.margins.right)).max(0) as u32,
+            height: (height as i64 - top - vertical(self.margins.bottom)).max(0) as u32,
+        };
+        (inner, left, top)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const ULTRAWIDE: ScreenDimensions = ScreenDimensions {
+        width: 3440,
+        height: 1440,
+    };
+
+    #[rstest]
+    #[case(Placement::Center, 1320)]
+    #[case(Placement::ThirdsLeft, 747)]
+    #[case(Placement::ThirdsRight, 1893)]
+    #[case(Placement::GoldenLeft, 914)]
+    #[case(Placement::GoldenRight, 1726)]
+    fn placement_position(#[case] placement: Placement, #[case] x: i64) {
+        assert_eq!(placement.position(&ULTRAWIDE, 800, 400), (x, 520));
+    }
+
+    #[rstest]
+    #[case(Placement::ThirdsLeft, 0)]
+    #[case(Placement::ThirdsRight, 640)]
+    fn wide_comics_stay_on_screen(#[case] placement: Placement, #[case] x: i64) {
+        assert_eq!(placement.position(&ULTRAWIDE, 2800, 400).0, x);
+    }
+
+    #[test]
+    fn mirrored_variants() {
+        for (left, right) in [
+            (Placement::ThirdsLeft, Placement::ThirdsRight),
+            (Placement::GoldenLeft, Placement::GoldenRight),
+        ] {
+            let (left, _) = left.position(&ULTRAWIDE, 800, 400);
+            let (right, _) = right.position(&ULTRAWIDE, 800, 400);
+            assert_eq!(left + 800, ULTRAWIDE.width as i64 - right);
+        }
+    }
+
+    #[rstest]
+    #[case("5%", Length::Percent(5.0))]
+    #[case("-10%", Length::Percent(-10.0))]
+    #[case("40", Length::Pixels(40))]
+    #[case("-12px", Length::Pixels(-12))]
+    fn length_parse(#[case] input: &str, #[case] expected: Length) {
+        assert_eq!(input.parse::<Length>().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("5em")]
+    #[case("%")]
+    fn length_parse_error(#[case] input: &str) {
+        assert!(input.parse::<Length>().is_err());
+    }
+
+    #[rstest]
+    #[case(Layout::default(), (1320, 520))]
+    #[case(Layout { offset_y: Length::Percent(-10.0), ..Default::default() }, (1320, 376))]
+    #[case(Layout { offset_x: Length::Pixels(30), ..Default::default() }, (1350, 520))]
+    #[case(
+        Layout { placement: Placement::ThirdsLeft, margins: Margins::uniform(Length::Percent(5.0)), ..Default::default() },
+        (804, 520)
+    )]
+    fn layout_position(#[case] layout: Layout, #[case] expected: (i64, i64)) {
+        assert_eq!(layout.position(&ULTRAWIDE, 800, 400), expected);
+    }
+
+    #[rstest]
+    #[case(Layout::default(), (800, 400), (800, 400))]
+    #[case(Layout::default(), (6880, 1440), (3440, 720))]
+    #[case(Layout::default(), (1000, 2880), (500, 1440))]
+    #[case(Layout { margins: Margins::uniform(Length::Pixels(120)), ..Default::default() }, (3440, 1440), (2867, 1200))]
+    #[case(Layout { margins: "0,10%,0,0".parse().unwrap(), ..Default::default() }, (3440, 1440), (3096, 1296))]
+    fn oversized_comics_fit(
+        #[case] layout: Layout,
+        #[case] size: (u32, u32),
+        #[case] expected: (u32, u32),
+    ) {
+        assert_eq!(layout.scaled_size(&ULTRAWIDE, size.0, size.1), expected);
+    }
+
+    #[rstest]
+    #[case(ScaleMode::None, (6880, 1440))]
+    #[case(ScaleMode::Fit, (3440, 720))]
+    #[case(ScaleMode::Fill, (6880, 1440))]
+    #[case(ScaleMode::Stretch, (3440, 1440))]
+    #[case(ScaleMode::Percent(50.0), (1720, 360))]
+    fn scale_modes(#[case] scale: ScaleMode, #[case] expected: (u32, u32)) {
+        let layout = Layout {
+            scale,
+            ..Default::default()
+        };
+        assert_eq!(layout.scaled_size(&ULTRAWIDE, 6880, 1440), expected);
+        // Small comics are enlarged alike, except without scaling
+        let small = layout.scaled_size(&ULTRAWIDE, 688, 144);
+        assert_eq!(small == (688, 144), scale == ScaleMode::None);
+    }
+
+    #[rstest]
+    #[case("fit", ScaleMode::Fit)]
+    #[case("Stretch", ScaleMode::Stretch)]
+    #[case("75%", ScaleMode::Percent(75.0))]
+    fn scale_mode_parse(#[case] input: &str, #[case] expected: ScaleMode) {
+        assert_eq!(input.parse::<ScaleMode>().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("0%")]
+    #[case("zoom")]
+    fn scale_mode_parse_error(#[case] input: &str) {
+        assert!(input.parse::<ScaleMode>().is_err());
+    }
+
+    #[test]
+    fn reserve_room_below() {
+        let layout = Layout {
+            margins: Margins::uniform(Length::Percent(5.0)),
+            ..Default::default()
+        };
+        let reserved = layout.reserve(&ULTRAWIDE, 0, 100);
+        assert_eq!(reserved.margins.top, Length::Percent(5.0));
+        assert_eq!(reserved.margins.bottom, Length::Pixels(172));
+        // A comic as high as the screen is shrunk above the reserved space
+        assert_eq!(reserved.scaled_size(&ULTRAWIDE, 1440, 1440), (1196, 1196));
+        assert_eq!(reserved.position(&ULTRAWIDE, 1196, 1196), (1122, 72));
+    }
+
+    #[test]
+    fn margins_keep_wide_comics_inside() {
+        let layout = Layout {
+            placement: Placement::ThirdsRight,
+            margins: Margins::uniform(Length::Pixels(100)),
+            ..Default::default()
+        };
+        assert_eq!(layout.position(&ULTRAWIDE, 2800, 400).0, 540);
+    }
+
+    #[rstest]
+    #[case("10%", Margins::uniform(Length::Percent(10.0)))]
+    #[case(
+        "5%,40",
+        Margins { top: Length::Percent(5.0), right: Length::Pixels(40), bottom: Length::Percent(5.0), left: Length::Pixels(40) }
+    )]
+    #[case(
+        "0, 10%, 20px, 0",
+        Margins { top: Length::Pixels(0), right: Length::Percent(10.0), bottom: Length::Pixels(20), left: Length::Pixels(0) }
+    )]
+    fn margins_parse(#[case] input: &str, #[case] expected: Margins) {
+        let margins = input.parse::<Margins>().unwrap();
+        assert_eq!(margins, expected);
+        assert_eq!(margins.to_string().parse::<Margins>().unwrap(), expected);
+    }
+
+    #[test]
+    fn margins_parse_error() {
+        assert!("1,2,3,4,5".parse::<Margins>().is_err());
+        assert!("1,,2".parse::<Margins>().is_err());
+    }
+
+    #[test]
+    fn uneven_margins_position() {
+        let layout = Layout {
+            margins: "0,0,0,40%".parse().unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(layout.position(&ULTRAWIDE, 800, 400), (2008, 520));
+    }
+
+    #[rstest]
+    #[case(Anchor::TopLeft, (0, 0))]
+    #[case(Anchor::BottomRight, (2640, 1040))]
+    #[case(Anchor::Right, (2640, 520))]
+    #[case(Anchor::Top, (747, 0))]
+    fn anchored_position(#[case] anchor: Anchor, #[case] expected: (i64, i64)) {
+        let layout = Layout {
+            placement: Placement::ThirdsLeft,
+            anchor,
+            ..Default::default()
+        };
+        assert_eq!(layout.position(&ULTRAWIDE, 800, 400), expected);
+    }
+
+    #[test]
+    fn offsets_nudge_anchored_comics() {
+        let layout = Layout {
+            anchor: Anchor::BottomRight,
+            offset_x: Length::Pixels(-60),
+            offset_y: Length::Percent(-5.0),
+            ..Default::default()
+        };
+        assert_eq!(layout.position(&ULTRAWIDE, 800, 400), (2580, 968));
+    }
+}
@@ -1,88 +1,510 @@
 use std::fs::File;
-use std::io::{copy, BufReader};
+use std::io::{BufWriter, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
-use image::imageops::overlay;
-use image::{DynamicImage, ImageBuffer, ImageReader};
+use image::imageops::{self, overlay, FilterType};
+use image::{DynamicImage, ImageFormat, ImageReader, RgbaImage};
+use jiff::civil::Date;
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-#[derive(Clone, Debug, Default, PartialEq, clap::ValueEnum, Serialize)]
-/// Foreground color for drawings, either light or dark
+use crate::background::Background;
+use crate::decoration::Decoration;
+use crate::layout::Layout;
+use crate::locale::{abbreviate, Language};
+use crate::net::{ComicFetcher, UreqFetcher};
+use crate::recolor::Recolor;
+
+pub mod appearance;
+pub mod archive;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod background;
+pub mod badge;
+pub mod batch;
+pub mod boot;
+pub mod browse;
+pub mod cache;
+pub mod collage;
+pub mod colorscheme;
+pub mod config;
+pub mod contact;
+pub mod decoration;
+pub mod device;
+pub mod dirs;
+pub mod download;
+pub mod effects;
+pub mod font;
+pub mod gallery;
+pub mod history;
+pub mod hooks;
+pub mod layout;
+pub mod locale;
+pub mod net;
+pub mod notify;
+pub mod panels;
+pub mod plan;
+pub mod poll;
+pub mod progress;
+pub mod qr;
+pub mod quality;
+pub mod quantize;
+pub mod random;
+pub mod recolor;
+pub mod schedule;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod setter;
+pub mod share;
+pub mod split;
+pub mod state;
+pub mod stats;
+pub mod template;
+pub mod text;
+pub mod theme;
+pub mod tile;
+pub mod timings;
+pub mod watermark;
+#[cfg(all(unix, feature = "wayland"))]
+pub mod wayland;
+pub mod web;
+pub mod workspace;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+/// Foreground color for drawings: light, dark or any color, written `light`, `dark` or
+/// `#RRGGBB`
 pub enum ForegroundColor {
     #[default]
     Light,
     Dark,
+    /// The line art recolored to this color, colors of the comic are lost
+    Custom(image::Rgba<u8>),
+}
+
+impl ForegroundColor {
+    /// Color of the drawings once rendered
+    pub fn ink(&self) -> image::Rgba<u8> {
+        match self {
+            ForegroundColor::Light => image::Rgba([255, 255, 255, 255]),
+            ForegroundColor::Dark => image::Rgba([0, 0, 0, 255]),
+            ForegroundColor::Custom(ink) => *ink,
+        }
+    }
+
+    /// Whether the drawings are closer to white than to black
+    pub fn is_light(&self) -> bool {
+        let [r, g, b, _] = self.ink().0;
+        // Rec. 601 luma
+        299 * r as u32 + 587 * g as u32 + 114 * b as u32 >= 128 * 1000
+    }
+}
+
+impl FromStr for ForegroundColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "light" => Ok(ForegroundColor::Light),
+            "dark" => Ok(ForegroundColor::Dark),
+            _ => theme::parse_hex_color(s)
+                .map(ForegroundColor::Custom)
+                .map_err(|_| format!("Invalid foreground '{s}', expected light, dark or #RRGGBB")),
+        }
+    }
 }
 
-#[derive(Debug)]
+impl TryFrom<String> for ForegroundColor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<ForegroundColor> for String {
+    fn from(fg: ForegroundColor) -> Self {
+        match fg {
+            ForegroundColor::Light => "light".to_string(),
+            ForegroundColor::Dark => "dark".to_string(),
+            ForegroundColor::Custom(ink) => format!("#{:02X}{:02X}{:02X}", ink[0], ink[1], ink[2]),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 /// Represents dimensions of a screen
 pub struct ScreenDimensions {
     pub width: u32,
     pub height: u32,
 }
 
+impl ScreenDimensions {
+    /// Use the dimensions of an existing image file, e.g. the current wallpaper
+    pub fn from_image_file(path: &Path) -> Result<Self, XkcdError> {
+        let (width, height) = image::image_dimensions(path)?;
+        info!(
+            "read dimensions {}x{} from {}",
+            width,
+            height,
+            path.display()
+        );
+        Ok(ScreenDimensions { width, height })
+    }
+}
+
+impl FromStr for ScreenDimensions {
+    type Err = String;
+
+    /// Parse dimensions in `WIDTHxHEIGHT` format, e.g. `2560x1440`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .trim()
+            .split_once(['x', 'X', '×'])
+            .ok_or("Size must be in WIDTHxHEIGHT format (e.g. 2560x1440)")?;
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|&v| v > 0)
+                .ok_or(format!("Invalid dimension '{value}'"))
+        };
+
+        Ok(ScreenDimensions {
+            width: parse(width)?,
+            height: parse(height)?,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Comic selected on the command line, either by number or relative to the newest comic
+pub enum ComicRef {
+    Number(u32),
+    /// The comic `n` issues before the newest one
+    Latest(u32),
+}
+
+impl ComicRef {
+    /// Comic number to download, `None` for the newest comic
+    ///
+    /// Fetches the newest comic's metadata when an offset has to be resolved.
+    pub fn resolve(&self) -> Result<Option<u32>, XkcdError> {
+        self.resolve_with(|| Ok(Metadata::from_comic_id(None)?.num))
+    }
+
+    /// Like `resolve`, with the newest comic's number given by `latest`
+    pub fn resolve_with(
+        &self,
+        latest: impl FnOnce() -> Result<u64, XkcdError>,
+    ) -> Result<Option<u32>, XkcdError> {
+        match *self {
+            ComicRef::Number(num) => Ok(Some(num)),
+            ComicRef::Latest(0) => Ok(None),
+            ComicRef::Latest(offset) => {
+                let latest = latest()?;
+                let num = latest.checked_sub(offset as u64).filter(|num| *num > 0);
+                info!("resolved latest-{} to comic {:?}", offset, num);
+                num.map(|num| Some(num as u32)).ok_or_else(|| {
+                    XkcdError::Other(format!(
+                        "latest-{offset} is before the first comic, the newest is {latest}"
+                    ))
+                })
+            }
+        }
+    }
+}
+
+impl FromStr for ComicRef {
+    type Err = String;
+
+    /// Parse a comic number, `latest`, `latest-N` or `~N`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let offset = |value: &str| {
+            value
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid comic offset '{value}'"))
+        };
+
+        if s == "latest" {
+            Ok(ComicRef::Latest(0))
+        } else if let Some(rest) = s.strip_prefix("latest-") {
+            Ok(ComicRef::Latest(offset(rest)?))
+        } else if let Some(rest) = s.strip_prefix('~') {
+            Ok(ComicRef::Latest(offset(rest)?))
+        } else {
+            s.parse::<u32>()
+                .ok()
+                .filter(|num| *num > 0)
+                .map(ComicRef::Number)
+                .ok_or(match s.contains("..") || s.contains(',') {
+                    true => format!("Invalid comic '{s}', use --comics for lists and ranges"),
+                    false => {
+                        format!("Invalid comic '{s}', expected a number, latest, latest-N or ~N")
+                    }
+                })
+        }
+    }
+}
+
+/// Number of a uniformly random comic, fetching the newest comic's number first
+pub fn random_comic_number() -> Result<u64, XkcdError> {
+    let latest = Metadata::from_comic_id(None)?.num;
+    let num = random::Rng::from_clock().comic(latest);
+    info!("picked random comic {} of {}", num, latest);
+    Ok(num)
+}
+
+/// Rows composed and encoded at a time by `save_wallpaper_streaming`
+const STREAMING_BAND_ROWS: u32 = 256;
+
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum XkcdError {
+    #[error("Not found")]
+    NotFound,
+    /// The API has no comic with this number, such as 404 or one after the newest comic
+    #[error("Comic {0} does not exist")]
+    ComicNotFound(u32),
+    #[error("Rate limited by server")]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("Request timed out")]
+    Timeout,
+    #[error("HTTP error: status {0}")]
+    Http(u16),
+    #[error("Network error: {0}")]
+    Network(ureq::Error),
+    /// Error of the async client, see [`asynchronous::ReqwestFetcher`]
+    #[cfg(feature = "async")]
     #[error("Network error: {0}")]
-    Network(#[from] ureq::Error),
+    AsyncNetwork(reqwest::Error),
     #[error("Image error: {0}")]
     Image(#[from] image::ImageError),
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("PNG encoding error: {0}")]
+    Png(#[from] png::EncodingError),
     #[error("Tempfile error: {0}")]
     Tempfile(#[from] tempfile::PersistError),
+    #[error("Config error: {0}")]
+    Config(String),
+    #[error("Wallpaper setter error: {0}")]
+    Setter(String),
+    #[error("Hook error: {0}")]
+    Hook(String),
+    #[error("Script error: {0}")]
+    Script(String),
+    #[error("{}", match .0 {
+        Some(num) => format!("Comic {num} is not cached"),
+        None => "No comics are cached".to_string(),
+    })]
+    NotCached(Option<u64>),
+    #[error("Comic {num} is unsuitable: {reason}")]
+    Unsuitable {
+        num: u64,
+        reason: quality::Unsuitable,
+    },
     #[error("Other error: {0}")]
     Other(String),
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl From<ureq::Error> for XkcdError {
+    fn from(err: ureq::Error) -> Self {
+        match err {
+            ureq::Error::StatusCode(status) => XkcdError::from_status(status, None),
+            ureq::Error::Timeout(_) => XkcdError::Timeout,
+            err => XkcdError::Network(err),
+        }
+    }
+}
+
+impl XkcdError {
+    /// Classify an unsuccessful HTTP status, `retry_after` is the delay requested by the server
+    pub fn from_status(status: u16, retry_after: Option<Duration>) -> Self {
+        match (status, retry_after) {
+            (404, _) => XkcdError::NotFound,
+            (429, _) | (503, Some(_)) => XkcdError::RateLimited { retry_after },
+            _ => XkcdError::Http(status),
+        }
+    }
+
+    /// `ComicNotFound` for a `NotFound` while getting the metadata of `comic`
+    pub(crate) fn for_comic(self, comic: Option<u32>) -> Self {
+        match (self, comic) {
+            (XkcdError::NotFound, Some(num)) => XkcdError::ComicNotFound(num),
+            (err, _) => err,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 /// Metadata obtained through the xkcd API
 pub struct Metadata {
     pub num: u64,
     pub safe_title: String,
     pub img: String,
+    #[serde(default)]
+    pub alt: String,
+    /// Link the comic points to, for some comics the large version of the image
+    #[serde(default)]
+    pub link: String,
+    /// Title as published, `safe_title` is the same without HTML entities and markup
+    #[serde(default)]
+    pub title: String,
+    /// Text of the comic, only available for older comics
+    #[serde(default)]
+    pub transcript: String,
+    /// Announcement shown above the comic on xkcd.com, mostly empty
+    #[serde(default)]
+    pub news: String,
     pub day: String,
     pub month: String,
     pub year: String,
 }
 
 impl Metadata {
+    /// Publish date, `None` if the API returned an invalid date
+    pub fn date(&self) -> Option<Date> {
+        Date::new(
+            self.year.trim().parse().ok()?,
+            self.month.trim().parse().ok()?,
+            self.day.trim().parse().ok()?,
+        )
+        .ok()
+    }
+
     pub fn from_comic_id(comic_number: Option<u32>) -> Result<Metadata, XkcdError> {
-        let metadata_url = match comic_number {
-            Some(num) => format!("https://xkcd.com/{}/info.0.json", num),
-            None => "https://xkcd.com/info.0.json".to_string(),
-        };
+        Metadata::from_comic_id_with(&UreqFetcher, comic_number)
+    }
+
+    /// Same as `from_comic_id`, but downloaded with `fetcher`
+    pub fn from_comic_id_with(
+        fetcher: &dyn ComicFetcher,
+        comic_number: Option<u32>,
+    ) -> Result<Metadata, XkcdError> {
+        let metadata_url = Metadata::url(comic_number);
         info!("downloading metadata from url {}", metadata_url);
+        let _timer = timings::stage("metadata");
 
-        let recv_body = ureq::get(metadata_url)
-            .call()?
-            .body_mut()
-            .read_json::<Metadata>()?;
+        let body = fetcher
+            .get(&metadata_url)
+            .map_err(|err| err.for_comic(comic_number))?;
+        let recv_body = Metadata::read(body)?;
         info!("metadata downloaded successfully");
 
         Ok(recv_body)
     }
 
+    /// API URL of a comic's metadata, the newest one for `None`
+    pub(crate) fn url(comic_number: Option<u32>) -> String {
+        match comic_number {
+            Some(num) => format!("https://xkcd.com/{}/info.0.json", num),
+            None => "https://xkcd.com/info.0.json".to_string(),
+        }
+    }
+
+    /// Parse a response of the metadata API
+    pub(crate) fn read(reader: impl Read) -> Result<Metadata, XkcdError> {
+        serde_json::from_reader(reader)
+            .map_err(|err| XkcdError::Other(format!("invalid comic metadata: {err}")))
+    }
+
     pub fn to_image(&self) -> Result<ComicImage, XkcdError> {
-        // NamedTempFile over tempfile because it requires .png suffix to be supported by ImageReader
-        let mut file = tempfile::NamedTempFile::with_suffix(".png")?;
-        download_img(&self.img, file.as_file_mut())?;
+        self.to_image_with(&UreqFetcher)
+    }
+
+    /// Same as `to_image`, but downloaded with `fetcher`
+    pub fn to_image_with(&self, fetcher: &dyn ComicFetcher) -> Result<ComicImage, XkcdError> {
+        let (bytes, resolution) = {
+            let _timer = timings::stage("download");
+            download_img(fetcher, self)?
+        };
+        self.decode(bytes, resolution)
+    }
 
-        let img = ImageReader::open(file.path())?.decode()?;
+    /// Download the comic image to `path`, at 2x resolution when available
+    pub fn download_to(&self, path: &Path) -> Result<Resolution, XkcdError> {
+        let (bytes, resolution) = download_img(&UreqFetcher, self)?;
+        std::fs::write(path, bytes)?;
+        Ok(resolution)
+    }
+
+    /// Download the large version of the comic, `None` if it does not have one
+    pub fn to_large_image(&self) -> Result<Option<ComicImage>, XkcdError> {
+        self.to_large_image_with(&UreqFetcher)
+    }
+
+    /// Same as `to_large_image`, but downloaded with `fetcher`
+    pub fn to_large_image_with(
+        &self,
+        fetcher: &dyn ComicFetcher,
+    ) -> Result<Option<ComicImage>, XkcdError> {
+        let Some(url) = download::large_image_url(fetcher, self)? else {
+            return Ok(None);
+        };
+        let bytes = {
+            let _timer = timings::stage("download");
+            info!("downloading large img {}", url);
+            read_body(fetcher.get(&url)?)?
+        };
+        self.decode(bytes, Resolution::Large).map(Some)
+    }
+
+    /// Decode a downloaded image, the format is sniffed from its contents
+    ///
+    /// Comics are PNG, JPEG or GIF. When the contents are not recognised, the extension of the
+    /// image URL decides.
+    pub(crate) fn decode(
+        &self,
+        bytes: Vec<u8>,
+        resolution: Resolution,
+    ) -> Result<ComicImage, XkcdError> {
+        let img = {
+            let _timer = timings::stage("decode");
+            let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+            if reader.format().is_none() {
+                if let Ok(format) = ImageFormat::from_path(&self.img) {
+                    reader.set_format(format);
+                }
+            }
+            reader.decode()?
+        };
 
         Ok(ComicImage {
             img,
             metadata: self.clone(),
+            resolution,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 /// Wrapper for xkcd image which contains metadata
 pub struct Image {
     pub img: DynamicImage,
     pub metadata: Metadata,
+    /// Version of the comic image that was downloaded, for wallpapers the one they were made of
+    pub resolution: Resolution,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// Which version of a comic image was downloaded
+pub enum Resolution {
+    /// Not recorded, e.g. for images cached by an older version
+    #[default]
+    Unknown,
+    Regular,
+    /// The 2x version, see [`download::scaled_image_url`]
+    Double,
+    /// The "large version" some comics link to, see [`download::large_image_url`]
+    Large,
 }
 type ComicImage = Image;
 type WallpaperImage = Image;
@@ -93,27 +515,34 @@ impl Image {
     /// # Filename placeholders
     /// The output filename can use placeholders which will be substituted with corresponding metadata
     ///
-    /// y   Two-digit year (e.g., 25)
+    /// Y   Year (e.g., 2025)
+    /// y   Year as well, kept from earlier versions (e.g., 2025)
     /// m   Two-digit month (e.g., 06)
     /// d   Two-digit day (e.g., 22)
+    /// B   Month name (e.g., June)
     /// n   Comic number
-    /// t   Title   
-    /// For instance `./output/%y-%m-%d-%t` would generated a file `./output/2025-06-20-SomeTitle`.
-    pub fn save(&self, filename: &str) {
-        let filename = convert_fmt_filename(filename, &self.metadata);
-        let _ = self.img.save(filename);
+    /// t   Title
+    ///
+    /// Other `strftime` placeholders are taken from the publish date as well.
+    /// For instance `./output/%Y-%m-%d-%t` would generated a file `./output/2025-06-20-SomeTitle`.
+    ///
+    /// Returns the path the image was written to, the image format follows its extension.
+    pub fn save(&self, filename: &str) -> Result<PathBuf, XkcdError> {
+        let _timer = timings::stage("encode");
+        let path = self.output_path(filename);
+        self.img.save(&path)?;
+        Ok(path)
+    }
+
+    /// Path `save` writes to for `filename`, with placeholders substituted
+    pub fn output_path(&self, filename: &str) -> PathBuf {
+        PathBuf::from(convert_fmt_filename(filename, &self.metadata))
     }
 }
 
 impl ComicImage {
     pub fn from_metadata(metadata: Metadata) -> Result<Self, XkcdError> {
-        // NamedTempFile over tempfile because it requires .png suffix to be supported by ImageReader
-        let mut file = tempfile::NamedTempFile::with_suffix(".png")?;
-        download_img(&metadata.img, file.as_file_mut())?;
-
-        let img = ImageReader::open(file.path())?.decode()?;
-
-        Ok(WallpaperImage { img, metadata })
+        metadata.to_image()
     }
 }
 
@@ -121,107 +550,547 @@ impl ComicImage {
 pub fn get_wallpaper_from_comic(
     comic_img: ComicImage,
     fg_color: ForegroundColor,
-    bg_color: image::Rgba<u8>,
+    background: impl Into<Background>,
     screen_dimensions: ScreenDimensions,
+    layout: Layout,
+    recolor: Recolor,
+    decoration: Decoration,
 ) -> WallpaperImage {
+    let _timer = timings::stage("composite");
+    let background = background.into();
     let metadata = comic_img.metadata;
-    let mut comic_img = comic_img.img.to_owned();
+    let resolution = comic_img.resolution;
+    let comic_buffer = recolor.apply(
+        comic_img.img,
+        &fg_color,
+        decoration.fill().unwrap_or(background.fill()),
+    );
+    let background = background.behind(&comic_buffer, &screen_dimensions);
+    let captions = decoration.captions(&metadata, fg_color.ink(), &screen_dimensions);
+    let (top, bottom) = decoration.reserved(&captions);
+    let layout = layout.reserve(&screen_dimensions, top, bottom);
+    let comic_buffer = scale_comic(comic_buffer, &layout, &screen_dimensions);
 
-    if fg_color == ForegroundColor::Light {
-        info!("inverting image colors");
-        comic_img.invert();
+    info!("placing comic on the background with {:?}", layout);
+    let (x, y) = layout.position(
+        &screen_dimensions,
+        comic_buffer.width(),
+        comic_buffer.height(),
+    );
+    let mut background_buffer = background.canvas(&screen_dimensions);
+    for (layer, dx, dy) in decoration.under(comic_buffer.width(), comic_buffer.height()) {
+        overlay(&mut background_buffer, &layer, x + dx, y + dy);
     }
-
-    let mut comic_buffer = comic_img.into_rgba8();
-
-    let comic_background_color = match fg_color {
-        ForegroundColor::Light => image::Rgba([0, 0, 0, 255]),
-        ForegroundColor::Dark => image::Rgba([255, 255, 255, 255]),
-    };
-
-    info!("replacing background pixels with background colors");
-    for (_x, _y, pixel) in comic_buffer.enumerate_pixels_mut() {
-        if *pixel == comic_background_color {
-            *pixel = bg_color;
-        }
+    overlay(&mut background_buffer, &comic_buffer, x, y);
+    let (width, height) = comic_buffer.dimensions();
+    for (layer, dx, dy) in decoration.over(captions, width, height) {
+        overlay(&mut background_buffer, &layer, x + dx, y + dy);
     }
 
-    // Place comic in the middle of the background buffer
-    info!("placing comic in center of the background");
-    let mut background_buffer =
-        ImageBuffer::from_pixel(screen_dimensions.width, screen_dimensions.height, bg_color);
-    overlay(
-        &mut background_buffer,
-        &comic_buffer,
-        (screen_dimensions.width / 2 - comic_buffer.width() / 2).into(),
-        (screen_dimensions.height / 2 - comic_buffer.height() / 2).into(),
-    );
-
     WallpaperImage {
         img: DynamicImage::ImageRgba8(background_buffer),
         metadata,
+        resolution,
     }
 }
 
-fn download_img(original_url: &str, mut output_file: &File) -> Result<(), XkcdError> {
-    let scaled_url = original_url.replace(".png", "_2x.png");
+/// Same as `get_wallpaper_from_comic` followed by `Image::save`, but composes and encodes the
+/// wallpaper in horizontal bands so the full canvas is never held in memory. Only writes PNG.
+///
+/// Picture and blurred backgrounds are still scaled to the full screen once, before the bands
+/// are cut.
+///
+/// Returns the path the wallpaper was written to.
+#[allow(clippy::too_many_arguments)]
+pub fn save_wallpaper_streaming(
+    comic_img: ComicImage,
+    fg_color: ForegroundColor,
+    background: impl Into<Background>,
+    screen_dimensions: ScreenDimensions,
+    layout: Layout,
+    recolor: Recolor,
+    decoration: Decoration,
+    filename: &str,
+) -> Result<PathBuf, XkcdError> {
+    let background = background.into();
+    let path = PathBuf::from(convert_fmt_filename(filename, &comic_img.metadata));
+    let captions = decoration.captions(&comic_img.metadata, fg_color.ink(), &screen_dimensions);
+    let (top, bottom) = decoration.reserved(&captions);
+    let layout = layout.reserve(&screen_dimensions, top, bottom);
+    let (comic_buffer, background, canvas) = {
+        let _timer = timings::stage("composite");
+        let comic_buffer = recolor.apply(
+            comic_img.img,
+            &fg_color,
+            decoration.fill().unwrap_or(background.fill()),
+        );
+        let background = background.behind(&comic_buffer, &screen_dimensions);
+        let canvas = match background {
+            Background::Color(_) => None,
+            _ => Some(background.canvas(&screen_dimensions)),
+        };
+        (
+            scale_comic(comic_buffer, &layout, &screen_dimensions),
+            background,
+            canvas,
+        )
+    };
+    let (x, y) = layout.position(
+        &screen_dimensions,
+        comic_buffer.width(),
+        comic_buffer.height(),
+    );
+    let (width, height) = comic_buffer.dimensions();
+    let layers = decoration.under(width, height);
+    let layers_over = decoration.over(captions, width, height);
+
+    info!(
+        "writing wallpaper to {} in bands of {} rows",
+        path.display(),
+        STREAMING_BAND_ROWS
+    );
+    let file = BufWriter::new(File::create(&path)?);
+    let mut encoder = png::Encoder::new(file, screen_dimensions.width, screen_dimensions.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    let mut stream = writer.stream_writer()?;
+
+    for band_start in (0..screen_dimensions.height).step_by(STREAMING_BAND_ROWS as usize) {
+        let rows = STREAMING_BAND_ROWS.min(screen_dimensions.height - band_start);
+        let band = {
+            let _timer = timings::stage("composite");
+            let mut band =
+                background.band(canvas.as_ref(), screen_dimensions.width, band_start, rows);
+            for (layer, dx, dy) in &layers {
+                overlay(&mut band, layer, x + dx, y + dy - band_start as i64);
+            }
+            overlay(&mut band, &comic_buffer, x, y - band_start as i64);
+            for (layer, dx, dy) in &layers_over {
+                overlay(&mut band, layer, x + dx, y + dy - band_start as i64);
+            }
+            band
+        };
+        let _timer = timings::stage("encode");
+        stream.write_all(band.as_raw())?;
+    }
+    stream.finish()?;
+
+    Ok(path)
+}
 
-    info!("downloading img {}", scaled_url);
-    let mut response = match ureq::get(scaled_url).call() {
-        Ok(res) => res,
-        Err(_) => {
-            warn!(
-                "cannot get image with 2x resolution, falling back to regular res. {}",
-                original_url
+/// Resize a comic to its size on the screen, see [`Layout::scaled_size`], then sharpen it
+/// when the layout says so
+pub(crate) fn scale_comic(
+    comic_buffer: RgbaImage,
+    layout: &Layout,
+    screen_dimensions: &ScreenDimensions,
+) -> RgbaImage {
+    let (width, height) = comic_buffer.dimensions();
+    let (scaled_width, scaled_height) = layout.scaled_size(screen_dimensions, width, height);
+    let scaled = match (scaled_width, scaled_height) == (width, height) {
+        true => comic_buffer,
+        false => {
+            info!(
+                "scaling comic from {}x{} to {}x{} ({})",
+                width, height, scaled_width, scaled_height, layout.scale
             );
-            ureq::get(original_url).call()?
+            imageops::resize(
+                &comic_buffer,
+                scaled_width,
+                scaled_height,
+                FilterType::Lanczos3,
+            )
         }
     };
+    match layout.sharpen {
+        Some(sharpen) => effects::sharpen(&scaled, sharpen),
+        None => scaled,
+    }
+}
+
+/// Download a comic image, at 2x resolution when available
+pub(crate) fn download_img(
+    fetcher: &dyn ComicFetcher,
+    metadata: &Metadata,
+) -> Result<(Vec<u8>, Resolution), XkcdError> {
+    download_img_with(fetcher, metadata, |url| read_body(fetcher.get(url)?))
+}
 
-    info!("reading response into BufReader");
-    let mut reader = BufReader::new(response.body_mut().with_config().reader());
-    copy(&mut reader, &mut output_file)?;
+/// Like `download_img`, with the bytes of each URL tried downloaded by `download`
+///
+/// `fetcher` is only used to look up the 2x URL, see [`download::scaled_image_url`].
+pub(crate) fn download_img_with(
+    fetcher: &dyn ComicFetcher,
+    metadata: &Metadata,
+    mut download: impl FnMut(&str) -> Result<Vec<u8>, XkcdError>,
+) -> Result<(Vec<u8>, Resolution), XkcdError> {
+    if let Some(scaled_url) = download::scaled_image_url(fetcher, metadata) {
+        info!("downloading img {}", scaled_url);
+        match download(&scaled_url) {
+            Ok(bytes) => return Ok((bytes, Resolution::Double)),
+            Err(err) => warn!(
+                "cannot get image with 2x resolution ({}), falling back to regular res. {}",
+                err, metadata.img
+            ),
+        }
+    }
 
-    Ok(())
+    info!("downloading img {}", metadata.img);
+    Ok((download(&metadata.img)?, Resolution::Regular))
 }
 
+fn read_body(mut body: Box<dyn Read + Send>) -> Result<Vec<u8>, XkcdError> {
+    let mut bytes = Vec::new();
+    body.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Substitute placeholders in an output file name template
+///
+/// `%t` is the title and `%n` the comic number, other placeholders follow `strftime` and are
+/// taken from the publish date, with month and weekday names in the language of the locale.
+/// `%y` is the full year like `%Y`, as it was before `strftime` placeholders were supported.
 fn convert_fmt_filename(format_filename: &str, metadata: &Metadata) -> String {
-    let replacements = [
-        ("%y", metadata.year.as_str()),
-        ("%m", metadata.month.as_str()),
-        ("%d", metadata.day.as_str()),
-        ("%t", metadata.safe_title.as_str()),
-        ("%n", &metadata.num.to_string()),
-    ];
+    let output = format_comic(format_filename, metadata, Language::from_env());
+    info!("converted filename from {} to {}", format_filename, output);
+    output
+}
 
-    let mut output = format_filename.to_owned();
-    for (token, value) in &replacements {
-        output = output.replace(token, value);
-    }
+pub(crate) fn format_comic(format: &str, metadata: &Metadata, language: Language) -> String {
+    let date = metadata.date();
+    let mut output = String::with_capacity(format.len());
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
 
-    info!("converted filename from {} to {}", format_filename, output);
+        // `%-d` drops the zero padding, as in GNU strftime
+        let mut spec = chars.next();
+        let pad = spec != Some('-');
+        if !pad {
+            spec = chars.next();
+        }
+        let number = |value: i64, width: usize| match pad {
+            true => format!("{value:0width$}"),
+            false => value.to_string(),
+        };
+
+        match (spec, date) {
+            (Some('t'), _) => output.push_str(&metadata.safe_title),
+            (Some('n'), _) => output.push_str(&metadata.num.to_string()),
+            (Some('%'), _) => output.push('%'),
+            // Unlike strftime, `%y` is the full year as it always was in file name templates
+            (Some('Y' | 'y'), Some(date)) => output.push_str(&date.year().to_string()),
+            (Some('m'), Some(date)) => output.push_str(&number(date.month().into(), 2)),
+            (Some('d'), Some(date)) => output.push_str(&number(date.day().into(), 2)),
+            (Some('e'), Some(date)) => output.push_str(&format!("{:>2}", date.day())),
+            (Some('j'), Some(date)) => output.push_str(&number(date.day_of_year().into(), 3)),
+            (Some('F'), Some(date)) => output.push_str(&date.to_string()),
+            (Some('B'), Some(date)) => output.push_str(language.month(date.month())),
+            (Some('b' | 'h'), Some(date)) => {
+                output.push_str(&abbreviate(language.month(date.month())))
+            }
+            (Some('A'), Some(date)) => {
+                output.push_str(language.weekday(date.weekday().to_monday_one_offset()))
+            }
+            (Some('a'), Some(date)) => output.push_str(&abbreviate(
+                language.weekday(date.weekday().to_monday_one_offset()),
+            )),
+            (Some('u'), Some(date)) => {
+                output.push_str(&date.weekday().to_monday_one_offset().to_string())
+            }
+            (Some('w'), Some(date)) => {
+                output.push_str(&date.weekday().to_sunday_zero_offset().to_string())
+            }
+            // Keep what the API returned when the date cannot be parsed
+            (Some('Y' | 'y'), None) => output.push_str(&metadata.year),
+            (Some('m'), None) => output.push_str(&metadata.month),
+            (Some('d' | 'e'), None) => output.push_str(&metadata.day),
+            (Some(other), _) => {
+                output.push('%');
+                if !pad {
+                    output.push('-');
+                }
+                output.push(other);
+            }
+            (None, _) => output.push('%'),
+        }
+    }
     output
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::layout::{Length, Placement};
     use rstest::rstest;
 
     #[rstest]
-    #[case("%y.png", "2025.png")]
-    #[case("output/file.png", "output/file.png")]
-    #[case("%y-%m-%d_%t", "2025-06-27_Some title")]
-    fn convert_filename_ok(#[case] input: &str, #[case] output: &str) {
-        let metadata = Metadata {
+    #[case(ureq::Error::StatusCode(404), "NotFound")]
+    #[case(ureq::Error::StatusCode(429), "RateLimited { retry_after: None }")]
+    #[case(ureq::Error::StatusCode(503), "Http(503)")]
+    #[case(ureq::Error::HostNotFound, "Network(HostNotFound)")]
+    fn xkcd_error_from_ureq(#[case] input: ureq::Error, #[case] expected: &str) {
+        assert_eq!(format!("{:?}", XkcdError::from(input)), expected);
+    }
+
+    /// Serves canned bodies by URL, anything else is not found
+    struct MockFetcher(std::collections::HashMap<&'static str, Vec<u8>>);
+
+    impl ComicFetcher for MockFetcher {
+        fn get(&self, url: &str) -> Result<Box<dyn std::io::Read + Send>, XkcdError> {
+            match self.0.get(url) {
+                Some(body) => Ok(Box::new(std::io::Cursor::new(body.clone()))),
+                None => Err(XkcdError::NotFound),
+            }
+        }
+    }
+
+    #[test]
+    fn save_reports_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let wallpaper = Image {
+            img: DynamicImage::ImageRgba8(RgbaImage::new(2, 2)),
+            metadata: metadata("2011", "7", "20"),
+            resolution: Resolution::Regular,
+        };
+
+        let template = dir.path().join("%n-%Y.png");
+        let path = wallpaper.save(template.to_str().unwrap()).unwrap();
+        assert_eq!(path, dir.path().join("42-2011.png"));
+        assert!(path.is_file());
+
+        let missing_dir = dir.path().join("missing").join("%n.png");
+        assert!(wallpaper.save(missing_dir.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn metadata_full_api_response() {
+        let metadata: Metadata = serde_json::from_str(
+            r#"{"month": "1", "num": 10, "link": "", "year": "2006", "news": "",
+                "safe_title": "Pi Equals", "transcript": "Pi = 3.141592...",
+                "alt": "My most famous drawing", "img": "https://imgs.xkcd.com/comics/pi.jpg",
+                "title": "Pi Equals", "day": "1"}"#,
+        )
+        .unwrap();
+        assert_eq!(metadata.title, "Pi Equals");
+        assert_eq!(metadata.transcript, "Pi = 3.141592...");
+        assert_eq!(metadata.alt, "My most famous drawing");
+        assert!(metadata.news.is_empty());
+    }
+
+    #[test]
+    fn download_with_fetcher() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(3, 2))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let fetcher = MockFetcher(
+            [
+                (
+                    "https://xkcd.com/927/info.0.json",
+                    br#"{"num": 927, "safe_title": "Standards", "img": "https://imgs.xkcd.com/comics/standards.png", "day": "20", "month": "7", "year": "2011"}"#.to_vec(),
+                ),
+                // No 2x version, the regular image is used
+                ("https://imgs.xkcd.com/comics/standards.png", png),
+            ]
+            .into(),
+        );
+
+        let metadata = Metadata::from_comic_id_with(&fetcher, Some(927)).unwrap();
+        assert_eq!(metadata.safe_title, "Standards");
+        let comic = metadata.to_image_with(&fetcher).unwrap();
+        assert_eq!((comic.img.width(), comic.img.height()), (3, 2));
+        assert!(matches!(
+            Metadata::from_comic_id_with(&fetcher, Some(1)),
+            Err(XkcdError::ComicNotFound(1))
+        ));
+    }
+
+    #[rstest]
+    #[case("https://imgs.xkcd.com/comics/sky.jpg", image::ImageFormat::Jpeg)]
+    #[case("https://imgs.xkcd.com/comics/flag.gif", image::ImageFormat::Gif)]
+    #[case("https://imgs.xkcd.com/comics/tiny.png", image::ImageFormat::Png)]
+    fn download_other_formats(#[case] url: &'static str, #[case] format: image::ImageFormat) {
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(image::RgbImage::new(5, 4))
+            .write_to(&mut std::io::Cursor::new(&mut bytes), format)
+            .unwrap();
+        let fetcher = MockFetcher([(url, bytes)].into());
+        let metadata: Metadata = serde_json::from_value(serde_json::json!({
+            "num": 1, "safe_title": "", "img": url, "day": "1", "month": "1", "year": "2006"
+        }))
+        .unwrap();
+        let comic = metadata.to_image_with(&fetcher).unwrap();
+        assert_eq!((comic.img.width(), comic.img.height()), (5, 4));
+    }
+
+    #[rstest]
+    #[case("2560x1440", 2560, 1440)]
+    #[case("1920X1080", 1920, 1080)]
+    #[case(" 800 × 600 ", 800, 600)]
+    fn screen_dimensions_parse_ok(#[case] input: &str, #[case] width: u32, #[case] height: u32) {
+        assert_eq!(input.parse(), Ok(ScreenDimensions { width, height }));
+    }
+
+    #[rstest]
+    #[case("2560")]
+    #[case("2560x")]
+    #[case("0x1440")]
+    #[case("-1x1440")]
+    fn screen_dimensions_parse_error(#[case] input: &str) {
+        assert!(input.parse::<ScreenDimensions>().is_err());
+    }
+
+    #[rstest]
+    #[case(image::Rgba([31, 36, 31, 255]))]
+    #[case(theme::TRANSPARENT)]
+    fn streaming_matches_in_memory(#[case] bg: image::Rgba<u8>) {
+        let mut comic = RgbaImage::from_pixel(300, 20, image::Rgba([255, 255, 255, 255]));
+        comic.put_pixel(10, 5, image::Rgba([0, 0, 0, 255]));
+        let comic = ComicImage {
+            img: DynamicImage::ImageRgba8(comic),
+            metadata: Metadata {
+                num: 1,
+                safe_title: String::new(),
+                img: String::new(),
+                alt: String::new(),
+                link: String::new(),
+                title: String::new(),
+                transcript: String::new(),
+                news: String::new(),
+                day: String::new(),
+                month: String::new(),
+                year: String::new(),
+            },
+            resolution: Resolution::Double,
+        };
+        let dimensions = ScreenDimensions {
+            width: 320,
+            height: 600,
+        };
+        let layout = Layout {
+            placement: Placement::ThirdsRight,
+            offset_y: Length::Percent(-10.0),
+            ..Default::default()
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let filename = dir.path().join("%n.png");
+        let path = save_wallpaper_streaming(
+            comic.clone(),
+            ForegroundColor::Light,
+            bg,
+            dimensions.clone(),
+            layout,
+            Recolor::default(),
+            Decoration::default(),
+            filename.to_str().unwrap(),
+        )
+        .unwrap();
+        let in_memory = get_wallpaper_from_comic(
+            comic,
+            ForegroundColor::Light,
+            bg,
+            dimensions,
+            layout,
+            Recolor::default(),
+            Decoration::default(),
+        );
+
+        assert_eq!(path, dir.path().join("1.png"));
+        let in_memory = in_memory.img.into_rgba8();
+        assert_eq!(image::open(path).unwrap().into_rgba8(), in_memory);
+        assert_eq!(in_memory.get_pixel(0, 0), &bg);
+    }
+
+    fn metadata(year: &str, month: &str, day: &str) -> Metadata {
+        Metadata {
             num: 42,
             safe_title: "Some title".to_string(),
-            year: "2025".to_string(),
-            month: "06".to_string(),
-            day: "27".to_string(),
+            year: year.to_string(),
+            month: month.to_string(),
+            day: day.to_string(),
             img: "https://example.com".to_string(),
-        };
+            alt: String::new(),
+            link: String::new(),
+            title: String::new(),
+            transcript: String::new(),
+            news: String::new(),
+        }
+    }
+
+    #[rstest]
+    #[case("927", Ok(ComicRef::Number(927)))]
+    #[case("latest", Ok(ComicRef::Latest(0)))]
+    #[case("Latest-3", Ok(ComicRef::Latest(3)))]
+    #[case("~2", Ok(ComicRef::Latest(2)))]
+    #[case("0", Err(()))]
+    #[case("latest+1", Err(()))]
+    #[case("~x", Err(()))]
+    fn comic_ref_parse(#[case] input: &str, #[case] expected: Result<ComicRef, ()>) {
+        assert_eq!(input.parse::<ComicRef>().map_err(|_| ()), expected);
+    }
+
+    #[rstest]
+    #[case(ComicRef::Number(927), Some(927))]
+    #[case(ComicRef::Latest(0), None)]
+    #[case(ComicRef::Latest(3), Some(97))]
+    fn comic_ref_resolve_with(#[case] comic: ComicRef, #[case] expected: Option<u32>) {
+        assert_eq!(comic.resolve_with(|| Ok(100)).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case(Some(404), "ComicNotFound(404)")]
+    #[case(None, "NotFound")]
+    fn missing_comic_metadata(#[case] comic: Option<u32>, #[case] expected: &str) {
+        let fetcher = MockFetcher(Default::default());
+        let err = Metadata::from_comic_id_with(&fetcher, comic).unwrap_err();
+        assert_eq!(format!("{err:?}"), expected);
+    }
+
+    #[rstest]
+    #[case("%Y.png", "2025.png")]
+    #[case("%Y-%m-%d_%t", "2025-06-07_Some title")]
+    #[case("%-m-%-d %n", "6-7 42")]
+    #[case("%A %e %B, %j", "Saturday  7 June, 158")]
+    #[case("%a %b %F", "Sat Jun 2025-06-07")]
+    #[case("100%% %q%", "100% %q%")]
+    fn convert_filename_ok(#[case] input: &str, #[case] output: &str) {
+        assert_eq!(
+            format_comic(input, &metadata("2025", "6", "7"), Language::English),
+            output
+        );
+    }
+
+    #[rstest]
+    #[case("%y.png", "2025.png")]
+    #[case("output/file.png", "output/file.png")]
+    #[case("%y-%m-%d_%t", "2025-06-27_Some title")]
+    fn convert_filename_compatible(#[case] input: &str, #[case] output: &str) {
+        assert_eq!(
+            format_comic(input, &metadata("2025", "06", "27"), Language::English),
+            output
+        );
+    }
+
+    #[rstest]
+    #[case(Language::Portuguese, "sábado 7 de junho")]
+    #[case(Language::German, "Samstag 7 de Juni")]
+    fn convert_filename_localized(#[case] language: Language, #[case] output: &str) {
+        let metadata = metadata("2025", "6", "7");
+        assert_eq!(format_comic("%A %-d de %B", &metadata, language), output);
+    }
 
-        assert_eq!(convert_fmt_filename(input, &metadata), output);
+    #[test]
+    fn convert_filename_invalid_date() {
+        let metadata = metadata("2025", "13", "x");
+        assert_eq!(
+            format_comic("%Y-%m-%d %B", &metadata, Language::English),
+            "2025-13-x %B"
+        );
     }
 }
@@ -0,0 +1,237 @@
+use std::env;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// Language used for month and weekday names in output file names
+pub enum Language {
+    #[default]
+    English,
+    Dutch,
+    French,
+    German,
+    Italian,
+    Portuguese,
+    Spanish,
+}
+
+impl Language {
+    /// Language of the `LC_ALL`, `LC_TIME` or `LANG` environment variables, English by default
+    pub fn from_env() -> Language {
+        ["LC_ALL", "LC_TIME", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Language::from_locale(&value))
+            .unwrap_or_default()
+    }
+
+    /// Language of a POSIX locale name such as `pt_PT.UTF-8`
+    pub fn from_locale(locale: &str) -> Option<Language> {
+        let language = locale.split(['_', '.', '@', '-']).next()?;
+        Some(match language.to_lowercase().as_str() {
+            "en" | "c" | "posix" => Language::English,
+            "nl" => Language::Dutch,
+            "fr" => Language::French,
+            "de" => Language::German,
+            "it" => Language::Italian,
+            "pt" => Language::Portuguese,
+            "es" => Language::Spanish,
+            _ => return None,
+        })
+    }
+
+    /// Name of `month`, 1 to 12
+    pub fn month(&self, month: i8) -> &'static str {
+        let names: [&str; 12] = match self {
+            Language::English => [
+                "January",
+                "February",
+                "March",
+                "April",
+                "May",
+                "June",
+                "July",
+                "August",
+                "September",
+                "October",
+                "November",
+                "December",
+            ],
+            Language::Dutch => [
+                "januari",
+                "februari",
+                "maart",
+                "april",
+                "mei",
+                "juni",
+                "juli",
+                "augustus",
+                "september",
+                "oktober",
+                "november",
+                "december",
+            ],
+            Language::French => [
+                "janvier",
+                "février",
+                "mars",
+                "avril",
+                "mai",
+                "juin",
+                "juillet",
+                "août",
+                "septembre",
+                "octobre",
+                "novembre",
+                "décembre",
+            ],
+            Language::German => [
+                "Januar",
+                "Februar",
+                "März",
+                "April",
+                "Mai",
+                "Juni",
+                "Juli",
+                "August",
+                "September",
+                "Oktober",
+                "November",
+                "Dezember",
+            ],
+            Language::Italian => [
+                "gennaio",
+                "febbraio",
+                "marzo",
+                "aprile",
+                "maggio",
+                "giugno",
+                "luglio",
+                "agosto",
+                "settembre",
+                "ottobre",
+                "novembre",
+                "dicembre",
+            ],
+            Language::Portuguese => [
+                "janeiro",
+                "fevereiro",
+                "março",
+                "abril",
+                "maio",
+                "junho",
+                "julho",
+                "agosto",
+                "setembro",
+                "outubro",
+                "novembro",
+                "dezembro",
+            ],
+            Language::Spanish => [
+                "enero",
+                "febrero",
+                "marzo",
+                "abril",
+                "mayo",
+                "junio",
+                "julio",
+                "agosto",
+                "septiembre",
+                "octubre",
+                "noviembre",
+                "diciembre",
+            ],
+        };
+        names[(month.clamp(1, 12) - 1) as usize]
+    }
+
+    /// Name of a weekday, 1 (Monday) to 7 (Sunday)
+    pub fn weekday(&self, weekday: i8) -> &'static str {
+        let names: [&str; 7] = match self {
+            Language::English => [
+                "Monday",
+                "Tuesday",
+                "Wednesday",
+                "Thursday",
+                "Friday",
+                "Saturday",
+                "Sunday",
+            ],
+            Language::Dutch => [
+                "maandag",
+                "dinsdag",
+                "woensdag",
+                "donderdag",
+                "vrijdag",
+                "zaterdag",
+                "zondag",
+            ],
+            Language::French => [
+                "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche",
+            ],
+            Language::German => [
+                "Montag",
+                "Dienstag",
+                "Mittwoch",
+                "Donnerstag",
+                "Freitag",
+                "Samstag",
+                "Sonntag",
+            ],
+            Language::Italian => [
+                "lunedì",
+                "martedì",
+                "mercoledì",
+                "giovedì",
+                "venerdì",
+                "sabato",
+                "domenica",
+            ],
+            Language::Portuguese => [
+                "segunda-feira",
+                "terça-feira",
+                "quarta-feira",
+                "quinta-feira",
+                "sexta-feira",
+                "sábado",
+                "domingo",
+            ],
+            Language::Spanish => [
+                "lunes",
+                "martes",
+                "miércoles",
+                "jueves",
+                "viernes",
+                "sábado",
+                "domingo",
+            ],
+        };
+        names[(weekday.clamp(1, 7) - 1) as usize]
+    }
+}
+
+/// First three characters of a name, used for `%b` and `%a`
+pub fn abbreviate(name: &str) -> String {
+    name.chars().take(3).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("pt_PT.UTF-8", Some(Language::Portuguese))]
+    #[case("de_DE@euro", Some(Language::German))]
+    #[case("C", Some(Language::English))]
+    #[case("ja_JP.UTF-8", None)]
+    fn language_from_locale(#[case] locale: &str, #[case] expected: Option<Language>) {
+        assert_eq!(Language::from_locale(locale), expected);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Language::French.month(8), "août");
+        assert_eq!(Language::Spanish.weekday(3), "miércoles");
+        assert_eq!(abbreviate(Language::English.month(12)), "Dec");
+    }
+}
@@ -1,11 +1,89 @@
-use clap::Parser;
-use log::info;
-use xkcd_wallpaper::{get_wallpaper_from_comic, ForegroundColor, Metadata, ScreenDimensions};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::Duration;
+
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use image::DynamicImage;
+use jiff::civil::Date;
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, ToSpan};
+use log::{error, info, warn};
+use xkcd_wallpaper::appearance::ForegroundSetting;
+use xkcd_wallpaper::archive::{self, ArchiveIndex};
+use xkcd_wallpaper::background::Background;
+use xkcd_wallpaper::badge::Badge;
+use xkcd_wallpaper::batch::{Batch, ComicList};
+use xkcd_wallpaper::boot::BootTarget;
+use xkcd_wallpaper::browse::{self, BrowseEntry};
+use xkcd_wallpaper::cache::{self, CachePolicy, Checksums, ComicCache};
+use xkcd_wallpaper::collage::{Arrangement, Collage};
+use xkcd_wallpaper::colorscheme::{render_scheme, SchemeFormat};
+use xkcd_wallpaper::config::Config;
+use xkcd_wallpaper::contact::ContactSheet;
+use xkcd_wallpaper::decoration::{Border, Card, Decoration, Shadow};
+use xkcd_wallpaper::device::{DeviceProfile, DEVICES};
+use xkcd_wallpaper::dirs::{DirOverrides, Dirs};
+use xkcd_wallpaper::effects::{Dither, Halftone, Sharpen};
+use xkcd_wallpaper::font::Font;
+use xkcd_wallpaper::gallery::{self, GalleryItem};
+use xkcd_wallpaper::history::{options_hash, History, HistoryEntry};
+use xkcd_wallpaper::hooks::{self, HookContext};
+use xkcd_wallpaper::layout::{Anchor, Corner, Layout, Length, Margins, Placement, ScaleMode};
+use xkcd_wallpaper::net::{self, NetworkConfig};
+use xkcd_wallpaper::notify;
+use xkcd_wallpaper::panels::{self, Reflow};
+use xkcd_wallpaper::plan::{self, Manifest, PlanEntry};
+use xkcd_wallpaper::poll;
+use xkcd_wallpaper::progress::{self, TerminalProgress};
+use xkcd_wallpaper::qr;
+use xkcd_wallpaper::quality::{InteractiveAction, InteractiveConfig, QualityOptions};
+use xkcd_wallpaper::quantize::quantize;
+use xkcd_wallpaper::random::{Rng, MISSING_COMICS};
+use xkcd_wallpaper::recolor::{Recolor, Tone};
+use xkcd_wallpaper::schedule::{CalendarRule, Schedule};
+#[cfg(feature = "scripting")]
+use xkcd_wallpaper::script::Script;
+use xkcd_wallpaper::setter::{self, AlternateOutput, Backend, WallpaperSetter};
+use xkcd_wallpaper::split::{self, Pages};
+use xkcd_wallpaper::state::StateStore;
+use xkcd_wallpaper::stats::Stats;
+use xkcd_wallpaper::template::Template;
+use xkcd_wallpaper::text::{TextPosition, TextStyle};
+use xkcd_wallpaper::theme::{
+    parse_background_color, parse_hex_color, Base16Scheme, Palette, SchemeSlots, Theme, THEMES,
+    TRANSPARENT,
+};
+use xkcd_wallpaper::tile::Tiling;
+use xkcd_wallpaper::watermark::Watermark;
+use xkcd_wallpaper::web;
+use xkcd_wallpaper::workspace::{self, WorkspaceBackend, WorkspaceComic};
+use xkcd_wallpaper::{
+    effects, get_wallpaper_from_comic, random_comic_number, save_wallpaper_streaming, timings,
+    ComicRef, ForegroundColor, Image, Metadata, ScreenDimensions, XkcdError,
+};
+
+const DEFAULT_OUTPUT: &str = "./%Y-%m-%d_%t.png";
+const DEFAULT_BG: image::Rgba<u8> = image::Rgba([0x1F, 0x24, 0x1F, 0xFF]);
+const HIGH_CONTRAST_STROKE_RADIUS: u32 = 1;
+/// Gray levels of e-ink panels, dithered to when neither --gray-levels nor --device gives them
+const DEFAULT_GRAY_LEVELS: u8 = 16;
+/// Canvases with at least this many pixels (8K UHD) are composed and encoded in bands
+const STREAMING_MIN_PIXELS: u64 = 7680 * 4320;
+/// How often the daemon regenerates when no day/night schedule is configured
+const DAEMON_REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long the daemon waits before trying again after a failure
+const DAEMON_RETRY_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Hour of the day whose palette is used for planned wallpapers
+const PLAN_HOUR: i8 = 9;
 
 #[derive(Parser)]
 #[command(
     version,
     long_about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
     after_help = "Examples:
 
     Generate a 2560x1440 wallpaper from comic number 3084
@@ -22,101 +100,2798 @@ use xkcd_wallpaper::{get_wallpaper_from_comic, ForegroundColor, Metadata, Screen
     a Year-Month-Day-Title format, e.g. 2025-06-20-SomeTitle.
 
         xkcd-wallpaper \\
-            --width 1920 --height 1080 \\
-            --output ./output/%y-%m-%d-%t
+            --size 1920x1080 \\
+            --output ./output/%Y-%m-%d-%t
 
 Format string format:
     You can use the following placeholders in the format string:
-        %y   Two-digit year (e.g., 25)
+        %Y   Year (e.g., 2025)
+        %y   Year as well, kept from earlier versions (e.g., 2025)
         %m   Two-digit month (e.g., 06)
         %d   Two-digit day (e.g., 22)
+        %B   Month name, %b abbreviated (e.g., June, Jun)
+        %A   Weekday name, %a abbreviated (e.g., Friday, Fri)
+        %j   Day of the year (e.g., 171)
         %n   Comic number
-        %t   Title   
+        %t   Title
+    Other strftime placeholders such as %F, %e, %u and %w are supported,
+    and %-m, %-d or %-j drop the zero padding. Month and weekday names
+    follow the LC_TIME or LANG locale.
 "
 )]
 /// Download xkcd wallpapers
 ///
-/// To use simply call `xkcd-wallpaper --width 1920 --height 1080`
+/// To use simply call `xkcd-wallpaper --size 1920x1080`
 struct Cli {
-    #[arg(long, help = "Width of output wallpaper")]
-    width: u32,
-    #[arg(long, help = "Height of output wallpaper")]
-    height: u32,
-    #[arg(long, value_parser=parse_hex_color, default_value = "#1F241F", help="Background color in HEX format")]
-    bg: image::Rgba<u8>,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    generate: GenerateArgs,
     #[arg(
-        short,
         long,
-        default_value_t,
-        value_enum,
-        help = "Foreground color, either dark or light"
+        global = true,
+        env = "XKCD_WALLPAPER_CONFIG",
+        help = "Path to the configuration file [default: <config dir>/xkcd-wallpaper/config.toml]"
+    )]
+    config: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        env = "XKCD_WALLPAPER_CACHE_DIR",
+        help = "Directory for cached files"
+    )]
+    cache_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        env = "XKCD_WALLPAPER_STATE_DIR",
+        help = "Directory for persistent state"
+    )]
+    state_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        env = "XKCD_WALLPAPER_CA_BUNDLE",
+        value_name = "PEM",
+        help = "Extra root certificates to trust, e.g. of a TLS-intercepting proxy"
+    )]
+    ca_bundle: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        help = "Skip TLS certificate verification, only use on networks you trust"
+    )]
+    insecure: bool,
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        help = "Retry failed downloads up to N times on timeouts and server errors [default: 3]"
+    )]
+    retries: Option<u32>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "MS",
+        help = "Wait MS milliseconds before the first retry, doubled for every next one [default: 1000]"
+    )]
+    retry_delay: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        help = "Give up on connections and responses that stall for SECS seconds [default: 10 to connect, 60 to read]"
+    )]
+    timeout: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "URL",
+        help = "HTTP or SOCKS proxy to connect through, instead of the one in HTTPS_PROXY, HTTP_PROXY or ALL_PROXY"
     )]
-    fg: ForegroundColor,
+    proxy: Option<String>,
     #[arg(
         long,
-        help = "Optional comic number, by default the latest xkcd will be used."
+        global = true,
+        value_name = "N",
+        help = "Make at most N requests per minute, to go easy on xkcd.com in batches"
     )]
-    comic: Option<u32>,
-    #[arg(short, long, default_value = "./%y-%m-%d_%t.png")]
-    output: String,
+    rate_limit: Option<u32>,
 }
 
-fn main() {
-    env_logger::init();
-    info!("parsing CLI arguments");
-    let cli = Cli::parse();
+#[derive(Subcommand)]
+enum Command {
+    /// List previously generated wallpapers
+    History(HistoryArgs),
+    /// Show cache and usage statistics
+    Stats(StatsArgs),
+    /// Restore the wallpaper that was configured before using --set
+    Restore(RestoreArgs),
+    /// Upload a wallpaper and print its URL
+    Share(ShareArgs),
+    /// Pick a comic from the history with fuzzy search and generate a wallpaper from it
+    Browse(BrowseArgs),
+    /// Write a static HTML page with thumbnails of the generated wallpapers
+    ExportGallery(ExportGalleryArgs),
+    /// Compose thumbnails of recently generated wallpapers into a single index image
+    ContactSheet(ContactSheetArgs),
+    /// Open the current or given comic on xkcd.com or explain xkcd
+    Open(OpenArgs),
+    /// Set today's wallpaper from the ones generated with --plan, without network access
+    ApplyPlan(ApplyPlanArgs),
+    /// Inspect and maintain the comic cache
+    #[command(subcommand)]
+    Cache(CacheCommand),
+}
 
-    let screen_dimensions = ScreenDimensions {
-        width: cli.width,
-        height: cli.height,
-    };
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Check cached files against their checksums and remove or repair corrupt ones
+    Verify(CacheVerifyArgs),
+}
 
-    info!("starting comic download");
-    let comic_img = Metadata::from_comic_id(cli.comic)
-        .expect("Failed to download metadata")
-        .to_image()
-        .expect("Failed to convert metadata to image.");
+#[derive(Args)]
+struct CacheVerifyArgs {
+    #[arg(
+        long,
+        help = "Download corrupt comic images again instead of deleting them"
+    )]
+    redownload: bool,
+}
 
-    info!("converting xkcd image into wallpaper");
-    get_wallpaper_from_comic(comic_img, cli.fg, cli.bg, screen_dimensions).save(&cli.output);
+#[derive(Args)]
+struct ContactSheetArgs {
+    #[arg(help = "Image file the contact sheet is written to")]
+    output: PathBuf,
+    #[arg(
+        short = 'n',
+        long,
+        default_value_t = 24,
+        help = "Number of most recent wallpapers to include"
+    )]
+    last: usize,
+    #[arg(long, default_value_t = 6, help = "Thumbnails per row")]
+    columns: u32,
+    #[arg(
+        long,
+        default_value_t = 320,
+        help = "Width of each thumbnail in pixels"
+    )]
+    thumbnail_width: u32,
 }
 
-/// Parse a colour in “#RRGGBB”
-fn parse_hex_color(s: &str) -> Result<image::Rgba<u8>, String> {
-    let hex = s.trim_start_matches('#');
-    let full = match hex.len() {
-        6 => format!("{hex}FF"),
-        _ => return Err("Hex colour must be 6 hex digits (e.g. #1e90ff)".into()),
-    };
-    let v = u32::from_str_radix(&full, 16).map_err(|_| "Invalid hex digits")?;
+#[derive(Args)]
+struct ExportGalleryArgs {
+    #[arg(
+        default_value = "gallery",
+        help = "Directory the gallery is written to"
+    )]
+    out_dir: PathBuf,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Also include images in DIR that are not in the history, can be repeated"
+    )]
+    scan: Vec<PathBuf>,
+    #[arg(
+        long,
+        help = "Copy full-size images into the gallery instead of linking to them"
+    )]
+    copy: bool,
+}
 
-    Ok(image::Rgba([
-        ((v >> 24) & 0xFF) as u8, // R
-        ((v >> 16) & 0xFF) as u8, // G
-        ((v >> 8) & 0xFF) as u8,  // B
-        (v & 0xFF) as u8,         // A
-    ]))
+#[derive(Args)]
+struct BrowseArgs {
+    #[command(flatten)]
+    generate: Box<GenerateArgs>,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rstest::rstest;
+#[derive(Args)]
+struct ShareArgs {
+    #[arg(help = "Image to upload [default: the most recently generated wallpaper]")]
+    path: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Upload endpoint [default: https://0x0.st or the one in the config]"
+    )]
+    endpoint: Option<String>,
+}
 
-    #[rstest]
-    #[case("#FF0000", 255, 0, 0)]
-    #[case("FF0000", 255, 0, 0)]
-    #[case("#FF69B4", 255, 105, 180)]
-    fn hex_parse_ok(#[case] input: &str, #[case] r: u8, #[case] g: u8, #[case] b: u8) {
-        let rgba = image::Rgba([r, g, b, 255]);
-        assert_eq!(parse_hex_color(input), Ok(rgba));
-    }
+#[derive(Args)]
+struct OpenArgs {
+    #[arg(
+        value_name = "COMIC",
+        help = "Comic number or latest, latest-N or ~N [default: the comic of the current wallpaper]"
+    )]
+    comic: Option<ComicRef>,
+    #[arg(long, help = "Open the explanation on explainxkcd.com instead")]
+    explain: bool,
+}
 
-    #[rstest]
-    #[case("FF00")]
-    #[case("ZZ0000")]
-    #[case("")]
-    fn hex_parse_error(#[case] input: &str) {
-        assert!(parse_hex_color(input).is_err())
+#[derive(Args)]
+struct ApplyPlanArgs {
+    #[arg(
+        long,
+        value_enum,
+        help = "Wallpaper backend, detected from the desktop environment by default"
+    )]
+    backend: Option<Backend>,
+    #[arg(long, help = "Keep running and apply the planned wallpaper every day")]
+    daemon: bool,
+}
+
+#[derive(Args)]
+struct RestoreArgs {
+    #[arg(
+        long,
+        value_enum,
+        help = "Wallpaper backend, detected from the desktop environment by default"
+    )]
+    backend: Option<Backend>,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    #[arg(long, default_value_t = 5, help = "Number of most used comics to show")]
+    top: usize,
+    #[arg(long, help = "Print statistics as JSON")]
+    json: bool,
+}
+
+#[derive(Args)]
+struct HistoryArgs {
+    #[arg(long, help = "Only show entries for this comic number")]
+    comic: Option<u64>,
+    #[arg(
+        long,
+        value_name = "DATE",
+        help = "Only show entries generated on or after DATE, e.g. 2025-06-01"
+    )]
+    since: Option<Date>,
+    #[arg(
+        long,
+        value_name = "DATE",
+        help = "Only show entries generated on or before DATE"
+    )]
+    until: Option<Date>,
+    #[arg(short = 'n', long, help = "Only show the N most recent entries")]
+    limit: Option<usize>,
+    #[arg(long, help = "Print entries as JSON")]
+    json: bool,
+}
+
+#[derive(Args, Clone)]
+#[group(id = "generate")]
+#[command(group(ArgGroup::new("dimensions").required(true).args(["size", "width", "size_from", "device"])))]
+struct GenerateArgs {
+    #[arg(
+        long,
+        help = "Size of output wallpaper in WIDTHxHEIGHT format, e.g. 2560x1440"
+    )]
+    size: Option<ScreenDimensions>,
+    #[arg(
+        long,
+        value_parser = parse_device,
+        help = "E-reader profile setting size, grayscale dithering and screensaver file name"
+    )]
+    device: Option<&'static DeviceProfile>,
+    #[arg(
+        long,
+        value_name = "IMAGE",
+        help = "Use the size of an existing image, e.g. your current wallpaper"
+    )]
+    size_from: Option<PathBuf>,
+    #[arg(long, requires = "height", help = "Width of output wallpaper")]
+    width: Option<u32>,
+    #[arg(
+        long,
+        requires = "width",
+        conflicts_with_all = ["size", "size_from", "device"],
+        help = "Height of output wallpaper"
+    )]
+    height: Option<u32>,
+    #[arg(
+        long,
+        value_parser = parse_background_color,
+        help = "Background color in HEX format, or transparent for a PNG where only the drawings are opaque [default: #1F241F]"
+    )]
+    bg: Option<image::Rgba<u8>>,
+    #[arg(
+        short,
+        long,
+        value_name = "COLOR",
+        help = "Foreground color: dark, light, an ink in HEX format like #88C0D0, or system to follow the light/dark appearance [default: light]"
+    )]
+    fg: Option<ForegroundSetting>,
+    #[arg(
+        long,
+        value_parser = parse_theme,
+        help = "Color theme preset, overridden by --bg and --fg"
+    )]
+    theme: Option<&'static Theme>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with = "theme",
+        help = "Take the colors from a Base16 or Base24 scheme file, overridden by --bg and --fg"
+    )]
+    scheme: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "BG,FG",
+        default_value = "base00,base05",
+        help = "Slots of the --scheme used for the background and the drawings"
+    )]
+    scheme_slots: SchemeSlots,
+    #[arg(
+        long,
+        value_name = "FORMAT=PATH",
+        value_parser = parse_scheme_export,
+        help = "Write a matching terminal color scheme (alacritty, kitty or xresources) to PATH, can be repeated"
+    )]
+    export_scheme: Vec<(SchemeFormat, PathBuf)>,
+    #[arg(
+        long,
+        conflicts_with_all = ["bg", "theme", "scheme"],
+        help = "Pure black and white rendering with thickened strokes"
+    )]
+    high_contrast: bool,
+    #[arg(
+        long,
+        value_name = "CELL[,ANGLE]",
+        num_args = 0..=1,
+        default_missing_value = "8,45",
+        conflicts_with = "high_contrast",
+        help = "Turn the comic's shading into dots CELL pixels apart on a grid rotated by ANGLE degrees [default: 8,45], like newspaper print"
+    )]
+    halftone: Option<Halftone>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["bg", "high_contrast"],
+        help = "Place the comic on a picture, scaled and cropped to the screen, with drawings that contrast with it unless --fg is set"
+    )]
+    bg_image: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "SIGMA",
+        num_args = 0..=1,
+        default_missing_value = "40",
+        conflicts_with_all = ["bg_image", "high_contrast"],
+        help = "Fill the screen with the comic itself, blurred by SIGMA pixels [default: 40], behind the sharp comic"
+    )]
+    bg_blur: Option<f32>,
+    #[arg(
+        long,
+        default_value_t = 0,
+        value_name = "N",
+        help = "Also replace pixels within N of the comic's background in every channel, removing halos from JPEG artifacts and anti-aliasing"
+    )]
+    bg_tolerance: u8,
+    #[arg(
+        long,
+        help = "Blend anti-aliased edges of the drawings into the background instead of leaving a light or dark fringe"
+    )]
+    smooth_edges: bool,
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        value_parser = parse_brightness,
+        value_name = "AMOUNT",
+        help = "Brighten the drawings by AMOUNT from -1 (black) to 1 (white), before they are placed on the background"
+    )]
+    brightness: f32,
+    #[arg(
+        long,
+        default_value_t = 1.0,
+        value_parser = parse_factor,
+        value_name = "FACTOR",
+        help = "Stretch the drawings' tones away from mid gray by FACTOR, below 1 to soften them"
+    )]
+    contrast: f32,
+    #[arg(
+        long,
+        default_value_t = 1.0,
+        value_parser = parse_factor,
+        value_name = "GAMMA",
+        help = "Brighten the drawings' midtones with a GAMMA above 1, darken them below 1"
+    )]
+    gamma: f32,
+    #[arg(
+        long,
+        value_name = "RADIUS[,X,Y[,OPACITY]]",
+        num_args = 0..=1,
+        default_missing_value = "16,0,8,0.5",
+        conflicts_with = "high_contrast",
+        help = "Cast a soft shadow under the comic, blurred by RADIUS pixels and moved by X,Y [default: 16,0,8,0.5]"
+    )]
+    shadow: Option<Shadow>,
+    #[arg(
+        long,
+        value_name = "WIDTH:COLOR[:PADDING]",
+        help = "Frame the comic with a border, e.g. 4px:#FFFFFF:12px for a white frame 12 pixels away from it"
+    )]
+    border: Option<Border>,
+    #[arg(
+        long,
+        value_name = "COLOR[:RADIUS[:PADDING]]",
+        help = "Place the comic on a card of COLOR with rounded corners, e.g. #2E3440:16px:32px"
+    )]
+    card: Option<Card>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POSITION",
+        num_args = 0..=1,
+        default_missing_value = "above",
+        help = "Write the comic's title above or below it [default: above]"
+    )]
+    show_title: Option<TextPosition>,
+    #[arg(
+        long,
+        default_value_t = 32,
+        value_name = "PX",
+        help = "Height of a line of the title, in multiples of 8 pixels with the built-in font"
+    )]
+    title_size: u32,
+    #[arg(
+        long,
+        value_parser = parse_hex_color,
+        value_name = "COLOR",
+        help = "Color of the title in HEX format [default: that of the drawings]"
+    )]
+    title_color: Option<image::Rgba<u8>>,
+    #[arg(
+        long,
+        help = "Write the comic's alt text below it, wrapped at --alt-width"
+    )]
+    show_alt: bool,
+    #[arg(
+        long,
+        default_value = "50%",
+        value_name = "LENGTH",
+        help = "Widest line of the alt text, in pixels or percent of the screen width"
+    )]
+    alt_width: Length,
+    #[arg(
+        long,
+        default_value_t = 16,
+        value_name = "PX",
+        help = "Height of a line of the alt text, in multiples of 8 pixels with the built-in font"
+    )]
+    alt_size: u32,
+    #[arg(
+        long,
+        value_parser = parse_hex_color,
+        value_name = "COLOR",
+        help = "Color of the alt text in HEX format [default: that of the drawings]"
+    )]
+    alt_color: Option<image::Rgba<u8>>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "TrueType or OpenType font of the title and alt text [default: built-in pixel font]"
+    )]
+    font: Option<PathBuf>,
+    #[arg(
+        long = "layout",
+        value_name = "FILE",
+        conflicts_with_all = ["show_title", "show_alt"],
+        help = "Compose the wallpaper from the elements of a TOML or JSON layout template, instead of placing the comic with the layout options"
+    )]
+    template: Option<PathBuf>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "CORNER",
+        num_args = 0..=1,
+        default_missing_value = "bottom-right",
+        help = "Draw a QR code linking to the comic in a corner of the wallpaper [default: bottom-right]"
+    )]
+    qr: Option<Corner>,
+    #[arg(
+        long,
+        requires = "qr",
+        help = "Link the QR code to the explanation on explainxkcd.com instead"
+    )]
+    qr_explain: bool,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "CORNER",
+        num_args = 0..=1,
+        default_missing_value = "bottom-left",
+        help = "Draw a badge with the comic's number and date in a corner of the wallpaper [default: bottom-left]"
+    )]
+    badge: Option<Corner>,
+    #[arg(
+        long,
+        default_value = "#%n — %F",
+        value_name = "FORMAT",
+        help = "Text of the badge, with the placeholders of --output"
+    )]
+    badge_format: String,
+    #[arg(
+        long,
+        default_value_t = 16,
+        value_name = "PX",
+        help = "Height of the badge's text, in multiples of 8 pixels with the built-in font"
+    )]
+    badge_size: u32,
+    #[arg(
+        long,
+        value_parser = parse_hex_color,
+        value_name = "COLOR",
+        help = "Color of the badge's text in HEX format [default: that of the background]"
+    )]
+    badge_color: Option<image::Rgba<u8>>,
+    #[arg(
+        long,
+        value_parser = parse_hex_color,
+        value_name = "COLOR",
+        help = "Color behind the badge's text in HEX format [default: that of the drawings]"
+    )]
+    badge_bg: Option<image::Rgba<u8>>,
+    #[arg(
+        long,
+        default_value_t = 0.8,
+        value_parser = parse_opacity,
+        value_name = "OPACITY",
+        help = "Opacity of the badge, from 0 to 1"
+    )]
+    badge_opacity: f32,
+    #[arg(
+        long,
+        value_name = "TEXT",
+        help = "Draw fixed text over the wallpaper, e.g. who the machine belongs to"
+    )]
+    watermark: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Anchor::Bottom,
+        value_name = "POSITION",
+        help = "Where the watermark goes on the screen"
+    )]
+    watermark_position: Anchor,
+    #[arg(
+        long,
+        default_value_t = 24,
+        value_name = "PX",
+        help = "Height of a line of the watermark, in multiples of 8 pixels with the built-in font"
+    )]
+    watermark_size: u32,
+    #[arg(
+        long,
+        value_parser = parse_hex_color,
+        value_name = "COLOR",
+        help = "Color of the watermark in HEX format [default: that of the drawings]"
+    )]
+    watermark_color: Option<image::Rgba<u8>>,
+    #[arg(
+        long,
+        default_value_t = 0.3,
+        value_parser = parse_opacity,
+        value_name = "OPACITY",
+        help = "Opacity of the watermark, from 0 to 1"
+    )]
+    watermark_opacity: f32,
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        value_name = "DEGREES",
+        help = "Counterclockwise rotation of the watermark"
+    )]
+    watermark_rotation: f32,
+    #[arg(
+        long,
+        value_name = "STRENGTH",
+        value_parser = parse_opacity,
+        conflicts_with = "high_contrast",
+        help = "Darken the edges of the wallpaper towards the corners by STRENGTH from 0 to 1, lightening them on light backgrounds"
+    )]
+    vignette: Option<f32>,
+    #[arg(
+        long,
+        value_name = "AMOUNT",
+        value_parser = parse_opacity,
+        conflicts_with = "high_contrast",
+        help = "Overlay monochrome noise of up to AMOUNT of the full range, e.g. 0.05, breaking up flat areas that band on some screens"
+    )]
+    grain: Option<f32>,
+    #[arg(long, help = "Convert the wallpaper to grayscale, whatever its colors")]
+    grayscale: bool,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "METHOD",
+        help = "Convert the wallpaper to gray levels an e-ink panel can show, dithered with METHOD"
+    )]
+    dither: Option<Dither>,
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u8).range(2..),
+        help = "Number of gray levels of the display, for --dither and --device [default: the device's, or 16]"
+    )]
+    gray_levels: Option<u8>,
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(2..=256),
+        help = "Write a PNG with a palette of at most N colors, 1-bit with 2 colors, for much smaller files"
+    )]
+    png_colors: Option<u16>,
+    #[cfg(feature = "scripting")]
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Rhai script transforming the finished wallpaper, see the README"
+    )]
+    script: Option<PathBuf>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "Horizontal placement of the comic, at the center or a rule-of-thirds or golden-ratio focal point"
+    )]
+    placement: Placement,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "Edge or corner to pin the comic to, inside the margins; left and right override --placement"
+    )]
+    position: Anchor,
+    #[arg(
+        long,
+        alias = "padding",
+        default_value = "0",
+        value_name = "LENGTHS",
+        help = "Space kept free around the comic, in pixels or percent of the screen size, e.g. 5%, or per side like 0,30%,0,0 (top, right, bottom, left). Larger comics are shrunk to fit"
+    )]
+    margin: Margins,
+    #[arg(
+        long,
+        default_value = "shrink",
+        value_name = "MODE",
+        help = "Size of the comic inside the margins: shrink (only comics that don't fit), none, fit, fill, stretch or a percentage of fit like 75%"
+    )]
+    scale: ScaleMode,
+    #[arg(
+        long,
+        value_name = "AMOUNT[,RADIUS]",
+        num_args = 0..=1,
+        default_missing_value = "1,1",
+        help = "Sharpen the comic once scaled with an unsharp mask of AMOUNT and RADIUS pixels [default: 1,1], keeping upscaled line art crisp"
+    )]
+    sharpen: Option<Sharpen>,
+    #[arg(
+        long,
+        default_value = "0",
+        allow_hyphen_values = true,
+        value_name = "LENGTH",
+        help = "Move the comic right after --placement and --position, in pixels or percent of the screen width, e.g. -10%"
+    )]
+    offset_x: Length,
+    #[arg(
+        long,
+        default_value = "0",
+        allow_hyphen_values = true,
+        value_name = "LENGTH",
+        help = "Move the comic down after --position, in pixels or percent of the screen height, e.g. -10%"
+    )]
+    offset_y: Length,
+    #[arg(
+        long,
+        value_name = "COMIC",
+        help = "Comic number, latest, latest-N or ~N for the Nth comic before the newest one [default: latest]"
+    )]
+    comic: Option<ComicRef>,
+    #[arg(
+        long,
+        value_name = "LIST",
+        conflicts_with_all = ["comic", "random", "date", "workspaces", "plan", "set", "daemon", "alternate_output"],
+        help = "Generate a wallpaper for each comic in LIST, e.g. 1,42,303 or 100..150"
+    )]
+    comics: Option<ComicList>,
+    #[arg(
+        long,
+        conflicts_with_all = ["comic", "workspaces", "plan"],
+        help = "Pick a random comic, a new one every time the daemon regenerates"
+    )]
+    random: bool,
+    #[arg(
+        long,
+        value_name = "DATE",
+        conflicts_with_all = ["comic", "random", "workspaces", "plan"],
+        help = "Use the comic published on or nearest to DATE, e.g. 2023-04-01"
+    )]
+    date: Option<Date>,
+    #[arg(
+        long,
+        value_name = "LIST",
+        conflicts_with_all = ["comic", "comics", "random", "date", "workspaces", "plan", "template", "show_title", "show_alt"],
+        help = "Combine the comics in LIST into one wallpaper, e.g. 3084,3085,3086"
+    )]
+    collage: Option<ComicList>,
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..=64),
+        conflicts_with_all = ["comic", "comics", "random", "date", "collage", "workspaces", "plan", "template", "show_title", "show_alt"],
+        help = "Combine N random comics into one wallpaper, new ones every time the daemon regenerates"
+    )]
+    collage_random: Option<u32>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        value_name = "ARRANGEMENT",
+        help = "How the comics of a collage are arranged: in a grid of equal cells or in masonry columns"
+    )]
+    collage_arrangement: Arrangement,
+    #[arg(
+        long,
+        default_value_t = 32,
+        value_name = "PX",
+        help = "Pixels between the comics of a collage and around them"
+    )]
+    collage_spacing: u32,
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Columns of a collage [default: the number showing the comics the largest]"
+    )]
+    collage_columns: Option<u32>,
+    #[arg(
+        long,
+        conflicts_with_all = ["collage", "collage_random", "template", "show_title", "show_alt"],
+        help = "Repeat the comic across the whole screen like a tiled wallpaper"
+    )]
+    tile: bool,
+    #[arg(
+        long,
+        default_value_t = 1.0,
+        value_parser = parse_tile_scale,
+        value_name = "FACTOR",
+        requires = "tile",
+        help = "Size of the tiles relative to the comic, e.g. 0.5 for half size"
+    )]
+    tile_scale: f32,
+    #[arg(
+        long,
+        default_value_t = 0,
+        value_name = "PX",
+        requires = "tile",
+        help = "Pixels between neighbouring tiles"
+    )]
+    tile_gap: u32,
+    #[arg(
+        long,
+        default_value = "0",
+        allow_hyphen_values = true,
+        value_name = "LENGTH",
+        requires = "tile",
+        help = "Shift every other row of tiles, in pixels or percent of the distance between tiles, e.g. 50% for a brick pattern"
+    )]
+    tile_offset: Length,
+    #[arg(
+        long,
+        value_name = "PAGES",
+        num_args = 0..=1,
+        default_missing_value = "auto",
+        conflicts_with_all = ["collage", "collage_random", "tile", "workspaces", "plan"],
+        help = "Cut tall comics into PAGES wallpapers (auto for as many as the screen's shape calls for) [default: auto], written with -page1, -page2... suffixes"
+    )]
+    split: Option<Pages>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "DIRECTION",
+        num_args = 0..=1,
+        default_missing_value = "auto",
+        conflicts_with_all = ["collage", "collage_random", "split"],
+        help = "Detect the panels of the comic and stack them vertically or in a horizontal row, or either when it fills the screen better (auto) [default: auto]"
+    )]
+    reflow: Option<Reflow>,
+    #[arg(
+        long,
+        value_name = "TOLERANCE",
+        num_args = 0..=1,
+        default_missing_value = "16",
+        help = "Crop the uniform margins of the comic, rows and columns within TOLERANCE of its corner color in every channel [default: 16]"
+    )]
+    trim: Option<u8>,
+    #[arg(
+        long,
+        conflicts_with_all = ["workspaces", "plan"],
+        help = "Never use the network, render only comics in the cache"
+    )]
+    offline: bool,
+    #[arg(
+        long,
+        help = "Use the large version of comics that link one, whatever the screen size (see `large` in [download])"
+    )]
+    prefer_large: bool,
+    #[arg(
+        long,
+        help = "Don't show download progress and the rendering spinner, they are only shown on a terminal"
+    )]
+    no_progress: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Pick another comic, up to N times, when a comic is interactive, colored with --strict-mono or has an extreme aspect ratio: a new random one with --random, otherwise the next one, or earlier ones after the newest"
+    )]
+    retry_different: u32,
+    #[arg(long, help = "Reject comics with colored drawings")]
+    strict_mono: bool,
+    #[arg(
+        short,
+        long,
+        help = "Output file, supports placeholders [default: ./%Y-%m-%d_%t.png]"
+    )]
+    output: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Export the image in a format suitable for a boot screen"
+    )]
+    boot: Option<BootTarget>,
+    #[arg(
+        long,
+        requires = "boot",
+        help = "Install the exported boot background into the system, usually requires root"
+    )]
+    boot_install: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["device", "boot"],
+        help = "Compose and encode the wallpaper in bands to bound memory use, default for 8K and larger PNG outputs"
+    )]
+    streaming: bool,
+    #[arg(long, help = "Print the alt text of the comic to stdout")]
+    print_alt: bool,
+    #[arg(
+        long,
+        help = "Show a desktop notification with the comic title and alt text"
+    )]
+    notify: bool,
+    #[arg(long, help = "Print the time spent in each stage of the pipeline")]
+    timings: bool,
+    #[arg(long, help = "Set the generated image as desktop wallpaper")]
+    set: bool,
+    #[arg(
+        long,
+        value_enum,
+        requires = "set",
+        help = "Wallpaper backend, detected from the desktop environment by default"
+    )]
+    backend: Option<Backend>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        help = "Change the output path on every run, for desktops that do not reload a wallpaper at the same path"
+    )]
+    alternate_output: Option<AlternateOutput>,
+    #[arg(
+        long,
+        conflicts_with_all = ["comic", "alternate_output", "boot", "device"],
+        help = "Generate one wallpaper per virtual desktop from the [workspaces] config, assigned to them with --set"
+    )]
+    workspaces: bool,
+    #[arg(
+        long,
+        value_name = "DAYS",
+        num_args = 0..=1,
+        default_missing_value = "7",
+        conflicts_with_all = ["comic", "workspaces", "alternate_output", "set", "daemon"],
+        help = "Generate a daily random wallpaper for the next DAYS days [default: 7], applied with apply-plan"
+    )]
+    plan: Option<u32>,
+    #[arg(
+        long,
+        help = "Keep running and regenerate the wallpaper when the day/night palette changes"
+    )]
+    daemon: bool,
+}
+
+fn main() {
+    env_logger::init();
+    info!("parsing CLI arguments");
+    let cli = Cli::parse();
+
+    let config_path = cli.config.clone().unwrap_or_else(Config::default_path);
+    let config = Config::load(&config_path).expect("Failed to load config");
+    let dirs = Dirs::resolve(
+        DirOverrides {
+            cache_dir: cli.cache_dir.clone(),
+            state_dir: cli.state_dir.clone(),
+            ..Default::default()
+        }
+        .or(config.paths.clone()),
+    );
+    info!("using directories {:?}", dirs);
+    net::configure(
+        &NetworkConfig {
+            ca_bundle: cli.ca_bundle.clone(),
+            insecure: cli.insecure,
+            retries: cli.retries,
+            retry_delay_ms: cli.retry_delay,
+            timeout_secs: cli.timeout,
+            connect_timeout_secs: None,
+            proxy: cli.proxy.clone(),
+            rate_limit: cli.rate_limit,
+        }
+        .or(config.network.clone()),
+    )
+    .expect("Failed to configure network");
+
+    match &cli.command {
+        Some(Command::History(args)) => print_history(args, &dirs),
+        Some(Command::Stats(args)) => print_stats(args, &dirs),
+        Some(Command::Restore(args)) => restore(args, &dirs),
+        Some(Command::Share(args)) => share(args, &config, &dirs),
+        Some(Command::Browse(args)) => browse(args, &config, &dirs),
+        Some(Command::ExportGallery(args)) => export_gallery(args, &dirs),
+        Some(Command::ContactSheet(args)) => contact_sheet(args, &dirs),
+        Some(Command::Open(args)) => open_comic(args, &dirs),
+        Some(Command::ApplyPlan(args)) => apply_plan(args, &config, &dirs),
+        Some(Command::Cache(CacheCommand::Verify(args))) => verify_cache(args, &dirs),
+        None => run_generate(&cli.generate, &config, &dirs),
+    }
+}
+
+fn run_generate(cli: &GenerateArgs, config: &Config, dirs: &Dirs) {
+    let _progress = match cli.no_progress {
+        true => None,
+        false => TerminalProgress::start().map(|progress| progress::set_sink(progress)),
+    };
+    let screen_dimensions = cli
+        .screen_dimensions()
+        .expect("Failed to determine wallpaper size");
+
+    let schedule = config
+        .daynight
+        .schedule()
+        .expect("Invalid day/night schedule");
+
+    if let Some(ComicList(comics)) = &cli.comics {
+        let palette = current_palette(config, schedule.as_ref(), Timestamp::now());
+        let args = GenerateArgs {
+            comics: None,
+            notify: false,
+            ..cli.clone()
+        };
+        let quality = QualityOptions {
+            strict_mono: cli.strict_mono,
+            interactive: config.interactive.placeholders(),
+            ..Default::default()
+        };
+        let render = |comic: Image| {
+            if cli.strict_mono {
+                quality
+                    .check(&comic)
+                    .map_err(|reason| XkcdError::Unsuitable {
+                        num: comic.metadata.num,
+                        reason,
+                    })?;
+            }
+            generate_comic(
+                &args,
+                config,
+                dirs,
+                &screen_dimensions,
+                palette.clone(),
+                comic,
+                Vec::new(),
+            )
+        };
+        let policy = match cli.offline {
+            true => CachePolicy::Offline,
+            false => CachePolicy::Prefer,
+        };
+        let mut failed = 0;
+        let batch = Batch::new(comics.iter().copied(), render)
+            .with_cache(&dirs.cache, policy)
+            .with_large(cli.prefer_large || config.download.large.wanted(&screen_dimensions));
+        for (num, result) in batch {
+            match result {
+                Ok(output) => println!("{}: {}", num, output.display()),
+                Err(err) => {
+                    failed += 1;
+                    eprintln!("{num}: {err}");
+                }
+            }
+        }
+        if failed > 0 {
+            eprintln!("{} of {} comics failed", failed, comics.len());
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(days) = cli.plan {
+        plan_wallpapers(
+            cli,
+            config,
+            dirs,
+            &screen_dimensions,
+            schedule.as_ref(),
+            days,
+        )
+        .expect("Failed to plan wallpapers");
+        return;
+    }
+
+    if !cli.daemon {
+        let palette = current_palette(config, schedule.as_ref(), Timestamp::now());
+        render(cli, config, dirs, &screen_dimensions, palette)
+            .expect("Failed to generate wallpaper");
+        // Hyprland workspace wallpapers are switched by this process
+        if cli.workspaces
+            && cli.set
+            && WorkspaceBackend::detect() == Some(WorkspaceBackend::Hyprland)
+        {
+            info!("following workspace switches until Hyprland exits");
+            workspace::wait();
+        }
+        // The layer-shell surfaces disappear with the process, like with swaybg
+        #[cfg(all(unix, feature = "wayland"))]
+        if cli.set && cli.backend == Some(Backend::LayerShell) {
+            info!("showing the wallpaper until the compositor closes the connection");
+            xkcd_wallpaper::wayland::wait();
+        }
+        return;
+    }
+
+    info!("running as daemon");
+    // Only worth polling when the wallpaper shows the newest comic
+    let follows_latest = match cli.workspaces {
+        true => config
+            .workspaces
+            .comics()
+            .iter()
+            .any(WorkspaceComic::follows_latest),
+        false => {
+            !cli.offline
+                && !cli.random
+                && cli.date.is_none()
+                && cli.collage().is_none()
+                && matches!(cli.comic, None | Some(ComicRef::Latest(_)))
+        }
+    };
+    // Kept in the state store, so comics published while the daemon was stopped are noticed
+    let store = StateStore::in_state_dir(&dirs.state);
+    let mut published = store.load().daemon.published;
+    let mut next_refresh = Timestamp::now();
+    // Set while the server asked to wait, polling would only be refused too
+    let mut rate_limited = false;
+    loop {
+        let fingerprint = match follows_latest && !rate_limited {
+            true => poll::latest_fingerprint()
+                .inspect_err(|err| warn!("failed to check for a new comic: {}", err))
+                .ok(),
+            false => None,
+        };
+        if fingerprint.is_some() {
+            if let Err(err) = store.update(|state| state.daemon.last_check = Some(Timestamp::now()))
+            {
+                warn!("failed to save daemon state: {}", err);
+            }
+        }
+        let new_comic = fingerprint.is_some() && published.is_some() && fingerprint != published;
+        if new_comic {
+            info!("a new comic was published");
+        }
+
+        let now = Timestamp::now();
+        if new_comic || now >= next_refresh {
+            let palette = current_palette(config, schedule.as_ref(), now);
+            let refresh_in = schedule
+                .as_ref()
+                .and_then(|schedule| schedule.next_change(now, &TimeZone::system()))
+                .map(|next| now.duration_until(next).unsigned_abs())
+                .unwrap_or(DAEMON_REFRESH_INTERVAL);
+            let result = render(cli, config, dirs, &screen_dimensions, palette);
+            rate_limited = matches!(
+                result,
+                Err(XkcdError::RateLimited {
+                    retry_after: Some(_)
+                })
+            );
+            match result {
+                Ok(()) => {
+                    published = fingerprint.or(published);
+                    next_refresh = now + refresh_in;
+                    let published = published.clone();
+                    if let Err(err) = store.update(|state| state.daemon.published = published) {
+                        warn!("failed to save daemon state: {}", err);
+                    }
+                }
+                Err(err) => {
+                    error!("failed to generate wallpaper: {}", err);
+                    next_refresh = now + retry_in(&err, refresh_in);
+                }
+            }
+        }
+
+        let now = Timestamp::now();
+        let mut wake_in = match next_refresh > now {
+            true => now.duration_until(next_refresh).unsigned_abs(),
+            false => Duration::ZERO,
+        };
+        if follows_latest && !rate_limited {
+            wake_in = wake_in.min(poll::next_poll(now, poll::jitter()));
+        }
+        info!("sleeping for {}s", wake_in.as_secs());
+        sleep(wake_in);
+    }
+}
+
+/// How long the daemon waits after `err` before trying again, at most until the next refresh
+/// in `refresh_in` unless the server asked for a longer wait
+fn retry_in(err: &XkcdError, refresh_in: Duration) -> Duration {
+    let retry_in = refresh_in.min(DAEMON_RETRY_INTERVAL);
+    match err {
+        XkcdError::RateLimited {
+            retry_after: Some(delay),
+        } => retry_in.max(*delay),
+        _ => retry_in,
+    }
+}
+
+/// Generate the wallpapers of the next `days` days and record them in the plan manifest
+///
+/// Each day gets its daily random comic, with the palette of that morning.
+fn plan_wallpapers(
+    cli: &GenerateArgs,
+    config: &Config,
+    dirs: &Dirs,
+    screen_dimensions: &ScreenDimensions,
+    schedule: Option<&Schedule>,
+    days: u32,
+) -> Result<(), XkcdError> {
+    let latest = Metadata::from_comic_id(None)?.num;
+    let tz = TimeZone::system();
+    let today = Timestamp::now().to_zoned(tz.clone()).date();
+    let mut manifest = Manifest::load(&dirs.state)?;
+    remove_outputs(manifest.prune(today));
+
+    for offset in 0..days as i64 {
+        let invalid_date = |err: jiff::Error| XkcdError::Other(format!("invalid plan date: {err}"));
+        let date = today.checked_add(offset.days()).map_err(invalid_date)?;
+        let num = plan::daily_comic(date, latest);
+        let morning = date
+            .at(PLAN_HOUR, 0, 0, 0)
+            .to_zoned(tz.clone())
+            .map_err(invalid_date)?
+            .timestamp();
+        info!("planning comic {} for {}", num, date);
+        let args = GenerateArgs {
+            comic: Some(ComicRef::Number(num as u32)),
+            output: Some(setter::with_suffix(cli.output(), &format!("-{date}"))),
+            notify: false,
+            plan: None,
+            ..cli.clone()
+        };
+        let palette = current_palette(config, schedule, morning);
+        let output = generate(&args, config, dirs, screen_dimensions, palette)?;
+        let entry = PlanEntry {
+            num,
+            output: std::path::absolute(&output)?,
+        };
+        if let Some(replaced) = manifest.days.insert(date, entry.clone()) {
+            if replaced.output != entry.output {
+                remove_outputs([replaced]);
+            }
+        }
+    }
+
+    manifest.save(&dirs.state)?;
+    println!(
+        "Planned {} wallpapers in {}",
+        days,
+        Manifest::path(&dirs.state).display()
+    );
+    Ok(())
+}
+
+/// Delete the wallpapers of planned days that are over
+fn remove_outputs(entries: impl IntoIterator<Item = PlanEntry>) {
+    for entry in entries {
+        info!("removing planned wallpaper {}", entry.output.display());
+        if let Err(err) = fs::remove_file(&entry.output) {
+            warn!("failed to remove {}: {}", entry.output.display(), err);
+        }
+    }
+}
+
+/// Background and foreground colors configured for the current date and period of the day
+///
+/// Calendar rules take precedence over the day/night palettes.
+fn current_palette(
+    config: &Config,
+    schedule: Option<&Schedule>,
+    now: Timestamp,
+) -> (Option<image::Rgba<u8>>, Option<ForegroundColor>) {
+    let tz = TimeZone::system();
+    let calendar = CalendarRule::first_match(&config.calendar, now.to_zoned(tz.clone()).date())
+        .unwrap_or_default();
+    let daynight = match schedule {
+        Some(schedule) => {
+            let period = schedule.period_at(now, &tz);
+            info!("current period is {:?}", period);
+            config.daynight.palette(period).clone()
+        }
+        None => Palette::default(),
+    };
+
+    let (calendar_bg, calendar_fg) = calendar.resolve().expect("Invalid calendar palette");
+    let (daynight_bg, daynight_fg) = daynight.resolve().expect("Invalid day/night palette");
+    (calendar_bg.or(daynight_bg), calendar_fg.or(daynight_fg))
+}
+
+/// Generate the wallpaper, or one per workspace with `--workspaces`
+fn render(
+    cli: &GenerateArgs,
+    config: &Config,
+    dirs: &Dirs,
+    screen_dimensions: &ScreenDimensions,
+    palette: (Option<image::Rgba<u8>>, Option<ForegroundColor>),
+) -> Result<(), XkcdError> {
+    if !cli.workspaces {
+        return generate(cli, config, dirs, screen_dimensions, palette).map(|_| ());
+    }
+
+    let comics = workspace::pick(&config.workspaces.comics(), &mut Rng::from_clock())?;
+    let mut wallpapers = Vec::with_capacity(comics.len());
+    for (index, comic) in comics.into_iter().enumerate() {
+        let args = GenerateArgs {
+            comic: Some(comic),
+            output: Some(setter::with_suffix(
+                cli.output(),
+                &format!("-workspace{}", index + 1),
+            )),
+            set: false,
+            workspaces: false,
+            ..cli.clone()
+        };
+        wallpapers.push(generate(
+            &args,
+            config,
+            dirs,
+            screen_dimensions,
+            palette.clone(),
+        )?);
+    }
+
+    if cli.set {
+        let workspaces = WorkspaceBackend::detect().ok_or_else(|| {
+            XkcdError::Setter("per-workspace wallpapers need KDE Plasma or Hyprland".to_string())
+        })?;
+        let backend = cli
+            .backend
+            .or_else(Backend::detect)
+            .ok_or_else(|| XkcdError::Setter("cannot detect wallpaper backend".to_string()))?;
+        workspaces.assign(&wallpapers, backend)?;
+    }
+    Ok(())
+}
+
+/// Download the comic and write the wallpaper, colors from `palette` are used unless set on the CLI
+///
+/// Returns the path of the written wallpaper.
+fn generate(
+    cli: &GenerateArgs,
+    config: &Config,
+    dirs: &Dirs,
+    screen_dimensions: &ScreenDimensions,
+    palette: (Option<image::Rgba<u8>>, Option<ForegroundColor>),
+) -> Result<PathBuf, XkcdError> {
+    // Discard stages left over from a previous run of the daemon
+    timings::take();
+
+    let large = cli.prefer_large || config.download.large.wanted(screen_dimensions);
+    let mut collage_comics = fetch_collage(cli, dirs, large, &config.interactive)?;
+    let comic_img = match collage_comics.is_empty() {
+        true => fetch_suitable_comic(cli, dirs, large, &config.interactive)?,
+        false => collage_comics.remove(0),
+    };
+
+    generate_comic(
+        cli,
+        config,
+        dirs,
+        screen_dimensions,
+        palette,
+        comic_img,
+        collage_comics,
+    )
+}
+
+/// Write the wallpaper of `comic_img`, cut into pages with `--split`
+///
+/// Returns the path of the written wallpaper, the first page's when split.
+fn generate_comic(
+    cli: &GenerateArgs,
+    config: &Config,
+    dirs: &Dirs,
+    screen_dimensions: &ScreenDimensions,
+    palette: (Option<image::Rgba<u8>>, Option<ForegroundColor>),
+    comic_img: Image,
+    collage_comics: Vec<Image>,
+) -> Result<PathBuf, XkcdError> {
+    let pages = match cli.split {
+        Some(pages) => split::split(&comic_img.img, pages, screen_dimensions),
+        None => Vec::new(),
+    };
+    if pages.len() < 2 {
+        return generate_from(
+            cli,
+            config,
+            dirs,
+            screen_dimensions,
+            palette,
+            comic_img,
+            collage_comics,
+        );
+    }
+    // The first page takes the place of the whole comic, for --set and the caller
+    let mut outputs = Vec::with_capacity(pages.len());
+    for (index, page) in pages.into_iter().enumerate() {
+        let first = index == 0;
+        let args = GenerateArgs {
+            output: Some(setter::with_suffix(
+                cli.output(),
+                &format!("-page{}", index + 1),
+            )),
+            set: cli.set && first,
+            print_alt: cli.print_alt && first,
+            notify: cli.notify && first,
+            ..cli.clone()
+        };
+        let page = Image {
+            img: page,
+            ..comic_img.clone()
+        };
+        outputs.push(generate_from(
+            &args,
+            config,
+            dirs,
+            screen_dimensions,
+            palette.clone(),
+            page,
+            Vec::new(),
+        )?);
+    }
+    Ok(outputs.swap_remove(0))
+}
+
+/// Write the wallpaper of `comic_img`, with `collage_comics` after it in a collage
+///
+/// Returns the path of the written wallpaper.
+fn generate_from(
+    cli: &GenerateArgs,
+    config: &Config,
+    dirs: &Dirs,
+    screen_dimensions: &ScreenDimensions,
+    palette: (Option<image::Rgba<u8>>, Option<ForegroundColor>),
+    mut comic_img: Image,
+    mut collage_comics: Vec<Image>,
+) -> Result<PathBuf, XkcdError> {
+    let (palette_bg, palette_fg) = palette;
+    let (cli_fg, system_bg) = match &cli.fg {
+        Some(setting) => {
+            let (fg, bg) = setting.resolve();
+            (Some(fg), bg)
+        }
+        None => (None, None),
+    };
+    let scheme = match &cli.scheme {
+        Some(path) => Some(Base16Scheme::load(path)?.palette(&cli.scheme_slots)?),
+        None => None,
+    };
+    let bg_image = match &cli.bg_image {
+        Some(path) => {
+            info!("loading background image {}", path.display());
+            Some(Background::Image(image::open(path)?))
+        }
+        None => None,
+    };
+    let fg = cli_fg
+        .or(bg_image.as_ref().map(Background::contrasting_foreground))
+        .or(cli.theme.map(|theme| theme.foreground.clone()))
+        .or(scheme.as_ref().map(|(_, fg)| fg.clone()))
+        .or(palette_fg)
+        .unwrap_or_default();
+    let bg = if cli.high_contrast {
+        comic_img.img = effects::high_contrast(&comic_img.img, HIGH_CONTRAST_STROKE_RADIUS);
+        for comic in &mut collage_comics {
+            comic.img = effects::high_contrast(&comic.img, HIGH_CONTRAST_STROKE_RADIUS);
+        }
+        match fg.is_light() {
+            true => image::Rgba([0, 0, 0, 255]),
+            false => image::Rgba([255, 255, 255, 255]),
+        }
+    } else {
+        cli.bg
+            .or(cli.theme.map(|theme| theme.background))
+            .or(scheme.as_ref().map(|(bg, _)| *bg))
+            .or(palette_bg)
+            .or(system_bg)
+            .unwrap_or(DEFAULT_BG)
+    };
+    let background = match cli.bg_blur {
+        Some(sigma) => Background::Blur { color: bg, sigma },
+        None => bg_image.unwrap_or(Background::Color(bg)),
+    };
+
+    if let Some(tolerance) = cli.trim {
+        comic_img.img = effects::trim(&comic_img.img, tolerance);
+        for comic in &mut collage_comics {
+            comic.img = effects::trim(&comic.img, tolerance);
+        }
+    }
+    if let Some(settings) = cli.halftone {
+        comic_img.img = effects::halftone(&comic_img.img, settings);
+        for comic in &mut collage_comics {
+            comic.img = effects::halftone(&comic.img, settings);
+        }
+    }
+    if let Some(reflow) = cli.reflow {
+        comic_img.img = panels::reflow(&comic_img.img, reflow, screen_dimensions);
+    }
+    if let Some(device) = cli.device {
+        let (width, height) = (comic_img.img.width(), comic_img.img.height());
+        if device.rotate_landscape && width > height {
+            info!("rotating landscape comic for portrait device");
+            let _timer = timings::stage("transforms");
+            comic_img.img = comic_img.img.rotate90();
+        }
+    }
+
+    for (format, path) in &cli.export_scheme {
+        info!("exporting {:?} color scheme to {}", format, path.display());
+        fs::write(path, render_scheme(*format, background.color(), fg.ink()))?;
+    }
+
+    let mut options = format!(
+        "{}x{} bg={:?} fg={:?} high_contrast={} placement={:?}",
+        screen_dimensions.width,
+        screen_dimensions.height,
+        bg.0,
+        fg,
+        cli.high_contrast,
+        cli.placement
+    );
+    // Only appended when set, so hashes of earlier entries stay comparable
+    let layout = cli.layout();
+    let placed = Layout {
+        anchor: layout.anchor,
+        scale: layout.scale,
+        sharpen: layout.sharpen,
+        ..Layout::from(cli.placement)
+    };
+    if layout != placed {
+        options.push_str(&format!(
+            " padding={} offset={},{}",
+            layout.margins, layout.offset_x, layout.offset_y
+        ));
+    }
+    if layout.scale != ScaleMode::default() {
+        options.push_str(&format!(" scale={}", layout.scale));
+    }
+    if let Some(sharpen) = layout.sharpen {
+        options.push_str(&format!(" sharpen={}", sharpen));
+    }
+    if layout.anchor != Anchor::default() {
+        options.push_str(&format!(" position={:?}", layout.anchor));
+    }
+    if cli.bg_tolerance > 0 {
+        options.push_str(&format!(" bg_tolerance={}", cli.bg_tolerance));
+    }
+    if cli.smooth_edges {
+        options.push_str(" smooth_edges");
+    }
+    let tone = cli.recolor().tone;
+    if tone != Tone::default() {
+        options.push_str(&format!(
+            " tone={},{},{}",
+            tone.brightness, tone.contrast, tone.gamma
+        ));
+    }
+    if let Some(path) = &cli.bg_image {
+        options.push_str(&format!(" bg_image={}", path.display()));
+    }
+    if let Some(sigma) = cli.bg_blur {
+        options.push_str(&format!(" bg_blur={}", sigma));
+    }
+    if let Some(shadow) = cli.shadow {
+        options.push_str(&format!(" shadow={}", shadow));
+    }
+    if let Some(border) = cli.border {
+        options.push_str(&format!(" border={}", border));
+    }
+    if let Some(card) = cli.card {
+        options.push_str(&format!(" card={}", card));
+    }
+    let font = match &cli.font {
+        Some(path) => Font::load(path)?,
+        None => Font::default(),
+    };
+    let decoration = cli.decoration(font.clone());
+    if let Some((position, style)) = &decoration.title {
+        options.push_str(&format!(
+            " title={:?},{},{:?}",
+            position, style.size, style.color
+        ));
+    }
+    if let Some((width, style)) = &decoration.alt {
+        options.push_str(&format!(" alt={},{},{:?}", width, style.size, style.color));
+    }
+    if let Some(path) = &cli.font {
+        options.push_str(&format!(" font={}", path.display()));
+    }
+    let template = cli.template.as_deref().map(Template::load).transpose()?;
+    if let Some(path) = &cli.template {
+        options.push_str(&format!(" layout={}", path.display()));
+    }
+    if let Some(strength) = cli.vignette {
+        options.push_str(&format!(" vignette={}", strength));
+    }
+    if let Some(amount) = cli.grain {
+        options.push_str(&format!(" grain={}", amount));
+    }
+    if cli.grayscale {
+        options.push_str(" grayscale");
+    }
+    if let Some(method) = cli.dither {
+        options.push_str(&format!(" dither={:?},{}", method, cli.gray_levels()));
+    }
+    if let Some(colors) = cli.png_colors {
+        options.push_str(&format!(" png_colors={}", colors));
+    }
+    if let Some(settings) = cli.halftone {
+        options.push_str(&format!(" halftone={}", settings));
+    }
+    if let Some(tolerance) = cli.trim {
+        options.push_str(&format!(" trim={}", tolerance));
+    }
+    if let Some(reflow) = cli.reflow {
+        options.push_str(&format!(" reflow={:?}", reflow));
+    }
+    if let Some(pages) = cli.split {
+        options.push_str(&format!(" split={:?}", pages));
+    }
+    if let Some(corner) = cli.qr {
+        options.push_str(&format!(" qr={:?},{}", corner, cli.qr_explain));
+    }
+    let collage = cli.collage();
+    if let Some(collage) = &collage {
+        options.push_str(&format!(
+            " collage={:?},{},{:?}",
+            collage.arrangement, collage.spacing, collage.columns
+        ));
+    }
+    let tiling = cli.tiling();
+    if let Some(tiling) = &tiling {
+        options.push_str(&format!(
+            " tile={},{},{}",
+            tiling.scale, tiling.gap, tiling.offset
+        ));
+    }
+    let badge = cli.badge(font.clone());
+    let watermark = cli.watermark(font.clone());
+    if let Some(badge) = &badge {
+        options.push_str(&format!(
+            " badge={:?},{:?},{},{:?},{:?},{}",
+            badge.corner,
+            badge.format,
+            badge.style.size,
+            badge.style.color,
+            badge.background,
+            badge.opacity
+        ));
+    }
+    if let Some(watermark) = &watermark {
+        options.push_str(&format!(
+            " watermark={:?},{:?},{},{:?},{},{}",
+            watermark.text,
+            watermark.position,
+            watermark.style.size,
+            watermark.style.color,
+            watermark.opacity,
+            watermark.rotation
+        ));
+    }
+
+    let store = StateStore::in_state_dir(&dirs.state);
+    let output = match cli.alternate_output {
+        Some(mode) => mode.template(cli.output(), store.load().second_output, Timestamp::now()),
+        None => cli.output().to_string(),
+    };
+    let png = output.to_lowercase().ends_with(".png");
+    if background == Background::Color(TRANSPARENT) && !png {
+        warn!(
+            "only PNG keeps a transparent background, {} may be opaque",
+            output
+        );
+    }
+    if cli.png_colors.is_some() && !png {
+        warn!("only PNG has a palette, {} keeps all its colors", output);
+    }
+
+    let metadata = comic_img.metadata.clone();
+    let (ink, paper) = (fg.ink(), background.color());
+    let output_path = if cli.streams(screen_dimensions) {
+        info!("streaming wallpaper to disk");
+        let output_path = comic_img.output_path(&output);
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        save_wallpaper_streaming(
+            comic_img,
+            fg,
+            background,
+            screen_dimensions.clone(),
+            cli.layout(),
+            cli.recolor(),
+            decoration,
+            &output,
+        )?
+    } else {
+        info!("converting xkcd image into wallpaper");
+        let mut wallpaper = match (&template, &collage, &tiling) {
+            (_, _, Some(tiling)) => tiling.compose(
+                comic_img,
+                fg,
+                background,
+                screen_dimensions.clone(),
+                cli.recolor(),
+                decoration,
+            ),
+            (_, Some(collage), None) => collage.compose(
+                [comic_img].into_iter().chain(collage_comics).collect(),
+                fg,
+                background,
+                screen_dimensions.clone(),
+                cli.recolor(),
+                decoration,
+            ),
+            (Some(template), None, None) => template.compose(
+                comic_img,
+                fg,
+                background,
+                screen_dimensions.clone(),
+                cli.recolor(),
+                decoration,
+                &font,
+            ),
+            (None, None, None) => get_wallpaper_from_comic(
+                comic_img,
+                fg,
+                background,
+                screen_dimensions.clone(),
+                cli.layout(),
+                cli.recolor(),
+                decoration,
+            ),
+        };
+        #[cfg(feature = "scripting")]
+        if let Some(path) = &cli.script {
+            let mut img = wallpaper.img.into_rgba8();
+            Script::load(path)?.apply(&mut img, &wallpaper.metadata)?;
+            wallpaper.img = DynamicImage::ImageRgba8(img);
+        }
+        if let Some(corner) = cli.qr {
+            let url = match cli.qr_explain {
+                true => web::explain_url(metadata.num),
+                false => web::comic_url(metadata.num),
+            };
+            let code = qr::encode(url.as_bytes())?;
+            let mut img = wallpaper.img.into_rgba8();
+            let (dark, light) = qr::colors(ink, paper);
+            qr::overlay(&mut img, &code, corner, dark, light);
+            wallpaper.img = DynamicImage::ImageRgba8(img);
+        }
+        if let Some(badge) = &badge {
+            let mut img = wallpaper.img.into_rgba8();
+            badge.overlay(&mut img, &metadata, ink, paper);
+            wallpaper.img = DynamicImage::ImageRgba8(img);
+        }
+        if let Some(watermark) = &watermark {
+            let mut img = wallpaper.img.into_rgba8();
+            watermark.overlay(&mut img, ink);
+            wallpaper.img = DynamicImage::ImageRgba8(img);
+        }
+        if let Some(strength) = cli.vignette {
+            let mut img = wallpaper.img.into_rgba8();
+            effects::vignette(&mut img, strength, paper);
+            wallpaper.img = DynamicImage::ImageRgba8(img);
+        }
+        if let Some(amount) = cli.grain {
+            let mut img = wallpaper.img.into_rgba8();
+            effects::grain(&mut img, amount, wallpaper.metadata.num);
+            wallpaper.img = DynamicImage::ImageRgba8(img);
+        }
+        if cli.grayscale {
+            wallpaper.img = effects::desaturate(&wallpaper.img);
+        }
+        let dither = cli.dither.or(cli
+            .device
+            .filter(|device| device.dither)
+            .map(|_| Dither::default()));
+        if cli.device.is_some() || dither.is_some() {
+            let gray = effects::grayscale(&wallpaper.img);
+            wallpaper.img = DynamicImage::ImageLuma8(match dither {
+                Some(method) => effects::dither(&gray, cli.gray_levels(), method),
+                None => gray,
+            });
+        }
+        if let Some(boot) = cli.boot {
+            let _timer = timings::stage("transforms");
+            wallpaper.img = boot.prepare_image(wallpaper.img, screen_dimensions);
+        }
+
+        let output_path = wallpaper.output_path(&output);
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match cli.png_colors.filter(|_| png) {
+            Some(colors) => {
+                quantize(&wallpaper.img.to_rgba8(), colors).save(&output_path)?;
+                output_path
+            }
+            None => wallpaper.save(&output)?,
+        }
+    };
+
+    if let (Some(boot), true) = (cli.boot, cli.boot_install) {
+        let files = boot.install(&output_path, screen_dimensions, Path::new("/"))?;
+        for file in files {
+            println!("Installed {}", file.display());
+        }
+        println!("Run `{}` to apply the boot background", boot.next_step());
+    }
+
+    let hook_context = HookContext {
+        output: &output_path,
+        num: metadata.num,
+        title: &metadata.safe_title,
+        alt: &metadata.alt,
+    };
+    if let Some(command) = &config.hooks.post_generate {
+        if let Err(err) = hooks::run("post_generate", command, &hook_context) {
+            error!("{}", err);
+        }
+    }
+
+    if cli.set {
+        let backend = cli
+            .backend
+            .or_else(Backend::detect)
+            .ok_or_else(|| XkcdError::Setter("cannot detect wallpaper backend".to_string()))?;
+        WallpaperSetter::new(backend, &dirs.state).set(&output_path)?;
+
+        if let Some(command) = &config.hooks.post_set {
+            if let Err(err) = hooks::run("post_set", command, &hook_context) {
+                error!("{}", err);
+            }
+        }
+    }
+
+    if cli.alternate_output.is_some() {
+        let output_path = std::path::absolute(&output_path)?;
+        let mut replaced = None;
+        if let Err(err) = store.update(|state| {
+            state.second_output = !state.second_output;
+            replaced = state.alternate_output.replace(output_path.clone());
+        }) {
+            warn!("failed to save state: {}", err);
+            replaced = None;
+        }
+        // `ab` only ever writes two files, timestamped ones would pile up
+        if let Some(replaced) = replaced.filter(|replaced| {
+            *replaced != output_path && cli.alternate_output == Some(AlternateOutput::Timestamp)
+        }) {
+            info!("removing previous output {}", replaced.display());
+            if let Err(err) = fs::remove_file(&replaced) {
+                warn!("failed to remove {}: {}", replaced.display(), err);
+            }
+        }
+    }
+
+    if cli.print_alt {
+        println!("{}", metadata.alt);
+    }
+    if cli.notify {
+        let summary = format!("xkcd #{}: {}", metadata.num, metadata.safe_title);
+        let url = web::comic_url(metadata.num);
+        if let Err(err) = notify::send_clickable(&summary, &metadata.alt, &url) {
+            warn!("{}", err);
+        }
+    }
+
+    let entry = HistoryEntry {
+        timestamp: Timestamp::now(),
+        num: metadata.num,
+        title: metadata.safe_title.clone(),
+        alt: Some(metadata.alt.clone()).filter(|alt| !alt.is_empty()),
+        options_hash: options_hash(&options),
+        output: std::path::absolute(&output_path)?,
+    };
+    if let Err(err) = History::in_state_dir(&dirs.state).append(&entry) {
+        warn!("failed to record wallpaper in history: {}", err);
+    }
+    if let Err(err) =
+        StateStore::in_state_dir(&dirs.state).update(|state| state.last_seen = Some(metadata.num))
+    {
+        warn!("failed to save state: {}", err);
+    }
+    if let Err(err) = config.cache.enforce(&dirs.cache) {
+        warn!("failed to evict comics from the cache: {}", err);
+    }
+
+    if cli.timings {
+        print_timings();
+    }
+    Ok(output_path)
+}
+
+/// Print the time spent in each pipeline stage to stderr
+fn print_timings() {
+    let stages = timings::take();
+    let total: Duration = stages.iter().map(|(_, duration)| *duration).sum();
+    for (name, duration) in stages {
+        eprintln!("{:<12} {:>9.1} ms", name, duration.as_secs_f64() * 1000.0);
+    }
+    eprintln!("{:<12} {:>9.1} ms", "total", total.as_secs_f64() * 1000.0);
+    if let Some(rss) = timings::peak_rss() {
+        eprintln!(
+            "{:<12} {:>9.1} MiB",
+            "peak rss",
+            rss as f64 / (1024.0 * 1024.0)
+        );
+    }
+}
+
+/// Random comics that may turn out not to exist or be interactive before `--random` gives up
+const MAX_SKIPPED_COMICS: u32 = 5;
+
+/// Comics tried in place of one that is unsuitable, does not exist or is interactive
+///
+/// Random comics are drawn again among the ones not tried yet. Otherwise the comics after the
+/// selected one are tried in order, then the ones before it once there are no newer comics.
+struct Candidates {
+    tried: BTreeSet<u64>,
+    rng: Option<Rng>,
+    /// Comics that can be picked offline, any comic when `None`
+    cached: Option<BTreeSet<u64>>,
+    /// Newest comic, looked up for the first random pick online
+    latest: Option<u64>,
+    forward: bool,
+}
+
+impl Candidates {
+    /// Next comic to try, `None` once every candidate was tried
+    fn next(&mut self, cache: &ComicCache) -> Result<Option<u32>, XkcdError> {
+        let num = match (&mut self.rng, &self.cached) {
+            (Some(rng), Some(cached)) => {
+                let nums = cached.difference(&self.tried).copied().collect::<Vec<_>>();
+                match nums.is_empty() {
+                    true => None,
+                    false => Some(nums[rng.below(nums.len() as u64) as usize]),
+                }
+            }
+            (Some(rng), None) => {
+                let latest = match self.latest {
+                    Some(latest) => latest,
+                    None => *self
+                        .latest
+                        .insert(cache.metadata(None, CachePolicy::Prefer)?.num),
+                };
+                let missing = MISSING_COMICS.iter().filter(|num| **num <= latest).count();
+                let tried = self.tried.range(..=latest).count();
+                match tried + missing >= latest as usize {
+                    true => None,
+                    false => loop {
+                        let num = rng.comic(latest);
+                        if !self.tried.contains(&num) {
+                            break Some(num);
+                        }
+                    },
+                }
+            }
+            (None, cached) => {
+                let (Some(&first), Some(&last)) = (self.tried.first(), self.tried.last()) else {
+                    return Ok(None);
+                };
+                match (self.forward, cached) {
+                    (true, Some(cached)) => cached.range(last + 1..).next().copied(),
+                    (false, Some(cached)) => cached.range(..first).next_back().copied(),
+                    (true, None) => (last + 1..).find(|num| !MISSING_COMICS.contains(num)),
+                    (false, None) => (1..first).rev().find(|num| !MISSING_COMICS.contains(num)),
+                }
+            }
+        };
+        Ok(num.map(|num| num as u32))
+    }
+}
+
+/// Download the requested comic, picking another one while it fails the quality checks
+///
+/// With `large` set, the large version of the image is used for comics that have one. Interactive
+/// comics are handled as `interactive` says.
+fn fetch_suitable_comic(
+    cli: &GenerateArgs,
+    dirs: &Dirs,
+    large: bool,
+    interactive: &InteractiveConfig,
+) -> Result<Image, XkcdError> {
+    let quality = QualityOptions {
+        strict_mono: cli.strict_mono,
+        interactive: interactive.placeholders(),
+        ..Default::default()
+    };
+    let check = cli.strict_mono || cli.retry_different > 0;
+
+    let cache = ComicCache::new(&dirs.cache);
+    let (mut comic_number, policy) = match cli.offline {
+        true => (offline_comic_number(cli, &cache)?, CachePolicy::Offline),
+        false => {
+            let comic_number = match (cli.comic, cli.random, cli.date) {
+                (_, _, Some(date)) => Some(archive::comic_for_date(date, &dirs.cache)? as u32),
+                (_, true, _) => Some(random_comic_number()? as u32),
+                (Some(comic), false, None) => comic.resolve()?,
+                (None, false, None) => None,
+            };
+            (comic_number, CachePolicy::Prefer)
+        }
+    };
+    let mut candidates = Candidates {
+        tried: BTreeSet::new(),
+        rng: cli.random.then(Rng::from_clock),
+        cached: match cli.offline {
+            true => Some(cache.index()?.into_keys().collect()),
+            false => None,
+        },
+        latest: None,
+        // There are no comics after the newest one
+        forward: comic_number.is_some(),
+    };
+    let (mut attempt, mut skipped) = (0, 0);
+    let mut unsuitable = None;
+    loop {
+        info!("starting comic download");
+        let metadata = match cache.metadata(comic_number, policy) {
+            Err(XkcdError::ComicNotFound(num)) if cli.random && skipped < MAX_SKIPPED_COMICS => {
+                candidates.tried.insert(num as u64);
+                let Some(next) = candidates.next(&cache)? else {
+                    return Err(XkcdError::ComicNotFound(num));
+                };
+                skipped += 1;
+                warn!("comic {} does not exist, picking another one", num);
+                comic_number = Some(next);
+                continue;
+            }
+            Err(XkcdError::ComicNotFound(num)) if candidates.forward && unsuitable.is_some() => {
+                info!("comic {} does not exist, trying earlier comics", num);
+                candidates.forward = false;
+                match candidates.next(&cache)? {
+                    Some(next) => {
+                        comic_number = Some(next);
+                        continue;
+                    }
+                    None => return Err(unsuitable.unwrap_or(XkcdError::ComicNotFound(num))),
+                }
+            }
+            result => result?,
+        };
+        candidates.tried.insert(metadata.num);
+        let fallback = interactive.fallback(&metadata).filter(|_| !cli.offline);
+        if fallback.is_none() && interactive.is_interactive(metadata.num) {
+            let skip = interactive.action != InteractiveAction::Warn;
+            if cli.random && skip && skipped < MAX_SKIPPED_COMICS {
+                if let Some(next) = candidates.next(&cache)? {
+                    skipped += 1;
+                    warn!("comic {} is interactive, picking another one", metadata.num);
+                    comic_number = Some(next);
+                    continue;
+                }
+            }
+            warn!(
+                "comic {} is interactive, its static image may make a poor wallpaper",
+                metadata.num
+            );
+        }
+        // Fallback images are not cached, the placeholder may be cached under the same name
+        let (metadata, image_policy) = match fallback {
+            Some(fallback) => (fallback, CachePolicy::Bypass),
+            None => (metadata, policy),
+        };
+        let large_comic = match large && !cli.offline {
+            true => metadata.to_large_image().unwrap_or_else(|err| {
+                warn!("cannot get large image, using the regular one: {}", err);
+                None
+            }),
+            false => None,
+        };
+        let comic = match large_comic {
+            Some(comic) => comic,
+            None => cache.image(&metadata, image_policy)?,
+        };
+        if !check {
+            return Ok(comic);
+        }
+
+        let num = comic.metadata.num;
+        let reason = match quality.check(&comic) {
+            Ok(()) => return Ok(comic),
+            Err(reason) => reason,
+        };
+        let next = match attempt < cli.retry_different {
+            true => candidates.next(&cache)?,
+            false => None,
+        };
+        let Some(next) = next else {
+            return Err(XkcdError::Unsuitable { num, reason });
+        };
+        attempt += 1;
+        warn!(
+            "comic {} is unsuitable ({}), trying comic {} (attempt {}/{})",
+            num, reason, next, attempt, cli.retry_different
+        );
+        unsuitable = Some(XkcdError::Unsuitable { num, reason });
+        comic_number = Some(next);
+    }
+}
+
+/// Comics of `--collage` or `--collage-random`, none without a collage
+///
+/// Comics are checked and replaced like with [`fetch_suitable_comic`]. Random comics are picked
+/// again when they repeat one that is already in the collage.
+fn fetch_collage(
+    cli: &GenerateArgs,
+    dirs: &Dirs,
+    large: bool,
+    interactive: &InteractiveConfig,
+) -> Result<Vec<Image>, XkcdError> {
+    let mut comics: Vec<Image> = Vec::new();
+    if let Some(ComicList(nums)) = &cli.collage {
+        for num in nums {
+            let args = GenerateArgs {
+                comic: Some(ComicRef::Number(*num)),
+                ..cli.clone()
+            };
+            comics.push(fetch_suitable_comic(&args, dirs, large, interactive)?);
+        }
+    }
+    if let Some(count) = cli.collage_random {
+        let args = GenerateArgs {
+            random: true,
+            ..cli.clone()
+        };
+        let mut repeats = 0;
+        while comics.len() < count as usize {
+            let comic = fetch_suitable_comic(&args, dirs, large, interactive)?;
+            let num = comic.metadata.num;
+            if comics.iter().any(|known| known.metadata.num == num) && repeats < MAX_SKIPPED_COMICS
+            {
+                repeats += 1;
+                info!(
+                    "comic {} is already in the collage, picking another one",
+                    num
+                );
+                continue;
+            }
+            comics.push(comic);
+        }
+    }
+    Ok(comics)
+}
+
+/// Comic number picked by `--comic`, `--random` or `--date` among the cached comics
+///
+/// `None` stands for the newest cached comic.
+fn offline_comic_number(cli: &GenerateArgs, cache: &ComicCache) -> Result<Option<u32>, XkcdError> {
+    let cached = cache.index()?;
+    let newest = || {
+        cached
+            .keys()
+            .next_back()
+            .copied()
+            .ok_or(XkcdError::NotCached(None))
+    };
+    let num = match (cli.comic, cli.random, cli.date) {
+        (_, _, Some(date)) => {
+            let index = ArchiveIndex {
+                comics: cached
+                    .iter()
+                    .filter_map(|(num, metadata)| Some((*num, metadata.date()?)))
+                    .collect(),
+                ..Default::default()
+            };
+            Some(index.nearest(date).ok_or(XkcdError::NotCached(None))?)
+        }
+        (_, true, _) => {
+            let nums = cached.keys().copied().collect::<Vec<_>>();
+            match nums.is_empty() {
+                true => return Err(XkcdError::NotCached(None)),
+                false => Some(nums[Rng::from_clock().below(nums.len() as u64) as usize]),
+            }
+        }
+        (Some(comic), false, None) => comic.resolve_with(newest)?.map(u64::from),
+        (None, false, None) => None,
+    };
+    if let Some(num) = num {
+        info!("using cached comic {}", num);
+    }
+    Ok(num.map(|num| num as u32))
+}
+
+/// Print history entries matching the filters in `args`
+fn print_history(args: &HistoryArgs, dirs: &Dirs) {
+    let tz = TimeZone::system();
+    let mut entries: Vec<_> = History::in_state_dir(&dirs.state)
+        .entries()
+        .expect("Failed to read history")
+        .into_iter()
+        .filter(|entry| {
+            let date = entry.timestamp.to_zoned(tz.clone()).date();
+            args.comic.is_none_or(|num| num == entry.num)
+                && args.since.is_none_or(|since| date >= since)
+                && args.until.is_none_or(|until| date <= until)
+        })
+        .collect();
+    if let Some(limit) = args.limit {
+        entries.drain(..entries.len().saturating_sub(limit));
+    }
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&entries).expect("Failed to serialize history")
+        );
+        return;
+    }
+    for entry in entries {
+        println!(
+            "{}  #{:<5} {}  {}",
+            entry
+                .timestamp
+                .to_zoned(tz.clone())
+                .strftime("%a %Y-%m-%d %H:%M"),
+            entry.num,
+            entry.title,
+            entry.output.display()
+        );
+    }
+}
+
+/// Pick a comic interactively and generate the wallpaper for it
+fn browse(args: &BrowseArgs, config: &Config, dirs: &Dirs) {
+    let history = History::in_state_dir(&dirs.state)
+        .entries()
+        .expect("Failed to read history");
+    let archive = ArchiveIndex::load(&dirs.cache).expect("Failed to read archive index");
+    let entries = BrowseEntry::from_index(&ComicCache::new(&dirs.cache), &archive, &history)
+        .expect("Failed to read comic cache");
+    if entries.is_empty() {
+        println!("No comics to browse yet, generate a wallpaper first");
+        return;
+    }
+
+    let Some(entry) = browse::pick(&entries).expect("Failed to run comic picker") else {
+        return;
+    };
+    println!("Generating wallpaper for #{} {}", entry.num, entry.title);
+    let generate = GenerateArgs {
+        comic: Some(ComicRef::Number(entry.num as u32)),
+        ..(*args.generate).clone()
+    };
+    run_generate(&generate, config, dirs);
+}
+
+/// Export the history and scanned directories as an HTML gallery
+fn export_gallery(args: &ExportGalleryArgs, dirs: &Dirs) {
+    let history = History::in_state_dir(&dirs.state)
+        .entries()
+        .expect("Failed to read history");
+    let mut items = GalleryItem::from_history(&history);
+    for dir in &args.scan {
+        let scanned = GalleryItem::scan_dir(dir, &items).expect("Failed to scan directory");
+        items.extend(scanned);
+    }
+
+    let index =
+        gallery::export(&items, &args.out_dir, args.copy).expect("Failed to export gallery");
+    println!("Wrote {} wallpapers to {}", items.len(), index.display());
+}
+
+/// Write a contact sheet of the most recent wallpapers in the history
+fn contact_sheet(args: &ContactSheetArgs, dirs: &Dirs) {
+    let history = History::in_state_dir(&dirs.state)
+        .entries()
+        .expect("Failed to read history");
+    // Newest first to pick the last wallpapers, each file once
+    let mut recent: Vec<&HistoryEntry> = Vec::new();
+    for entry in history.iter().rev() {
+        if recent.len() == args.last {
+            break;
+        }
+        if entry.output.is_file() && recent.iter().all(|known| known.output != entry.output) {
+            recent.push(entry);
+        }
+    }
+    let wallpapers: Vec<_> = recent
+        .iter()
+        .rev()
+        .map(|entry| (entry.num, entry.output.as_path()))
+        .collect();
+
+    let sheet = ContactSheet {
+        columns: args.columns,
+        thumbnail_width: args.thumbnail_width,
+    }
+    .compose(&wallpapers)
+    .expect("Failed to compose contact sheet");
+    sheet
+        .save(&args.output)
+        .expect("Failed to write contact sheet");
+    println!(
+        "Wrote {} wallpapers to {}",
+        wallpapers.len(),
+        args.output.display()
+    );
+}
+
+/// Open a comic page in the browser
+fn open_comic(args: &OpenArgs, dirs: &Dirs) {
+    let num = match args.comic {
+        Some(comic) => match comic.resolve().expect("Failed to resolve comic") {
+            Some(num) => num as u64,
+            None => {
+                Metadata::from_comic_id(None)
+                    .expect("Failed to fetch the newest comic")
+                    .num
+            }
+        },
+        None => StateStore::in_state_dir(&dirs.state)
+            .load()
+            .last_seen
+            .or_else(|| {
+                History::in_state_dir(&dirs.state)
+                    .entries()
+                    .ok()?
+                    .last()
+                    .map(|entry| entry.num)
+            })
+            .expect("No wallpaper generated yet, pass a comic number"),
+    };
+    let url = match args.explain {
+        true => web::explain_url(num),
+        false => web::comic_url(num),
+    };
+    web::open(&url).expect("Failed to open browser");
+}
+
+/// Check the cache for corrupt files, deleting them or downloading them again
+fn verify_cache(args: &CacheVerifyArgs, dirs: &Dirs) {
+    let corrupt = cache::verify(&dirs.cache).expect("Failed to verify cache");
+    if corrupt.is_empty() {
+        println!("No corrupt files in {}", dirs.cache.display());
+        return;
+    }
+
+    let cache = ComicCache::new(&dirs.cache);
+    let mut checksums = Checksums::load(&dirs.cache).expect("Failed to read cache checksums");
+    for file in &corrupt {
+        fs::remove_file(&file.path).expect("Failed to delete corrupt file");
+        checksums.remove(&file.path);
+        let repaired = (args.redownload && file.is_image()).then(|| {
+            cache
+                .metadata(Some(file.num as u32), CachePolicy::Prefer)
+                .and_then(|metadata| metadata.download_to(&file.path))
+                .and_then(|_| checksums.record(&file.path))
+        });
+        let outcome = match repaired {
+            None => "deleted".to_string(),
+            Some(Ok(())) => "downloaded again".to_string(),
+            Some(Err(err)) => format!("deleted, download failed: {err}"),
+        };
+        println!("{}: {}, {}", file.path.display(), file.problem, outcome);
+    }
+    checksums.save().expect("Failed to write cache checksums");
+}
+
+/// Set today's planned wallpaper, every day with `--daemon`
+fn apply_plan(args: &ApplyPlanArgs, config: &Config, dirs: &Dirs) {
+    let backend = args
+        .backend
+        .or_else(Backend::detect)
+        .expect("Cannot detect wallpaper backend, use --backend");
+    let setter = WallpaperSetter::new(backend, &dirs.state);
+    loop {
+        let now = Timestamp::now().to_zoned(TimeZone::system());
+        let mut manifest = Manifest::load(&dirs.state).expect("Failed to read the plan");
+        match manifest.days.get(&now.date()).cloned() {
+            Some(entry) => {
+                setter
+                    .set(&entry.output)
+                    .expect("Failed to set planned wallpaper");
+                println!("Set comic {} planned for {}", entry.num, now.date());
+                if let Err(err) = StateStore::in_state_dir(&dirs.state)
+                    .update(|state| state.last_seen = Some(entry.num))
+                {
+                    warn!("failed to save state: {}", err);
+                }
+                if let Some(command) = &config.hooks.post_set {
+                    let context = HookContext {
+                        output: &entry.output,
+                        num: entry.num,
+                        title: "",
+                        alt: "",
+                    };
+                    if let Err(err) = hooks::run("post_set", command, &context) {
+                        error!("{}", err);
+                    }
+                }
+                remove_outputs(manifest.prune(now.date()));
+                if let Err(err) = manifest.save(&dirs.state) {
+                    warn!("failed to save the plan: {}", err);
+                }
+            }
+            None if args.daemon => warn!(
+                "no wallpaper planned for {}, generate more with --plan",
+                now.date()
+            ),
+            None => {
+                eprintln!(
+                    "No wallpaper planned for {}, generate some with --plan",
+                    now.date()
+                );
+                std::process::exit(1);
+            }
+        }
+
+        if !args.daemon {
+            return;
+        }
+        // Shortly after midnight, so the date has changed for sure
+        let wake = now
+            .tomorrow()
+            .and_then(|tomorrow| tomorrow.start_of_day())
+            .map(|midnight| midnight.timestamp() + 1.minute())
+            .expect("Tomorrow is a valid date");
+        let wake_in = Timestamp::now().duration_until(wake).unsigned_abs();
+        info!("sleeping for {}s", wake_in.as_secs());
+        sleep(wake_in);
+    }
+}
+
+/// Upload a wallpaper and print the resulting URL
+fn share(args: &ShareArgs, config: &Config, dirs: &Dirs) {
+    let path = args.path.clone().unwrap_or_else(|| {
+        History::in_state_dir(&dirs.state)
+            .entries()
+            .expect("Failed to read history")
+            .pop()
+            .expect("No wallpaper generated yet, pass a path to share")
+            .output
+    });
+
+    let mut share_config = config.share.clone();
+    if let Some(endpoint) = &args.endpoint {
+        share_config.endpoint = endpoint.clone();
+    }
+    let url = share_config
+        .upload(&path)
+        .expect("Failed to upload wallpaper");
+    println!("{url}");
+}
+
+/// Restore the wallpaper configured before `--set`
+fn restore(args: &RestoreArgs, dirs: &Dirs) {
+    let backend = args
+        .backend
+        .or_else(Backend::detect)
+        .expect("Cannot detect wallpaper backend, use --backend");
+    match WallpaperSetter::new(backend, &dirs.state)
+        .restore()
+        .expect("Failed to restore wallpaper")
+    {
+        Some(previous) => println!("Restored {previous}"),
+        None => println!("No previous wallpaper recorded for {backend:?}"),
+    }
+}
+
+/// Print cache and history statistics
+fn print_stats(args: &StatsArgs, dirs: &Dirs) {
+    let history = History::in_state_dir(&dirs.state)
+        .entries()
+        .expect("Failed to read history");
+    let stats = Stats::collect(&dirs.cache, &history, args.top).expect("Failed to collect stats");
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&stats).expect("Failed to serialize stats")
+        );
+        return;
+    }
+
+    let tz = TimeZone::system();
+    let format_date = |timestamp: Option<Timestamp>| {
+        timestamp
+            .map(|timestamp| {
+                timestamp
+                    .to_zoned(tz.clone())
+                    .strftime("%Y-%m-%d")
+                    .to_string()
+            })
+            .unwrap_or_else(|| "-".to_string())
+    };
+    println!(
+        "Cache:        {:.1} MiB in {}",
+        stats.cache_bytes as f64 / (1024.0 * 1024.0),
+        dirs.cache.display()
+    );
+    println!("Cached:       {} comics", stats.cached_comics);
+    println!(
+        "Generated:    {} wallpapers from {} comics",
+        stats.generations, stats.unique_comics
+    );
+    println!(
+        "Period:       {} to {}",
+        format_date(stats.first_generation),
+        format_date(stats.last_generation)
+    );
+    if !stats.top_comics.is_empty() {
+        println!("Most used:");
+        for comic in &stats.top_comics {
+            println!("  {:>4}x  #{:<5} {}", comic.count, comic.num, comic.title);
+        }
+    }
+}
+
+impl GenerateArgs {
+    /// Output filename template, the device convention is used when `--output` is not given
+    fn output(&self) -> &str {
+        self.output
+            .as_deref()
+            .or(self.device.map(|device| device.output))
+            .unwrap_or(DEFAULT_OUTPUT)
+    }
+
+    fn recolor(&self) -> Recolor {
+        Recolor {
+            bg_tolerance: self.bg_tolerance,
+            smooth_edges: self.smooth_edges,
+            tone: Tone {
+                brightness: self.brightness,
+                contrast: self.contrast,
+                gamma: self.gamma,
+            },
+        }
+    }
+
+    fn collage(&self) -> Option<Collage> {
+        (self.collage.is_some() || self.collage_random.is_some()).then_some(Collage {
+            arrangement: self.collage_arrangement,
+            spacing: self.collage_spacing,
+            columns: self.collage_columns,
+        })
+    }
+
+    fn gray_levels(&self) -> u8 {
+        self.gray_levels
+            .or(self.device.map(|device| device.gray_levels))
+            .unwrap_or(DEFAULT_GRAY_LEVELS)
+    }
+
+    fn tiling(&self) -> Option<Tiling> {
+        self.tile.then_some(Tiling {
+            scale: self.tile_scale,
+            gap: self.tile_gap,
+            offset: self.tile_offset,
+        })
+    }
+
+    fn badge(&self, font: Font) -> Option<Badge> {
+        self.badge.map(|corner| Badge {
+            corner,
+            format: self.badge_format.clone(),
+            style: TextStyle {
+                size: self.badge_size,
+                color: self.badge_color,
+                font,
+            },
+            background: self.badge_bg,
+            opacity: self.opacity(self.badge_opacity),
+        })
+    }
+
+    fn watermark(&self, font: Font) -> Option<Watermark> {
+        self.watermark.as_ref().map(|text| Watermark {
+            text: text.clone(),
+            position: self.watermark_position,
+            style: TextStyle {
+                size: self.watermark_size,
+                color: self.watermark_color,
+                font,
+            },
+            opacity: self.opacity(self.watermark_opacity),
+            rotation: self.watermark_rotation,
+        })
+    }
+
+    /// `opacity` of an overlay, opaque with `--high-contrast` which leaves no grays
+    fn opacity(&self, opacity: f32) -> f32 {
+        match self.high_contrast {
+            true => 1.0,
+            false => opacity,
+        }
+    }
+
+    fn decoration(&self, font: Font) -> Decoration {
+        Decoration {
+            shadow: self.shadow,
+            border: self.border,
+            card: self.card,
+            title: self.show_title.map(|position| {
+                (
+                    position,
+                    TextStyle {
+                        size: self.title_size,
+                        color: self.title_color,
+                        font: font.clone(),
+                    },
+                )
+            }),
+            alt: self.show_alt.then_some((
+                self.alt_width,
+                TextStyle {
+                    size: self.alt_size,
+                    color: self.alt_color,
+                    font,
+                },
+            )),
+        }
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            placement: self.placement,
+            anchor: self.position,
+            scale: self.scale,
+            margins: self.margin,
+            offset_x: self.offset_x,
+            offset_y: self.offset_y,
+            sharpen: self.sharpen,
+        }
+    }
+
+    /// Whether the wallpaper is written with `save_wallpaper_streaming`
+    ///
+    /// Device and boot exports and scripts post-process the full canvas, and only PNG can be streamed.
+    fn streams(&self, screen_dimensions: &ScreenDimensions) -> bool {
+        let large = screen_dimensions.width as u64 * screen_dimensions.height as u64
+            >= STREAMING_MIN_PIXELS;
+        let png = self.output().to_lowercase().ends_with(".png");
+        #[cfg(feature = "scripting")]
+        if self.script.is_some() {
+            return false;
+        }
+        (self.streaming || large)
+            && png
+            && self.device.is_none()
+            && self.boot.is_none()
+            && self.template.is_none()
+            && self.collage().is_none()
+            && !self.tile
+            && self.vignette.is_none()
+            && self.grain.is_none()
+            && !self.grayscale
+            && self.dither.is_none()
+            && self.png_colors.is_none()
+            && self.qr.is_none()
+            && self.badge.is_none()
+            && self.watermark.is_none()
+    }
+
+    /// Target dimensions from either `--size`, `--size-from`, `--device` or `--width` and `--height`
+    fn screen_dimensions(&self) -> Result<ScreenDimensions, XkcdError> {
+        match (
+            &self.size,
+            &self.size_from,
+            self.device,
+            self.width,
+            self.height,
+        ) {
+            (Some(size), _, _, _, _) => Ok(size.clone()),
+            (_, Some(path), _, _, _) => ScreenDimensions::from_image_file(path),
+            (_, _, Some(device), _, _) => Ok(device.screen_dimensions()),
+            (_, _, _, Some(width), Some(height)) => Ok(ScreenDimensions { width, height }),
+            _ => unreachable!(
+                "clap requires one of --size, --size-from, --device or --width and --height"
+            ),
+        }
+    }
+}
+
+/// Parse a `FORMAT=PATH` color scheme export
+fn parse_scheme_export(s: &str) -> Result<(SchemeFormat, PathBuf), String> {
+    let (format, path) = s
+        .split_once('=')
+        .ok_or("Scheme export must be in FORMAT=PATH format (e.g. kitty=./xkcd.conf)")?;
+    Ok((format.parse()?, PathBuf::from(path)))
+}
+
+/// Parse an opacity from 0 to 1
+fn parse_opacity(s: &str) -> Result<f32, String> {
+    s.parse()
+        .ok()
+        .filter(|opacity| (0.0..=1.0).contains(opacity))
+        .ok_or_else(|| format!("Invalid opacity '{s}', expected a number from 0 to 1"))
+}
+
+/// Parse a brightness change from -1 to 1
+fn parse_brightness(s: &str) -> Result<f32, String> {
+    s.parse()
+        .ok()
+        .filter(|amount| (-1.0..=1.0).contains(amount))
+        .ok_or_else(|| format!("Invalid brightness '{s}', expected a number from -1 to 1"))
+}
+
+/// Parse a positive contrast or gamma factor
+fn parse_factor(s: &str) -> Result<f32, String> {
+    s.parse()
+        .ok()
+        .filter(|factor: &f32| factor.is_finite() && *factor > 0.0)
+        .ok_or_else(|| format!("Invalid factor '{s}', expected a positive number like 1.2"))
+}
+
+/// Parse a positive scale factor of the tiles
+fn parse_tile_scale(s: &str) -> Result<f32, String> {
+    s.parse()
+        .ok()
+        .filter(|scale: &f32| scale.is_finite() && *scale > 0.0)
+        .ok_or_else(|| format!("Invalid scale '{s}', expected a positive number like 0.5"))
+}
+
+/// Parse the name of a built-in device profile
+fn parse_device(s: &str) -> Result<&'static DeviceProfile, String> {
+    DeviceProfile::by_name(s).ok_or_else(|| {
+        let names: Vec<_> = DEVICES.iter().map(|device| device.name).collect();
+        format!("Unknown device, available devices: {}", names.join(", "))
+    })
+}
+
+/// Parse the name of a built-in theme
+fn parse_theme(s: &str) -> Result<&'static Theme, String> {
+    Theme::by_name(s).ok_or_else(|| {
+        let names: Vec<_> = THEMES.iter().map(|theme| theme.name).collect();
+        format!("Unknown theme, available themes: {}", names.join(", "))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn candidates(tried: &[u64], cached: Option<&[u64]>, random: bool) -> Candidates {
+        Candidates {
+            tried: tried.iter().copied().collect(),
+            rng: random.then(|| Rng::new(7)),
+            cached: cached.map(|cached| cached.iter().copied().collect()),
+            latest: None,
+            forward: true,
+        }
+    }
+
+    #[test]
+    fn candidates_move_through_the_selection() {
+        let cache = ComicCache::new("/nonexistent");
+        assert_eq!(
+            candidates(&[100], None, false).next(&cache).unwrap(),
+            Some(101)
+        );
+        // There is no comic 404
+        assert_eq!(
+            candidates(&[403], None, false).next(&cache).unwrap(),
+            Some(405)
+        );
+
+        let mut before = candidates(&[405, 406], None, false);
+        before.forward = false;
+        assert_eq!(before.next(&cache).unwrap(), Some(403));
+        let mut first = candidates(&[1], None, false);
+        first.forward = false;
+        assert_eq!(first.next(&cache).unwrap(), None);
+    }
+
+    #[test]
+    fn candidates_offline_are_cached() {
+        let cache = ComicCache::new("/nonexistent");
+        let cached = [1, 5, 9];
+        let mut sequential = candidates(&[5], Some(&cached), false);
+        assert_eq!(sequential.next(&cache).unwrap(), Some(9));
+        sequential.tried.insert(9);
+        assert_eq!(sequential.next(&cache).unwrap(), None);
+        sequential.forward = false;
+        assert_eq!(sequential.next(&cache).unwrap(), Some(1));
+
+        let mut random = candidates(&[5], Some(&cached), true);
+        let next = random.next(&cache).unwrap().unwrap();
+        assert!(next == 1 || next == 9);
+        random.tried.extend([1, 9]);
+        assert_eq!(random.next(&cache).unwrap(), None);
+    }
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from([&["xkcd-wallpaper", "--size", "1920x1080"], args].concat())
+    }
+
+    #[rstest]
+    #[case(&["--grain", "0.05"])]
+    #[case(&["--vignette", "0.4"])]
+    #[case(&["--shadow"])]
+    fn effect_conflicts_with_high_contrast(#[case] effect: &[&str]) {
+        assert!(parse(effect).is_ok());
+        let err = parse(&[effect, &["--high-contrast"]].concat())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn overlays_are_opaque_in_high_contrast() {
+        let cli = parse(&["--badge", "--watermark", "mine", "--high-contrast"]).unwrap();
+        let args = cli.generate;
+        assert_eq!(args.badge(Font::default()).unwrap().opacity, 1.0);
+        assert_eq!(args.watermark(Font::default()).unwrap().opacity, 1.0);
+
+        let args = parse(&["--badge"]).unwrap().generate;
+        assert_eq!(args.badge(Font::default()).unwrap().opacity, 0.8);
+    }
+
+    #[rstest]
+    #[case(XkcdError::Timeout, 24 * 60, 15)]
+    #[case(XkcdError::Timeout, 5, 5)]
+    #[case(XkcdError::RateLimited { retry_after: None }, 24 * 60, 15)]
+    #[case(XkcdError::RateLimited { retry_after: Some(Duration::from_secs(60 * 60)) }, 24 * 60, 60)]
+    #[case(XkcdError::RateLimited { retry_after: Some(Duration::from_secs(60)) }, 5, 5)]
+    fn daemon_retries_after_rate_limit(
+        #[case] err: XkcdError,
+        #[case] refresh_minutes: u64,
+        #[case] expected_minutes: u64,
+    ) {
+        assert_eq!(
+            retry_in(&err, Duration::from_secs(refresh_minutes * 60)),
+            Duration::from_secs(expected_minutes * 60)
+        );
+    }
+
+    #[test]
+    fn candidates_random_are_new() {
+        let cache = ComicCache::new("/nonexistent");
+        let mut random = candidates(&[1, 2], None, true);
+        random.latest = Some(4);
+        assert!(matches!(random.next(&cache).unwrap(), Some(3 | 4)));
+        random.tried.extend([3, 4]);
+        assert_eq!(random.next(&cache).unwrap(), None);
+
+        let tried = (1..=403).chain([405]).collect::<Vec<_>>();
+        let mut random = candidates(&tried, None, true);
+        random.latest = Some(405);
+        assert_eq!(random.next(&cache).unwrap(), None);
     }
 }
@@ -0,0 +1,81 @@
+use std::process::Command;
+use std::thread;
+
+use log::info;
+
+use crate::{web, XkcdError};
+
+/// Shows the notification with a default action and opens `$3` when it is clicked. Falls back to
+/// a plain notification on notify-send versions without `--action`.
+const CLICKABLE_SCRIPT: &str = r#"action=$(notify-send --app-name=xkcd-wallpaper --wait --action=default=Open -- "$1" "$2") || exec notify-send --app-name=xkcd-wallpaper -- "$1" "$2"
+[ "$action" = default ] && exec "$4" "$3""#;
+
+/// Show a desktop notification, through notify-send or osascript on macOS
+pub fn send(summary: &str, body: &str) -> Result<(), XkcdError> {
+    info!("sending notification {:?}", summary);
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification \"{}\" with title \"{}\"",
+            escape_applescript(body),
+            escape_applescript(summary)
+        );
+        let mut command = Command::new("osascript");
+        command.args(["-e", &script]);
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.args(["--app-name=xkcd-wallpaper", "--", summary, body]);
+        command
+    };
+
+    let status = command
+        .status()
+        .map_err(|err| XkcdError::Other(format!("failed to send notification: {err}")))?;
+    if !status.success() {
+        return Err(XkcdError::Other(format!(
+            "notification command exited with {status}"
+        )));
+    }
+    Ok(())
+}
+
+/// Like `send`, but clicking the notification opens `url` in the browser
+///
+/// Waiting for the click happens in a background process, so this returns right away. macOS
+/// notifications sent through osascript cannot have actions and are shown without one.
+pub fn send_clickable(summary: &str, body: &str, url: &str) -> Result<(), XkcdError> {
+    if cfg!(target_os = "macos") || cfg!(windows) {
+        return send(summary, body);
+    }
+
+    info!("sending notification {:?} opening {}", summary, url);
+    let mut child = Command::new("sh")
+        .args([
+            "-c",
+            CLICKABLE_SCRIPT,
+            "sh",
+            summary,
+            body,
+            url,
+            web::opener(),
+        ])
+        .spawn()
+        .map_err(|err| XkcdError::Other(format!("failed to send notification: {err}")))?;
+    // Reap the process once the notification is gone, the daemon would collect zombies otherwise
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+fn escape_applescript(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applescript_escaping() {
+        assert_eq!(escape_applescript(r#"say "hi" \o/"#), r#"say \"hi\" \\o/"#);
+    }
+}
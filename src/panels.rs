@@ -0,0 +1,240 @@
+use std::ops::Range;
+
+use image::imageops::overlay;
+use image::{DynamicImage, GrayImage, Rgba, RgbaImage};
+use log::info;
+
+use crate::split::BLANK_LUMA;
+use crate::{timings, ScreenDimensions};
+
+/// Blank rows or columns from which they separate panels, narrower ones are spaces in the art
+const MIN_GUTTER: u32 = 4;
+
+/// Top left corner and size of a panel in the comic
+pub type Panel = (u32, u32, u32, u32);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+/// How the panels of a comic are rearranged
+pub enum Reflow {
+    /// Whichever of the comic as drawn, stacked or in a row shows it the largest on the screen
+    #[default]
+    Auto,
+    /// Panels stacked from top to bottom, for portrait screens
+    Vertical,
+    /// Panels in a single row, for ultrawide screens
+    Horizontal,
+}
+
+/// Panels of a comic in reading order, and the narrowest gutter between them
+///
+/// Panels are found by cutting the comic into bands at blank rows, then each band at blank
+/// columns. Comics without gutters are a single panel.
+pub fn detect(img: &GrayImage) -> (Vec<Panel>, u32) {
+    let blank_row = |y: u32, columns: &Range<u32>| {
+        columns
+            .clone()
+            .all(|x| img.get_pixel(x, y)[0] >= BLANK_LUMA)
+    };
+    let blank_column =
+        |x: u32, rows: &Range<u32>| rows.clone().all(|y| img.get_pixel(x, y)[0] >= BLANK_LUMA);
+
+    let mut panels = Vec::new();
+    let mut narrowest = None;
+    let (bands, gutter) = segments(img.height(), |y| blank_row(y, &(0..img.width())));
+    narrowest = narrowest.into_iter().chain(gutter).min();
+    for rows in bands {
+        let (columns, gutter) = segments(img.width(), |x| blank_column(x, &rows));
+        narrowest = narrowest.into_iter().chain(gutter).min();
+        for columns in columns {
+            // Panels of a band may be shorter than the band
+            let (content, _) = segments(img.height(), |y| {
+                !rows.contains(&y) || blank_row(y, &columns)
+            });
+            let top = content.first().map_or(rows.start, |content| content.start);
+            let bottom = content.last().map_or(rows.end, |content| content.end);
+            panels.push((columns.start, top, columns.len() as u32, bottom - top));
+        }
+    }
+    (panels, narrowest.unwrap_or(MIN_GUTTER))
+}
+
+/// Runs of non-blank positions in `0..length`, split at gaps of [`MIN_GUTTER`] or more blank
+/// positions, and the narrowest of those gaps
+fn segments(length: u32, blank: impl Fn(u32) -> bool) -> (Vec<Range<u32>>, Option<u32>) {
+    let mut segments: Vec<Range<u32>> = Vec::new();
+    let mut narrowest: Option<u32> = None;
+    let mut position = 0;
+    while position < length {
+        if blank(position) {
+            position += 1;
+            continue;
+        }
+        let start = position;
+        while position < length && !blank(position) {
+            position += 1;
+        }
+        match segments.last_mut() {
+            Some(last) if start - last.end < MIN_GUTTER => last.end = position,
+            Some(last) => {
+                let gutter = start - last.end;
+                narrowest = Some(narrowest.map_or(gutter, |narrowest| narrowest.min(gutter)));
+                segments.push(start..position);
+            }
+            None => segments.push(start..position),
+        }
+    }
+    (segments, narrowest)
+}
+
+/// The comic with its panels rearranged as `reflow` says, on the white of the comic
+///
+/// Comics of a single panel are returned as they are, as are those that
+/// [`Reflow::Auto`] finds best shown as drawn.
+pub fn reflow(
+    img: &DynamicImage,
+    reflow: Reflow,
+    screen_dimensions: &ScreenDimensions,
+) -> DynamicImage {
+    let _timer = timings::stage("transforms");
+    let (panels, gap) = detect(&img.to_luma8());
+    if panels.len() < 2 {
+        info!("no panels to reflow");
+        return img.clone();
+    }
+    let fit = |(width, height): (u32, u32)| {
+        (screen_dimensions.width as f64 / width.max(1) as f64)
+            .min(screen_dimensions.height as f64 / height.max(1) as f64)
+    };
+    let reflow = match reflow {
+        Reflow::Auto => {
+            // Without the margins, which reflowing drops as well
+            let left = panels.iter().map(|panel| panel.0).min().unwrap_or(0);
+            let top = panels.iter().map(|panel| panel.1).min().unwrap_or(0);
+            let right = panels
+                .iter()
+                .map(|panel| panel.0 + panel.2)
+                .max()
+                .unwrap_or(0);
+            let bottom = panels
+                .iter()
+                .map(|panel| panel.1 + panel.3)
+                .max()
+                .unwrap_or(0);
+            let as_drawn = fit((right - left, bottom - top));
+            let (vertical, horizontal) = (
+                fit(stacked_size(&panels, gap, Reflow::Vertical)),
+                fit(stacked_size(&panels, gap, Reflow::Horizontal)),
+            );
+            match (vertical > as_drawn, horizontal > as_drawn) {
+                (false, false) => return img.clone(),
+                (true, false) => Reflow::Vertical,
+                (false, true) => Reflow::Horizontal,
+                (true, true) => match vertical >= horizontal {
+                    true => Reflow::Vertical,
+                    false => Reflow::Horizontal,
+                },
+            }
+        }
+        reflow => reflow,
+    };
+
+    info!("reflowing {} panels {:?}", panels.len(), reflow);
+    let (width, height) = stacked_size(&panels, gap, reflow);
+    let mut canvas = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+    let mut along = 0;
+    for &(x, y, panel_width, panel_height) in &panels {
+        let panel = img.crop_imm(x, y, panel_width, panel_height).into_rgba8();
+        let (left, top) = match reflow {
+            Reflow::Horizontal => (along, (height - panel_height) / 2),
+            _ => ((width - panel_width) / 2, along),
+        };
+        overlay(&mut canvas, &panel, left as i64, top as i64);
+        along += gap
+            + match reflow {
+                Reflow::Horizontal => panel_width,
+                _ => panel_height,
+            };
+    }
+    DynamicImage::ImageRgba8(canvas)
+}
+
+/// Size of `panels` stacked or in a row, `gap` pixels apart
+fn stacked_size(panels: &[Panel], gap: u32, reflow: Reflow) -> (u32, u32) {
+    let gaps = gap * panels.len().saturating_sub(1) as u32;
+    let widths = panels.iter().map(|panel| panel.2);
+    let heights = panels.iter().map(|panel| panel.3);
+    match reflow {
+        Reflow::Horizontal => (widths.sum::<u32>() + gaps, heights.max().unwrap_or(0)),
+        _ => (widths.max().unwrap_or(0), heights.sum::<u32>() + gaps),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+    use rstest::rstest;
+
+    /// Three framed 100x100 panels in a row, 10 pixels apart and from the edges
+    fn strip() -> GrayImage {
+        GrayImage::from_fn(340, 120, |x, y| {
+            let framed = (10..110).contains(&y)
+                && [10, 120, 230].iter().any(|left| {
+                    (*left..left + 100).contains(&x)
+                        && (x == *left || x == left + 99 || y == 10 || y == 109)
+                });
+            match framed {
+                true => Luma([0]),
+                false => Luma([255]),
+            }
+        })
+    }
+
+    #[test]
+    fn detect_panels() {
+        let (panels, gap) = detect(&strip());
+        assert_eq!(
+            panels,
+            [(10, 10, 100, 100), (120, 10, 100, 100), (230, 10, 100, 100)]
+        );
+        assert_eq!(gap, 10);
+        // Words a few pixels apart are not panels
+        let mut text = GrayImage::from_pixel(40, 10, Luma([255]));
+        for x in (5..15).chain(17..30) {
+            text.put_pixel(x, 5, Luma([0]));
+        }
+        assert_eq!(detect(&text).0, [(5, 5, 25, 1)]);
+    }
+
+    #[rstest]
+    #[case(Reflow::Vertical, (1920, 1080), (100, 320))]
+    #[case(Reflow::Horizontal, (1080, 1920), (320, 100))]
+    #[case(Reflow::Auto, (1080, 1920), (100, 320))]
+    #[case(Reflow::Auto, (1920, 1080), (340, 120))]
+    fn reflow_panels(
+        #[case] reflow_as: Reflow,
+        #[case] (width, height): (u32, u32),
+        #[case] expected: (u32, u32),
+    ) {
+        let img = DynamicImage::ImageLuma8(strip());
+        let screen = ScreenDimensions { width, height };
+        let reflowed = reflow(&img, reflow_as, &screen);
+        assert_eq!((reflowed.width(), reflowed.height()), expected);
+    }
+
+    #[test]
+    fn stacked_in_reading_order() {
+        let mut img = strip();
+        // Mark the middle panel
+        img.put_pixel(170, 60, Luma([0]));
+        let screen = ScreenDimensions {
+            width: 1080,
+            height: 1920,
+        };
+        let reflowed = reflow(&DynamicImage::ImageLuma8(img), Reflow::Vertical, &screen);
+        let reflowed = reflowed.to_luma8();
+        assert_eq!(reflowed.get_pixel(50, 160)[0], 0);
+        assert_eq!(reflowed.get_pixel(50, 50)[0], 255);
+        assert_eq!(reflowed.get_pixel(50, 270)[0], 255);
+    }
+}
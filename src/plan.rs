@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use jiff::civil::Date;
+use serde::{Deserialize, Serialize};
+
+use crate::random::Rng;
+use crate::{state, XkcdError};
+
+const MANIFEST_FILE: &str = "plan.json";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+/// Wallpapers generated ahead of time with `--plan`, by the date they are shown on
+pub struct Manifest {
+    pub days: BTreeMap<Date, PlanEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PlanEntry {
+    pub num: u64,
+    pub output: PathBuf,
+}
+
+impl Manifest {
+    pub fn path(state_dir: &Path) -> PathBuf {
+        state_dir.join(MANIFEST_FILE)
+    }
+
+    pub fn load(state_dir: &Path) -> Result<Manifest, XkcdError> {
+        state::load_json(&Manifest::path(state_dir))
+    }
+
+    pub fn save(&self, state_dir: &Path) -> Result<(), XkcdError> {
+        state::save_json(&Manifest::path(state_dir), self)
+    }
+
+    /// Forget the days before `today`, returns the removed entries
+    pub fn prune(&mut self, today: Date) -> Vec<PlanEntry> {
+        let kept = self.days.split_off(&today);
+        std::mem::replace(&mut self.days, kept)
+            .into_values()
+            .collect()
+    }
+}
+
+/// Comic of the day for `date`, the same on every run for the same `latest` comic
+pub fn daily_comic(date: Date, latest: u64) -> u64 {
+    let seed = (date.year() as u64) << 16 | (date.month() as u64) << 8 | date.day() as u64;
+    // Consecutive dates give similar seeds, throw away the first few values
+    let mut rng = Rng::new(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    for _ in 0..4 {
+        rng.next_u64();
+    }
+    rng.comic(latest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::civil::date;
+
+    #[test]
+    fn daily_comic_is_stable() {
+        let day = date(2025, 6, 23);
+        assert_eq!(daily_comic(day, 3000), daily_comic(day, 3000));
+        let week = (0..7)
+            .map(|offset| daily_comic(day + jiff::Span::new().days(offset), 3000))
+            .collect::<std::collections::BTreeSet<_>>();
+        assert!(week.len() > 1);
+    }
+
+    #[test]
+    fn manifest_round_trip_and_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manifest = Manifest::default();
+        for (day, num) in [(22, 1), (23, 2), (24, 3)] {
+            manifest.days.insert(
+                date(2025, 6, day),
+                PlanEntry {
+                    num,
+                    output: PathBuf::from(format!("/w/{num}.png")),
+                },
+            );
+        }
+        manifest.save(dir.path()).unwrap();
+        let mut loaded = Manifest::load(dir.path()).unwrap();
+        assert_eq!(loaded, manifest);
+
+        let removed = loaded.prune(date(2025, 6, 23));
+        assert_eq!(
+            removed.iter().map(|entry| entry.num).collect::<Vec<_>>(),
+            [1]
+        );
+        assert_eq!(
+            loaded.days.keys().copied().collect::<Vec<_>>(),
+            [date(2025, 6, 23), date(2025, 6, 24)]
+        );
+    }
+}
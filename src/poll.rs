@@ -0,0 +1,132 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use jiff::civil::Weekday;
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, ToSpan};
+use log::info;
+
+use crate::{net, Metadata, XkcdError};
+
+const LATEST_URL: &str = "https://xkcd.com/info.0.json";
+/// Days on which xkcd publishes new comics
+const PUBLISH_DAYS: [Weekday; 3] = [Weekday::Monday, Weekday::Wednesday, Weekday::Friday];
+/// New comics appear around midnight US Eastern time, the window in UTC covers daylight saving
+/// time and late uploads
+const WINDOW_START_HOUR: i8 = 3;
+const WINDOW_HOURS: i64 = 6;
+/// Poll interval inside a publish window
+const WINDOW_POLL: Duration = Duration::from_secs(10 * 60);
+/// Longest sleep between polls outside a publish window, in case a comic comes out of schedule
+const IDLE_POLL_MAX: Duration = Duration::from_secs(6 * 60 * 60);
+/// Fraction of the poll interval by which polls are randomly moved
+const JITTER: f64 = 0.2;
+
+/// Whether a new comic is expected around `now`
+pub fn in_publish_window(now: Timestamp) -> bool {
+    window_starts(now)
+        .into_iter()
+        .any(|start| start <= now && now < start + WINDOW_HOURS.hours())
+}
+
+/// Time until the next check for a new comic
+///
+/// Frequent inside the Monday, Wednesday and Friday publish windows and rare otherwise. `jitter`
+/// is a random value in `0..1` that spreads polls of different clients apart.
+pub fn next_poll(now: Timestamp, jitter: f64) -> Duration {
+    if in_publish_window(now) {
+        return WINDOW_POLL.mul_f64(1.0 + JITTER * (2.0 * jitter - 1.0));
+    }
+
+    let until_window = window_starts(now)
+        .into_iter()
+        .find(|start| *start > now)
+        .map(|start| now.duration_until(start).unsigned_abs())
+        .unwrap_or(IDLE_POLL_MAX);
+    until_window.min(IDLE_POLL_MAX) + WINDOW_POLL.mul_f64(jitter)
+}
+
+/// Publish windows starting from the day before `now` until a week later, in order
+fn window_starts(now: Timestamp) -> Vec<Timestamp> {
+    let today = now.to_zoned(TimeZone::UTC).date();
+    (-1..=7)
+        .filter_map(|offset| today.checked_add(offset.days()).ok())
+        .filter(|date| PUBLISH_DAYS.contains(&date.weekday()))
+        .filter_map(|date| {
+            date.at(WINDOW_START_HOUR, 0, 0, 0)
+                .to_zoned(TimeZone::UTC)
+                .ok()
+        })
+        .map(|zoned| zoned.timestamp())
+        .collect()
+}
+
+/// Random value in `0..1` for `next_poll`, taken from the clock as it only spreads requests
+pub fn jitter() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or_default();
+    // Scramble the low bits, consecutive calls are only microseconds apart
+    let mixed = nanos.wrapping_mul(0x9E37_79B9);
+    mixed as f64 / (u32::MAX as f64 + 1.0)
+}
+
+/// Value that changes whenever a new comic is published
+///
+/// Uses the `ETag` or `Last-Modified` of a HEAD request, and only downloads the metadata of the
+/// newest comic when the server sends neither.
+pub fn latest_fingerprint() -> Result<String, XkcdError> {
+    let response = net::head(LATEST_URL)?;
+    let header = ["etag", "last-modified"].iter().find_map(|name| {
+        response
+            .headers()
+            .get(*name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| format!("{name}: {value}"))
+    });
+    let fingerprint = match header {
+        Some(header) => header,
+        None => format!("num: {}", Metadata::from_comic_id(None)?.num),
+    };
+    info!("newest comic fingerprint is {:?}", fingerprint);
+    Ok(fingerprint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    // Monday
+    #[case("2025-06-23T05:00:00Z", true)]
+    #[case("2025-06-23T02:59:00Z", false)]
+    #[case("2025-06-23T09:00:00Z", false)]
+    // Tuesday
+    #[case("2025-06-24T05:00:00Z", false)]
+    fn publish_window(#[case] now: &str, #[case] expected: bool) {
+        assert_eq!(in_publish_window(now.parse().unwrap()), expected);
+    }
+
+    #[rstest]
+    // Inside the window, between 8 and 12 minutes
+    #[case("2025-06-23T05:00:00Z", 0.0, 8 * 60)]
+    #[case("2025-06-23T05:00:00Z", 1.0, 12 * 60)]
+    // Tuesday 23:00, four hours before Wednesday's window
+    #[case("2025-06-24T23:00:00Z", 0.0, 4 * 60 * 60)]
+    #[case("2025-06-24T23:00:00Z", 0.5, 4 * 60 * 60 + 5 * 60)]
+    // Saturday, capped until Monday
+    #[case("2025-06-28T12:00:00Z", 0.0, 6 * 60 * 60)]
+    fn next_poll_interval(#[case] now: &str, #[case] jitter: f64, #[case] seconds: u64) {
+        assert_eq!(
+            next_poll(now.parse().unwrap(), jitter),
+            Duration::from_secs(seconds)
+        );
+    }
+
+    #[test]
+    fn jitter_in_range() {
+        let jitter = jitter();
+        assert!((0.0..1.0).contains(&jitter));
+    }
+}
@@ -0,0 +1,270 @@
+use std::cell::RefCell;
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+thread_local! {
+    static SINK: RefCell<Option<Arc<dyn ProgressSink>>> = const { RefCell::new(None) };
+}
+
+/// Receives progress of downloads and rendering, e.g. to drive a progress bar
+///
+/// Install one with [`set_sink`]. It receives the progress of work done on the same thread,
+/// such as [`ComicCache::download_comic`](crate::cache::ComicCache::download_comic) and
+/// [`get_wallpaper_from_comic`](crate::get_wallpaper_from_comic). All methods do nothing by
+/// default.
+pub trait ProgressSink: Send + Sync {
+    /// `bytes` of `url` were downloaded so far, out of `total` if the server sent its size
+    fn downloaded(&self, _url: &str, _bytes: u64, _total: Option<u64>) {}
+
+    /// A stage such as `download`, `decode` or `composite` started, see [`crate::timings`]
+    fn stage_started(&self, _name: &'static str) {}
+
+    /// A stage finished after `elapsed`
+    fn stage_finished(&self, _name: &'static str, _elapsed: Duration) {}
+}
+
+/// Report progress on this thread to `sink` until the returned guard is dropped
+pub fn set_sink(sink: Arc<dyn ProgressSink>) -> SinkGuard {
+    let previous = SINK.with(|current| current.borrow_mut().replace(sink));
+    SinkGuard { previous }
+}
+
+/// Restores the previously installed sink when dropped, see [`set_sink`]
+pub struct SinkGuard {
+    previous: Option<Arc<dyn ProgressSink>>,
+}
+
+impl Drop for SinkGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        SINK.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Call `f` with the sink of this thread, if there is one
+fn report(f: impl FnOnce(&dyn ProgressSink)) {
+    // Cloned so that a sink may install another one while reporting
+    let sink = SINK.with(|current| current.borrow().clone());
+    if let Some(sink) = sink {
+        f(&*sink);
+    }
+}
+
+pub(crate) fn stage_started(name: &'static str) {
+    report(|sink| sink.stage_started(name));
+}
+
+pub(crate) fn stage_finished(name: &'static str, elapsed: Duration) {
+    report(|sink| sink.stage_finished(name, elapsed));
+}
+
+/// Reports the bytes read through it as the download of `url`
+///
+/// [`UreqFetcher`](crate::net::UreqFetcher) wraps its responses in one, other
+/// [`ComicFetcher`](crate::net::ComicFetcher)s can do the same.
+pub struct ProgressReader<R> {
+    inner: R,
+    url: String,
+    bytes: u64,
+    total: Option<u64>,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R, url: &str, total: Option<u64>) -> Self {
+        ProgressReader {
+            inner,
+            url: url.to_string(),
+            bytes: 0,
+            total,
+        }
+    }
+
+    /// Count `bytes` as already downloaded, for a resumed download
+    pub fn starting_at(self, bytes: u64) -> Self {
+        ProgressReader { bytes, ..self }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read > 0 || self.bytes == 0 {
+            self.bytes += read as u64;
+            report(|sink| sink.downloaded(&self.url, self.bytes, self.total));
+        }
+        Ok(read)
+    }
+}
+
+const SPINNER: &[char] = &['|', '/', '-', '\\'];
+const BAR_WIDTH: usize = 30;
+const TICK: Duration = Duration::from_millis(100);
+
+/// Progress bar for downloads and a spinner for the other stages, drawn on stderr
+pub struct TerminalProgress {
+    state: Mutex<TerminalState>,
+}
+
+#[derive(Default)]
+struct TerminalState {
+    /// Stages in progress, the innermost last
+    stages: Vec<&'static str>,
+    download: Option<(u64, Option<u64>)>,
+    frame: usize,
+    /// Width of the line drawn last, to clear it
+    drawn: usize,
+}
+
+impl TerminalProgress {
+    /// Progress display redrawn in the background, `None` if stdout or stderr is not a terminal
+    pub fn start() -> Option<Arc<TerminalProgress>> {
+        if !io::stdout().is_terminal() || !io::stderr().is_terminal() {
+            return None;
+        }
+        let progress = Arc::new(TerminalProgress {
+            state: Mutex::new(TerminalState::default()),
+        });
+        // Keeps the spinner turning during long stages, ends with the last reference
+        let weak: Weak<TerminalProgress> = Arc::downgrade(&progress);
+        thread::spawn(move || loop {
+            thread::sleep(TICK);
+            let Some(progress) = weak.upgrade() else {
+                break;
+            };
+            progress.redraw(|state| state.frame += 1);
+        });
+        Some(progress)
+    }
+
+    /// Update the state with `f` and draw it, or clear the line once all stages are done
+    fn redraw(&self, f: impl FnOnce(&mut TerminalState)) {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        f(&mut state);
+        let line = match state.stages.last() {
+            Some(stage) => progress_line(stage, state.download, state.frame),
+            None => String::new(),
+        };
+        if line.is_empty() && state.drawn == 0 {
+            return;
+        }
+        let padding = state.drawn.saturating_sub(line.chars().count());
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r{line}{:padding$}\r", "");
+        let _ = stderr.flush();
+        state.drawn = line.chars().count();
+    }
+}
+
+impl ProgressSink for TerminalProgress {
+    fn downloaded(&self, _url: &str, bytes: u64, total: Option<u64>) {
+        self.redraw(|state| state.download = Some((bytes, total)));
+    }
+
+    fn stage_started(&self, name: &'static str) {
+        self.redraw(|state| {
+            state.stages.push(name);
+            state.download = None;
+        });
+    }
+
+    fn stage_finished(&self, name: &'static str, _elapsed: Duration) {
+        self.redraw(|state| {
+            if let Some(index) = state.stages.iter().rposition(|stage| *stage == name) {
+                state.stages.remove(index);
+            }
+            state.download = None;
+        });
+    }
+}
+
+/// Line shown for `stage`, with a bar when the size of the download in progress is known
+fn progress_line(stage: &str, download: Option<(u64, Option<u64>)>, frame: usize) -> String {
+    let spinner = SPINNER[frame % SPINNER.len()];
+    let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    match download {
+        Some((bytes, Some(total))) if total > 0 => {
+            let filled = (BAR_WIDTH as u64 * bytes.min(total) / total) as usize;
+            format!(
+                "{spinner} {stage} [{}{}] {:.1}/{:.1} MiB",
+                "#".repeat(filled),
+                " ".repeat(BAR_WIDTH - filled),
+                mib(bytes),
+                mib(total)
+            )
+        }
+        Some((bytes, _)) => format!("{spinner} {stage} {:.1} MiB", mib(bytes)),
+        None => format!("{spinner} {stage}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timings;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl ProgressSink for Recorder {
+        fn downloaded(&self, url: &str, bytes: u64, total: Option<u64>) {
+            let event = format!("{url} {bytes}/{total:?}");
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn stage_started(&self, name: &'static str) {
+            self.0.lock().unwrap().push(format!("start {name}"));
+        }
+
+        fn stage_finished(&self, name: &'static str, _elapsed: Duration) {
+            self.0.lock().unwrap().push(format!("finish {name}"));
+        }
+    }
+
+    #[test]
+    fn reports_to_installed_sink() {
+        let recorder = Arc::new(Recorder::default());
+        {
+            let _guard = set_sink(recorder.clone());
+            let _timer = timings::stage("download");
+            let mut reader = ProgressReader::new(io::repeat(1).take(5), "u", Some(5));
+            io::copy(&mut reader, &mut io::sink()).unwrap();
+        }
+        // The guard is gone, so this is not reported
+        drop(timings::stage("decode"));
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            ["start download", "u 5/Some(5)", "finish download"]
+        );
+    }
+
+    #[test]
+    fn progress_lines() {
+        assert_eq!(progress_line("decode", None, 1), "/ decode");
+        assert_eq!(
+            progress_line("download", Some((1024 * 1024, Some(4 * 1024 * 1024))), 0),
+            format!(
+                "| download [{}{}] 1.0/4.0 MiB",
+                "#".repeat(7),
+                " ".repeat(23)
+            )
+        );
+        assert_eq!(
+            progress_line("download", Some((512 * 1024, None)), 4),
+            "| download 0.5 MiB"
+        );
+    }
+
+    #[test]
+    fn guards_restore_previous_sink() {
+        let (outer, inner) = (Arc::new(Recorder::default()), Arc::new(Recorder::default()));
+        let _outer = set_sink(outer.clone());
+        drop(set_sink(inner.clone()));
+        drop(timings::stage("encode"));
+        assert_eq!(outer.0.lock().unwrap().len(), 2);
+        assert!(inner.0.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+use image::{Rgba, RgbaImage};
+use log::info;
+use qrcode::{Color, EcLevel};
+
+use crate::layout::Corner;
+use crate::{ForegroundColor, ScreenDimensions, XkcdError};
+
+pub use qrcode::QrCode;
+
+/// Light modules around the symbol that scanners need to find it
+const QUIET_ZONE: u32 = 4;
+
+/// Smallest code holding `data`, at error correction level M
+pub fn encode(data: &[u8]) -> Result<QrCode, XkcdError> {
+    let code = QrCode::with_error_correction_level(data, EcLevel::M).map_err(|err| {
+        XkcdError::Other(format!(
+            "{} bytes do not fit in a QR code: {}",
+            data.len(),
+            err
+        ))
+    })?;
+    info!(
+        "encoded {} bytes as a {:?} QR code",
+        data.len(),
+        code.version()
+    );
+    Ok(code)
+}
+
+/// `code` with its quiet zone, `module` pixels per module
+fn render(code: &QrCode, module: u32, dark: Rgba<u8>, light: Rgba<u8>) -> RgbaImage {
+    let size = code.width() as u32;
+    let side = (size + 2 * QUIET_ZONE) * module;
+    RgbaImage::from_fn(side, side, |x, y| {
+        let (x, y) = (x / module, y / module);
+        let inside = QUIET_ZONE..QUIET_ZONE + size;
+        match inside.contains(&x)
+            && inside.contains(&y)
+            && code[((x - QUIET_ZONE) as usize, (y - QUIET_ZONE) as usize)] == Color::Dark
+        {
+            true => dark,
+            false => light,
+        }
+    })
+}
+
+/// Draw `code` in `corner` of `img`, about an eighth of the screen's short side wide
+pub fn overlay(
+    img: &mut RgbaImage,
+    code: &QrCode,
+    corner: Corner,
+    dark: Rgba<u8>,
+    light: Rgba<u8>,
+) {
+    let (width, height) = img.dimensions();
+    let short_side = width.min(height);
+    let module = (short_side / 8 / (code.width() as u32 + 2 * QUIET_ZONE)).max(2);
+    let qr = render(code, module, dark, light);
+    let screen_dimensions = ScreenDimensions { width, height };
+    let (x, y) = corner.position(&screen_dimensions, qr.width(), qr.height());
+    info!("drawing {}px QR code at {},{}", qr.width(), x, y);
+    image::imageops::overlay(img, &qr, x, y);
+}
+
+/// Dark and light module colors from the drawings and the background of the wallpaper
+///
+/// Black and white when both are light or both dark, which scanners could not tell apart.
+pub fn colors(ink: Rgba<u8>, background: Rgba<u8>) -> (Rgba<u8>, Rgba<u8>) {
+    let opaque = |mut color: Rgba<u8>| {
+        color[3] = 255;
+        color
+    };
+    let light = |color| ForegroundColor::Custom(color).is_light();
+    match (light(ink), light(background)) {
+        (true, false) => (opaque(background), opaque(ink)),
+        (false, true) => (opaque(ink), opaque(background)),
+        _ => (Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("https://xkcd.com/1/", 25)]
+    #[case("https://xkcd.com/about/?long", 29)]
+    #[case("https://xkcd.com/3000/", 25)]
+    #[case("https://www.explainxkcd.com/wiki/index.php/3000", 33)]
+    fn smallest_version(#[case] url: &str, #[case] size: usize) {
+        let code = encode(url.as_bytes()).unwrap();
+        assert_eq!(code.width(), size);
+        let is_dark = |x, y| code[(x, y)] == Color::Dark;
+        // Finder patterns in three corners, and the dark module next to the bottom left one
+        for (x, y) in [(0, 0), (size - 7, 0), (0, size - 7)] {
+            assert!(is_dark(x, y) && is_dark(x + 6, y + 6));
+            assert!(!is_dark(x + 1, y + 1) && is_dark(x + 3, y + 3));
+        }
+        assert!(is_dark(8, size - 8));
+    }
+
+    #[test]
+    fn too_long() {
+        // 2331 bytes fill version 40 at level M
+        assert!(encode(&[b'x'; 2332]).is_err());
+        assert_eq!(encode(&[b'x'; 2331]).unwrap().width(), 177);
+    }
+
+    #[rstest]
+    #[case(Rgba([0xEE, 0xEE, 0xEE, 255]), Rgba([0x1D, 0x1F, 0x21, 255]), (Rgba([0x1D, 0x1F, 0x21, 255]), Rgba([0xEE, 0xEE, 0xEE, 255])))]
+    #[case(Rgba([0, 0, 0, 255]), Rgba([0xF2, 0xF0, 0xE6, 255]), (Rgba([0, 0, 0, 255]), Rgba([0xF2, 0xF0, 0xE6, 255])))]
+    #[case(Rgba([0x40, 0x40, 0x40, 255]), Rgba([0, 0, 0, 0]), (Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255])))]
+    fn module_colors(
+        #[case] ink: Rgba<u8>,
+        #[case] background: Rgba<u8>,
+        #[case] expected: (Rgba<u8>, Rgba<u8>),
+    ) {
+        assert_eq!(colors(ink, background), expected);
+    }
+
+    #[rstest]
+    #[case(Corner::TopLeft, (6, 6))]
+    #[case(Corner::BottomRight, (400 - 6 - 66, 300 - 6 - 66))]
+    fn overlay_corner(#[case] corner: Corner, #[case] (x, y): (u32, u32)) {
+        let (dark, light) = (Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255]));
+        let code = encode(b"https://xkcd.com/1/").unwrap();
+        let mut img = RgbaImage::from_pixel(400, 300, Rgba([0, 0, 255, 255]));
+        overlay(&mut img, &code, corner, dark, light);
+        // 2 pixel modules, the quiet zone then the top left finder
+        assert_eq!(img.get_pixel(x - 1, y - 1), &Rgba([0, 0, 255, 255]));
+        assert_eq!(img.get_pixel(x, y), &light);
+        assert_eq!(img.get_pixel(x + 8, y + 8), &dark);
+        assert_eq!(img.get_pixel(x + 65, y + 65), &light);
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use image::DynamicImage;
+use log::info;
+use serde::Deserialize;
+
+use crate::{Image, Metadata};
+
+/// Comics whose static image is a placeholder for an interactive or animated comic
+pub const INTERACTIVE_COMICS: &[u64] = &[
+    880, 1037, 1110, 1190, 1193, 1331, 1335, 1350, 1416, 1506, 1525, 1608, 1663, 2067, 2198, 2445,
+    2601, 2712, 2765, 2916,
+];
+
+/// Channel difference above which a pixel counts as colored
+const COLOR_CHANNEL_DIFFERENCE: u8 = 48;
+/// Share of colored pixels above which a comic counts as colored
+const COLORED_PIXEL_RATIO: f32 = 0.01;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// What to do with an interactive comic
+pub enum InteractiveAction {
+    /// Use its static image, with a warning
+    Warn,
+    /// Pick another comic in random mode, otherwise like `Warn`
+    #[default]
+    Skip,
+    /// Use the static image configured in `fallbacks`, otherwise like `Skip`
+    Fallback,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// Handling of interactive comics, the `[interactive]` section of the configuration file
+///
+/// ```toml
+/// [interactive]
+/// comics = [1416, 1608]
+/// action = "fallback"
+/// fallbacks = { 1608 = "https://example.com/hoverboard.png" }
+/// ```
+pub struct InteractiveConfig {
+    /// Comic numbers counting as interactive, replacing [`INTERACTIVE_COMICS`]
+    pub comics: Option<Vec<u64>>,
+    pub action: InteractiveAction,
+    /// Static image URLs to use instead of the placeholder, by comic number (TOML keys are
+    /// strings)
+    pub fallbacks: BTreeMap<String, String>,
+}
+
+impl InteractiveConfig {
+    /// Comic numbers counting as interactive
+    pub fn comics(&self) -> &[u64] {
+        self.comics.as_deref().unwrap_or(INTERACTIVE_COMICS)
+    }
+
+    pub fn is_interactive(&self, num: u64) -> bool {
+        self.comics().contains(&num)
+    }
+
+    /// Interactive comics that are left with their placeholder image, see [`QualityOptions`]
+    pub fn placeholders(&self) -> Vec<u64> {
+        self.comics()
+            .iter()
+            .filter(|num| {
+                self.action != InteractiveAction::Fallback
+                    || !self.fallbacks.contains_key(&num.to_string())
+            })
+            .copied()
+            .collect()
+    }
+
+    /// `metadata` with its image replaced by the configured fallback, if the action asks for it
+    pub fn fallback(&self, metadata: &Metadata) -> Option<Metadata> {
+        if self.action != InteractiveAction::Fallback || !self.is_interactive(metadata.num) {
+            return None;
+        }
+        let img = self.fallbacks.get(&metadata.num.to_string())?;
+        info!("using fallback image {} for comic {}", img, metadata.num);
+        Some(Metadata {
+            img: img.clone(),
+            ..metadata.clone()
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Requirements a comic must meet to be used as wallpaper
+pub struct QualityOptions {
+    /// Reject comics with colored drawings
+    pub strict_mono: bool,
+    /// Reject comics whose longest side exceeds the shortest by more than this factor
+    pub max_aspect_ratio: f32,
+    /// Reject these comics as interactive
+    pub interactive: Vec<u64>,
+}
+
+impl Default for QualityOptions {
+    fn default() -> Self {
+        QualityOptions {
+            strict_mono: false,
+            max_aspect_ratio: 4.0,
+            interactive: INTERACTIVE_COMICS.to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Reason a comic is unsuitable as wallpaper
+pub enum Unsuitable {
+    Interactive,
+    Colored,
+    AspectRatio(f32),
+}
+
+impl fmt::Display for Unsuitable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unsuitable::Interactive => write!(f, "comic is interactive"),
+            Unsuitable::Colored => write!(f, "comic is colored"),
+            Unsuitable::AspectRatio(ratio) => write!(f, "aspect ratio {ratio:.1} is too extreme"),
+        }
+    }
+}
+
+impl QualityOptions {
+    /// Check whether `comic` is suitable as wallpaper
+    pub fn check(&self, comic: &Image) -> Result<(), Unsuitable> {
+        if self.interactive.contains(&comic.metadata.num) {
+            return Err(Unsuitable::Interactive);
+        }
+
+        let (width, height) = (comic.img.width() as f32, comic.img.height() as f32);
+        let ratio = width.max(height) / width.min(height).max(1.0);
+        if ratio > self.max_aspect_ratio {
+            return Err(Unsuitable::AspectRatio(ratio));
+        }
+
+        if self.strict_mono && is_colored(&comic.img) {
+            return Err(Unsuitable::Colored);
+        }
+        Ok(())
+    }
+}
+
+/// Whether a noticeable share of the image has colored pixels
+pub fn is_colored(img: &DynamicImage) -> bool {
+    let rgb = img.to_rgb8();
+    let colored = rgb
+        .pixels()
+        .filter(|pixel| {
+            let [r, g, b] = pixel.0;
+            r.max(g).max(b) - r.min(g).min(b) > COLOR_CHANNEL_DIFFERENCE
+        })
+        .count();
+    let ratio = colored as f32 / (rgb.width() * rgb.height()).max(1) as f32;
+    info!("{:.2}% of pixels are colored", ratio * 100.0);
+    ratio > COLORED_PIXEL_RATIO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Resolution;
+    use image::{Rgb, RgbImage};
+    use rstest::rstest;
+
+    fn comic(num: u64, img: RgbImage) -> Image {
+        Image {
+            img: DynamicImage::ImageRgb8(img),
+            metadata: Metadata {
+                num,
+                safe_title: String::new(),
+                img: String::new(),
+                alt: String::new(),
+                link: String::new(),
+                title: String::new(),
+                transcript: String::new(),
+                news: String::new(),
+                day: String::new(),
+                month: String::new(),
+                year: String::new(),
+            },
+            resolution: Resolution::Regular,
+        }
+    }
+
+    #[rstest]
+    #[case(comic(1, RgbImage::from_pixel(40, 30, Rgb([255, 255, 255]))), false, Ok(()))]
+    #[case(
+        comic(1416, RgbImage::new(40, 30)),
+        false,
+        Err(Unsuitable::Interactive)
+    )]
+    #[case(
+        comic(1, RgbImage::new(100, 10)),
+        false,
+        Err(Unsuitable::AspectRatio(10.0))
+    )]
+    #[case(comic(1, RgbImage::from_pixel(40, 30, Rgb([200, 30, 30]))), false, Ok(()))]
+    #[case(comic(1, RgbImage::from_pixel(40, 30, Rgb([200, 30, 30]))), true, Err(Unsuitable::Colored))]
+    fn quality_check(
+        #[case] comic: Image,
+        #[case] strict_mono: bool,
+        #[case] expected: Result<(), Unsuitable>,
+    ) {
+        let options = QualityOptions {
+            strict_mono,
+            ..Default::default()
+        };
+        assert_eq!(options.check(&comic), expected);
+    }
+
+    #[rstest]
+    #[case(InteractiveAction::Fallback, 1608, Some("https://example.com/a.png"))]
+    #[case(InteractiveAction::Fallback, 1416, None)]
+    #[case(InteractiveAction::Skip, 1608, None)]
+    fn interactive_fallback(
+        #[case] action: InteractiveAction,
+        #[case] num: u64,
+        #[case] expected: Option<&str>,
+    ) {
+        let config = InteractiveConfig {
+            comics: None,
+            action,
+            fallbacks: [("1608".to_string(), "https://example.com/a.png".to_string())].into(),
+        };
+        let metadata = comic(num, RgbImage::new(1, 1)).metadata;
+        let fallback = config.fallback(&metadata);
+        assert_eq!(fallback.as_ref().map(|m| m.img.as_str()), expected);
+        assert_eq!(config.placeholders().contains(&num), expected.is_none());
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use image::{Rgba, RgbaImage};
+use log::info;
+
+use crate::{timings, XkcdError};
+
+#[derive(Clone, Debug, PartialEq)]
+/// An image reduced to a palette of at most 256 colors, as written to an indexed PNG
+pub struct Indexed {
+    pub width: u32,
+    pub height: u32,
+    pub palette: Vec<Rgba<u8>>,
+    /// Index in the palette of every pixel, row by row
+    pub indices: Vec<u8>,
+}
+
+/// Reduce `img` to at most `colors` colors with the median cut algorithm
+///
+/// Images with no more colors than that keep them exactly.
+pub fn quantize(img: &RgbaImage, colors: u16) -> Indexed {
+    let _timer = timings::stage("quantize");
+    let mut counts: HashMap<[u8; 4], u64> = HashMap::new();
+    for pixel in img.pixels() {
+        *counts.entry(pixel.0).or_default() += 1;
+    }
+    let colors = colors.clamp(1, 256) as usize;
+    info!("quantizing {} colors to at most {}", counts.len(), colors);
+
+    // Boxes of colors, split at the weighted median of their widest channel until there are
+    // enough of them
+    let mut boxes = vec![counts.into_iter().collect::<Vec<_>>()];
+    while boxes.len() < colors {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(index, colors)| (index, widest_channel(colors)))
+            .max_by_key(|(_, (_, range))| *range);
+        let Some((index, (channel, _))) = widest else {
+            break;
+        };
+        let mut colors = boxes.swap_remove(index);
+        colors.sort_unstable_by_key(|(color, _)| color[channel]);
+        let total = colors.iter().map(|(_, count)| count).sum::<u64>();
+        let mut below = 0;
+        let median = colors
+            .iter()
+            .position(|(_, count)| {
+                below += count;
+                below * 2 >= total
+            })
+            .unwrap_or(0);
+        // Both halves keep at least one color
+        let upper = colors.split_off((median + 1).min(colors.len() - 1));
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    let mut palette = Vec::with_capacity(boxes.len());
+    let mut lookup = HashMap::new();
+    for (index, colors) in boxes.iter().enumerate() {
+        let total = colors.iter().map(|(_, count)| count).sum::<u64>().max(1);
+        let mut sums = [0u64; 4];
+        for (color, count) in colors {
+            for (sum, channel) in sums.iter_mut().zip(color) {
+                *sum += *channel as u64 * count;
+            }
+            lookup.insert(*color, index as u8);
+        }
+        palette.push(Rgba(sums.map(|sum| ((sum + total / 2) / total) as u8)));
+    }
+    Indexed {
+        width: img.width(),
+        height: img.height(),
+        palette,
+        indices: img.pixels().map(|pixel| lookup[&pixel.0]).collect(),
+    }
+}
+
+/// Channel whose values spread the most among `colors`, and how much they spread
+fn widest_channel(colors: &[([u8; 4], u64)]) -> (usize, u8) {
+    (0..4)
+        .map(|channel| {
+            let values = colors.iter().map(|(color, _)| color[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap_or((0, 0))
+}
+
+impl Indexed {
+    /// Fewest bits per pixel that index every color of the palette
+    pub fn bit_depth(&self) -> u8 {
+        match self.palette.len() {
+            0..=2 => 1,
+            3..=4 => 2,
+            5..=16 => 4,
+            _ => 8,
+        }
+    }
+
+    /// Write the image to `path` as an indexed PNG, with transparency only if the palette has any
+    pub fn save(&self, path: &Path) -> Result<(), XkcdError> {
+        let _timer = timings::stage("encode");
+        let depth = self.bit_depth();
+        info!(
+            "writing {}-bit indexed PNG with {} colors to {}",
+            depth,
+            self.palette.len(),
+            path.display()
+        );
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(match depth {
+            1 => png::BitDepth::One,
+            2 => png::BitDepth::Two,
+            4 => png::BitDepth::Four,
+            _ => png::BitDepth::Eight,
+        });
+        encoder.set_palette(
+            self.palette
+                .iter()
+                .flat_map(|color| [color[0], color[1], color[2]])
+                .collect::<Vec<_>>(),
+        );
+        if self.palette.iter().any(|color| color[3] < 255) {
+            encoder.set_trns(
+                self.palette
+                    .iter()
+                    .map(|color| color[3])
+                    .collect::<Vec<_>>(),
+            );
+        }
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.packed())?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Indices packed [`Indexed::bit_depth`] bits to a pixel, each row starting on a new byte
+    fn packed(&self) -> Vec<u8> {
+        let depth = self.bit_depth() as usize;
+        let per_byte = 8 / depth;
+        let width = self.width as usize;
+        let mut packed = Vec::with_capacity(width.div_ceil(per_byte) * self.height as usize);
+        for row in self.indices.chunks(width.max(1)) {
+            for pixels in row.chunks(per_byte) {
+                let byte = pixels.iter().enumerate().fold(0u8, |byte, (index, pixel)| {
+                    byte | pixel << (8 - depth * (index + 1))
+                });
+                packed.push(byte);
+            }
+        }
+        packed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    #[test]
+    fn few_colors_are_kept() {
+        let mut img = RgbaImage::from_pixel(4, 2, WHITE);
+        img.put_pixel(1, 0, BLACK);
+        img.put_pixel(2, 1, Rgba([255, 0, 0, 255]));
+        let indexed = quantize(&img, 16);
+        assert_eq!(indexed.palette.len(), 3);
+        for (pixel, index) in img.pixels().zip(&indexed.indices) {
+            assert_eq!(&indexed.palette[*index as usize], pixel);
+        }
+    }
+
+    #[test]
+    fn similar_colors_are_merged() {
+        // Near black and near white, anti-aliased line art
+        let img = RgbaImage::from_fn(16, 16, |x, _| match x < 8 {
+            true => Rgba([x as u8, x as u8, x as u8, 255]),
+            false => Rgba([240 + x as u8, 240 + x as u8, 240 + x as u8, 255]),
+        });
+        let indexed = quantize(&img, 2);
+        assert_eq!(indexed.palette.len(), 2);
+        assert_eq!(indexed.bit_depth(), 1);
+        assert_ne!(indexed.indices[0], indexed.indices[15]);
+        assert!(indexed.palette.iter().any(|color| color[0] < 8));
+        assert!(indexed.palette.iter().any(|color| color[0] > 240));
+    }
+
+    #[rstest]
+    #[case(2, 1)]
+    #[case(4, 2)]
+    #[case(5, 4)]
+    #[case(200, 8)]
+    fn bit_depth(#[case] colors: usize, #[case] depth: u8) {
+        let indexed = Indexed {
+            width: 0,
+            height: 0,
+            palette: vec![BLACK; colors],
+            indices: Vec::new(),
+        };
+        assert_eq!(indexed.bit_depth(), depth);
+    }
+
+    #[test]
+    fn save_indexed_png() {
+        let img = RgbaImage::from_fn(11, 3, |x, y| match (x + y) % 3 {
+            0 => BLACK,
+            1 => WHITE,
+            _ => Rgba([0, 0, 0, 0]),
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("indexed.png");
+        quantize(&img, 256).save(&path).unwrap();
+        assert_eq!(image::open(&path).unwrap().into_rgba8(), img);
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Comic numbers that have no comic
+pub const MISSING_COMICS: &[u64] = &[404];
+
+/// Small xorshift generator for picking random comics, not suitable for anything secret
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Zero would stay zero forever
+        Rng(seed.max(1))
+    }
+
+    /// Seeded from the clock and the process id
+    pub fn from_clock() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or_default();
+        Rng::new(nanos ^ (std::process::id() as u64).rotate_left(32))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform value in `0..n`, `n` must not be zero
+    pub fn below(&mut self, n: u64) -> u64 {
+        // Rejection sampling avoids the modulo bias
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % n;
+            }
+        }
+    }
+
+    /// Uniformly random comic number up to `latest`, skipping numbers without a comic such as 404
+    pub fn comic(&mut self, latest: u64) -> u64 {
+        let latest = latest.max(1);
+        loop {
+            let num = 1 + self.below(latest);
+            if !MISSING_COMICS.contains(&num) || latest == 1 {
+                return num;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comics_in_range() {
+        let mut rng = Rng::new(42);
+        for _ in 0..2000 {
+            let num = rng.comic(500);
+            assert!((1..=500).contains(&num) && num != 404);
+        }
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let (mut a, mut b) = (Rng::new(7), Rng::new(7));
+        assert_eq!(
+            (0..5).map(|_| a.below(100)).collect::<Vec<_>>(),
+            (0..5).map(|_| b.below(100)).collect::<Vec<_>>()
+        );
+    }
+}
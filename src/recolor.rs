@@ -0,0 +1,273 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use log::info;
+
+use crate::ForegroundColor;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// How the colors of a comic are turned into those of the wallpaper
+pub struct Recolor {
+    /// Largest difference in any channel from the comic's background for a pixel to be replaced
+    /// by the wallpaper background, 0 only replaces exact matches
+    pub bg_tolerance: u8,
+    /// Blend the other pixels into the background by how far they are from the comic's
+    /// background, instead of keeping them as they are
+    ///
+    /// Each pixel is treated as ink of the least opacity that gives its color over the comic's
+    /// background, so gray anti-aliased edges fade into any background without a fringe and
+    /// colors are kept.
+    pub smooth_edges: bool,
+    /// Adjustment of the drawings, the new background is left as it is
+    pub tone: Tone,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Brightness, contrast and gamma adjustment of the drawings
+pub struct Tone {
+    /// Added to every channel, from -1 (all black) to 1 (all white)
+    pub brightness: f32,
+    /// Factor stretching the channels away from mid gray, 1 keeps them as they are
+    pub contrast: f32,
+    /// Midtones are brightened above 1 and darkened below
+    pub gamma: f32,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Tone {
+            brightness: 0.0,
+            contrast: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl Tone {
+    /// New value of each channel value, gamma first, then contrast and brightness
+    pub fn table(&self) -> [u8; 256] {
+        std::array::from_fn(|value| {
+            let value = (value as f32 / 255.0).powf(1.0 / self.gamma.max(f32::EPSILON));
+            let value = (value - 0.5) * self.contrast + 0.5 + self.brightness;
+            (value * 255.0).round().clamp(0.0, 255.0) as u8
+        })
+    }
+}
+
+impl Recolor {
+    /// Invert the comic for light drawings and replace its background with `bg_color`
+    ///
+    /// With a [`ForegroundColor::Custom`] ink, the drawings are painted in the ink with the
+    /// opacity of their darkness, so anti-aliased edges always blend into the background.
+    ///
+    /// A fully transparent `bg_color` leaves the background to whatever the comic is later laid
+    /// over. The drawings are then always blended, carrying their opacity in the alpha channel.
+    pub fn apply(
+        &self,
+        mut comic_img: DynamicImage,
+        fg_color: &ForegroundColor,
+        bg_color: Rgba<u8>,
+    ) -> RgbaImage {
+        if *fg_color == ForegroundColor::Light {
+            info!("inverting image colors");
+            comic_img.invert();
+        }
+
+        let mut comic_buffer = comic_img.into_rgba8();
+
+        let comic_background_color = match fg_color {
+            ForegroundColor::Light => Rgba([0, 0, 0, 255]),
+            ForegroundColor::Dark | ForegroundColor::Custom(_) => Rgba([255, 255, 255, 255]),
+        };
+
+        info!(
+            "replacing background pixels with background colors, tolerance {}, smooth edges {}",
+            self.bg_tolerance, self.smooth_edges
+        );
+        let tone = (self.tone != Tone::default()).then(|| {
+            info!("adjusting drawings with {:?}", self.tone);
+            self.tone.table()
+        });
+        for pixel in comic_buffer.pixels_mut() {
+            if self.is_background(pixel, &comic_background_color) {
+                *pixel = bg_color;
+                continue;
+            } else if let ForegroundColor::Custom(ink) = fg_color {
+                *pixel = tint(pixel, &comic_background_color, ink, &bg_color);
+            } else if self.smooth_edges || bg_color[3] == 0 {
+                *pixel = blend(pixel, &comic_background_color, &bg_color);
+            }
+            if let Some(table) = &tone {
+                for channel in 0..3 {
+                    pixel[channel] = table[pixel[channel] as usize];
+                }
+            }
+        }
+        comic_buffer
+    }
+
+    fn is_background(&self, pixel: &Rgba<u8>, background: &Rgba<u8>) -> bool {
+        pixel[3] == background[3]
+            && pixel.0[..3]
+                .iter()
+                .zip(&background.0[..3])
+                .all(|(channel, bg)| channel.abs_diff(*bg) <= self.bg_tolerance)
+    }
+}
+
+/// `pixel` seen as ink over `background`, moved over `bg_color` instead
+///
+/// The ink's opacity is the least that produces `pixel` over `background` (as in GIMP's color
+/// to alpha), so `pixel + (1 - opacity) * (bg_color - background)` keeps the ink and swaps the
+/// background showing through it.
+fn blend(pixel: &Rgba<u8>, background: &Rgba<u8>, bg_color: &Rgba<u8>) -> Rgba<u8> {
+    let opacity = opacity(pixel, background);
+    let mut blended = *pixel;
+    if bg_color[3] == 0 {
+        // The ink alone, `background + (pixel - background) / opacity`
+        for channel in 0..3 {
+            let difference = pixel[channel] as f32 - background[channel] as f32;
+            let value = background[channel] as f32 + difference / opacity;
+            blended[channel] = value.round().clamp(0.0, 255.0) as u8;
+        }
+        blended[3] = (opacity * pixel[3] as f32).round() as u8;
+        return blended;
+    }
+    for channel in 0..3 {
+        let shift = bg_color[channel] as f32 - background[channel] as f32;
+        let value = pixel[channel] as f32 + (1.0 - opacity) * shift;
+        blended[channel] = value.round().clamp(0.0, 255.0) as u8;
+    }
+    blended
+}
+
+/// `ink` over `bg_color` with the opacity `pixel` has over `background`
+fn tint(pixel: &Rgba<u8>, background: &Rgba<u8>, ink: &Rgba<u8>, bg_color: &Rgba<u8>) -> Rgba<u8> {
+    let opacity = opacity(pixel, background);
+    let mut tinted = *pixel;
+    if bg_color[3] == 0 {
+        tinted = *ink;
+        tinted[3] = (opacity * pixel[3] as f32).round() as u8;
+        return tinted;
+    }
+    for channel in 0..3 {
+        let value =
+            bg_color[channel] as f32 + opacity * (ink[channel] as f32 - bg_color[channel] as f32);
+        tinted[channel] = value.round().clamp(0.0, 255.0) as u8;
+    }
+    tinted
+}
+
+/// Least opacity of ink that gives `pixel` over `background`
+fn opacity(pixel: &Rgba<u8>, background: &Rgba<u8>) -> f32 {
+    let difference = (0..3)
+        .map(|channel| pixel[channel].abs_diff(background[channel]))
+        .max()
+        .unwrap_or(0);
+    difference as f32 / 255.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(0, [255, 255, 255], true)]
+    #[case(0, [250, 255, 255], false)]
+    #[case(8, [250, 247, 255], true)]
+    #[case(8, [246, 255, 255], false)]
+    fn near_background_pixels(
+        #[case] bg_tolerance: u8,
+        #[case] rgb: [u8; 3],
+        #[case] replaced: bool,
+    ) {
+        let [r, g, b] = rgb;
+        let comic = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([r, g, b, 255])));
+        let bg = Rgba([10, 20, 30, 255]);
+        let recolor = Recolor {
+            bg_tolerance,
+            ..Default::default()
+        };
+        let recolored = recolor.apply(comic, &ForegroundColor::Dark, bg);
+        assert_eq!(recolored.get_pixel(0, 0) == &bg, replaced);
+    }
+
+    #[rstest]
+    // Ink stays ink
+    #[case(ForegroundColor::Dark, [0, 0, 0], [0, 0, 0])]
+    // Half-covered edges land halfway between the ink and the new background
+    #[case(ForegroundColor::Dark, [128, 128, 128], [0, 5, 10])]
+    #[case(ForegroundColor::Light, [128, 128, 128], [127, 132, 137])]
+    // Saturated colors are kept
+    #[case(ForegroundColor::Dark, [255, 0, 0], [255, 0, 0])]
+    fn smooth_edges(#[case] fg: ForegroundColor, #[case] rgb: [u8; 3], #[case] expected: [u8; 3]) {
+        let [r, g, b] = rgb;
+        let comic = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([r, g, b, 255])));
+        let recolor = Recolor {
+            smooth_edges: true,
+            ..Default::default()
+        };
+        let recolored = recolor.apply(comic, &fg, Rgba([0, 10, 20, 255]));
+        let [r, g, b] = expected;
+        assert_eq!(*recolored.get_pixel(0, 0), Rgba([r, g, b, 255]));
+    }
+
+    #[rstest]
+    #[case([0, 0, 0], [0x88, 0xC0, 0xD0])]
+    #[case([128, 128, 128], [0x48, 0x69, 0x75])]
+    #[case([255, 255, 255], [0x08, 0x12, 0x1A])]
+    fn custom_ink(#[case] rgb: [u8; 3], #[case] expected: [u8; 3]) {
+        let [r, g, b] = rgb;
+        let comic = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([r, g, b, 255])));
+        let fg = ForegroundColor::Custom(Rgba([0x88, 0xC0, 0xD0, 255]));
+        let recolored = Recolor::default().apply(comic, &fg, Rgba([0x08, 0x12, 0x1A, 255]));
+        let [r, g, b] = expected;
+        assert_eq!(*recolored.get_pixel(0, 0), Rgba([r, g, b, 255]));
+    }
+
+    #[rstest]
+    #[case(Tone::default(), [0, 64, 128, 200, 255])]
+    #[case(Tone { brightness: 0.1, ..Default::default() }, [26, 90, 154, 226, 255])]
+    #[case(Tone { contrast: 2.0, ..Default::default() }, [0, 1, 129, 255, 255])]
+    #[case(Tone { gamma: 2.0, ..Default::default() }, [0, 128, 181, 226, 255])]
+    fn tone_table(#[case] tone: Tone, #[case] expected: [u8; 5]) {
+        let table = tone.table();
+        assert_eq!([0, 64, 128, 200, 255].map(|value| table[value]), expected);
+    }
+
+    #[test]
+    fn tone_leaves_background() {
+        // Light gray lines on black once inverted, boosted to white
+        let mut comic = RgbaImage::from_pixel(2, 1, Rgba([255, 255, 255, 255]));
+        comic.put_pixel(1, 0, Rgba([60, 60, 60, 255]));
+        let recolor = Recolor {
+            tone: Tone {
+                contrast: 2.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let bg = Rgba([0x1F, 0x24, 0x1F, 255]);
+        let recolored = recolor.apply(DynamicImage::ImageRgba8(comic), &ForegroundColor::Light, bg);
+        assert_eq!(recolored.get_pixel(0, 0), &bg);
+        assert_eq!(recolored.get_pixel(1, 0), &Rgba([255, 255, 255, 255]));
+    }
+
+    #[rstest]
+    #[case(ForegroundColor::Dark, [255, 255, 255], [255, 255, 255, 0])]
+    #[case(ForegroundColor::Dark, [128, 128, 128], [0, 0, 0, 127])]
+    #[case(
+        ForegroundColor::Custom(Rgba([0x88, 0xC0, 0xD0, 255])),
+        [0, 0, 0],
+        [0x88, 0xC0, 0xD0, 255]
+    )]
+    fn transparent_background(
+        #[case] fg: ForegroundColor,
+        #[case] rgb: [u8; 3],
+        #[case] expected: [u8; 4],
+    ) {
+        let [r, g, b] = rgb;
+        let comic = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([r, g, b, 255])));
+        let recolored = Recolor::default().apply(comic, &fg, Rgba([255, 255, 255, 0]));
+        assert_eq!(*recolored.get_pixel(0, 0), Rgba(expected));
+    }
+}
@@ -0,0 +1,370 @@
+use std::f64::consts::PI;
+
+use jiff::civil::{Date, Time, Weekday};
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, ToSpan};
+use serde::{Deserialize, Deserializer};
+
+use crate::theme::{deserialize_background_color, Palette};
+use crate::XkcdError;
+
+/// Zenith angle of the sun at sunrise and sunset, accounts for refraction and the solar disc
+const SUNRISE_ZENITH_DEG: f64 = 90.833;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Part of the day used to pick a palette
+pub enum Period {
+    Day,
+    Night,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Determines when the day and night periods start
+pub enum Schedule {
+    /// Fixed local times
+    Fixed { day_start: Time, night_start: Time },
+    /// Sunrise and sunset at a given location, in degrees
+    Solar { latitude: f64, longitude: f64 },
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// Day and night palettes from the config file
+///
+/// ```toml
+/// [daynight]
+/// day_start = "07:00"
+/// night_start = "19:30"
+/// # or follow the sun instead of fixed times
+/// # latitude = 41.15
+/// # longitude = -8.61
+///
+/// [daynight.day]
+/// theme = "cb-protan"
+///
+/// [daynight.night]
+/// bg = "#101418"
+/// fg = "light"
+/// ```
+pub struct DayNightConfig {
+    pub day_start: Option<String>,
+    pub night_start: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub day: Palette,
+    pub night: Palette,
+}
+
+impl DayNightConfig {
+    /// Build the configured schedule, `None` if no schedule is configured
+    pub fn schedule(&self) -> Result<Option<Schedule>, XkcdError> {
+        let parse_time = |value: &str| {
+            value
+                .parse::<Time>()
+                .map_err(|err| XkcdError::Config(format!("invalid time '{value}': {err}")))
+        };
+
+        match (
+            &self.day_start,
+            &self.night_start,
+            self.latitude,
+            self.longitude,
+        ) {
+            (None, None, None, None) => Ok(None),
+            (Some(day_start), Some(night_start), None, None) => Ok(Some(Schedule::Fixed {
+                day_start: parse_time(day_start)?,
+                night_start: parse_time(night_start)?,
+            })),
+            (None, None, Some(latitude), Some(longitude))
+                if (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude) =>
+            {
+                Ok(Some(Schedule::Solar {
+                    latitude,
+                    longitude,
+                }))
+            }
+            _ => Err(XkcdError::Config(
+                "daynight needs either day_start and night_start, or a valid latitude and longitude"
+                    .to_string(),
+            )),
+        }
+    }
+
+    /// Palette configured for `period`
+    pub fn palette(&self, period: Period) -> &Palette {
+        match period {
+            Period::Day => &self.day,
+            Period::Night => &self.night,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// Calendar based palette rule from the config file, unset conditions match any date
+///
+/// ```toml
+/// [[calendar]]
+/// weekday = "monday"
+/// theme = "cb-deutan"
+///
+/// [[calendar]]
+/// month = 12
+/// bg = "#0F3B1F"
+/// fg = "light"
+/// ```
+pub struct CalendarRule {
+    #[serde(deserialize_with = "deserialize_weekday")]
+    pub weekday: Option<Weekday>,
+    pub month: Option<i8>,
+    pub day: Option<i8>,
+    pub theme: Option<String>,
+    #[serde(deserialize_with = "deserialize_background_color")]
+    pub bg: Option<image::Rgba<u8>>,
+    pub fg: Option<crate::ForegroundColor>,
+}
+
+impl CalendarRule {
+    /// Whether the rule applies on `date`
+    pub fn matches(&self, date: Date) -> bool {
+        self.weekday.is_none_or(|weekday| weekday == date.weekday())
+            && self.month.is_none_or(|month| month == date.month())
+            && self.day.is_none_or(|day| day == date.day())
+    }
+
+    /// Colors set by this rule
+    pub fn palette(&self) -> Palette {
+        Palette {
+            theme: self.theme.clone(),
+            bg: self.bg,
+            fg: self.fg.clone(),
+        }
+    }
+
+    /// Palette of the first rule in `rules` matching `date`
+    pub fn first_match(rules: &[CalendarRule], date: Date) -> Option<Palette> {
+        rules
+            .iter()
+            .find(|rule| rule.matches(date))
+            .map(CalendarRule::palette)
+    }
+}
+
+fn parse_weekday(value: &str) -> Option<Weekday> {
+    let weekdays = [
+        ("monday", Weekday::Monday),
+        ("tuesday", Weekday::Tuesday),
+        ("wednesday", Weekday::Wednesday),
+        ("thursday", Weekday::Thursday),
+        ("friday", Weekday::Friday),
+        ("saturday", Weekday::Saturday),
+        ("sunday", Weekday::Sunday),
+    ];
+    let value = value.trim().to_lowercase();
+    weekdays
+        .into_iter()
+        .find(|(name, _)| value.len() >= 3 && name.starts_with(&value))
+        .map(|(_, weekday)| weekday)
+}
+
+fn deserialize_weekday<'de, D>(deserializer: D) -> Result<Option<Weekday>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<String>::deserialize(deserializer)?;
+    value
+        .map(|value| {
+            parse_weekday(&value)
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid weekday '{value}'")))
+        })
+        .transpose()
+}
+
+impl Schedule {
+    /// Period at instant `now`, fixed times are interpreted in time zone `tz`
+    pub fn period_at(&self, now: Timestamp, tz: &TimeZone) -> Period {
+        // The most recent change before `now` determines the current period
+        self.changes_around(now, tz)
+            .into_iter()
+            .filter(|(instant, _)| *instant <= now)
+            .max_by_key(|(instant, _)| *instant)
+            .map(|(_, period)| period)
+            .unwrap_or_else(|| self.polar_period(now))
+    }
+
+    /// First instant after `now` at which the period changes, if any in the next few days
+    pub fn next_change(&self, now: Timestamp, tz: &TimeZone) -> Option<Timestamp> {
+        self.changes_around(now, tz)
+            .into_iter()
+            .map(|(instant, _)| instant)
+            .filter(|instant| *instant > now)
+            .min()
+    }
+
+    /// Instants at which a period starts, from the day before to two days after `now`
+    fn changes_around(&self, now: Timestamp, tz: &TimeZone) -> Vec<(Timestamp, Period)> {
+        let today = now.to_zoned(tz.clone()).date();
+        (-1..=2)
+            .filter_map(|offset| today.checked_add(offset.days()).ok())
+            .flat_map(|date| self.changes_on(date, tz))
+            .collect()
+    }
+
+    fn changes_on(&self, date: Date, tz: &TimeZone) -> Vec<(Timestamp, Period)> {
+        match self {
+            Schedule::Fixed {
+                day_start,
+                night_start,
+            } => [(day_start, Period::Day), (night_start, Period::Night)]
+                .into_iter()
+                .filter_map(|(time, period)| {
+                    let zoned = date.to_datetime(*time).to_zoned(tz.clone()).ok()?;
+                    Some((zoned.timestamp(), period))
+                })
+                .collect(),
+            Schedule::Solar {
+                latitude,
+                longitude,
+            } => match sunrise_sunset(date, *latitude, *longitude) {
+                Some((sunrise, sunset)) => vec![(sunrise, Period::Day), (sunset, Period::Night)],
+                None => vec![],
+            },
+        }
+    }
+
+    /// Period during polar day or night, when the sun does not rise or set
+    fn polar_period(&self, now: Timestamp) -> Period {
+        match self {
+            Schedule::Solar { latitude, .. } => {
+                let date = now.to_zoned(TimeZone::UTC).date();
+                let declination = solar_position(date).1;
+                // The sun stays up when it is on the same side of the equator as the observer
+                if latitude.signum() == declination.signum() {
+                    Period::Day
+                } else {
+                    Period::Night
+                }
+            }
+            Schedule::Fixed { .. } => Period::Day,
+        }
+    }
+}
+
+/// Equation of time in minutes and solar declination in radians at noon UTC of `date`
+fn solar_position(date: Date) -> (f64, f64) {
+    let gamma = 2.0 * PI / 365.0 * (date.day_of_year() as f64 - 1.0);
+    let eqtime = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+    let declination = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+    (eqtime, declination)
+}
+
+/// Sunrise and sunset on `date` using the NOAA approximation, `None` during polar day or night
+fn sunrise_sunset(date: Date, latitude: f64, longitude: f64) -> Option<(Timestamp, Timestamp)> {
+    let (eqtime, declination) = solar_position(date);
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = SUNRISE_ZENITH_DEG.to_radians().cos()
+        / (latitude.cos() * declination.cos())
+        - latitude.tan() * declination.tan();
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+
+    // Minutes after midnight UTC
+    let sunrise = 720.0 - 4.0 * (longitude + hour_angle) - eqtime;
+    let sunset = 720.0 - 4.0 * (longitude - hour_angle) - eqtime;
+
+    let midnight = date.to_zoned(TimeZone::UTC).ok()?.timestamp();
+    let at = |minutes: f64| {
+        Timestamp::from_second(midnight.as_second() + (minutes * 60.0).round() as i64).ok()
+    };
+    Some((at(sunrise)?, at(sunset)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn fixed(day_start: &str, night_start: &str) -> Schedule {
+        Schedule::Fixed {
+            day_start: day_start.parse().unwrap(),
+            night_start: night_start.parse().unwrap(),
+        }
+    }
+
+    #[rstest]
+    #[case(fixed("07:00", "19:00"), "2025-06-20T12:00:00Z", Period::Day)]
+    #[case(fixed("07:00", "19:00"), "2025-06-20T06:59:00Z", Period::Night)]
+    #[case(fixed("07:00", "19:00"), "2025-06-20T19:00:00Z", Period::Night)]
+    #[case(fixed("20:00", "06:00"), "2025-06-20T02:00:00Z", Period::Day)]
+    #[case(Schedule::Solar { latitude: 41.15, longitude: -8.61 }, "2025-06-20T12:00:00Z", Period::Day)]
+    #[case(Schedule::Solar { latitude: 41.15, longitude: -8.61 }, "2025-06-20T23:00:00Z", Period::Night)]
+    #[case(Schedule::Solar { latitude: 37.77, longitude: -122.42 }, "2025-06-21T02:00:00Z", Period::Day)]
+    #[case(Schedule::Solar { latitude: 78.22, longitude: 15.65 }, "2025-06-20T00:00:00Z", Period::Day)]
+    #[case(Schedule::Solar { latitude: 78.22, longitude: 15.65 }, "2025-12-20T12:00:00Z", Period::Night)]
+    fn schedule_period(#[case] schedule: Schedule, #[case] now: &str, #[case] expected: Period) {
+        let now: Timestamp = now.parse().unwrap();
+        assert_eq!(schedule.period_at(now, &TimeZone::UTC), expected);
+    }
+
+    #[rstest]
+    #[case("mon", Some(Weekday::Monday))]
+    #[case("Thursday", Some(Weekday::Thursday))]
+    #[case("t", None)]
+    #[case("funday", None)]
+    fn weekday_parse(#[case] input: &str, #[case] expected: Option<Weekday>) {
+        assert_eq!(parse_weekday(input), expected);
+    }
+
+    #[rstest]
+    #[case(Some(Weekday::Friday), None, None, true)]
+    #[case(Some(Weekday::Monday), None, None, false)]
+    #[case(None, Some(6), Some(20), true)]
+    #[case(Some(Weekday::Friday), Some(12), None, false)]
+    fn calendar_rule_matches(
+        #[case] weekday: Option<Weekday>,
+        #[case] month: Option<i8>,
+        #[case] day: Option<i8>,
+        #[case] expected: bool,
+    ) {
+        // 2025-06-20 is a Friday
+        let date: Date = "2025-06-20".parse().unwrap();
+        let rule = CalendarRule {
+            weekday,
+            month,
+            day,
+            ..Default::default()
+        };
+        assert_eq!(rule.matches(date), expected);
+    }
+
+    #[test]
+    fn schedule_next_change() {
+        let now: Timestamp = "2025-06-20T20:00:00Z".parse().unwrap();
+        let expected: Timestamp = "2025-06-21T07:00:00Z".parse().unwrap();
+        assert_eq!(
+            fixed("07:00", "19:00").next_change(now, &TimeZone::UTC),
+            Some(expected)
+        );
+    }
+
+    #[test]
+    fn solar_sunrise_is_plausible() {
+        // Porto, sunrise around 05:02 UTC and sunset around 20:09 UTC on the summer solstice
+        let date: Date = "2025-06-21".parse().unwrap();
+        let (sunrise, sunset) = sunrise_sunset(date, 41.15, -8.61).unwrap();
+        let sunrise_expected: Timestamp = "2025-06-21T05:02:00Z".parse().unwrap();
+        let sunset_expected: Timestamp = "2025-06-21T20:09:00Z".parse().unwrap();
+        assert!(sunrise.duration_until(sunrise_expected).as_secs().abs() < 5 * 60);
+        assert!(sunset.duration_until(sunset_expected).as_secs().abs() < 5 * 60);
+    }
+}
@@ -0,0 +1,210 @@
+//! Transform scripts written in [Rhai](https://rhai.rs), enabled with the `scripting` feature
+//!
+//! A script runs once on the finished wallpaper, which it sees as `image`, with the metadata of
+//! the comic as the `comic` map:
+//!
+//! ```text
+//! // warm tint that fades towards the bottom
+//! for y in 0..image.height {
+//!     let t = y.to_float() / image.height.to_float();
+//!     for x in 0..image.width {
+//!         let p = image.get(x, y);
+//!         p[0] += (255 - p[0]) * 0.2 * t;
+//!         p[2] *= 1.0 - 0.3 * t;
+//!         image.set(x, y, p);
+//!     }
+//! }
+//! ```
+//!
+//! `image.get(x, y)` returns the `[r, g, b, a]` channels (0-255) of a pixel, coordinates outside
+//! the image are clamped to its edges so kernels can read past them. `image.set(x, y, pixel)`
+//! writes them back, rounded and clamped, and ignores pixels outside the image. Assigning
+//! `image` to another variable copies it, to read the original pixels while writing new ones.
+//! `comic` has every field of [`Metadata`]: `num`, `title`, `safe_title`, `alt`, `transcript`,
+//! `year`, `month`, `day` and so on.
+
+use std::fs;
+use std::path::Path;
+
+use image::{Rgba, RgbaImage};
+use log::info;
+use rhai::{Array, Dynamic, Engine, Scope, AST};
+
+use crate::{timings, Metadata, XkcdError};
+
+/// The wallpaper as scripts see it
+#[derive(Clone, Debug)]
+struct ScriptImage(RgbaImage);
+
+impl ScriptImage {
+    fn width(&mut self) -> i64 {
+        self.0.width() as i64
+    }
+
+    fn height(&mut self) -> i64 {
+        self.0.height() as i64
+    }
+
+    /// Channels of the pixel at `x`, `y`, clamped to the edges of the image
+    fn get(&mut self, x: i64, y: i64) -> Array {
+        let x = x.clamp(0, self.0.width() as i64 - 1) as u32;
+        let y = y.clamp(0, self.0.height() as i64 - 1) as u32;
+        self.0
+            .get_pixel(x, y)
+            .0
+            .iter()
+            .map(|channel| Dynamic::from_int(*channel as i64))
+            .collect()
+    }
+
+    /// Write the channels of the pixel at `x`, `y`, ignored outside the image
+    fn set(&mut self, x: i64, y: i64, pixel: Array) -> Result<(), Box<rhai::EvalAltResult>> {
+        if pixel.len() != 4 {
+            return Err(format!("pixel must be [r, g, b, a], got {} values", pixel.len()).into());
+        }
+        let mut channels = [0; 4];
+        for (channel, value) in channels.iter_mut().zip(&pixel) {
+            let value = match value.as_int() {
+                Ok(value) => value as f64,
+                Err(_) => value
+                    .as_float()
+                    .map_err(|kind| format!("pixel channels must be numbers, got {kind}"))?,
+            };
+            *channel = value.round().clamp(0.0, 255.0) as u8;
+        }
+        if (0..self.0.width() as i64).contains(&x) && (0..self.0.height() as i64).contains(&y) {
+            self.0.put_pixel(x as u32, y as u32, Rgba(channels));
+        }
+        Ok(())
+    }
+}
+
+/// A compiled transform script
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Script, XkcdError> {
+        Script::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Script, XkcdError> {
+        let mut engine = Engine::new();
+        engine
+            .register_type_with_name::<ScriptImage>("Image")
+            .register_get("width", ScriptImage::width)
+            .register_get("height", ScriptImage::height)
+            .register_fn("get", ScriptImage::get)
+            .register_fn("set", ScriptImage::set)
+            .on_print(|text| info!("script: {}", text));
+        let ast = engine
+            .compile(source)
+            .map_err(|err| XkcdError::Script(err.to_string()))?;
+        Ok(Script { engine, ast })
+    }
+
+    /// Run the script on `img`, which is left as it was when the script fails
+    pub fn apply(&self, img: &mut RgbaImage, metadata: &Metadata) -> Result<(), XkcdError> {
+        info!("running transform script");
+        let _timer = timings::stage("script");
+        let comic =
+            rhai::serde::to_dynamic(metadata).map_err(|err| XkcdError::Script(err.to_string()))?;
+        let mut scope = Scope::new();
+        scope.push("image", ScriptImage(img.clone()));
+        scope.push_constant_dynamic("comic", comic);
+
+        self.engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|err| XkcdError::Script(err.to_string()))?;
+        let ScriptImage(transformed) = scope.remove::<ScriptImage>("image").ok_or_else(|| {
+            XkcdError::Script("`image` was replaced by something else than an image".to_string())
+        })?;
+        *img = transformed;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            num: 927,
+            safe_title: "Standards".to_string(),
+            alt: "Fortunately, the charging one has been solved now".to_string(),
+            day: "20".to_string(),
+            month: "7".to_string(),
+            year: "2011".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn run(source: &str, img: &mut RgbaImage) -> Result<(), XkcdError> {
+        Script::parse(source)?.apply(img, &metadata())
+    }
+
+    #[rstest]
+    #[case("let p = image.get(1, 1); image.set(1, 1, [255 - p[0], 255 - p[1], 255 - p[2], p[3]]);", [245, 235, 225, 255])]
+    #[case("image.set(1, 1, [2 + 3 * 4, 12.6, 300, -5]);", [14, 13, 255, 0])]
+    #[case("image.set(1, 1, [comic.num % 256, comic.safe_title.len, comic.alt.len, parse_int(comic.year) - 2000]);", [159, 9, 49, 11])]
+    fn script_apply(#[case] source: &str, #[case] expected: [u8; 4]) {
+        let mut img = RgbaImage::from_pixel(2, 2, Rgba([10, 20, 30, 255]));
+        run(source, &mut img).unwrap();
+        assert_eq!(img.get_pixel(1, 1).0, expected);
+        assert_eq!(img.get_pixel(0, 0).0, [10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn script_reads_neighbours() {
+        // Horizontal box blur reading from a copy, edges clamped
+        let mut img = RgbaImage::from_fn(3, 1, |x, _| Rgba([x as u8 * 30, 0, 0, 255]));
+        let source = r#"
+            let source = image;
+            for x in 0..image.width {
+                let sum = 0;
+                for dx in -1..=1 {
+                    sum += source.get(x + dx, 0)[0];
+                }
+                image.set(x, 0, [sum / 3.0, 0, 0, 255]);
+            }
+        "#;
+        run(source, &mut img).unwrap();
+        let reds: Vec<u8> = img.pixels().map(|pixel| pixel[0]).collect();
+        assert_eq!(reds, [10, 30, 50]);
+    }
+
+    #[test]
+    fn warm_tint_example() {
+        let source = r#"
+            for y in 0..image.height {
+                let t = y.to_float() / image.height.to_float();
+                for x in 0..image.width {
+                    let p = image.get(x, y);
+                    p[0] += (255 - p[0]) * 0.2 * t;
+                    p[2] *= 1.0 - 0.3 * t;
+                    image.set(x, y, p);
+                }
+            }
+        "#;
+        let mut img = RgbaImage::from_pixel(1, 2, Rgba([100, 100, 100, 255]));
+        run(source, &mut img).unwrap();
+        assert_eq!(img.get_pixel(0, 0).0, [100, 100, 100, 255]);
+        assert_eq!(img.get_pixel(0, 1).0, [116, 100, 85, 255]);
+    }
+
+    #[rstest]
+    #[case("let x = ;")]
+    #[case("image.set(0, 0, [1, 2, 3]);")]
+    #[case("image.set(0, 0, [\"red\", 0, 0, 0]);")]
+    #[case("undefined_function(1);")]
+    #[case("image = 1;")]
+    fn script_error(#[case] source: &str) {
+        let mut img = RgbaImage::from_pixel(2, 2, Rgba([10, 20, 30, 255]));
+        assert!(matches!(run(source, &mut img), Err(XkcdError::Script(_))));
+        assert_eq!(img.dimensions(), (2, 2));
+    }
+}
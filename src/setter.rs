@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use jiff::Timestamp;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::dirs::home_dir;
+use crate::state;
+use crate::XkcdError;
+
+const PREVIOUS_FILE: &str = "previous-wallpaper.json";
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+/// Tool used to change the desktop wallpaper
+pub enum Backend {
+    /// GNOME and derivatives, through gsettings
+    Gnome,
+    /// X11 window managers, through feh
+    Feh,
+    /// Wayland compositors, through swww
+    Swww,
+    /// macOS, through osascript
+    Macos,
+    /// wlroots compositors, drawn by xkcd-wallpaper itself while it runs
+    LayerShell,
+}
+
+impl Backend {
+    /// Guess the backend from the running desktop environment
+    pub fn detect() -> Option<Backend> {
+        if cfg!(target_os = "macos") {
+            return Some(Backend::Macos);
+        }
+
+        let desktop = env::var("XDG_CURRENT_DESKTOP")
+            .unwrap_or_default()
+            .to_lowercase();
+        if ["gnome", "unity", "budgie", "pantheon"]
+            .iter()
+            .any(|name| desktop.contains(name))
+        {
+            Some(Backend::Gnome)
+        } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+            Some(Backend::Swww)
+        } else if env::var_os("DISPLAY").is_some() {
+            Some(Backend::Feh)
+        } else {
+            None
+        }
+    }
+
+    /// Set the wallpaper to the image at `path`
+    pub fn set(&self, path: &Path) -> Result<(), XkcdError> {
+        let path = std::path::absolute(path)?;
+        info!("setting wallpaper {} with {:?}", path.display(), self);
+        self.apply(&self.value_for(&path))
+    }
+
+    /// Currently configured wallpaper, in the backend's own format
+    pub fn current(&self) -> Result<Option<String>, XkcdError> {
+        let value = match self {
+            Backend::Gnome => run(
+                "gsettings",
+                &["get", "org.gnome.desktop.background", "picture-uri"],
+            )?
+            .trim()
+            .trim_matches('\'')
+            .to_string(),
+            Backend::Feh => {
+                let fehbg = home_dir().join(".fehbg");
+                match fs::read_to_string(fehbg) {
+                    Ok(contents) => contents
+                        .lines()
+                        .last()
+                        .and_then(|line| line.split('\'').nth(1))
+                        .unwrap_or_default()
+                        .to_string(),
+                    Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            Backend::Swww => run("swww", &["query"])?
+                .lines()
+                .next()
+                .and_then(|line| line.split("image: ").nth(1))
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+            Backend::Macos => run(
+                "osascript",
+                &[
+                    "-e",
+                    "tell application \"System Events\" to get picture of current desktop",
+                ],
+            )?
+            .trim()
+            .to_string(),
+            // Nothing else is drawn on our surfaces, the previous wallpaper is left untouched
+            Backend::LayerShell => String::new(),
+        };
+        Ok(Some(value).filter(|value| !value.is_empty()))
+    }
+
+    /// Representation of `path` as returned by `current`
+    fn value_for(&self, path: &Path) -> String {
+        match self {
+            Backend::Gnome => format!("file://{}", path.display()),
+            _ => path.display().to_string(),
+        }
+    }
+
+    /// Set the wallpaper from a value in the backend's own format
+    fn apply(&self, value: &str) -> Result<(), XkcdError> {
+        match self {
+            Backend::Gnome => {
+                for key in ["picture-uri", "picture-uri-dark"] {
+                    run(
+                        "gsettings",
+                        &["set", "org.gnome.desktop.background", key, value],
+                    )?;
+                }
+            }
+            Backend::Feh => {
+                run("feh", &["--bg-fill", value])?;
+            }
+            Backend::Swww => {
+                run("swww", &["img", value])?;
+            }
+            Backend::Macos => {
+                let script = format!(
+                    "tell application \"System Events\" to tell every desktop to set picture to \"{}\"",
+                    value.replace('"', "\\\"")
+                );
+                run("osascript", &["-e", &script])?;
+            }
+            Backend::LayerShell => show_on_layer_shell(Path::new(value))?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+/// Output paths that change on every run, for desktops that ignore a wallpaper whose path is
+/// unchanged
+pub enum AlternateOutput {
+    /// Alternate between `-a` and `-b` variants of the output file
+    Ab,
+    /// Add the Unix time to the output file, the previous one is removed once replaced
+    Timestamp,
+}
+
+impl AlternateOutput {
+    /// Output file template for this run, `second` picks the `-b` file for `Ab`
+    pub fn template(&self, template: &str, second: bool, now: Timestamp) -> String {
+        let suffix = match self {
+            AlternateOutput::Ab if second => "-b".to_string(),
+            AlternateOutput::Ab => "-a".to_string(),
+            AlternateOutput::Timestamp => format!("-{}", now.as_second()),
+        };
+        with_suffix(template, &suffix)
+    }
+}
+
+/// Add `suffix` to the file name in `template`, before its extension
+pub fn with_suffix(template: &str, suffix: &str) -> String {
+    // Before the extension of the file name, not a dot in a directory name
+    let name_start = template.rfind(['/', '\\']).map_or(0, |index| index + 1);
+    match template[name_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let (stem, extension) = template.split_at(name_start + dot);
+            format!("{stem}{suffix}{extension}")
+        }
+        _ => format!("{template}{suffix}"),
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+struct PreviousWallpaper {
+    /// Wallpaper configured before xkcd-wallpaper took over
+    previous: Option<String>,
+    /// Last wallpaper set by xkcd-wallpaper
+    last_set: String,
+}
+
+/// Remembers the wallpaper that was configured before `--set`, per backend
+pub struct WallpaperSetter {
+    backend: Backend,
+    state_file: PathBuf,
+}
+
+impl WallpaperSetter {
+    pub fn new(backend: Backend, state_dir: &Path) -> Self {
+        WallpaperSetter {
+            backend,
+            state_file: state_dir.join(PREVIOUS_FILE),
+        }
+    }
+
+    /// Set the wallpaper, remembering the current one unless it was set by us
+    pub fn set(&self, path: &Path) -> Result<(), XkcdError> {
+        let mut state = self.load()?;
+        let current = self.backend.current()?;
+        let entry = state.entry(self.backend).or_default();
+        if current.as_deref() != Some(entry.last_set.as_str()) {
+            info!("remembering previous wallpaper {:?}", current);
+            entry.previous = current;
+        }
+
+        self.backend.set(path)?;
+        entry.last_set = self.backend.value_for(&std::path::absolute(path)?);
+        self.save(&state)
+    }
+
+    /// Restore the wallpaper configured before the first `set`, returns it if there was one
+    pub fn restore(&self) -> Result<Option<String>, XkcdError> {
+        let mut state = self.load()?;
+        let Some(previous) = state.remove(&self.backend).and_then(|entry| entry.previous) else {
+            return Ok(None);
+        };
+
+        info!("restoring wallpaper {}", previous);
+        self.backend.apply(&previous)?;
+        self.save(&state)?;
+        Ok(Some(previous))
+    }
+
+    fn load(&self) -> Result<BTreeMap<Backend, PreviousWallpaper>, XkcdError> {
+        state::load_json(&self.state_file)
+    }
+
+    fn save(&self, state: &BTreeMap<Backend, PreviousWallpaper>) -> Result<(), XkcdError> {
+        state::save_json(&self.state_file, state)
+    }
+}
+
+#[cfg(all(unix, feature = "wayland"))]
+fn show_on_layer_shell(path: &Path) -> Result<(), XkcdError> {
+    crate::wayland::show(path)
+}
+
+#[cfg(not(all(unix, feature = "wayland")))]
+fn show_on_layer_shell(_path: &Path) -> Result<(), XkcdError> {
+    Err(XkcdError::Setter(
+        "the layer-shell backend requires building with the wayland feature".to_string(),
+    ))
+}
+
+/// Run an external command, returning its standard output
+pub(crate) fn run(program: &str, args: &[&str]) -> Result<String, XkcdError> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|err| XkcdError::Setter(format!("failed to run {program}: {err}")))?;
+    if !output.status.success() {
+        return Err(XkcdError::Setter(format!(
+            "{program} exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Backend::Gnome, "/tmp/a.png", "file:///tmp/a.png")]
+    #[case(Backend::Feh, "/tmp/a.png", "/tmp/a.png")]
+    fn backend_value_for(#[case] backend: Backend, #[case] path: &str, #[case] expected: &str) {
+        assert_eq!(backend.value_for(Path::new(path)), expected);
+    }
+
+    #[test]
+    fn restore_without_previous() {
+        let dir = tempfile::tempdir().unwrap();
+        let setter = WallpaperSetter::new(Backend::Feh, dir.path());
+        assert_eq!(setter.restore().unwrap(), None);
+    }
+
+    #[rstest]
+    #[case(AlternateOutput::Ab, "./%Y-%m-%d_%t.png", false, "./%Y-%m-%d_%t-a.png")]
+    #[case(
+        AlternateOutput::Ab,
+        "/home/me/.cache/wall.png",
+        true,
+        "/home/me/.cache/wall-b.png"
+    )]
+    #[case(
+        AlternateOutput::Ab,
+        "/home/me/.cache/wall",
+        true,
+        "/home/me/.cache/wall-b"
+    )]
+    #[case(AlternateOutput::Timestamp, "wall.jpg", false, "wall-1750000000.jpg")]
+    fn alternate_output_template(
+        #[case] mode: AlternateOutput,
+        #[case] template: &str,
+        #[case] second: bool,
+        #[case] expected: &str,
+    ) {
+        let now = Timestamp::from_second(1_750_000_000).unwrap();
+        assert_eq!(mode.template(template, second, now), expected);
+    }
+}
@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::Path;
+
+use log::info;
+use serde::Deserialize;
+
+use crate::{net, XkcdError};
+
+const BOUNDARY: &str = "xkcd-wallpaper-boundary-7MA4YWxkTrZu0gW";
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// Upload endpoint used by `share`, read from the `[share]` config section
+///
+/// The endpoint receives a `multipart/form-data` POST and must answer with the URL as plain text.
+pub struct ShareConfig {
+    pub endpoint: String,
+    /// Name of the form field holding the file
+    pub field: String,
+}
+
+impl Default for ShareConfig {
+    fn default() -> Self {
+        ShareConfig {
+            endpoint: "https://0x0.st".to_string(),
+            field: "file".to_string(),
+        }
+    }
+}
+
+impl ShareConfig {
+    /// Upload the file at `path`, returning the URL it can be downloaded from
+    pub fn upload(&self, path: &Path) -> Result<String, XkcdError> {
+        let filename = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("wallpaper.png");
+        let body = multipart_body(&self.field, filename, &fs::read(path)?);
+
+        info!("uploading {} to {}", path.display(), self.endpoint);
+        let url = net::post(
+            &self.endpoint,
+            &format!("multipart/form-data; boundary={BOUNDARY}"),
+            &body,
+        )?
+        .body_mut()
+        .read_to_string()?;
+        Ok(url.trim().to_string())
+    }
+}
+
+fn multipart_body(field: &str, filename: &str, contents: &[u8]) -> Vec<u8> {
+    let filename = filename.replace('"', "");
+    let mut body = format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{field}\"; filename=\"{filename}\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(contents);
+    body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multipart_body_format() {
+        let body = multipart_body("file", "a\".png", b"data");
+        let expected = format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.png\"\r\nContent-Type: application/octet-stream\r\n\r\ndata\r\n--{BOUNDARY}--\r\n"
+        );
+        assert_eq!(String::from_utf8(body).unwrap(), expected);
+    }
+}
@@ -0,0 +1,191 @@
+use std::str::FromStr;
+
+use image::{DynamicImage, GrayImage};
+use log::info;
+
+use crate::ScreenDimensions;
+
+/// Comics up to this many pages tall are shrunk onto a single page rather than split
+const MAX_SINGLE_PAGE: f64 = 2.0;
+/// Most pages a comic is split into
+const MAX_PAGES: u32 = 16;
+/// Largest distance a cut is moved to fall between panels, as a part of the page height
+const CUT_WINDOW: f64 = 0.25;
+/// Luminance from which a pixel counts as the comic's white background
+pub(crate) const BLANK_LUMA: u8 = 240;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// Number of pages a tall comic is split into
+pub enum Pages {
+    /// As many as needed for each page to have the shape of the screen
+    #[default]
+    Auto,
+    Count(u32),
+}
+
+impl FromStr for Pages {
+    type Err = String;
+
+    /// Parse `auto` or a number of pages from 1 to 16
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "auto" => Ok(Pages::Auto),
+            count => count
+                .parse()
+                .ok()
+                .filter(|count| (1..=MAX_PAGES).contains(count))
+                .map(Pages::Count)
+                .ok_or_else(|| {
+                    format!("Invalid number of pages '{s}', expected auto or 1 to {MAX_PAGES}")
+                }),
+        }
+    }
+}
+
+impl Pages {
+    /// Number of pages a `width` x `height` comic is split into
+    pub fn count(&self, width: u32, height: u32, screen_dimensions: &ScreenDimensions) -> u32 {
+        match self {
+            Pages::Count(count) => (*count).clamp(1, height.max(1)),
+            Pages::Auto => {
+                // Height of a comic as wide as the page and with the shape of the screen
+                let page_height = width as f64 * screen_dimensions.height as f64
+                    / screen_dimensions.width.max(1) as f64;
+                let pages = height as f64 / page_height.max(1.0);
+                match pages <= MAX_SINGLE_PAGE {
+                    true => 1,
+                    false => (pages.ceil() as u32).min(MAX_PAGES),
+                }
+            }
+        }
+    }
+}
+
+/// Rows where a comic is cut into `count` pages, from 0 to the height of the comic
+///
+/// Cuts are spread evenly, each moved to the middle of the nearest gap between panels, i.e. run
+/// of blank rows, within [`CUT_WINDOW`] of a page.
+pub fn cuts(img: &GrayImage, count: u32) -> Vec<u32> {
+    let height = img.height();
+    let count = count.clamp(1, height.max(1));
+    let page = height as f64 / count as f64;
+    let window = (page * CUT_WINDOW) as u32;
+    let blank = |y: u32| (0..img.width()).all(|x| img.get_pixel(x, y)[0] >= BLANK_LUMA);
+
+    let mut cuts = vec![0];
+    for index in 1..count {
+        let even = (index as f64 * page).round() as u32;
+        let previous = *cuts.last().unwrap_or(&0);
+        let (start, end) = (
+            even.saturating_sub(window).max(previous + 1),
+            (even + window).min(height - 1),
+        );
+        // The middle of the blank run closest to the even cut
+        let mut best: Option<u32> = None;
+        let mut y = start;
+        while y <= end {
+            if !blank(y) {
+                y += 1;
+                continue;
+            }
+            let run_start = y;
+            while y <= end && blank(y) {
+                y += 1;
+            }
+            let middle = (run_start + y - 1).div_ceil(2);
+            if best.is_none_or(|best| middle.abs_diff(even) < best.abs_diff(even)) {
+                best = Some(middle);
+            }
+        }
+        cuts.push(best.unwrap_or(even).max(previous + 1));
+    }
+    cuts.push(height);
+    cuts
+}
+
+/// The comic cut into page-sized segments from top to bottom, itself when it fits on one page
+pub fn split(
+    img: &DynamicImage,
+    pages: Pages,
+    screen_dimensions: &ScreenDimensions,
+) -> Vec<DynamicImage> {
+    let count = pages.count(img.width(), img.height(), screen_dimensions);
+    if count <= 1 {
+        return vec![img.clone()];
+    }
+    let cuts = cuts(&img.to_luma8(), count);
+    info!(
+        "splitting {}x{} comic at rows {:?}",
+        img.width(),
+        img.height(),
+        cuts
+    );
+    cuts.windows(2)
+        .map(|rows| img.crop_imm(0, rows[0], img.width(), rows[1] - rows[0]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+    use rstest::rstest;
+
+    const PORTRAIT: ScreenDimensions = ScreenDimensions {
+        width: 1080,
+        height: 1920,
+    };
+    const LANDSCAPE: ScreenDimensions = ScreenDimensions {
+        width: 1920,
+        height: 1080,
+    };
+
+    #[rstest]
+    #[case(Pages::Auto, (740, 600), &LANDSCAPE, 1)]
+    #[case(Pages::Auto, (740, 4000), &LANDSCAPE, 10)]
+    #[case(Pages::Auto, (740, 4000), &PORTRAIT, 4)]
+    #[case(Pages::Auto, (100, 100_000), &LANDSCAPE, MAX_PAGES)]
+    #[case(Pages::Count(3), (740, 600), &LANDSCAPE, 3)]
+    fn page_count(
+        #[case] pages: Pages,
+        #[case] (width, height): (u32, u32),
+        #[case] screen: &ScreenDimensions,
+        #[case] expected: u32,
+    ) {
+        assert_eq!(pages.count(width, height, screen), expected);
+    }
+
+    #[rstest]
+    #[case("auto", Ok(Pages::Auto))]
+    #[case("4", Ok(Pages::Count(4)))]
+    #[case("0", Err(()))]
+    #[case("17", Err(()))]
+    fn parse_pages(#[case] s: &str, #[case] expected: Result<Pages, ()>) {
+        assert_eq!(s.parse::<Pages>().map_err(|_| ()), expected);
+    }
+
+    #[test]
+    fn cuts_between_panels() {
+        // Three panels of ink separated by blank gutters at rows 30..40 and 75..85
+        let img = GrayImage::from_fn(10, 120, |_, y| {
+            match (30..40).contains(&y) || (75..85).contains(&y) {
+                true => Luma([255]),
+                false => Luma([0]),
+            }
+        });
+        assert_eq!(cuts(&img, 3), [0, 35, 80, 120]);
+        // Without gutters the cuts are even
+        let ink = GrayImage::from_pixel(10, 120, Luma([0]));
+        assert_eq!(cuts(&ink, 3), [0, 40, 80, 120]);
+    }
+
+    #[test]
+    fn split_into_pages() {
+        let img = DynamicImage::ImageLuma8(GrayImage::from_pixel(100, 1000, Luma([0])));
+        let pages = split(&img, Pages::Count(4), &LANDSCAPE);
+        assert_eq!(pages.len(), 4);
+        assert!(pages.iter().all(|page| page.width() == 100));
+        assert_eq!(pages.iter().map(|page| page.height()).sum::<u32>(), 1000);
+        assert_eq!(split(&img, Pages::Count(1), &LANDSCAPE).len(), 1);
+    }
+}
@@ -0,0 +1,118 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use jiff::Timestamp;
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::XkcdError;
+
+const STATE_FILE: &str = "state.json";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+/// Selection and daemon state kept between runs
+pub struct State {
+    /// Comic of the most recently generated wallpaper
+    pub last_seen: Option<u64>,
+    /// Whether the `-b` file is next with `--alternate-output ab`
+    pub second_output: bool,
+    /// Last file written with `--alternate-output`, replaced by the next one
+    pub alternate_output: Option<PathBuf>,
+    pub daemon: DaemonState,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct DaemonState {
+    /// Fingerprint of the newest comic when the daemon last generated a wallpaper
+    pub published: Option<String>,
+    /// Last time the daemon checked for a new comic
+    pub last_check: Option<Timestamp>,
+}
+
+/// [`State`] stored as JSON in the state directory
+pub struct StateStore {
+    path: PathBuf,
+}
+
+impl StateStore {
+    pub fn in_state_dir(state_dir: &Path) -> Self {
+        StateStore {
+            path: state_dir.join(STATE_FILE),
+        }
+    }
+
+    /// Stored state, the default if there is none yet or it cannot be read
+    pub fn load(&self) -> State {
+        load_json(&self.path).unwrap_or_else(|err| {
+            warn!("ignoring state in {}: {}", self.path.display(), err);
+            State::default()
+        })
+    }
+
+    /// Change the stored state with `f`
+    pub fn update(&self, f: impl FnOnce(&mut State)) -> Result<(), XkcdError> {
+        let mut state = self.load();
+        f(&mut state);
+        save_json(&self.path, &state)
+    }
+}
+
+/// Read a JSON file, the default value if it does not exist
+pub(crate) fn load_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T, XkcdError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            serde_json::from_str(&contents).map_err(|err| XkcdError::Other(err.to_string()))
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(T::default()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Write a JSON file through a temporary file, so an interrupted write leaves the old contents
+pub(crate) fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<(), XkcdError> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let contents =
+        serde_json::to_string_pretty(value).map_err(|err| XkcdError::Other(err.to_string()))?;
+
+    info!("writing {}", path.display());
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(contents.as_bytes())?;
+    file.persist(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = StateStore::in_state_dir(&dir.path().join("nested"));
+        assert_eq!(store.load(), State::default());
+
+        store.update(|state| state.last_seen = Some(927)).unwrap();
+        store
+            .update(|state| state.daemon.published = Some("etag: \"abc\"".to_string()))
+            .unwrap();
+
+        let state = store.load();
+        assert_eq!(state.last_seen, Some(927));
+        assert_eq!(state.daemon.published.as_deref(), Some("etag: \"abc\""));
+    }
+
+    #[test]
+    fn malformed_state_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(STATE_FILE), "{not json").unwrap();
+        assert_eq!(
+            StateStore::in_state_dir(dir.path()).load(),
+            State::default()
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use jiff::Timestamp;
+use serde::Serialize;
+
+use crate::history::HistoryEntry;
+use crate::XkcdError;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+/// How often a comic was used for a wallpaper
+pub struct ComicCount {
+    pub num: u64,
+    pub title: String,
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+/// Usage statistics gathered from the cache directory and the history log
+pub struct Stats {
+    pub cache_bytes: u64,
+    pub cached_comics: usize,
+    pub generations: usize,
+    pub unique_comics: usize,
+    pub first_generation: Option<Timestamp>,
+    pub last_generation: Option<Timestamp>,
+    /// Most used comics, most frequent first
+    pub top_comics: Vec<ComicCount>,
+}
+
+impl Stats {
+    /// Gather statistics, keeping the `top` most used comics
+    pub fn collect(
+        cache_dir: &Path,
+        history: &[HistoryEntry],
+        top: usize,
+    ) -> Result<Stats, XkcdError> {
+        let mut counts: HashMap<u64, ComicCount> = HashMap::new();
+        for entry in history {
+            counts
+                .entry(entry.num)
+                .or_insert_with(|| ComicCount {
+                    num: entry.num,
+                    title: entry.title.clone(),
+                    count: 0,
+                })
+                .count += 1;
+        }
+        let unique_comics = counts.len();
+        let mut top_comics: Vec<_> = counts.into_values().collect();
+        top_comics.sort_by(|a, b| b.count.cmp(&a.count).then(a.num.cmp(&b.num)));
+        top_comics.truncate(top);
+
+        Ok(Stats {
+            cache_bytes: dir_size(cache_dir)?,
+            cached_comics: cached_comics(cache_dir)?,
+            generations: history.len(),
+            unique_comics,
+            first_generation: history.iter().map(|entry| entry.timestamp).min(),
+            last_generation: history.iter().map(|entry| entry.timestamp).max(),
+            top_comics,
+        })
+    }
+}
+
+/// Total size of all files below `path`, zero if it does not exist
+fn dir_size(path: &Path) -> Result<u64, XkcdError> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut size = 0;
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+/// Number of distinct comics in the cache, whose files are named after the comic number
+fn cached_comics(cache_dir: &Path) -> Result<usize, XkcdError> {
+    let entries = match fs::read_dir(cache_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut comics = BTreeSet::new();
+    for entry in entries {
+        let path = entry?.path();
+        if let Some(num) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok())
+        {
+            comics.insert(num);
+        }
+    }
+    Ok(comics.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(num: u64, timestamp: &str) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: timestamp.parse().unwrap(),
+            num,
+            title: format!("Comic {num}"),
+            alt: None,
+            options_hash: String::new(),
+            output: "/tmp/wallpaper.png".into(),
+        }
+    }
+
+    #[test]
+    fn stats_collect() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("3084.png"), [0u8; 10]).unwrap();
+        fs::write(dir.path().join("3084.json"), [0u8; 5]).unwrap();
+        fs::write(dir.path().join("42.png"), [0u8; 1]).unwrap();
+        fs::write(dir.path().join("index.json"), [0u8; 2]).unwrap();
+
+        let history = vec![
+            entry(42, "2025-06-21T08:00:00Z"),
+            entry(3084, "2025-06-20T08:00:00Z"),
+            entry(42, "2025-06-22T08:00:00Z"),
+        ];
+        let stats = Stats::collect(dir.path(), &history, 1).unwrap();
+
+        assert_eq!(stats.cache_bytes, 18);
+        assert_eq!(stats.cached_comics, 2);
+        assert_eq!(stats.generations, 3);
+        assert_eq!(stats.unique_comics, 2);
+        assert_eq!(stats.first_generation, Some(history[1].timestamp));
+        assert_eq!(
+            stats.top_comics,
+            vec![ComicCount {
+                num: 42,
+                title: "Comic 42".to_string(),
+                count: 2
+            }]
+        );
+    }
+}
@@ -0,0 +1,546 @@
+use std::fs;
+use std::path::Path;
+
+use image::imageops::overlay;
+use image::{DynamicImage, Rgba, RgbaImage};
+use log::info;
+use serde::{Deserialize, Deserializer};
+
+use crate::background::Background;
+use crate::decoration::Decoration;
+use crate::font::Font;
+use crate::layout::{Anchor, Layout, Length, Margins, ScaleMode};
+use crate::locale::Language;
+use crate::recolor::Recolor;
+use crate::text::TextStyle;
+use crate::theme::{deserialize_background_color, parse_background_color, parse_hex_color};
+use crate::{
+    format_comic, scale_comic, timings, ForegroundColor, Image, ScreenDimensions, XkcdError,
+};
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// Wallpaper built from elements drawn over the background in the order they are listed
+///
+/// ```toml
+/// [[element]]
+/// type = "rect"
+/// y = "80%"
+/// height = "20%"
+/// gradient = ["transparent", "#000000"]
+///
+/// [[element]]
+/// type = "comic"
+/// height = "80%"
+/// scale = "fit"
+///
+/// [[element]]
+/// type = "title"
+/// y = "84%"
+///
+/// [[element]]
+/// type = "date"
+/// x = "98%"
+/// y = "95%"
+/// align = "right"
+/// size = 16
+/// format = "%B %-d, %Y"
+/// ```
+///
+/// Files ending in `.json` hold the same as JSON, `{"element": [{"type": "comic"}]}`.
+/// Horizontal lengths are percentages of the screen width, vertical ones of its height.
+pub struct Template {
+    #[serde(rename = "element")]
+    pub elements: Vec<Element>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+/// Something drawn on the wallpaper
+pub enum Element {
+    Comic(ComicElement),
+    Title(TextElement),
+    /// Alt text, wrapped at half the screen unless the element has a width
+    Alt(TextElement),
+    /// Publish date, written with the element's `format`
+    Date(TextElement),
+    Rect(RectElement),
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// The comic, sized and placed inside an area as the CLI does inside the margins
+pub struct ComicElement {
+    pub x: Length,
+    pub y: Length,
+    pub width: Length,
+    pub height: Length,
+    pub scale: ScaleMode,
+    pub position: Anchor,
+}
+
+impl Default for ComicElement {
+    fn default() -> Self {
+        ComicElement {
+            x: Length::default(),
+            y: Length::default(),
+            width: Length::Percent(100.0),
+            height: Length::Percent(100.0),
+            scale: ScaleMode::default(),
+            position: Anchor::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+/// Which side of a text element its `x` is on
+pub enum Align {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// Text with its top at `y`, wrapped at `width` when set
+pub struct TextElement {
+    pub x: Length,
+    pub y: Length,
+    pub align: Align,
+    pub width: Option<Length>,
+    /// Height of a line in pixels, see [`TextStyle::size`]
+    pub size: u32,
+    /// Color of the text, that of the drawings when unset
+    #[serde(deserialize_with = "deserialize_text_color")]
+    pub color: Option<Rgba<u8>>,
+    /// Placeholders of `--format-filename`, for dates
+    pub format: String,
+}
+
+impl Default for TextElement {
+    fn default() -> Self {
+        TextElement {
+            x: Length::Percent(50.0),
+            y: Length::default(),
+            align: Align::default(),
+            width: None,
+            size: 32,
+            color: None,
+            format: "%F".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+/// Direction a gradient goes in, from its first color to its second
+pub enum Direction {
+    #[default]
+    Vertical,
+    Horizontal,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// A rectangle filled with `color` or `gradient`, blended over what is below it
+pub struct RectElement {
+    pub x: Length,
+    pub y: Length,
+    pub width: Length,
+    pub height: Length,
+    #[serde(deserialize_with = "deserialize_background_color")]
+    pub color: Option<Rgba<u8>>,
+    #[serde(deserialize_with = "deserialize_gradient")]
+    pub gradient: Option<[Rgba<u8>; 2]>,
+    pub direction: Direction,
+}
+
+impl Default for RectElement {
+    fn default() -> Self {
+        RectElement {
+            x: Length::default(),
+            y: Length::default(),
+            width: Length::Percent(100.0),
+            height: Length::Percent(100.0),
+            color: None,
+            gradient: None,
+            direction: Direction::default(),
+        }
+    }
+}
+
+impl RectElement {
+    /// The rectangle at its size on the screen, with its top left corner
+    fn render(&self, screen_dimensions: &ScreenDimensions) -> (RgbaImage, i64, i64) {
+        let (width, height) = (screen_dimensions.width, screen_dimensions.height);
+        let rect_width = self.width.resolve(width).max(1) as u32;
+        let rect_height = self.height.resolve(height).max(1) as u32;
+        let [from, to] = match (self.gradient, self.color) {
+            (Some(gradient), _) => gradient,
+            (None, Some(color)) => [color, color],
+            (None, None) => [Rgba([0, 0, 0, 0]); 2],
+        };
+        let img = RgbaImage::from_fn(rect_width, rect_height, |x, y| {
+            let (position, length) = match self.direction {
+                Direction::Vertical => (y, rect_height),
+                Direction::Horizontal => (x, rect_width),
+            };
+            let t = position as f32 / (length - 1).max(1) as f32;
+            Rgba(std::array::from_fn(|channel| {
+                (from[channel] as f32 + (to[channel] as f32 - from[channel] as f32) * t).round()
+                    as u8
+            }))
+        });
+        (img, self.x.resolve(width), self.y.resolve(height))
+    }
+}
+
+impl Template {
+    /// Read a template from TOML, or JSON for `.json` files
+    pub fn load(path: &Path) -> Result<Template, XkcdError> {
+        info!("loading layout template {}", path.display());
+        let contents = fs::read_to_string(path)?;
+        let json = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        let template = match json {
+            true => serde_json::from_str(&contents).map_err(|err| err.to_string()),
+            false => toml_edit::de::from_str(&contents).map_err(|err| err.to_string()),
+        };
+        template
+            .and_then(Template::validate)
+            .map_err(|err| XkcdError::Config(format!("{}: {}", path.display(), err)))
+    }
+
+    fn validate(self) -> Result<Template, String> {
+        for element in &self.elements {
+            if let Element::Rect(rect) = element {
+                if rect.color.is_some() == rect.gradient.is_some() {
+                    return Err("a rect needs either a color or a gradient".to_string());
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Compose the wallpaper of `comic_img`
+    ///
+    /// The comic is recolored and decorated like [`crate::get_wallpaper_from_comic`] does,
+    /// except for the captions of `decoration`, which are elements here. Text is drawn with
+    /// `font`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn compose(
+        &self,
+        comic_img: Image,
+        fg_color: ForegroundColor,
+        background: impl Into<Background>,
+        screen_dimensions: ScreenDimensions,
+        recolor: Recolor,
+        decoration: Decoration,
+        font: &Font,
+    ) -> Image {
+        let _timer = timings::stage("composite");
+        let background = background.into();
+        let metadata = comic_img.metadata;
+        let resolution = comic_img.resolution;
+        let comic_buffer = recolor.apply(
+            comic_img.img,
+            &fg_color,
+            decoration.fill().unwrap_or(background.fill()),
+        );
+        let background = background.behind(&comic_buffer, &screen_dimensions);
+        let mut canvas = background.canvas(&screen_dimensions);
+        let ink = fg_color.ink();
+        for element in &self.elements {
+            match element {
+                Element::Comic(comic) => {
+                    let layout = comic.layout(&screen_dimensions);
+                    let scaled = scale_comic(comic_buffer.clone(), &layout, &screen_dimensions);
+                    let (x, y) =
+                        layout.position(&screen_dimensions, scaled.width(), scaled.height());
+                    info!("placing comic on the background with {:?}", layout);
+                    for (layer, dx, dy) in decoration.under(scaled.width(), scaled.height()) {
+                        overlay(&mut canvas, &layer, x + dx, y + dy);
+                    }
+                    overlay(&mut canvas, &scaled, x, y);
+                }
+                Element::Title(text) => {
+                    text.draw(&mut canvas, &metadata.safe_title, None, ink, font)
+                }
+                Element::Alt(text) => {
+                    let width = text.width.unwrap_or(Length::Percent(50.0));
+                    text.draw(&mut canvas, &metadata.alt, Some(width), ink, font)
+                }
+                Element::Date(text) => {
+                    let date = format_comic(&text.format, &metadata, Language::from_env());
+                    text.draw(&mut canvas, &date, None, ink, font)
+                }
+                Element::Rect(rect) => {
+                    let (img, x, y) = rect.render(&screen_dimensions);
+                    overlay(&mut canvas, &img, x, y);
+                }
+            }
+        }
+
+        Image {
+            img: DynamicImage::ImageRgba8(canvas),
+            metadata,
+            resolution,
+        }
+    }
+}
+
+impl ComicElement {
+    /// Layout keeping the comic inside the element's area
+    fn layout(&self, screen_dimensions: &ScreenDimensions) -> Layout {
+        let (width, height) = (screen_dimensions.width, screen_dimensions.height);
+        let (x, y) = (self.x.resolve(width), self.y.resolve(height));
+        let right = width as i64 - x - self.width.resolve(width);
+        let bottom = height as i64 - y - self.height.resolve(height);
+        Layout {
+            anchor: self.position,
+            scale: self.scale,
+            margins: Margins {
+                top: Length::Pixels(y),
+                right: Length::Pixels(right),
+                bottom: Length::Pixels(bottom),
+                left: Length::Pixels(x),
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl TextElement {
+    /// Draw `text` on `canvas`, wrapped at `width` or the element's own width
+    fn draw(
+        &self,
+        canvas: &mut RgbaImage,
+        text: &str,
+        width: Option<Length>,
+        ink: Rgba<u8>,
+        font: &Font,
+    ) {
+        let style = TextStyle {
+            size: self.size,
+            color: self.color,
+            font: font.clone(),
+        };
+        let lines = match self.width.or(width) {
+            Some(width) => style.wrap(text, width.resolve(canvas.width()).max(1) as u32),
+            None => vec![text.to_string()],
+        };
+        if lines.iter().all(|line| line.is_empty()) {
+            return;
+        }
+        let img = style.render_lines(&lines, ink);
+        let x = self.x.resolve(canvas.width());
+        let x = match self.align {
+            Align::Left => x,
+            Align::Center => x - img.width() as i64 / 2,
+            Align::Right => x - img.width() as i64,
+        };
+        overlay(canvas, &img, x, self.y.resolve(canvas.height()));
+    }
+}
+
+fn deserialize_text_color<'de, D>(deserializer: D) -> Result<Option<Rgba<u8>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<String>::deserialize(deserializer)?;
+    value
+        .map(|value| parse_hex_color(&value).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+fn deserialize_gradient<'de, D>(deserializer: D) -> Result<Option<[Rgba<u8>; 2]>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some([from, to]) = Option::<[String; 2]>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    let parse = |value: &str| parse_background_color(value).map_err(serde::de::Error::custom);
+    Ok(Some([parse(&from)?, parse(&to)?]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+    use rstest::rstest;
+
+    const SCREEN: ScreenDimensions = ScreenDimensions {
+        width: 200,
+        height: 100,
+    };
+
+    fn comic(width: u32, height: u32) -> Image {
+        Image {
+            img: DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+                width,
+                height,
+                Rgba([0, 0, 0, 255]),
+            )),
+            metadata: Metadata {
+                safe_title: "Pi".to_string(),
+                alt: "make me a".to_string(),
+                year: "2006".to_string(),
+                month: "1".to_string(),
+                day: "4".to_string(),
+                ..Default::default()
+            },
+            resolution: Default::default(),
+        }
+    }
+
+    #[test]
+    fn parse_toml() {
+        let template: Template = toml_edit::de::from_str(
+            r##"
+            [[element]]
+            type = "rect"
+            y = "80%"
+            height = 20
+            gradient = ["transparent", "#000000"]
+            direction = "horizontal"
+
+            [[element]]
+            type = "comic"
+            width = "50%"
+            scale = "fit"
+            position = "top-left"
+
+            [[element]]
+            type = "date"
+            align = "right"
+            color = "#FF0000"
+            format = "%Y"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(
+            template.elements,
+            [
+                Element::Rect(RectElement {
+                    y: Length::Percent(80.0),
+                    height: Length::Pixels(20),
+                    gradient: Some([Rgba([0, 0, 0, 0]), Rgba([0, 0, 0, 255])]),
+                    direction: Direction::Horizontal,
+                    ..Default::default()
+                }),
+                Element::Comic(ComicElement {
+                    width: Length::Percent(50.0),
+                    scale: ScaleMode::Fit,
+                    position: Anchor::TopLeft,
+                    ..Default::default()
+                }),
+                Element::Date(TextElement {
+                    align: Align::Right,
+                    color: Some(Rgba([255, 0, 0, 255])),
+                    format: "%Y".to_string(),
+                    ..Default::default()
+                }),
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case("layout.toml", "[[element]]\ntype = \"title\"\nsize = 8\n")]
+    #[case("layout.json", r#"{"element": [{"type": "title", "size": 8}]}"#)]
+    fn load_formats(#[case] name: &str, #[case] contents: &str) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        let template = Template::load(&path).unwrap();
+        assert_eq!(
+            template.elements,
+            [Element::Title(TextElement {
+                size: 8,
+                ..Default::default()
+            })]
+        );
+    }
+
+    #[rstest]
+    #[case("[[element]]\ntype = \"circle\"\n")]
+    #[case("[[element]]\ntype = \"title\"\nwidht = 10\n")]
+    #[case("[[element]]\ntype = \"comic\"\nscale = \"huge\"\n")]
+    #[case("[[element]]\ntype = \"rect\"\n")]
+    #[case("[[element]]\ntype = \"rect\"\ncolor = \"#000000\"\ngradient = [\"#000000\", \"#FFFFFF\"]\n")]
+    fn load_errors(#[case] contents: &str) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("layout.toml");
+        fs::write(&path, contents).unwrap();
+        assert!(matches!(Template::load(&path), Err(XkcdError::Config(_))));
+    }
+
+    #[test]
+    fn gradient_rect() {
+        let rect = RectElement {
+            x: Length::Pixels(10),
+            y: Length::Percent(50.0),
+            width: Length::Pixels(3),
+            gradient: Some([Rgba([0, 0, 0, 255]), Rgba([200, 100, 0, 255])]),
+            direction: Direction::Horizontal,
+            ..Default::default()
+        };
+        let (img, x, y) = rect.render(&SCREEN);
+        assert_eq!((img.dimensions(), x, y), ((3, 100), 10, 50));
+        assert_eq!(img.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+        assert_eq!(img.get_pixel(1, 0), &Rgba([100, 50, 0, 255]));
+        assert_eq!(img.get_pixel(2, 99), &Rgba([200, 100, 0, 255]));
+    }
+
+    #[test]
+    fn compose_elements() {
+        let bg = Rgba([255, 255, 255, 255]);
+        let template = Template {
+            elements: vec![
+                Element::Rect(RectElement {
+                    y: Length::Percent(90.0),
+                    color: Some(Rgba([0, 0, 255, 255])),
+                    ..Default::default()
+                }),
+                Element::Comic(ComicElement {
+                    width: Length::Percent(50.0),
+                    position: Anchor::TopLeft,
+                    scale: ScaleMode::Fit,
+                    ..Default::default()
+                }),
+                Element::Date(TextElement {
+                    x: Length::Percent(100.0),
+                    y: Length::Pixels(0),
+                    align: Align::Right,
+                    size: 8,
+                    color: Some(Rgba([255, 0, 0, 255])),
+                    format: "%Y".to_string(),
+                    ..Default::default()
+                }),
+            ],
+        };
+        let wallpaper = template.compose(
+            comic(10, 10),
+            ForegroundColor::Dark,
+            bg,
+            SCREEN,
+            Recolor::default(),
+            Decoration::default(),
+            &Font::Builtin,
+        );
+        let img = wallpaper.img.into_rgba8();
+        assert_eq!(img.dimensions(), (200, 100));
+        // The comic fills the left half, over the rect at the bottom
+        assert_eq!(img.get_pixel(0, 99), img.get_pixel(99, 0));
+        assert_ne!(img.get_pixel(0, 99), &bg);
+        assert_eq!(img.get_pixel(150, 95), &Rgba([0, 0, 255, 255]));
+        assert_eq!(img.get_pixel(150, 50), &bg);
+        // "2006" is 23 pixels wide, ending at the right edge
+        let red = Rgba([255, 0, 0, 255]);
+        assert!((177..200).any(|x| (0..8).any(|y| img.get_pixel(x, y) == &red)));
+        assert!((150..177).all(|x| (0..8).all(|y| img.get_pixel(x, y) != &red)));
+    }
+}
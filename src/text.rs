@@ -0,0 +1,340 @@
+use image::imageops;
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::font::Font;
+
+/// Height of a line of the built-in font in font pixels, glyphs are 7 pixels high
+const LINE_HEIGHT: u32 = 8;
+/// Horizontal space taken by a glyph in font pixels, glyphs are 5 pixels wide
+const ADVANCE: u32 = 6;
+/// Extra space between wrapped lines in font pixels
+const LEADING: u32 = 2;
+
+/// 5x7 bitmap glyphs for the printable ASCII characters from ` ` to `~`, one column per byte
+/// with the top pixel in bit 0
+const GLYPHS: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // )
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], // *
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // @
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // f
+    [0x0C, 0x52, 0x52, 0x52, 0x3E], // g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // j
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // l
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // t
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // v
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Side of the comic a line of text goes on
+pub enum TextPosition {
+    #[default]
+    Above,
+    Below,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Look of a piece of text drawn on the wallpaper
+pub struct TextStyle {
+    /// Height of a line in pixels
+    ///
+    /// The built-in font is rounded down to a multiple of its 8 pixels.
+    pub size: u32,
+    /// Color of the text, that of the drawings when `None`
+    pub color: Option<Rgba<u8>>,
+    pub font: Font,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            size: 32,
+            color: None,
+            font: Font::default(),
+        }
+    }
+}
+
+impl TextStyle {
+    /// Size of a built-in font pixel
+    fn scale(&self) -> u32 {
+        (self.size / LINE_HEIGHT).max(1)
+    }
+
+    /// Width of `text` on a single line, in pixels
+    pub fn width(&self, text: &str) -> u32 {
+        match &self.font {
+            Font::Builtin => {
+                let glyphs = text.chars().count() as u32;
+                // No spacing after the last glyph
+                (glyphs * ADVANCE).saturating_sub(1) * self.scale()
+            }
+            Font::TrueType(font) => font.width(text, self.size),
+        }
+    }
+
+    /// Height of a line, in pixels
+    pub fn line_height(&self) -> u32 {
+        match &self.font {
+            Font::Builtin => LINE_HEIGHT * self.scale(),
+            Font::TrueType(_) => self.size.max(1),
+        }
+    }
+
+    /// Space between lines, in pixels
+    fn leading(&self) -> u32 {
+        match &self.font {
+            Font::Builtin => LEADING * self.scale(),
+            Font::TrueType(_) => self.size / 4,
+        }
+    }
+
+    /// `text` broken into lines at most `max_width` pixels wide, between words where possible
+    pub fn wrap(&self, text: &str, max_width: u32) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        let mut line = String::new();
+        for word in text.split_whitespace() {
+            let candidate = match line.is_empty() {
+                true => word.to_string(),
+                false => format!("{} {}", line, word),
+            };
+            if self.width(&candidate) <= max_width {
+                line = candidate;
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            // Words longer than a line are cut
+            for c in word.chars() {
+                line.push(c);
+                if self.width(&line) > max_width && line.chars().count() > 1 {
+                    line.pop();
+                    lines.push(std::mem::replace(&mut line, c.to_string()));
+                }
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// `lines` centered under one another
+    pub fn render_lines(&self, lines: &[String], ink: Rgba<u8>) -> RgbaImage {
+        let width = lines.iter().map(|line| self.width(line)).max().unwrap_or(0);
+        // Descenders touch the next line without some leading
+        let pitch = self.line_height() + self.leading();
+        let height = (pitch * lines.len() as u32).saturating_sub(self.leading());
+        let mut img = RgbaImage::new(width.max(1), height.max(1));
+        for (index, line) in lines.iter().enumerate() {
+            let x = (width - self.width(line)) / 2;
+            let y = index as u32 * pitch;
+            imageops::replace(&mut img, &self.render(line, ink), x as i64, y as i64);
+        }
+        img
+    }
+
+    /// `text` on a single line, in `ink` unless the style has its own color
+    ///
+    /// The built-in font draws dashes and curly quotes as their ASCII counterparts, and other
+    /// characters outside printable ASCII as `?`.
+    pub fn render(&self, text: &str, ink: Rgba<u8>) -> RgbaImage {
+        let color = self.color.unwrap_or(ink);
+        if let Font::TrueType(font) = &self.font {
+            return font.render(text, self.size, color);
+        }
+        let scale = self.scale();
+        let mut img = RgbaImage::new(self.width(text).max(1), self.line_height());
+        for (index, c) in text.chars().enumerate() {
+            let glyph = GLYPHS[builtin_char(c) as usize - ' ' as usize];
+            let glyph_x = index as u32 * ADVANCE * scale;
+            for (column, bits) in glyph.iter().enumerate() {
+                for row in 0..7 {
+                    if bits & (1 << row) == 0 {
+                        continue;
+                    }
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            img.put_pixel(
+                                glyph_x + column as u32 * scale + dx,
+                                row * scale + dy,
+                                color,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        img
+    }
+}
+
+/// Printable ASCII character the built-in font draws for `c`
+fn builtin_char(c: char) -> char {
+    match c {
+        ' '..='~' => c,
+        '\u{A0}' => ' ',
+        '\u{2010}'..='\u{2015}' | '\u{2212}' => '-',
+        '\u{2018}' | '\u{2019}' => '\'',
+        '\u{201C}' | '\u{201D}' => '"',
+        _ => '?',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(8, "", 0)]
+    #[case(8, "I", 5)]
+    #[case(8, "Pi", 11)]
+    #[case(35, "Pi", 44)]
+    fn text_width(#[case] size: u32, #[case] text: &str, #[case] width: u32) {
+        let style = TextStyle {
+            size,
+            ..Default::default()
+        };
+        assert_eq!(style.width(text), width);
+    }
+
+    #[test]
+    fn render_glyphs() {
+        let ink = Rgba([255, 255, 255, 255]);
+        let style = TextStyle {
+            size: 16,
+            ..Default::default()
+        };
+        let img = style.render("-|", ink);
+        assert_eq!(img.dimensions(), (22, 16));
+        // The middle row of `-` and the full height of `|`
+        assert_eq!(img.get_pixel(0, 6), &ink);
+        assert_eq!(img.get_pixel(0, 0)[3], 0);
+        assert_eq!(img.get_pixel(16, 0), &ink);
+        assert_eq!(img.get_pixel(16, 13), &ink);
+        assert_eq!(img.get_pixel(16, 14)[3], 0);
+    }
+
+    #[rstest]
+    // 5 glyphs fit in 29 pixels at size 8
+    #[case("Sudo make me a sandwich", 29, &["Sudo", "make", "me a", "sandw", "ich"])]
+    #[case("  spaced   out ", 100, &["spaced out"])]
+    #[case("", 100, &[])]
+    fn wrap_words(#[case] text: &str, #[case] max_width: u32, #[case] expected: &[&str]) {
+        let style = TextStyle {
+            size: 8,
+            ..Default::default()
+        };
+        assert_eq!(style.wrap(text, max_width), expected);
+    }
+
+    #[test]
+    fn lines_centered() {
+        let ink = Rgba([255, 255, 255, 255]);
+        let style = TextStyle {
+            size: 8,
+            ..Default::default()
+        };
+        let img = style.render_lines(&["|||".to_string(), "|".to_string()], ink);
+        assert_eq!(img.dimensions(), (17, 18));
+        assert_eq!(img.get_pixel(2, 10)[3], 0);
+        assert_eq!(img.get_pixel(8, 10), &ink);
+    }
+
+    #[test]
+    fn unknown_characters() {
+        let style = TextStyle::default();
+        let ink = Rgba([0, 0, 0, 255]);
+        assert_eq!(style.render("é", ink), style.render("?", ink));
+        assert_eq!(style.render("#1 — ‘a’", ink), style.render("#1 - 'a'", ink));
+    }
+}
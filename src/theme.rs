@@ -0,0 +1,378 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use image::Rgba;
+use serde::{Deserialize, Deserializer};
+
+use crate::{ForegroundColor, XkcdError};
+
+#[derive(Clone, Debug, PartialEq)]
+/// Named combination of background and foreground colors
+pub struct Theme {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub background: Rgba<u8>,
+    pub foreground: ForegroundColor,
+}
+
+/// Built-in theme presets
+///
+/// The colorblind presets keep the background on the hue axis each type of color vision
+/// deficiency still perceives well (blue/yellow for red-green deficiencies, red/cyan for
+/// tritanopia) and rely on strong luminance contrast with the drawings. The others use the
+/// background and default foreground colors of popular editor and terminal color schemes.
+pub const THEMES: &[Theme] = &[
+    Theme {
+        name: "cb-deutan",
+        description: "Deuteranopia friendly, light drawings on deep blue",
+        background: Rgba([0x0B, 0x2A, 0x5B, 0xFF]),
+        foreground: ForegroundColor::Light,
+    },
+    Theme {
+        name: "cb-protan",
+        description: "Protanopia friendly, dark drawings on pale yellow",
+        background: Rgba([0xF4, 0xE4, 0x9C, 0xFF]),
+        foreground: ForegroundColor::Dark,
+    },
+    Theme {
+        name: "cb-tritan",
+        description: "Tritanopia friendly, light drawings on dark red",
+        background: Rgba([0x5C, 0x0F, 0x14, 0xFF]),
+        foreground: ForegroundColor::Light,
+    },
+    Theme {
+        name: "nord",
+        description: "Nord, snow storm drawings on polar night",
+        background: Rgba([0x2E, 0x34, 0x40, 0xFF]),
+        foreground: ForegroundColor::Custom(Rgba([0xD8, 0xDE, 0xE9, 0xFF])),
+    },
+    Theme {
+        name: "gruvbox",
+        description: "Gruvbox dark, cream drawings on dark gray",
+        background: Rgba([0x28, 0x28, 0x28, 0xFF]),
+        foreground: ForegroundColor::Custom(Rgba([0xEB, 0xDB, 0xB2, 0xFF])),
+    },
+    Theme {
+        name: "gruvbox-light",
+        description: "Gruvbox light, dark gray drawings on cream",
+        background: Rgba([0xFB, 0xF1, 0xC7, 0xFF]),
+        foreground: ForegroundColor::Custom(Rgba([0x3C, 0x38, 0x36, 0xFF])),
+    },
+    Theme {
+        name: "catppuccin",
+        description: "Catppuccin Mocha, text color drawings on base",
+        background: Rgba([0x1E, 0x1E, 0x2E, 0xFF]),
+        foreground: ForegroundColor::Custom(Rgba([0xCD, 0xD6, 0xF4, 0xFF])),
+    },
+    Theme {
+        name: "catppuccin-latte",
+        description: "Catppuccin Latte, text color drawings on base",
+        background: Rgba([0xEF, 0xF1, 0xF5, 0xFF]),
+        foreground: ForegroundColor::Custom(Rgba([0x4C, 0x4F, 0x69, 0xFF])),
+    },
+    Theme {
+        name: "solarized-dark",
+        description: "Solarized dark, base0 drawings on base03",
+        background: Rgba([0x00, 0x2B, 0x36, 0xFF]),
+        foreground: ForegroundColor::Custom(Rgba([0x83, 0x94, 0x96, 0xFF])),
+    },
+    Theme {
+        name: "solarized-light",
+        description: "Solarized light, base00 drawings on base3",
+        background: Rgba([0xFD, 0xF6, 0xE3, 0xFF]),
+        foreground: ForegroundColor::Custom(Rgba([0x65, 0x7B, 0x83, 0xFF])),
+    },
+    Theme {
+        name: "dracula",
+        description: "Dracula, foreground drawings on background",
+        background: Rgba([0x28, 0x2A, 0x36, 0xFF]),
+        foreground: ForegroundColor::Custom(Rgba([0xF8, 0xF8, 0xF2, 0xFF])),
+    },
+];
+
+impl Theme {
+    /// Look up a built-in theme by name
+    pub fn by_name(name: &str) -> Option<&'static Theme> {
+        THEMES
+            .iter()
+            .find(|theme| theme.name.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// Colors from the config file, explicit `bg` and `fg` take precedence over `theme`
+pub struct Palette {
+    pub theme: Option<String>,
+    #[serde(deserialize_with = "deserialize_background_color")]
+    pub bg: Option<Rgba<u8>>,
+    pub fg: Option<ForegroundColor>,
+}
+
+impl Palette {
+    /// Resolve the palette into optional background and foreground colors
+    pub fn resolve(&self) -> Result<(Option<Rgba<u8>>, Option<ForegroundColor>), XkcdError> {
+        let theme = match &self.theme {
+            Some(name) => Some(
+                Theme::by_name(name)
+                    .ok_or_else(|| XkcdError::Config(format!("unknown theme '{name}'")))?,
+            ),
+            None => None,
+        };
+
+        Ok((
+            self.bg.or(theme.map(|theme| theme.background)),
+            self.fg
+                .clone()
+                .or(theme.map(|theme| theme.foreground.clone())),
+        ))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Colors of a Base16 or Base24 scheme file, by slot name such as `base00`
+pub struct Base16Scheme {
+    pub colors: BTreeMap<String, Rgba<u8>>,
+}
+
+impl Base16Scheme {
+    pub fn load(path: &Path) -> Result<Self, XkcdError> {
+        let contents = fs::read_to_string(path)?;
+        Base16Scheme::parse(&contents)
+            .map_err(|err| XkcdError::Config(format!("{}: {}", path.display(), err)))
+    }
+
+    /// Read the `baseXX: "RRGGBB"` lines of a scheme, other keys such as `scheme` and
+    /// `author` are ignored
+    ///
+    /// Both the classic flat layout and the newer one nesting the colors under `palette:` are
+    /// understood, a YAML parser is not needed for either.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut colors = BTreeMap::new();
+        for line in contents.lines() {
+            let line = line.split(" #").next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().trim_matches(['"', '\'']).to_lowercase();
+            if !is_slot(&key) {
+                continue;
+            }
+            let value = value.trim().trim_matches(['"', '\'']);
+            let color = parse_hex_color(value).map_err(|err| format!("{key}: {err}"))?;
+            colors.insert(key, color);
+        }
+        match colors.is_empty() {
+            true => Err("no base00 to base0F colors found".to_string()),
+            false => Ok(Base16Scheme { colors }),
+        }
+    }
+
+    /// Background and foreground from the slots picked by `slots`
+    pub fn palette(&self, slots: &SchemeSlots) -> Result<(Rgba<u8>, ForegroundColor), XkcdError> {
+        let color = |slot: &str| {
+            self.colors
+                .get(slot)
+                .copied()
+                .ok_or_else(|| XkcdError::Config(format!("scheme has no {slot}")))
+        };
+        Ok((
+            color(&slots.bg)?,
+            ForegroundColor::Custom(color(&slots.fg)?),
+        ))
+    }
+}
+
+/// `base00` to `base0f`, and `base10` to `base17` of Base24
+fn is_slot(key: &str) -> bool {
+    key.strip_prefix("base").is_some_and(|digits| {
+        digits.len() == 2 && u8::from_str_radix(digits, 16).is_ok_and(|slot| slot <= 0x17)
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Slots of a [`Base16Scheme`] used for the background and the drawings, written `BG,FG`
+pub struct SchemeSlots {
+    pub bg: String,
+    pub fg: String,
+}
+
+impl Default for SchemeSlots {
+    /// The default background and foreground of Base16
+    fn default() -> Self {
+        SchemeSlots {
+            bg: "base00".to_string(),
+            fg: "base05".to_string(),
+        }
+    }
+}
+
+impl FromStr for SchemeSlots {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let slot = |value: &str| {
+            let value = value.trim().to_lowercase();
+            match is_slot(&value) {
+                true => Ok(value),
+                false => Err(format!("Invalid slot '{value}', expected base00 to base17")),
+            }
+        };
+        match s.split_once(',') {
+            Some((bg, fg)) => Ok(SchemeSlots {
+                bg: slot(bg)?,
+                fg: slot(fg)?,
+            }),
+            None => Err(format!(
+                "Invalid slots '{s}', expected BG,FG like base00,base05"
+            )),
+        }
+    }
+}
+
+pub(crate) fn deserialize_background_color<'de, D>(
+    deserializer: D,
+) -> Result<Option<Rgba<u8>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<String>::deserialize(deserializer)?;
+    value
+        .map(|value| parse_background_color(&value).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Fully transparent background, only the drawings of the comic are opaque
+pub const TRANSPARENT: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+/// Parse a background colour, “#RRGGBB” or “transparent”
+pub fn parse_background_color(s: &str) -> Result<image::Rgba<u8>, String> {
+    match s.eq_ignore_ascii_case("transparent") {
+        true => Ok(TRANSPARENT),
+        false => parse_hex_color(s),
+    }
+}
+
+/// Parse a colour in “#RRGGBB”
+pub fn parse_hex_color(s: &str) -> Result<image::Rgba<u8>, String> {
+    let hex = s.trim_start_matches('#');
+    let full = match hex.len() {
+        6 => format!("{hex}FF"),
+        _ => return Err("Hex colour must be 6 hex digits (e.g. #1e90ff)".into()),
+    };
+    let v = u32::from_str_radix(&full, 16).map_err(|_| "Invalid hex digits")?;
+
+    Ok(image::Rgba([
+        ((v >> 24) & 0xFF) as u8, // R
+        ((v >> 16) & 0xFF) as u8, // G
+        ((v >> 8) & 0xFF) as u8,  // B
+        (v & 0xFF) as u8,         // A
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("cb-deutan")]
+    #[case("CB-Tritan")]
+    #[case("nord")]
+    #[case("Solarized-Light")]
+    fn theme_by_name_ok(#[case] name: &str) {
+        assert!(Theme::by_name(name).is_some());
+    }
+
+    #[test]
+    fn theme_by_name_missing() {
+        assert!(Theme::by_name("does-not-exist").is_none());
+    }
+
+    #[rstest]
+    #[case("#FF0000", 255, 0, 0)]
+    #[case("FF0000", 255, 0, 0)]
+    #[case("#FF69B4", 255, 105, 180)]
+    fn hex_parse_ok(#[case] input: &str, #[case] r: u8, #[case] g: u8, #[case] b: u8) {
+        let rgba = image::Rgba([r, g, b, 255]);
+        assert_eq!(parse_hex_color(input), Ok(rgba));
+    }
+
+    #[rstest]
+    #[case("FF00")]
+    #[case("ZZ0000")]
+    #[case("")]
+    fn hex_parse_error(#[case] input: &str) {
+        assert!(parse_hex_color(input).is_err())
+    }
+
+    #[rstest]
+    #[case("transparent", TRANSPARENT)]
+    #[case("Transparent", TRANSPARENT)]
+    #[case("#1F241F", Rgba([0x1F, 0x24, 0x1F, 0xFF]))]
+    fn background_parse(#[case] input: &str, #[case] expected: Rgba<u8>) {
+        assert_eq!(parse_background_color(input), Ok(expected));
+    }
+
+    #[rstest]
+    #[case("fg = \"dark\"", ForegroundColor::Dark)]
+    #[case("fg = \"#88C0D0\"", ForegroundColor::Custom(Rgba([0x88, 0xC0, 0xD0, 0xFF])))]
+    fn palette_foreground(#[case] toml: &str, #[case] expected: ForegroundColor) {
+        let palette: Palette = toml_edit::de::from_str(toml).unwrap();
+        assert_eq!(palette.fg, Some(expected.clone()));
+        assert_eq!(String::from(expected.clone()).parse(), Ok(expected));
+    }
+
+    const NORD: &str = r#"
+scheme: "Nord"
+author: "arcticicestudio"
+base00: "2E3440" # polar night
+base05: "E5E9F0"
+base0D: "81A1C1"
+"#;
+
+    #[test]
+    fn base16_scheme_palette() {
+        let scheme = Base16Scheme::parse(NORD).unwrap();
+        assert_eq!(scheme.colors.len(), 3);
+        assert_eq!(
+            scheme.palette(&SchemeSlots::default()).unwrap(),
+            (
+                Rgba([0x2E, 0x34, 0x40, 0xFF]),
+                ForegroundColor::Custom(Rgba([0xE5, 0xE9, 0xF0, 0xFF]))
+            )
+        );
+        let slots = "base00,base0D".parse().unwrap();
+        assert_eq!(
+            scheme.palette(&slots).unwrap().1,
+            ForegroundColor::Custom(Rgba([0x81, 0xA1, 0xC1, 0xFF]))
+        );
+        assert!(scheme.palette(&"base00,base17".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn base24_scheme_with_palette_section() {
+        let scheme = Base16Scheme::parse(
+            "system: base24\npalette:\n  base00: '#1E1E2E'\n  base12: '#F38BA8'\n",
+        )
+        .unwrap();
+        assert_eq!(scheme.colors["base12"], Rgba([0xF3, 0x8B, 0xA8, 0xFF]));
+    }
+
+    #[rstest]
+    #[case("scheme: x")]
+    #[case("base00: \"nothex\"")]
+    fn base16_scheme_error(#[case] contents: &str) {
+        assert!(Base16Scheme::parse(contents).is_err());
+    }
+
+    #[rstest]
+    #[case("base00")]
+    #[case("base00,base18")]
+    #[case("bg,fg")]
+    fn scheme_slots_error(#[case] input: &str) {
+        assert!(input.parse::<SchemeSlots>().is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use image::imageops::{self, overlay, FilterType};
+use image::DynamicImage;
+use log::info;
+
+use crate::background::Background;
+use crate::decoration::Decoration;
+use crate::layout::Length;
+use crate::recolor::Recolor;
+use crate::{timings, ForegroundColor, Image, ScreenDimensions};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The comic repeated across the whole screen, like a classic tiled wallpaper
+pub struct Tiling {
+    /// Size of the tiles relative to the comic
+    pub scale: f32,
+    /// Pixels between neighbouring tiles
+    pub gap: u32,
+    /// Horizontal shift of every other row, in pixels or percent of the distance between tiles
+    pub offset: Length,
+}
+
+impl Default for Tiling {
+    fn default() -> Self {
+        Tiling {
+            scale: 1.0,
+            gap: 0,
+            offset: Length::default(),
+        }
+    }
+}
+
+impl Tiling {
+    /// Top left corners of `width` x `height` tiles covering the screen
+    ///
+    /// A tile is centered on the screen and the others repeat around it, so the pattern is
+    /// symmetric whatever the size of the tiles.
+    pub fn positions(
+        &self,
+        width: u32,
+        height: u32,
+        screen_dimensions: &ScreenDimensions,
+    ) -> Vec<(i64, i64)> {
+        let (screen_width, screen_height) = (
+            screen_dimensions.width as i64,
+            screen_dimensions.height as i64,
+        );
+        let step_x = (width + self.gap).max(1) as i64;
+        let step_y = (height + self.gap).max(1) as i64;
+        let (center_x, center_y) = (
+            (screen_width - width as i64) / 2,
+            (screen_height - height as i64) / 2,
+        );
+        let shift = self.offset.resolve(step_x as u32);
+
+        let mut positions = Vec::new();
+        let mut y = center_y.rem_euclid(step_y) - step_y;
+        while y < screen_height {
+            // Rows are counted from the centered one, which is never shifted
+            let row = (y - center_y) / step_y;
+            let row_x = match row.rem_euclid(2) {
+                0 => center_x,
+                _ => center_x + shift,
+            };
+            let mut x = row_x.rem_euclid(step_x) - step_x;
+            while x < screen_width {
+                if x + width as i64 > 0 && y + height as i64 > 0 {
+                    positions.push((x, y));
+                }
+                x += step_x;
+            }
+            y += step_y;
+        }
+        positions
+    }
+
+    /// Wallpaper with the comic repeated across the screen
+    ///
+    /// The comic is recolored and decorated like [`crate::get_wallpaper_from_comic`] does,
+    /// without captions, and the decorations of neighbouring tiles keep the gap between them.
+    pub fn compose(
+        &self,
+        comic_img: Image,
+        fg_color: ForegroundColor,
+        background: impl Into<Background>,
+        screen_dimensions: ScreenDimensions,
+        recolor: Recolor,
+        decoration: Decoration,
+    ) -> Image {
+        let _timer = timings::stage("composite");
+        let background = background.into();
+        let metadata = comic_img.metadata;
+        let resolution = comic_img.resolution;
+        let mut comic_buffer = recolor.apply(
+            comic_img.img,
+            &fg_color,
+            decoration.fill().unwrap_or(background.fill()),
+        );
+        let background = background.behind(&comic_buffer, &screen_dimensions);
+        let (width, height) = comic_buffer.dimensions();
+        let side = |length: u32| ((length as f32 * self.scale).round() as u32).max(1);
+        let (tile_width, tile_height) = (side(width), side(height));
+        if (tile_width, tile_height) != (width, height) {
+            info!(
+                "scaling comic from {}x{} to {}x{} tiles",
+                width, height, tile_width, tile_height
+            );
+            comic_buffer =
+                imageops::resize(&comic_buffer, tile_width, tile_height, FilterType::Lanczos3);
+        }
+
+        let extent = decoration.extent();
+        let tiling = Tiling {
+            gap: self.gap + 2 * extent,
+            ..*self
+        };
+        let positions = tiling.positions(tile_width, tile_height, &screen_dimensions);
+        info!("repeating comic in {} tiles", positions.len());
+        let under = decoration.under(tile_width, tile_height);
+        let mut canvas = background.canvas(&screen_dimensions);
+        for (x, y) in positions {
+            for (layer, dx, dy) in &under {
+                overlay(&mut canvas, layer, x + dx, y + dy);
+            }
+            overlay(&mut canvas, &comic_buffer, x, y);
+        }
+
+        Image {
+            img: DynamicImage::ImageRgba8(canvas),
+            metadata,
+            resolution,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+    use image::{Rgba, RgbaImage};
+
+    const SCREEN: ScreenDimensions = ScreenDimensions {
+        width: 100,
+        height: 60,
+    };
+
+    #[test]
+    fn tiles_cover_screen() {
+        let tiling = Tiling {
+            gap: 10,
+            ..Default::default()
+        };
+        let positions = tiling.positions(30, 20, &SCREEN);
+        // One tile in the middle of the screen
+        assert!(positions.contains(&(35, 20)));
+        assert!(positions.contains(&(-5, -10)));
+        assert!(positions.contains(&(75, 50)));
+        // Every pixel is in a tile or a gap next to one, none of the tiles is off screen
+        assert_eq!(positions.len(), 3 * 3);
+        for &(x, y) in &positions {
+            assert!(x > -30 && x < 100 && y > -20 && y < 60);
+        }
+    }
+
+    #[test]
+    fn offset_rows() {
+        let tiling = Tiling {
+            gap: 0,
+            offset: Length::Percent(50.0),
+            ..Default::default()
+        };
+        let positions = tiling.positions(40, 20, &SCREEN);
+        let row = |y: i64| {
+            positions
+                .iter()
+                .filter(|position| position.1 == y)
+                .map(|position| position.0)
+                .collect::<Vec<_>>()
+        };
+        // The centered row is unshifted, the ones above and below move by half a tile
+        assert_eq!(row(20), [-10, 30, 70]);
+        assert_eq!(row(0), [-30, 10, 50, 90]);
+        assert_eq!(row(40), [-30, 10, 50, 90]);
+    }
+
+    #[test]
+    fn compose_tiles() {
+        let (bg, ink) = (Rgba([255, 255, 255, 255]), Rgba([0, 0, 0, 255]));
+        let comic = Image {
+            img: DynamicImage::ImageRgba8(RgbaImage::from_pixel(20, 20, ink)),
+            metadata: Metadata {
+                num: 7,
+                ..Default::default()
+            },
+            resolution: Default::default(),
+        };
+        let tiling = Tiling {
+            scale: 0.5,
+            gap: 10,
+            ..Default::default()
+        };
+        let wallpaper = tiling.compose(
+            comic,
+            ForegroundColor::Dark,
+            bg,
+            SCREEN,
+            Recolor::default(),
+            Decoration::default(),
+        );
+        assert_eq!(wallpaper.metadata.num, 7);
+        let img = wallpaper.img.into_rgba8();
+        // 10 pixel tiles every 20 pixels, one of them centered at (50, 30)
+        for x in [5, 25, 45, 65, 85] {
+            assert_eq!(img.get_pixel(x, 25), &ink, "x = {x}");
+            assert_eq!(img.get_pixel(x + 10, 25), &bg, "x = {}", x + 10);
+        }
+        assert_eq!(img.get_pixel(45, 5), &ink);
+        assert_eq!(img.get_pixel(45, 15), &bg);
+    }
+}
@@ -0,0 +1,76 @@
+use std::cell::RefCell;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use crate::progress;
+
+thread_local! {
+    static STAGES: RefCell<Vec<(&'static str, Duration)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Measures a pipeline stage until dropped, see [`stage`]
+pub struct StageTimer {
+    name: &'static str,
+    start: Instant,
+}
+
+impl Drop for StageTimer {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        STAGES.with(|stages| stages.borrow_mut().push((self.name, elapsed)));
+        progress::stage_finished(self.name, elapsed);
+    }
+}
+
+/// Start timing a stage, the time is recorded when the returned guard is dropped
+///
+/// The start and end of the stage are also reported to the [`progress`] sink.
+pub fn stage(name: &'static str) -> StageTimer {
+    progress::stage_started(name);
+    StageTimer {
+        name,
+        start: Instant::now(),
+    }
+}
+
+/// Take the stages recorded on this thread, durations of repeated stages are summed
+pub fn take() -> Vec<(&'static str, Duration)> {
+    let stages = STAGES.with(|stages| std::mem::take(&mut *stages.borrow_mut()));
+    let mut totals: Vec<(&'static str, Duration)> = Vec::new();
+    for (name, duration) in stages {
+        match totals
+            .iter_mut()
+            .find(|(total_name, _)| *total_name == name)
+        {
+            Some((_, total)) => *total += duration,
+            None => totals.push((name, duration)),
+        }
+    }
+    totals
+}
+
+/// Peak resident set size of the process in bytes, if the platform exposes it
+pub fn peak_rss() -> Option<u64> {
+    // VmHWM is the high water mark of the resident set, in kB
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stages_are_summed() {
+        take();
+        drop(stage("decode"));
+        drop(stage("encode"));
+        drop(stage("decode"));
+
+        let names: Vec<_> = take().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["decode", "encode"]);
+        assert!(take().is_empty());
+    }
+}
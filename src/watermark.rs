@@ -0,0 +1,120 @@
+use image::imageops;
+use image::{Rgba, RgbaImage};
+use log::info;
+
+use crate::effects;
+use crate::layout::Anchor;
+use crate::text::TextStyle;
+use crate::ScreenDimensions;
+
+#[derive(Clone, Debug, PartialEq)]
+/// Fixed text drawn over the wallpaper, e.g. to mark whose machines it is for
+pub struct Watermark {
+    /// Text of the watermark, one line per `\n`
+    pub text: String,
+    pub position: Anchor,
+    /// Text in the color of the drawings unless the style has its own color
+    pub style: TextStyle,
+    /// Opacity of the text, from 0 to 1
+    pub opacity: f32,
+    /// Counterclockwise rotation in degrees
+    pub rotation: f32,
+}
+
+impl Default for Watermark {
+    fn default() -> Self {
+        Watermark {
+            text: String::new(),
+            position: Anchor::Bottom,
+            style: TextStyle {
+                size: 24,
+                ..Default::default()
+            },
+            opacity: 0.3,
+            rotation: 0.0,
+        }
+    }
+}
+
+impl Watermark {
+    /// The watermark on its own, in `ink` unless the style has its own color
+    pub fn render(&self, ink: Rgba<u8>) -> RgbaImage {
+        let lines = self.text.lines().map(str::to_string).collect::<Vec<_>>();
+        let mut img = self.style.render_lines(&lines, ink);
+        if self.rotation % 360.0 != 0.0 {
+            img = effects::rotate(&img, self.rotation);
+        }
+        let opacity = self.opacity.clamp(0.0, 1.0);
+        for pixel in img.pixels_mut() {
+            pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+        }
+        img
+    }
+
+    /// Draw the watermark at its position on `img`
+    pub fn overlay(&self, img: &mut RgbaImage, ink: Rgba<u8>) {
+        let watermark = self.render(ink);
+        let (width, height) = img.dimensions();
+        let (x, y) = self.position.overlay_position(
+            &ScreenDimensions { width, height },
+            watermark.width(),
+            watermark.height(),
+        );
+        info!(
+            "drawing {}x{} watermark at {},{}",
+            watermark.width(),
+            watermark.height(),
+            x,
+            y
+        );
+        imageops::overlay(img, &watermark, x, y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watermark(text: &str) -> Watermark {
+        Watermark {
+            text: text.to_string(),
+            style: TextStyle {
+                size: 8,
+                ..Default::default()
+            },
+            opacity: 1.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lines_and_rotation() {
+        let ink = Rgba([255, 255, 255, 255]);
+        assert_eq!(watermark("II").render(ink).dimensions(), (11, 8));
+        assert_eq!(watermark("II\nI").render(ink).dimensions(), (11, 18));
+        let rotated = Watermark {
+            rotation: 90.0,
+            ..watermark("II")
+        };
+        assert_eq!(rotated.render(ink).dimensions(), (8, 11));
+    }
+
+    #[test]
+    fn faint_at_bottom() {
+        let watermark = Watermark {
+            opacity: 0.5,
+            style: TextStyle {
+                size: 8,
+                color: Some(Rgba([255, 0, 0, 255])),
+                ..Default::default()
+            },
+            ..watermark("-")
+        };
+        let mut img = RgbaImage::from_pixel(100, 50, Rgba([0, 0, 0, 0]));
+        watermark.overlay(&mut img, Rgba([255, 255, 255, 255]));
+        // Centered a pixel above the bottom edge, the bar of `-` is on its fourth row
+        assert_eq!(img.get_pixel(47, 44), &Rgba([255, 0, 0, 128]));
+        assert_eq!(img.get_pixel(46, 44)[3], 0);
+        assert_eq!(img.get_pixel(47, 43)[3], 0);
+    }
+}
@@ -0,0 +1,463 @@
+//! Built-in wlr-layer-shell renderer, enabled with the `wayland` feature
+//!
+//! Every output gets a surface on the background layer showing the wallpaper, scaled to cover
+//! it. Outputs plugged in later get one too, and scale changes are followed, until the process
+//! exits or the compositor goes away.
+
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use log::{error, info, warn};
+use smithay_client_toolkit::compositor::{CompositorHandler, CompositorState};
+use smithay_client_toolkit::output::{OutputHandler, OutputState};
+use smithay_client_toolkit::registry::{ProvidesRegistryState, RegistryState};
+use smithay_client_toolkit::shell::wlr_layer::{
+    Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
+    LayerSurfaceConfigure,
+};
+use smithay_client_toolkit::shell::WaylandSurface;
+use smithay_client_toolkit::shm::slot::{Buffer, SlotPool};
+use smithay_client_toolkit::shm::{Shm, ShmHandler};
+use smithay_client_toolkit::{
+    delegate_compositor, delegate_layer, delegate_output, delegate_registry, delegate_shm,
+    registry_handlers,
+};
+use wayland_client::globals::registry_queue_init;
+use wayland_client::protocol::{wl_callback, wl_output, wl_shm, wl_surface};
+use wayland_client::{Connection, Dispatch, EventQueue, QueueHandle};
+
+use crate::XkcdError;
+
+/// Renderer thread and the channel feeding it new wallpapers
+struct Handle {
+    images: Sender<RgbaImage>,
+    connection: Connection,
+    queue: QueueHandle<Renderer>,
+    thread: Option<JoinHandle<()>>,
+}
+
+static HANDLE: Mutex<Option<Handle>> = Mutex::new(None);
+
+/// Show the image at `path` on every output, connecting to the compositor on first use
+///
+/// The wallpaper stays visible while the process runs, see [`wait`].
+pub fn show(path: &Path) -> Result<(), XkcdError> {
+    let image = image::open(path)?.into_rgba8();
+    let mut handle = HANDLE.lock().unwrap_or_else(|err| err.into_inner());
+    if handle.is_none() {
+        let (images, receiver) = mpsc::channel();
+        let (renderer, event_queue, connection) = Renderer::connect(receiver)?;
+        let queue = event_queue.handle();
+        let thread = thread::spawn(move || renderer.run(event_queue));
+        *handle = Some(Handle {
+            images,
+            connection,
+            queue,
+            thread: Some(thread),
+        });
+    }
+
+    info!("showing {} on layer-shell surfaces", path.display());
+    let stopped = || XkcdError::Setter("the layer-shell renderer has stopped".to_string());
+    let handle = handle.as_ref().ok_or_else(stopped)?;
+    handle.images.send(image).map_err(|_| stopped())?;
+    // The renderer picks the image up when the compositor answers the sync
+    handle.connection.display().sync(&handle.queue, NewImage);
+    handle.connection.flush().map_err(|_| stopped())
+}
+
+/// Block until the renderer stops, which happens when the compositor goes away
+pub fn wait() {
+    let thread = HANDLE
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .as_mut()
+        .and_then(|handle| handle.thread.take());
+    if let Some(thread) = thread {
+        let _ = thread.join();
+    }
+}
+
+/// Marks the `wl_display.sync` callback sent along with a new wallpaper
+struct NewImage;
+
+/// Layer surface shown on one output
+struct Surface {
+    output: wl_output::WlOutput,
+    layer: LayerSurface,
+    size: Option<(u32, u32)>,
+    /// Kept until the next wallpaper replaces it, the compositor may still read from it
+    buffer: Option<Buffer>,
+}
+
+struct Renderer {
+    registry: RegistryState,
+    outputs: OutputState,
+    compositor: CompositorState,
+    layer_shell: LayerShell,
+    shm: Shm,
+    pool: SlotPool,
+    surfaces: Vec<Surface>,
+    images: Receiver<RgbaImage>,
+    image: Option<RgbaImage>,
+}
+
+impl Renderer {
+    /// Connect and bind the globals needed for background surfaces
+    fn connect(
+        images: Receiver<RgbaImage>,
+    ) -> Result<(Renderer, EventQueue<Renderer>, Connection), XkcdError> {
+        let failed = |err: &dyn std::fmt::Display| {
+            XkcdError::Setter(format!("could not connect to the compositor: {err}"))
+        };
+        let connection = Connection::connect_to_env().map_err(|err| failed(&err))?;
+        let (globals, event_queue) =
+            registry_queue_init(&connection).map_err(|err| failed(&err))?;
+        let queue = event_queue.handle();
+        let missing = |interface: &str| {
+            XkcdError::Setter(format!("the compositor does not support {interface}"))
+        };
+
+        let compositor =
+            CompositorState::bind(&globals, &queue).map_err(|_| missing("wl_compositor"))?;
+        let layer_shell =
+            LayerShell::bind(&globals, &queue).map_err(|_| missing("wlr-layer-shell"))?;
+        let shm = Shm::bind(&globals, &queue).map_err(|_| missing("wl_shm"))?;
+        let pool = SlotPool::new(1, &shm).map_err(|err| failed(&err))?;
+        let renderer = Renderer {
+            registry: RegistryState::new(&globals),
+            outputs: OutputState::new(&globals, &queue),
+            compositor,
+            layer_shell,
+            shm,
+            pool,
+            surfaces: Vec::new(),
+            images,
+            image: None,
+        };
+        Ok((renderer, event_queue, connection))
+    }
+
+    /// Answer the compositor until it goes away or the process exits
+    fn run(mut self, mut event_queue: EventQueue<Renderer>) {
+        loop {
+            if let Err(err) = event_queue.blocking_dispatch(&mut self) {
+                error!("layer-shell renderer stopped: {}", err);
+                return;
+            }
+        }
+    }
+
+    fn add_output(&mut self, queue: &QueueHandle<Self>, output: wl_output::WlOutput) {
+        let surface = self.compositor.create_surface(queue);
+        let layer = self.layer_shell.create_layer_surface(
+            queue,
+            surface,
+            Layer::Background,
+            Some("wallpaper"),
+            Some(&output),
+        );
+        // A zero size with all edges anchored lets the compositor pick the output size
+        layer.set_size(0, 0);
+        layer.set_anchor(Anchor::all());
+        layer.set_exclusive_zone(-1);
+        layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+        layer.commit();
+
+        info!(
+            "created background surface for output {}",
+            self.describe(&output)
+        );
+        self.surfaces.push(Surface {
+            output,
+            layer,
+            size: None,
+            buffer: None,
+        });
+    }
+
+    fn describe(&self, output: &wl_output::WlOutput) -> String {
+        self.outputs
+            .info(output)
+            .and_then(|info| info.name)
+            .unwrap_or_else(|| "without a name".to_string())
+    }
+
+    /// Take the newest wallpaper sent by [`show`] and draw it everywhere
+    fn update_image(&mut self) {
+        let Some(image) = self.images.try_iter().last() else {
+            return;
+        };
+        self.image = Some(image);
+        for index in 0..self.surfaces.len() {
+            self.draw(index);
+        }
+    }
+
+    /// Attach the current wallpaper to surface `index`, once it is configured
+    fn draw(&mut self, index: usize) {
+        let surface = &self.surfaces[index];
+        let (Some(image), Some((width, height))) = (&self.image, surface.size) else {
+            return;
+        };
+        if width == 0 || height == 0 {
+            warn!(
+                "output {} configured with an empty size",
+                self.describe(&surface.output)
+            );
+            return;
+        }
+        let scale = self
+            .outputs
+            .info(&surface.output)
+            .map_or(1, |info| info.scale_factor.max(1) as u32);
+        // Compositors without buffer scaling get a buffer of the logical size
+        let scale = match surface.layer.set_buffer_scale(scale) {
+            Ok(()) => scale,
+            Err(_) => 1,
+        };
+        let (width, height) = (width * scale, height * scale);
+        let pixels = xrgb_bytes(&fill(image, width, height));
+
+        let buffer = self.pool.create_buffer(
+            width as i32,
+            height as i32,
+            width as i32 * 4,
+            wl_shm::Format::Xrgb8888,
+        );
+        let (buffer, canvas) = match buffer {
+            Ok(buffer) => buffer,
+            Err(err) => {
+                error!("failed to draw wallpaper: {}", err);
+                return;
+            }
+        };
+        canvas.copy_from_slice(&pixels);
+
+        let surface = &mut self.surfaces[index];
+        let wl_surface = surface.layer.wl_surface();
+        if let Err(err) = buffer.attach_to(wl_surface) {
+            error!("failed to draw wallpaper: {}", err);
+            return;
+        }
+        wl_surface.damage_buffer(0, 0, width as i32, height as i32);
+        surface.layer.commit();
+        surface.buffer = Some(buffer);
+        info!(
+            "drew {}x{} wallpaper on output {}",
+            width,
+            height,
+            self.describe(&self.surfaces[index].output)
+        );
+    }
+}
+
+impl Dispatch<wl_callback::WlCallback, NewImage> for Renderer {
+    fn event(
+        renderer: &mut Self,
+        _: &wl_callback::WlCallback,
+        _: wl_callback::Event,
+        _: &NewImage,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        renderer.update_image();
+    }
+}
+
+impl OutputHandler for Renderer {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.outputs
+    }
+
+    fn new_output(
+        &mut self,
+        _: &Connection,
+        queue: &QueueHandle<Self>,
+        output: wl_output::WlOutput,
+    ) {
+        self.add_output(queue, output);
+    }
+
+    /// Redraw with the new scale of the output
+    fn update_output(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        output: wl_output::WlOutput,
+    ) {
+        if let Some(index) = self
+            .surfaces
+            .iter()
+            .position(|surface| surface.output == output)
+        {
+            self.draw(index);
+        }
+    }
+
+    fn output_destroyed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        output: wl_output::WlOutput,
+    ) {
+        info!(
+            "removing background surface of output {}",
+            self.describe(&output)
+        );
+        self.surfaces.retain(|surface| surface.output != output);
+    }
+}
+
+impl LayerShellHandler for Renderer {
+    fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, layer: &LayerSurface) {
+        self.surfaces.retain(|surface| &surface.layer != layer);
+    }
+
+    fn configure(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        layer: &LayerSurface,
+        configure: LayerSurfaceConfigure,
+        _: u32,
+    ) {
+        let Some(index) = self
+            .surfaces
+            .iter()
+            .position(|surface| &surface.layer == layer)
+        else {
+            return;
+        };
+        if self.surfaces[index].size != Some(configure.new_size) {
+            self.surfaces[index].size = Some(configure.new_size);
+            self.draw(index);
+        }
+    }
+}
+
+/// Scale changes are followed through [`OutputHandler::update_output`] instead
+impl CompositorHandler for Renderer {
+    fn scale_factor_changed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        _: i32,
+    ) {
+    }
+
+    fn transform_changed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        _: wl_output::Transform,
+    ) {
+    }
+
+    fn frame(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: u32) {}
+
+    fn surface_enter(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        _: &wl_output::WlOutput,
+    ) {
+    }
+
+    fn surface_leave(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        _: &wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl ShmHandler for Renderer {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+impl ProvidesRegistryState for Renderer {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry
+    }
+
+    registry_handlers!(OutputState);
+}
+
+delegate_compositor!(Renderer);
+delegate_output!(Renderer);
+delegate_shm!(Renderer);
+delegate_layer!(Renderer);
+delegate_registry!(Renderer);
+
+/// Scale `image` to cover `width` x `height`, cropping the overflow evenly on both sides
+fn fill(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    if image.dimensions() == (width, height) {
+        return image.clone();
+    }
+    let scale = f64::max(
+        width as f64 / image.width() as f64,
+        height as f64 / image.height() as f64,
+    );
+    let scaled_width = ((image.width() as f64 * scale).ceil() as u32).max(width);
+    let scaled_height = ((image.height() as f64 * scale).ceil() as u32).max(height);
+    let scaled = imageops::resize(image, scaled_width, scaled_height, FilterType::Triangle);
+    imageops::crop_imm(
+        &scaled,
+        (scaled_width - width) / 2,
+        (scaled_height - height) / 2,
+        width,
+        height,
+    )
+    .to_image()
+}
+
+/// Pixels as `XRGB8888`, which is stored as B, G, R, X bytes on little endian machines
+fn xrgb_bytes(image: &RgbaImage) -> Vec<u8> {
+    image
+        .pixels()
+        .flat_map(|pixel| {
+            let [r, g, b, _] = pixel.0;
+            u32::from_be_bytes([0xFF, r, g, b]).to_ne_bytes()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_crops_to_cover() {
+        let mut image = RgbaImage::new(400, 100);
+        image.put_pixel(0, 50, image::Rgba([255, 0, 0, 255]));
+        image.put_pixel(200, 50, image::Rgba([0, 255, 0, 255]));
+        let filled = fill(&image, 200, 200);
+        assert_eq!(filled.dimensions(), (200, 200));
+        // Scaled to 800x200, the left edge is cropped and the center stays in the middle
+        assert_eq!(filled.get_pixel(0, 100).0[0], 0);
+        assert!(filled.get_pixel(100, 100).0[1] > 0);
+    }
+
+    #[test]
+    fn xrgb_byte_order() {
+        let image = RgbaImage::from_pixel(1, 1, image::Rgba([0x11, 0x22, 0x33, 0x80]));
+        let expected = if cfg!(target_endian = "little") {
+            [0x33, 0x22, 0x11, 0xFF]
+        } else {
+            [0xFF, 0x11, 0x22, 0x33]
+        };
+        assert_eq!(xrgb_bytes(&image), expected);
+    }
+}
@@ -0,0 +1,58 @@
+use std::process::Command;
+
+use log::info;
+
+use crate::XkcdError;
+
+/// Page of a comic on xkcd.com
+pub fn comic_url(num: u64) -> String {
+    format!("https://xkcd.com/{num}/")
+}
+
+/// Explanation of a comic on the explain xkcd wiki
+pub fn explain_url(num: u64) -> String {
+    format!("https://www.explainxkcd.com/wiki/index.php/{num}")
+}
+
+/// Program that opens a URL in the default browser
+pub fn opener() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    }
+}
+
+/// Open `url` in the default browser
+pub fn open(url: &str) -> Result<(), XkcdError> {
+    info!("opening {} with {}", url, opener());
+    let status = Command::new(opener())
+        .arg(url)
+        .status()
+        .map_err(|err| XkcdError::Other(format!("failed to run {}: {}", opener(), err)))?;
+    // explorer exits with 1 even when it opened the page
+    if !status.success() && !cfg!(windows) {
+        return Err(XkcdError::Other(format!(
+            "{} exited with {}",
+            opener(),
+            status
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls() {
+        assert_eq!(comic_url(927), "https://xkcd.com/927/");
+        assert_eq!(
+            explain_url(927),
+            "https://www.explainxkcd.com/wiki/index.php/927"
+        );
+    }
+}
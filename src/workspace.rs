@@ -0,0 +1,417 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+
+use log::info;
+use serde::Deserialize;
+
+use crate::random::Rng;
+use crate::setter::{self, Backend};
+use crate::{ComicRef, Metadata, XkcdError};
+
+/// Workspaces filled with random comics when `[workspaces]` lists none
+const DEFAULT_COUNT: usize = 4;
+/// Attempts at a random comic not already shown on another workspace
+const DISTINCT_ATTEMPTS: usize = 16;
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// `[workspaces]` section, the comics shown with `--workspaces`
+///
+/// ```toml
+/// [workspaces]
+/// comics = [927, "latest", "random", "random"]
+/// ```
+///
+/// The first entry is shown on the first virtual desktop, and so on. Without `comics`, `count`
+/// workspaces get a random comic each.
+pub struct WorkspacesConfig {
+    pub comics: Vec<WorkspaceComic>,
+    pub count: Option<usize>,
+}
+
+impl WorkspacesConfig {
+    /// Comic for each workspace, in workspace order
+    pub fn comics(&self) -> Vec<WorkspaceComic> {
+        match self.comics.is_empty() {
+            true => vec![WorkspaceComic::Random; self.count.unwrap_or(DEFAULT_COUNT)],
+            false => self.comics.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "RawComic")]
+/// Comic shown on one workspace, a comic number, `latest`, `latest-N`, `~N` or `random`
+pub enum WorkspaceComic {
+    Comic(ComicRef),
+    Random,
+}
+
+impl WorkspaceComic {
+    /// Whether the comic changes when a new one is published
+    pub fn follows_latest(&self) -> bool {
+        matches!(self, WorkspaceComic::Comic(ComicRef::Latest(_)))
+    }
+}
+
+impl FromStr for WorkspaceComic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().eq_ignore_ascii_case("random") {
+            true => Ok(WorkspaceComic::Random),
+            false => s.parse().map(WorkspaceComic::Comic),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawComic {
+    Number(u32),
+    Name(String),
+}
+
+impl TryFrom<RawComic> for WorkspaceComic {
+    type Error = String;
+
+    fn try_from(raw: RawComic) -> Result<Self, Self::Error> {
+        match raw {
+            RawComic::Number(num) => num.to_string().parse(),
+            RawComic::Name(name) => name.parse(),
+        }
+    }
+}
+
+/// Comics to generate for `comics`, random ones drawn with `rng`
+///
+/// Only fetches the newest comic's number when there is a random pick.
+pub fn pick(comics: &[WorkspaceComic], rng: &mut Rng) -> Result<Vec<ComicRef>, XkcdError> {
+    let latest = match comics.contains(&WorkspaceComic::Random) {
+        true => Metadata::from_comic_id(None)?.num,
+        false => 0,
+    };
+    Ok(pick_up_to(comics, latest, rng))
+}
+
+/// Like `pick`, random comics are different from each other and the listed ones where possible
+fn pick_up_to(comics: &[WorkspaceComic], latest: u64, rng: &mut Rng) -> Vec<ComicRef> {
+    let mut picked = Vec::with_capacity(comics.len());
+    for comic in comics {
+        let comic = match comic {
+            WorkspaceComic::Comic(comic) => *comic,
+            WorkspaceComic::Random => {
+                let taken = |num: u64| {
+                    comics.contains(&WorkspaceComic::Comic(ComicRef::Number(num as u32)))
+                        || picked.contains(&ComicRef::Number(num as u32))
+                };
+                let mut num = rng.comic(latest);
+                for _ in 1..DISTINCT_ATTEMPTS {
+                    if !taken(num) {
+                        break;
+                    }
+                    num = rng.comic(latest);
+                }
+                ComicRef::Number(num as u32)
+            }
+        };
+        picked.push(comic);
+    }
+    info!("picked comics {:?} for the workspaces", picked);
+    picked
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Desktop that can show a different wallpaper on each virtual desktop
+pub enum WorkspaceBackend {
+    /// KDE Plasma, through its scripting interface
+    Plasma,
+    /// Hyprland, the wallpaper is changed by a watcher when switching workspaces
+    Hyprland,
+}
+
+impl WorkspaceBackend {
+    pub fn detect() -> Option<WorkspaceBackend> {
+        let desktop = env::var("XDG_CURRENT_DESKTOP")
+            .unwrap_or_default()
+            .to_lowercase();
+        if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            Some(WorkspaceBackend::Hyprland)
+        } else if desktop.contains("kde") {
+            Some(WorkspaceBackend::Plasma)
+        } else {
+            None
+        }
+    }
+
+    /// Show `wallpapers[i]` on the `i + 1`th workspace, wrapping around when there are more
+    /// workspaces
+    ///
+    /// Hyprland wallpapers are set with `backend` by a background thread, see [`wait`].
+    pub fn assign(&self, wallpapers: &[PathBuf], backend: Backend) -> Result<(), XkcdError> {
+        let wallpapers = wallpapers
+            .iter()
+            .map(std::path::absolute)
+            .collect::<Result<Vec<_>, _>>()?;
+        if wallpapers.is_empty() {
+            return Err(XkcdError::Setter("no workspace wallpapers".to_string()));
+        }
+
+        match self {
+            WorkspaceBackend::Plasma => {
+                let qdbus = ["qdbus6", "qdbus", "qdbus-qt5"]
+                    .into_iter()
+                    .find(|program| in_path(program))
+                    .ok_or_else(|| XkcdError::Setter("qdbus is not installed".to_string()))?;
+                info!(
+                    "assigning {} wallpapers through plasmashell",
+                    wallpapers.len()
+                );
+                setter::run(
+                    qdbus,
+                    &[
+                        "org.kde.plasmashell",
+                        "/PlasmaShell",
+                        "org.kde.PlasmaShell.evaluateScript",
+                        &plasma_script(&wallpapers),
+                    ],
+                )?;
+                Ok(())
+            }
+            WorkspaceBackend::Hyprland => hyprland::watch(wallpapers, backend),
+        }
+    }
+}
+
+/// Plasma script setting the wallpaper of every desktop containment
+///
+/// Containments of a virtual desktop get that desktop's wallpaper. Shared containments, one per
+/// screen and activity, are numbered in order instead.
+fn plasma_script(wallpapers: &[PathBuf]) -> String {
+    let urls = wallpapers
+        .iter()
+        .map(|path| format!("file://{}", path.display()))
+        .collect::<Vec<_>>();
+    let urls = serde_json::to_string(&urls).expect("strings serialize to JSON");
+    format!(
+        r#"var wallpapers = {urls};
+var all = desktops();
+for (var i = 0; i < all.length; i++) {{
+    var desktop = all[i];
+    var index = desktop.desktop >= 0 ? desktop.desktop : i;
+    desktop.wallpaperPlugin = "org.kde.image";
+    desktop.currentConfigGroup = ["Wallpaper", "org.kde.image", "General"];
+    desktop.writeConfig("Image", wallpapers[index % wallpapers.length]);
+}}"#
+    )
+}
+
+fn in_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .map(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+/// Wallpaper for the workspace with id `workspace`, Hyprland numbers them from 1
+fn wallpaper_for(wallpapers: &[PathBuf], workspace: i64) -> Option<&Path> {
+    match workspace {
+        ..=0 => None,
+        _ => wallpapers
+            .get((workspace - 1) as usize % wallpapers.len())
+            .map(PathBuf::as_path),
+    }
+}
+
+/// Block until the Hyprland watcher stops, which happens when the compositor exits
+pub fn wait() {
+    let thread = WATCHER.lock().unwrap_or_else(|err| err.into_inner()).take();
+    if let Some(thread) = thread {
+        let _ = thread.join();
+    }
+}
+
+/// Wallpapers shown by the Hyprland watcher, replaced on every `assign`
+static WALLPAPERS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+static WATCHER: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+#[cfg(unix)]
+mod hyprland {
+    use std::env;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+    use std::thread;
+
+    use log::{error, info, warn};
+    use serde::Deserialize;
+
+    use super::{wallpaper_for, WALLPAPERS, WATCHER};
+    use crate::setter::{self, Backend};
+    use crate::XkcdError;
+
+    #[derive(Deserialize)]
+    struct Workspace {
+        id: i64,
+    }
+
+    /// Show the wallpaper of the active workspace, and start following workspace switches
+    pub fn watch(wallpapers: Vec<PathBuf>, backend: Backend) -> Result<(), XkcdError> {
+        *WALLPAPERS.lock().unwrap_or_else(|err| err.into_inner()) = wallpapers;
+
+        let active = setter::run("hyprctl", &["activeworkspace", "-j"])?;
+        let active: Workspace = serde_json::from_str(&active)
+            .map_err(|err| XkcdError::Setter(format!("unexpected hyprctl output: {err}")))?;
+        show(active.id, backend)?;
+
+        let mut watcher = WATCHER.lock().unwrap_or_else(|err| err.into_inner());
+        if watcher.is_none() {
+            let events = UnixStream::connect(socket_path()?).map_err(|err| {
+                XkcdError::Setter(format!("failed to connect to Hyprland: {err}"))
+            })?;
+            info!("following Hyprland workspace switches");
+            *watcher = Some(thread::spawn(move || follow(events, backend)));
+        }
+        Ok(())
+    }
+
+    fn follow(events: UnixStream, backend: Backend) {
+        for line in BufReader::new(events).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    error!("lost the connection to Hyprland: {}", err);
+                    return;
+                }
+            };
+            if let Some(workspace) = workspace_event(&line) {
+                if let Err(err) = show(workspace, backend) {
+                    warn!("failed to set the workspace wallpaper: {}", err);
+                }
+            }
+        }
+        info!("Hyprland closed the event socket");
+    }
+
+    fn show(workspace: i64, backend: Backend) -> Result<(), XkcdError> {
+        let path = wallpaper_for(
+            &WALLPAPERS.lock().unwrap_or_else(|err| err.into_inner()),
+            workspace,
+        )
+        .map(|path| path.to_path_buf());
+        match path {
+            Some(path) => backend.set(&path),
+            // Special and named workspaces keep the current wallpaper
+            None => Ok(()),
+        }
+    }
+
+    /// Workspace id from a `workspacev2` or `focusedmonv2` event of the socket2 protocol
+    pub(super) fn workspace_event(line: &str) -> Option<i64> {
+        let (event, data) = line.split_once(">>")?;
+        let id = match event {
+            "workspacev2" => data.split(',').next()?,
+            "focusedmonv2" => data.rsplit(',').next()?,
+            _ => return None,
+        };
+        id.trim().parse().ok()
+    }
+
+    fn socket_path() -> Result<PathBuf, XkcdError> {
+        let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE")
+            .map_err(|_| XkcdError::Setter("Hyprland is not running".to_string()))?;
+        // Hyprland 0.40 moved its sockets from /tmp into the runtime directory
+        let runtime = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+        [runtime, Some(PathBuf::from("/tmp"))]
+            .into_iter()
+            .flatten()
+            .map(|dir| dir.join("hypr").join(&signature).join(".socket2.sock"))
+            .find(|path| path.exists())
+            .ok_or_else(|| XkcdError::Setter("cannot find the Hyprland event socket".to_string()))
+    }
+}
+
+#[cfg(not(unix))]
+mod hyprland {
+    use std::path::PathBuf;
+
+    use crate::setter::Backend;
+    use crate::XkcdError;
+
+    pub fn watch(_wallpapers: Vec<PathBuf>, _backend: Backend) -> Result<(), XkcdError> {
+        Err(XkcdError::Setter(
+            "Hyprland workspaces are only supported on Unix".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn parse_config() {
+        let config: WorkspacesConfig =
+            toml_edit::de::from_str("comics = [927, \"latest-1\", \"Random\"]").unwrap();
+        assert_eq!(
+            config.comics(),
+            vec![
+                WorkspaceComic::Comic(ComicRef::Number(927)),
+                WorkspaceComic::Comic(ComicRef::Latest(1)),
+                WorkspaceComic::Random,
+            ]
+        );
+        assert!(toml_edit::de::from_str::<WorkspacesConfig>("comics = [0]").is_err());
+
+        let config: WorkspacesConfig = toml_edit::de::from_str("count = 2").unwrap();
+        assert_eq!(config.comics(), vec![WorkspaceComic::Random; 2]);
+    }
+
+    #[test]
+    fn random_picks_are_distinct() {
+        let comics = [
+            WorkspaceComic::Comic(ComicRef::Number(1)),
+            WorkspaceComic::Random,
+            WorkspaceComic::Random,
+            WorkspaceComic::Comic(ComicRef::Latest(0)),
+        ];
+        let picked = pick_up_to(&comics, 3, &mut Rng::new(1));
+        assert_eq!(picked[0], ComicRef::Number(1));
+        assert_eq!(picked[3], ComicRef::Latest(0));
+        let mut random = vec![picked[1], picked[2]];
+        random.sort_by_key(|comic| format!("{comic:?}"));
+        assert_eq!(random, vec![ComicRef::Number(2), ComicRef::Number(3)]);
+    }
+
+    #[rstest]
+    #[case(1, Some("/w/1.png"))]
+    #[case(2, Some("/w/2.png"))]
+    #[case(3, Some("/w/1.png"))]
+    #[case(-98, None)]
+    fn workspace_wallpaper(#[case] workspace: i64, #[case] expected: Option<&str>) {
+        let wallpapers = [PathBuf::from("/w/1.png"), PathBuf::from("/w/2.png")];
+        assert_eq!(
+            wallpaper_for(&wallpapers, workspace),
+            expected.map(Path::new)
+        );
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    #[case("workspacev2>>3,3", Some(3))]
+    #[case("focusedmonv2>>DP-1,5", Some(5))]
+    #[case("workspace>>3", None)]
+    #[case("activewindow>>kitty,~", None)]
+    fn hyprland_events(#[case] line: &str, #[case] expected: Option<i64>) {
+        assert_eq!(hyprland::workspace_event(line), expected);
+    }
+
+    #[test]
+    fn plasma_script_urls() {
+        let script = plasma_script(&[PathBuf::from("/w/a \"b\".png")]);
+        assert!(script.starts_with(r#"var wallpapers = ["file:///w/a \"b\".png"];"#));
+    }
+}